@@ -218,10 +218,16 @@ fn process_broker_event(
                 "received ConfigUpdated, forward to {}",
                 destination_server_id
             );
-            outboxes
-                .get(&destination_server_id)
-                .unwrap()
-                .send(BrokerEvent::ConfigUpdated(config, destination_server_id));
+            // configs could be provided at runtime (e.g. by an API), so an unknown id must not stop the kernel
+            match outboxes.get(&destination_server_id) {
+                Some(outbox) => {
+                    outbox.send(BrokerEvent::ConfigUpdated(config, destination_server_id))
+                }
+                None => warn!(
+                    "received ConfigUpdated for unknown component {}, it is ignored",
+                    destination_server_id
+                ),
+            }
         }
         BrokerEvent::Batch(broker_events) => {
             for broker_event in broker_events.into_iter() {
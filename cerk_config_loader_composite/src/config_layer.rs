@@ -0,0 +1,234 @@
+use cerk::kernel::Config;
+use cerk::runtime::InternalServerId;
+use std::collections::HashMap;
+use std::fmt;
+
+/// The sources of the configs, ordered by priority (the last one wins).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ConfigSource {
+    /// the file at `CONFIG_PATH`
+    BaseFile,
+    /// the file at `CONFIG_OVERRIDE_PATH`
+    OverrideFile,
+    /// the env variables with the prefix `CERK_CONFIG__`
+    Environment,
+    /// the changes applied by the admin API
+    AdminApi,
+}
+
+impl fmt::Display for ConfigSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ConfigSource::BaseFile => "base file",
+            ConfigSource::OverrideFile => "override file",
+            ConfigSource::Environment => "environment",
+            ConfigSource::AdminApi => "admin API",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// The configs of all components provided by one source.
+pub type ConfigLayer = HashMap<InternalServerId, Config>;
+
+/// The source of every config value, the key is the path of the value, e.g., `amqp-input.uri`.
+pub type Provenance = HashMap<String, ConfigSource>;
+
+fn record_provenance(
+    path: String,
+    config: &Config,
+    source: ConfigSource,
+    provenance: &mut Provenance,
+) {
+    // a replaced value also replaces the sources of all its former children
+    let prefix = format!("{}.", path);
+    provenance.retain(|p, _| !p.starts_with(prefix.as_str()));
+    match config {
+        Config::HashMap(values) if !values.is_empty() => {
+            provenance.remove(path.as_str());
+            for (key, value) in values {
+                record_provenance(format!("{}.{}", path, key), value, source, provenance);
+            }
+        }
+        _ => {
+            provenance.insert(path, source);
+        }
+    }
+}
+
+fn merge_config(
+    path: String,
+    base: Option<Config>,
+    config: &Config,
+    source: ConfigSource,
+    provenance: &mut Provenance,
+) -> Config {
+    match (base, config) {
+        (Some(Config::HashMap(mut base)), Config::HashMap(values)) => {
+            for (key, value) in values {
+                let merged = merge_config(
+                    format!("{}.{}", path, key),
+                    base.remove(key),
+                    value,
+                    source,
+                    provenance,
+                );
+                base.insert(key.clone(), merged);
+            }
+            Config::HashMap(base)
+        }
+        _ => {
+            record_provenance(path, config, source, provenance);
+            config.clone()
+        }
+    }
+}
+
+impl ConfigSource {
+    /// The configs of the admin API replace the whole config of a component, so a `PUT` could also remove values.
+    fn replaces_components(&self) -> bool {
+        *self == ConfigSource::AdminApi
+    }
+}
+
+/// Merges the layers in the given order, maps are merged recursively and all other values are replaced.
+///
+/// The configs of the admin API are not merged, they replace the configs of the former layers.
+pub fn merge_layers(layers: &[(ConfigSource, &ConfigLayer)]) -> (ConfigLayer, Provenance) {
+    let mut merged = ConfigLayer::new();
+    let mut provenance = Provenance::new();
+    for (source, layer) in layers {
+        for (id, config) in layer.iter() {
+            let base = merged.remove(id).filter(|_| !source.replaces_components());
+            let config = merge_config(id.clone(), base, config, *source, &mut provenance);
+            merged.insert(id.clone(), config);
+        }
+    }
+    (merged, provenance)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn map(values: &[(&str, Config)]) -> Config {
+        Config::HashMap(
+            values
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.clone()))
+                .collect(),
+        )
+    }
+
+    fn layer(values: &[(&str, Config)]) -> ConfigLayer {
+        values
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn merge_replaces_values_and_merges_maps() {
+        let base = layer(&[
+            ("router", Config::Vec(vec![Config::String("a".to_string())])),
+            (
+                "amqp",
+                map(&[
+                    ("uri", Config::String("amqp://localhost".to_string())),
+                    ("prefetch", Config::U8(10)),
+                ]),
+            ),
+            ("printer", Config::Null),
+        ]);
+        let overrides = layer(&[
+            ("router", Config::Vec(vec![Config::String("b".to_string())])),
+            (
+                "amqp",
+                map(&[("uri", Config::String("amqp://prod".to_string()))]),
+            ),
+        ]);
+
+        let (merged, provenance) = merge_layers(&[
+            (ConfigSource::BaseFile, &base),
+            (ConfigSource::OverrideFile, &overrides),
+        ]);
+
+        assert_eq!(
+            merged,
+            layer(&[
+                ("router", Config::Vec(vec![Config::String("b".to_string())])),
+                (
+                    "amqp",
+                    map(&[
+                        ("uri", Config::String("amqp://prod".to_string())),
+                        ("prefetch", Config::U8(10)),
+                    ]),
+                ),
+                ("printer", Config::Null),
+            ])
+        );
+        assert_eq!(
+            provenance,
+            [
+                ("router".to_string(), ConfigSource::OverrideFile),
+                ("amqp.uri".to_string(), ConfigSource::OverrideFile),
+                ("amqp.prefetch".to_string(), ConfigSource::BaseFile),
+                ("printer".to_string(), ConfigSource::BaseFile),
+            ]
+            .iter()
+            .cloned()
+            .collect()
+        );
+    }
+
+    #[test]
+    fn admin_api_replaces_components() {
+        let base = layer(&[(
+            "amqp",
+            map(&[
+                ("uri", Config::String("amqp://localhost".to_string())),
+                ("prefetch", Config::U8(10)),
+            ]),
+        )]);
+        let admin = layer(&[(
+            "amqp",
+            map(&[("uri", Config::String("amqp://prod".to_string()))]),
+        )]);
+
+        let (merged, provenance) = merge_layers(&[
+            (ConfigSource::BaseFile, &base),
+            (ConfigSource::AdminApi, &admin),
+        ]);
+
+        assert_eq!(merged, admin);
+        assert_eq!(
+            provenance,
+            [("amqp.uri".to_string(), ConfigSource::AdminApi)]
+                .iter()
+                .cloned()
+                .collect()
+        );
+    }
+
+    #[test]
+    fn replaced_map_drops_provenance_of_children() {
+        let base = layer(&[(
+            "amqp",
+            map(&[("channel", map(&[("name", Config::String("a".to_string()))]))]),
+        )]);
+        let overrides = layer(&[("amqp", map(&[("channel", Config::Null)]))]);
+
+        let (_, provenance) = merge_layers(&[
+            (ConfigSource::BaseFile, &base),
+            (ConfigSource::AdminApi, &overrides),
+        ]);
+
+        assert_eq!(
+            provenance,
+            [("amqp.channel".to_string(), ConfigSource::AdminApi)]
+                .iter()
+                .cloned()
+                .collect()
+        );
+    }
+}
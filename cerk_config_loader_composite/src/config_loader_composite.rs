@@ -0,0 +1,286 @@
+use crate::config_layer::{merge_layers, ConfigLayer, ConfigSource, Provenance};
+use crate::env_layer::read_env_layer;
+use anyhow::{Context, Result};
+use cerk::kernel::{BrokerEvent, Config};
+use cerk::runtime::channel::{BoxedReceiver, BoxedSender};
+use cerk::runtime::{InternalServerFn, InternalServerFnRefStatic, InternalServerId};
+use cerk_config_loader_file::read_component_configs_from_file;
+use cerk_config_loader_http::{start_admin_server, validate_routing_rules, ConfigStore, ROUTER_ID};
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+struct CompositeState {
+    layers: BTreeMap<ConfigSource, ConfigLayer>,
+    merged: ConfigLayer,
+    provenance: Provenance,
+}
+
+impl CompositeState {
+    fn new(layers: BTreeMap<ConfigSource, ConfigLayer>) -> Self {
+        let mut state = CompositeState {
+            layers,
+            merged: ConfigLayer::new(),
+            provenance: Provenance::new(),
+        };
+        state.merge();
+        state
+    }
+
+    /// Merges all layers and returns the configs which changed, the router config is always the last one.
+    fn merge(&mut self) -> Vec<(InternalServerId, Config)> {
+        let layers: Vec<(ConfigSource, &ConfigLayer)> =
+            self.layers.iter().map(|(s, l)| (*s, l)).collect();
+        let (merged, provenance) = merge_layers(layers.as_slice());
+        let mut changed: Vec<(InternalServerId, Config)> = merged
+            .iter()
+            .filter(|(id, config)| self.merged.get(*id) != Some(config))
+            .map(|(id, config)| (id.clone(), config.clone()))
+            .collect();
+        changed.sort_by_key(|(id, _)| id == ROUTER_ID);
+        self.merged = merged;
+        self.provenance = provenance;
+        changed
+    }
+}
+
+struct CompositeConfigStore {
+    state: Mutex<CompositeState>,
+    sender_to_kernel: Mutex<BoxedSender>,
+}
+
+impl CompositeConfigStore {
+    fn send(&self, configs: Vec<(InternalServerId, Config)>) {
+        let provenance = self.state.lock().unwrap().provenance.clone();
+        let sender = self.sender_to_kernel.lock().unwrap();
+        for (id, config) in configs {
+            for (path, source) in provenance.iter() {
+                if path == &id || path.starts_with(format!("{}.", id).as_str()) {
+                    debug!("config value {} provided by {}", path, source);
+                }
+            }
+            sender.send(BrokerEvent::ConfigUpdated(config, id));
+        }
+    }
+}
+
+impl ConfigStore for CompositeConfigStore {
+    fn configs(&self) -> HashMap<InternalServerId, Config> {
+        self.state.lock().unwrap().merged.clone()
+    }
+
+    fn sources(&self) -> HashMap<String, String> {
+        self.state
+            .lock()
+            .unwrap()
+            .provenance
+            .iter()
+            .map(|(path, source)| (path.clone(), source.to_string()))
+            .collect()
+    }
+
+    fn validate(&self, id: &InternalServerId, config: &Config) -> Result<()> {
+        if id == ROUTER_ID {
+            let merged = self.configs();
+            validate_routing_rules(config, &|port_id| {
+                port_id != ROUTER_ID && merged.contains_key(port_id)
+            })?;
+        }
+        Ok(())
+    }
+
+    fn apply(&self, id: InternalServerId, config: Config) -> Result<()> {
+        let changed = {
+            let mut state = self.state.lock().unwrap();
+            state
+                .layers
+                .entry(ConfigSource::AdminApi)
+                .or_default()
+                .insert(id, config);
+            state.merge()
+        };
+        self.send(changed);
+        Ok(())
+    }
+}
+
+fn read_file_layer(path: &str) -> Result<ConfigLayer> {
+    Ok(read_component_configs_from_file(path)?
+        .into_iter()
+        .collect())
+}
+
+fn read_layers() -> Result<BTreeMap<ConfigSource, ConfigLayer>> {
+    let mut layers = BTreeMap::new();
+    let base_path = env::var("CONFIG_PATH").unwrap_or(String::from("./config.json"));
+    layers.insert(ConfigSource::BaseFile, read_file_layer(base_path.as_str())?);
+    if let Ok(override_path) = env::var("CONFIG_OVERRIDE_PATH") {
+        layers.insert(
+            ConfigSource::OverrideFile,
+            read_file_layer(override_path.as_str())?,
+        );
+    }
+    let (known, _) = merge_layers(
+        layers
+            .iter()
+            .map(|(s, l)| (*s, l))
+            .collect::<Vec<_>>()
+            .as_slice(),
+    );
+    layers.insert(
+        ConfigSource::Environment,
+        read_env_layer(env::vars(), &known).context("failed to read config from env variables")?,
+    );
+    Ok(layers)
+}
+
+fn start(sender_to_kernel: BoxedSender, tokio: &tokio::runtime::Handle) -> Result<()> {
+    let state = CompositeState::new(read_layers()?);
+    let mut configs: Vec<(InternalServerId, Config)> = state.merged.clone().into_iter().collect();
+    configs.sort_by_key(|(id, _)| id == ROUTER_ID);
+
+    let store = Arc::new(CompositeConfigStore {
+        state: Mutex::new(state),
+        sender_to_kernel: Mutex::new(sender_to_kernel),
+    });
+    store.send(configs);
+
+    if let Ok(address) = env::var("CONFIG_ADMIN_ADDRESS") {
+        let address: SocketAddr = address.parse()?;
+        start_admin_server(address, store, tokio)?;
+    }
+    Ok(())
+}
+
+/// This is the main function to start the config loader.
+pub fn config_loader_composite_start(
+    id: InternalServerId,
+    inbox: BoxedReceiver,
+    sender_to_kernel: BoxedSender,
+) {
+    info!("start composite config loader with id {}", id);
+    let tokio = tokio::runtime::Runtime::new().unwrap();
+    let mut sender_to_kernel = Some(sender_to_kernel);
+    loop {
+        match inbox.receive() {
+            BrokerEvent::Init => {
+                info!("{} initiated", id);
+                if let Some(sender_to_kernel) = sender_to_kernel.take() {
+                    if let Err(e) = start(sender_to_kernel, tokio.handle()) {
+                        error!("failed to load config {:?}", e);
+                    }
+                }
+            }
+            broker_event => warn!("event {} not implemented", broker_event),
+        }
+    }
+}
+
+/// This is the pointer for the main function to start the config loader.
+pub static CONFIG_LOADER_COMPOSITE: InternalServerFnRefStatic =
+    &(config_loader_composite_start as InternalServerFn);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cerk_runtime_threading::channel::new_channel_with_size;
+    use std::time::Duration;
+
+    fn put(address: &str, path: &str, body: &str) -> hyper::StatusCode {
+        let request = hyper::Request::put(format!("http://{}{}", address, path))
+            .body(hyper::Body::from(body.to_string()))
+            .unwrap();
+        let mut runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime
+            .block_on(hyper::Client::new().request(request))
+            .unwrap()
+            .status()
+    }
+
+    #[test]
+    fn admin_api_rejects_invalid_routing_rules() {
+        let base: ConfigLayer = [
+            ("router".to_string(), Config::Vec(vec![])),
+            ("printer".to_string(), Config::Null),
+        ]
+        .iter()
+        .cloned()
+        .collect();
+        let state = CompositeState::new([(ConfigSource::BaseFile, base)].iter().cloned().collect());
+        let (send, receive) = new_channel_with_size(10);
+        let store = Arc::new(CompositeConfigStore {
+            state: Mutex::new(state),
+            sender_to_kernel: Mutex::new(send),
+        });
+        let address = "127.0.0.1:3198";
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        start_admin_server(address.parse().unwrap(), store.clone(), runtime.handle()).unwrap();
+
+        for rules in &[
+            r#""{\"rules\":[]}""#,
+            r#"["unknown"]"#,
+            r#"["router"]"#,
+            r#"42"#,
+        ] {
+            assert_eq!(
+                put(address, "/routing", rules),
+                hyper::StatusCode::UNPROCESSABLE_ENTITY,
+                "{} was accepted",
+                rules
+            );
+        }
+        assert!(receive.receive_timeout(Duration::from_millis(10)).is_none());
+        assert_eq!(store.configs().get("router"), Some(&Config::Vec(vec![])));
+
+        assert_eq!(
+            put(
+                address,
+                "/routing",
+                r#""{\"printer\":{\"Contains\":[\"Type\",\"order\"]}}""#
+            ),
+            hyper::StatusCode::OK
+        );
+        assert!(matches!(
+            receive.receive_timeout(Duration::from_millis(100)),
+            Some(BrokerEvent::ConfigUpdated(_, id)) if id == "router"
+        ));
+    }
+
+    #[test]
+    fn admin_api_overrides_file_and_only_sends_changes() {
+        let base: ConfigLayer = [
+            ("router".to_string(), Config::Vec(vec![])),
+            ("printer".to_string(), Config::Null),
+            ("generator".to_string(), Config::U8(1)),
+        ]
+        .iter()
+        .cloned()
+        .collect();
+        let state = CompositeState::new([(ConfigSource::BaseFile, base)].iter().cloned().collect());
+        let (send, receive) = new_channel_with_size(10);
+        let store = CompositeConfigStore {
+            state: Mutex::new(state),
+            sender_to_kernel: Mutex::new(send),
+        };
+
+        assert!(store.apply("generator".to_string(), Config::U8(2)).is_ok());
+
+        match receive.receive_timeout(Duration::from_millis(100)) {
+            Some(BrokerEvent::ConfigUpdated(config, id)) => {
+                assert_eq!(id, "generator");
+                assert_eq!(config, Config::U8(2));
+            }
+            _ => panic!("expected ConfigUpdated"),
+        }
+        assert!(receive.receive_timeout(Duration::from_millis(10)).is_none());
+        assert_eq!(
+            store.sources().get("generator"),
+            Some(&"admin API".to_string())
+        );
+        assert_eq!(
+            store.sources().get("printer"),
+            Some(&"base file".to_string())
+        );
+    }
+}
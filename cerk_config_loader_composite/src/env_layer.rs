@@ -0,0 +1,191 @@
+use crate::config_layer::ConfigLayer;
+use anyhow::{Context, Result};
+use cerk::kernel::Config;
+use std::collections::HashMap;
+
+const ENV_PREFIX: &str = "CERK_CONFIG__";
+const ENV_SEPARATOR: &str = "__";
+/// The last segment of variables with JSON values, e.g., `CERK_CONFIG__ROUTER__JSON`.
+const JSON_SUFFIX: &str = "JSON";
+
+fn normalize(name: &str) -> String {
+    name.to_uppercase().replace('-', "_")
+}
+
+/// Finds the existing name, which matches the env variable segment, e.g. `AMQP_INPUT` matches `amqp-input`.
+fn resolve_name<'a, I>(segment: &str, existing: I) -> String
+where
+    I: Iterator<Item = &'a String>,
+{
+    let normalized = normalize(segment);
+    existing
+        .into_iter()
+        .find(|name| normalize(name) == normalized)
+        .cloned()
+        .unwrap_or_else(|| segment.to_lowercase())
+}
+
+/// Values are strings, unless the variable name ends with `__JSON`, e.g., a password `1234` stays a string.
+fn parse_value(name: &str, value: &str, json: bool) -> Result<Config> {
+    if json {
+        serde_json::from_str(value)
+            .with_context(|| format!("env variable {} is not a valid JSON config", name))
+    } else {
+        Ok(Config::String(value.to_string()))
+    }
+}
+
+fn insert(config: &mut Config, keys: &[&str], known: Option<&Config>, value: Config) {
+    let (key, rest) = match keys.split_first() {
+        Some(split) => split,
+        None => {
+            *config = value;
+            return;
+        }
+    };
+    if !matches!(config, Config::HashMap(_)) {
+        *config = Config::HashMap(HashMap::new());
+    }
+    let known_values = match known {
+        Some(Config::HashMap(values)) => Some(values),
+        _ => None,
+    };
+    let key = resolve_name(key, known_values.iter().flat_map(|values| values.keys()));
+    let known = known_values.and_then(|values| values.get(&key));
+    if let Config::HashMap(values) = config {
+        let child = values.entry(key).or_insert(Config::Null);
+        insert(child, rest, known, value);
+    }
+}
+
+/// Builds a config layer of the env variables with the prefix `CERK_CONFIG__`.
+///
+/// The names of the components and keys are separated by `__` and matched with the known configs,
+/// e.g., `CERK_CONFIG__AMQP_INPUT__URI` sets the `uri` of the component `amqp-input`.
+pub fn read_env_layer<I>(vars: I, known: &ConfigLayer) -> Result<ConfigLayer>
+where
+    I: Iterator<Item = (String, String)>,
+{
+    let mut vars: Vec<(Vec<String>, bool, String, String)> = vars
+        .filter_map(|(name, value)| {
+            let path = name.strip_prefix(ENV_PREFIX)?;
+            let mut path: Vec<String> = path.split(ENV_SEPARATOR).map(|s| s.to_string()).collect();
+            let json = path.len() > 1 && path.last().map(|s| s.as_str()) == Some(JSON_SUFFIX);
+            if json {
+                path.pop();
+            }
+            Some((path, json, name, value))
+        })
+        .collect();
+    // shorter paths first, so that `CERK_CONFIG__A__B` is applied on top of `CERK_CONFIG__A`
+    vars.sort_by_key(|(path, _, _, _)| path.len());
+
+    let mut layer = ConfigLayer::new();
+    for (path, json, name, value) in vars {
+        let (component, keys) = match path.split_first() {
+            Some((component, keys)) if !component.is_empty() => (component, keys),
+            _ => bail!("env variable {} has no component name", ENV_PREFIX),
+        };
+        if keys.iter().any(|k| k.is_empty()) {
+            bail!(
+                "env variable {}{} contains an empty key",
+                ENV_PREFIX,
+                path.join(ENV_SEPARATOR)
+            );
+        }
+        let id = resolve_name(component, known.keys());
+        let keys: Vec<&str> = keys.iter().map(|k| k.as_str()).collect();
+        let value = parse_value(name.as_str(), value.as_str(), json)?;
+        let config = layer.entry(id.clone()).or_insert(Config::Null);
+        insert(config, keys.as_slice(), known.get(&id), value);
+    }
+    Ok(layer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(vars: &[(&str, &str)]) -> impl Iterator<Item = (String, String)> {
+        vars.iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    #[test]
+    fn read_env_layer_with_known_names() -> Result<()> {
+        let known: ConfigLayer = [(
+            "amqp-input".to_string(),
+            Config::HashMap(
+                [("prefetch_count".to_string(), Config::U8(1))]
+                    .iter()
+                    .cloned()
+                    .collect(),
+            ),
+        )]
+        .iter()
+        .cloned()
+        .collect();
+
+        let layer = read_env_layer(
+            vars(&[
+                ("CERK_CONFIG__AMQP_INPUT__URI", "amqp://prod:5672/%2f"),
+                ("CERK_CONFIG__AMQP_INPUT__PREFETCH_COUNT__JSON", "300"),
+                ("CERK_CONFIG__AMQP_INPUT__PASSWORD", "1234"),
+                ("CERK_CONFIG__AMQP_INPUT__DURABLE", "true"),
+                ("CERK_CONFIG__ROUTER__JSON", r#"["amqp-input"]"#),
+                ("HOME", "/root"),
+            ]),
+            &known,
+        )?;
+
+        assert_eq!(
+            layer,
+            [
+                (
+                    "amqp-input".to_string(),
+                    Config::HashMap(
+                        [
+                            (
+                                "uri".to_string(),
+                                Config::String("amqp://prod:5672/%2f".to_string())
+                            ),
+                            ("prefetch_count".to_string(), Config::U32(300)),
+                            ("password".to_string(), Config::String("1234".to_string())),
+                            ("durable".to_string(), Config::String("true".to_string())),
+                        ]
+                        .iter()
+                        .cloned()
+                        .collect()
+                    )
+                ),
+                (
+                    "router".to_string(),
+                    Config::Vec(vec![Config::String("amqp-input".to_string())])
+                ),
+            ]
+            .iter()
+            .cloned()
+            .collect()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn read_env_layer_fails_on_invalid_json() {
+        assert!(read_env_layer(
+            vars(&[("CERK_CONFIG__ROUTER__JSON", "[amqp-input]")]),
+            &ConfigLayer::new()
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn read_env_layer_fails_on_empty_names() {
+        assert!(read_env_layer(vars(&[("CERK_CONFIG__", "1")]), &ConfigLayer::new()).is_err());
+        assert!(
+            read_env_layer(vars(&[("CERK_CONFIG__A____B", "1")]), &ConfigLayer::new()).is_err()
+        );
+    }
+}
@@ -0,0 +1,64 @@
+/*!
+
+This is a package for [CERK](https://github.com/ce-rust/cerk).
+CERK is an open source [CloudEvents](https://github.com/cloudevents/spec) Router written in Rust with a MicroKernel architecture.
+
+# Introduction
+
+CERK lets you route your [CloudEvents](https://github.com/cloudevents/spec) between different different ports.
+Ports are transport layer bindings over which CloudEvents can be exchanged.
+It is built with modularity and portability in mind.
+
+# Components
+
+CERK comes with a couple of prefabricated components, but implementing custom components is easy.
+
+A good overview is provided on [GitHub](https://github.com/ce-rust/cerk/).
+
+# This Component: Composite Config Loader
+
+This config loader merges the configurations from several sources.
+The sources are listed in priority order, a later source overrides the values of the former ones:
+
+1. the base file from the env variable `CONFIG_PATH`, default is `./config.json`
+2. the override file from the env variable `CONFIG_OVERRIDE_PATH`, only if it is set
+3. the env variables with the prefix `CERK_CONFIG__`
+4. the changes applied by the admin API, only if `CONFIG_ADMIN_ADDRESS` is set
+
+The files have the same format as the files of the [file based config loader](https://github.com/ce-rust/cerk/tree/master/cerk_config_loader_file/).
+The admin API is the same as the API of the [HTTP admin config loader](https://github.com/ce-rust/cerk/tree/master/cerk_config_loader_http/).
+
+Maps are merged recursively, all other values (also arrays) are replaced.
+A `PUT` of the admin API replaces the whole config of the component, so it could also remove values set by the files or env variables.
+Routing rules of the admin API are validated against the ports of the merged config before they are applied.
+The source of every value is recorded, it is logged on debug level and returned by `GET /sources` of the admin API.
+
+## Environment Variables
+
+The name of the component and the keys are separated by `__`.
+They are matched with the names in the files, so `CERK_CONFIG__AMQP_INPUT__URI` sets the `uri` of the port `amqp-input`.
+The routing rules are set with `CERK_CONFIG__ROUTER`.
+The values are always strings, e.g., a password `1234` is not converted to a number.
+Other types (numbers, booleans, arrays, ...) are set by variables ending with `__JSON`, their values are parsed as JSON.
+
+```bash
+CERK_CONFIG__AMQP_INPUT__URI=amqp://rabbitmq:5672/%2f
+CERK_CONFIG__AMQP_INPUT__PREFETCH_COUNT__JSON=300
+CERK_CONFIG__ROUTER__JSON='["dummy-logger-output"]'
+```
+
+*/
+
+#![deny(missing_docs)]
+
+#[macro_use]
+extern crate log;
+
+#[macro_use]
+extern crate anyhow;
+
+mod config_layer;
+mod config_loader_composite;
+mod env_layer;
+
+pub use self::config_loader_composite::{config_loader_composite_start, CONFIG_LOADER_COMPOSITE};
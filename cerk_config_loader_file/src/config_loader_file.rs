@@ -1,14 +1,20 @@
 use super::file_reader::read_file;
-use crate::config_parser::parse_json_to_events;
+use crate::config_parser::parse_to_component_configs;
+use crate::config_watcher::{start_watching, AppliedConfigs};
 use anyhow::Result;
-use cerk::kernel::BrokerEvent;
+use cerk::kernel::{BrokerEvent, Config};
 use cerk::runtime::channel::{BoxedReceiver, BoxedSender};
 use cerk::runtime::{InternalServerFn, InternalServerFnRefStatic, InternalServerId};
+use cerk_file_format::FileFormat;
 use std::env;
 
-pub fn read_configs_from_file(config_path: &str) -> Result<Vec<BrokerEvent>> {
+/// Reads the config file and returns the config of every component, the routing rules have the id `router`.
+pub fn read_component_configs_from_file(
+    config_path: &str,
+) -> Result<Vec<(InternalServerId, Config)>> {
+    let format = FileFormat::from_path(config_path)?;
     let content = read_file(config_path)?;
-    parse_json_to_events(content)
+    parse_to_component_configs(content, format)
 }
 
 /// This is the main function to start the config loader.
@@ -18,22 +24,31 @@ pub fn config_loader_file_start(
     sender_to_kernel: BoxedSender,
 ) {
     let config_path = env::var("CONFIG_PATH").unwrap_or(String::from("./config.json"));
+    let watch = matches!(env::var("CONFIG_WATCH").as_deref(), Ok("true"));
     info!(
         "start file based config loader with id {}, will consume config from {}",
         id, config_path
     );
+    let mut initiated = false;
     loop {
         match inbox.receive() {
+            BrokerEvent::Init if initiated => warn!("{} was already initiated", id),
             BrokerEvent::Init => {
                 info!("{} initiated", id);
-                match read_configs_from_file(config_path.as_str()) {
-                    Ok(config_events) => {
-                        for events in config_events {
-                            sender_to_kernel.send(events);
+                initiated = true;
+                let mut applied = AppliedConfigs::new();
+                match read_component_configs_from_file(config_path.as_str()) {
+                    Ok(configs) => {
+                        for (component_id, config) in configs {
+                            applied.insert(component_id.clone(), config.clone());
+                            sender_to_kernel.send(BrokerEvent::ConfigUpdated(config, component_id));
                         }
                     }
                     Err(e) => error!("failed to read config {:?}", e),
                 }
+                if watch {
+                    start_watching(config_path.clone(), applied, sender_to_kernel.clone_boxed());
+                }
             }
             broker_event => warn!("event {} not implemented", broker_event),
         }
@@ -52,7 +67,7 @@ mod tests {
 
     #[test]
     fn read_configs_from_file_sample() -> Result<()> {
-        let config = read_configs_from_file("./src/test_data/amqp_to_printer.json")?;
+        let config = read_component_configs_from_file("./src/test_data/amqp_to_printer.json")?;
 
         let amqp_config: HashMap<String, Config> = [
             (
@@ -112,8 +127,7 @@ mod tests {
             }
         };
 
-        let config: HashMap<InternalServerId, Config> =
-            config.iter().map(map_to_inner_tuple).collect();
+        let config: HashMap<InternalServerId, Config> = config.into_iter().collect();
 
         let vec: HashMap<InternalServerId, Config> = vec.iter().map(map_to_inner_tuple).collect();
 
@@ -1,5 +1,8 @@
-use anyhow::Result;
-use cerk::kernel::{BrokerEvent, Config};
+use crate::interpolation::interpolate;
+use anyhow::{Context, Result};
+use cerk::kernel::Config;
+use cerk::runtime::InternalServerId;
+use cerk_file_format::FileFormat;
 use serde::Deserialize;
 use std::collections::HashMap;
 use std::vec::Vec;
@@ -10,23 +13,21 @@ struct Configuration {
     ports: HashMap<String, Config>,
 }
 
-fn parse_json_to_config(content: String) -> Result<Configuration> {
-    let config = serde_json::from_str(content.as_str())?;
-    Ok(config)
+fn parse_to_config(content: String, format: FileFormat) -> Result<Configuration> {
+    let value =
+        interpolate(format.parse(content.as_str())?).context("failed to interpolate the config")?;
+    Ok(serde_json::from_value(value)?)
 }
 
-pub fn parse_json_to_events(content: String) -> Result<Vec<BrokerEvent>> {
-    let config = parse_json_to_config(content)?;
-    let mut events: Vec<BrokerEvent> = config
-        .ports
-        .iter()
-        .map(|(port, config)| BrokerEvent::ConfigUpdated(config.clone(), port.to_string()))
-        .collect();
-    events.push(BrokerEvent::ConfigUpdated(
-        config.routing_rules,
-        String::from("router"),
-    ));
-    Ok(events)
+/// Parses the content to the configs of all components, the router config is always the last one.
+pub fn parse_to_component_configs(
+    content: String,
+    format: FileFormat,
+) -> Result<Vec<(InternalServerId, Config)>> {
+    let config = parse_to_config(content, format)?;
+    let mut configs: Vec<(InternalServerId, Config)> = config.ports.into_iter().collect();
+    configs.push((String::from("router"), config.routing_rules));
+    Ok(configs)
 }
 
 #[cfg(test)]
@@ -35,7 +36,7 @@ mod tests {
     use rstest::rstest;
 
     fn compare(parsed: Configuration, json: String) -> Result<()> {
-        let config = parse_json_to_config(json.clone())?;
+        let config = parse_to_config(json.clone(), FileFormat::Json)?;
 
         assert_eq!(
             config, parsed,
@@ -96,4 +97,62 @@ mod tests {
         };
         compare(config, json)
     }
+
+    #[rstest(content, parsed,
+        case("routing_rules: ~\nports: {}", Config::Null),
+        case("routing_rules: true\nports: {}", Config::Bool(true)),
+        case("routing_rules: 42\nports: {}", Config::U8(42)),
+        case("routing_rules: 3000\nports: {}", Config::U32(3000)),
+        case("routing_rules: [output]\nports: {}", Config::Vec(vec![Config::String("output".to_string())])),
+    )]
+    fn parse_minimal_yaml(content: &str, parsed: Config) -> Result<()> {
+        let config = parse_to_config(content.to_string(), FileFormat::Yaml)?;
+        assert_eq!(config.routing_rules, parsed);
+        Ok(())
+    }
+
+    #[rstest(content, parsed,
+        case("routing_rules = false\n[ports]", Config::Bool(false)),
+        case("routing_rules = 42\n[ports]", Config::U8(42)),
+        case("routing_rules = 3000\n[ports]", Config::U32(3000)),
+        case("routing_rules = [\"output\"]\n[ports]", Config::Vec(vec![Config::String("output".to_string())])),
+    )]
+    fn parse_minimal_toml(content: &str, parsed: Config) -> Result<()> {
+        let config = parse_to_config(content.to_string(), FileFormat::Toml)?;
+        assert_eq!(config.routing_rules, parsed);
+        Ok(())
+    }
+
+    #[test]
+    fn parse_ports_yaml() -> Result<()> {
+        let yaml = r#"
+routing_rules:
+  - dummy
+ports:
+  dummy: ~
+  amqp:
+    uri: amqp://127.0.0.1:5672/%2f
+    prefetch_count: 300
+"#
+        .to_string();
+
+        let config = parse_to_config(yaml, FileFormat::Yaml)?;
+        assert_eq!(config.ports.get("dummy"), Some(&Config::Null));
+        assert_eq!(
+            config.ports.get("amqp"),
+            Some(&Config::HashMap(
+                [
+                    (
+                        "uri".to_string(),
+                        Config::String("amqp://127.0.0.1:5672/%2f".to_string())
+                    ),
+                    ("prefetch_count".to_string(), Config::U32(300)),
+                ]
+                .iter()
+                .cloned()
+                .collect()
+            ))
+        );
+        Ok(())
+    }
 }
@@ -0,0 +1,208 @@
+use crate::config_loader_file::read_component_configs_from_file;
+use anyhow::{Context, Result};
+use cerk::kernel::{BrokerEvent, Config};
+use cerk::runtime::channel::BoxedSender;
+use cerk::runtime::InternalServerId;
+use notify::{watcher, DebouncedEvent, RecursiveMode, Watcher};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::mpsc::channel;
+use std::thread;
+use std::time::Duration;
+
+const DEBOUNCE_MS: u64 = 500;
+
+/// The configs which were last sent to the components.
+pub(crate) type AppliedConfigs = HashMap<InternalServerId, Config>;
+
+/// Returns all configs which differ from the applied ones.
+pub(crate) fn changed_configs(
+    applied: &AppliedConfigs,
+    configs: Vec<(InternalServerId, Config)>,
+) -> Vec<(InternalServerId, Config)> {
+    for id in applied.keys() {
+        if !configs.iter().any(|(new_id, _)| new_id == id) {
+            warn!(
+                "config for {} was removed from the file, the last applied config stays active",
+                id
+            );
+        }
+    }
+    configs
+        .into_iter()
+        .filter(|(id, config)| applied.get(id) != Some(config))
+        .collect()
+}
+
+fn reload(config_path: &str, applied: &mut AppliedConfigs, sender_to_kernel: &BoxedSender) {
+    let configs = match read_component_configs_from_file(config_path) {
+        Ok(configs) => configs,
+        Err(e) => {
+            error!(
+                "rejected changed config file {}, the running config stays unchanged: {:?}",
+                config_path, e
+            );
+            return;
+        }
+    };
+    let changed = changed_configs(applied, configs);
+    if changed.is_empty() {
+        debug!("config file {} changed, but no config differs", config_path);
+        return;
+    }
+    for (id, config) in changed {
+        info!("config for {} changed, send update", id);
+        applied.insert(id.clone(), config.clone());
+        sender_to_kernel.send(BrokerEvent::ConfigUpdated(config, id));
+    }
+}
+
+fn watch(
+    config_path: String,
+    mut applied: AppliedConfigs,
+    sender_to_kernel: BoxedSender,
+) -> Result<()> {
+    let path = PathBuf::from(&config_path);
+    // the directory is watched, because editors replace the file and a Kubernetes ConfigMap update
+    // only swaps the `..data` symlink, so no event names the config file itself
+    let directory = match path.parent() {
+        Some(p) if !p.as_os_str().is_empty() => p.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    // the first event reloads the file, it could have changed after it was read on `Init`
+    let mut content = None;
+
+    let (tx, rx) = channel();
+    let mut watcher = watcher(tx, Duration::from_millis(DEBOUNCE_MS))?;
+    watcher
+        .watch(&directory, RecursiveMode::NonRecursive)
+        .with_context(|| format!("failed to watch {}", directory.display()))?;
+    info!("watching {} for changes", config_path);
+
+    for event in rx.iter() {
+        match event {
+            DebouncedEvent::NoticeWrite(_) | DebouncedEvent::NoticeRemove(_) => continue,
+            DebouncedEvent::Error(e, _) => {
+                warn!("failed to watch {}: {:?}", directory.display(), e);
+                continue;
+            }
+            _ => {}
+        }
+        // the file is compared on every event in the directory, it follows the symlinks
+        let new_content = fs::read(&path).ok();
+        if new_content.is_some() && new_content != content {
+            content = new_content;
+            reload(config_path.as_str(), &mut applied, &sender_to_kernel);
+        }
+    }
+    Ok(())
+}
+
+/// Watches the config file in a new thread and sends a `ConfigUpdated` event for every changed component config.
+pub(crate) fn start_watching(
+    config_path: String,
+    applied: AppliedConfigs,
+    sender_to_kernel: BoxedSender,
+) {
+    thread::spawn(move || {
+        if let Err(e) = watch(config_path, applied, sender_to_kernel) {
+            error!(
+                "failed to watch config file, hot reloading is disabled: {:?}",
+                e
+            );
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cerk_runtime_threading::channel::new_channel_with_size;
+    use std::env;
+    use std::os::unix::fs::symlink;
+    use std::path::Path;
+
+    #[test]
+    fn changed_configs_only_returns_differences() {
+        let applied: AppliedConfigs = [
+            ("router".to_string(), Config::Vec(vec![])),
+            ("unchanged".to_string(), Config::Null),
+            ("changed".to_string(), Config::U8(1)),
+        ]
+        .iter()
+        .cloned()
+        .collect();
+
+        let changed = changed_configs(
+            &applied,
+            vec![
+                ("unchanged".to_string(), Config::Null),
+                ("changed".to_string(), Config::U8(2)),
+                ("new".to_string(), Config::Bool(true)),
+                ("router".to_string(), Config::Vec(vec![])),
+            ],
+        );
+
+        assert_eq!(
+            changed,
+            vec![
+                ("changed".to_string(), Config::U8(2)),
+                ("new".to_string(), Config::Bool(true)),
+            ]
+        );
+    }
+
+    const CONFIG: &str = r#"{"routing_rules": [], "ports": {"output": {"version": "#;
+
+    fn write_config(path: &Path, version: u8) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, format!("{}{}}}}}}}", CONFIG, version)).unwrap();
+    }
+
+    /// Swaps the `..data` symlink atomically like a Kubernetes ConfigMap update.
+    fn swap_data(directory: &Path, target: &str) {
+        let tmp = directory.join("..data_tmp");
+        symlink(target, &tmp).unwrap();
+        fs::rename(&tmp, directory.join("..data")).unwrap();
+    }
+
+    #[test]
+    fn reload_after_configmap_symlink_swap() {
+        let directory = env::temp_dir().join(format!("cerk-config-watcher-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        write_config(&directory.join("..v1").join("config.json"), 1);
+        write_config(&directory.join("..v2").join("config.json"), 2);
+        swap_data(&directory, "..v1");
+        symlink("..data/config.json", directory.join("config.json")).unwrap();
+
+        let config_path = directory.join("config.json");
+        let applied = read_component_configs_from_file(config_path.to_str().unwrap())
+            .unwrap()
+            .into_iter()
+            .collect();
+        let (sender, receiver) = new_channel_with_size(10);
+        start_watching(config_path.to_str().unwrap().to_string(), applied, sender);
+        // the watcher is registered asynchronously
+        thread::sleep(Duration::from_millis(DEBOUNCE_MS));
+
+        swap_data(&directory, "..v2");
+        match receiver.receive_timeout(Duration::from_secs(5)) {
+            Some(BrokerEvent::ConfigUpdated(config, id)) => {
+                assert_eq!(id, "output");
+                assert_eq!(
+                    config,
+                    Config::HashMap(
+                        [("version".to_string(), Config::U8(2))]
+                            .iter()
+                            .cloned()
+                            .collect()
+                    )
+                );
+            }
+            Some(event) => panic!("expected a ConfigUpdated event, got {}", event),
+            None => panic!("the config was not reloaded"),
+        }
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
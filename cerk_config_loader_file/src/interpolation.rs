@@ -0,0 +1,239 @@
+use anyhow::{bail, Context, Result};
+use serde_json::Value;
+use std::env;
+use std::fs;
+
+const FILE_PREFIX: &str = "file:";
+const DEFAULT_SEPARATOR: &str = ":-";
+
+/// Replaces all `${ENV_VAR}`, `${ENV_VAR:-default}` and `${file:/path}` placeholders
+/// in the string values of the parsed file with the value of the environment variable or file.
+///
+/// The values are substituted before they are converted into `Config`, comments and keys are not interpolated.
+/// A string which consists of a single placeholder becomes a number or a boolean, if the substituted value is one.
+pub(crate) fn interpolate(value: Value) -> Result<Value> {
+    interpolate_value(value, &|name| env::var(name).ok())
+}
+
+fn interpolate_value(value: Value, lookup: &dyn Fn(&str) -> Option<String>) -> Result<Value> {
+    Ok(match value {
+        Value::String(value) => {
+            let interpolated = interpolate_string(value.as_str(), lookup)?;
+            if is_single_placeholder(value.as_str()) {
+                typed_value(interpolated)
+            } else {
+                Value::String(interpolated)
+            }
+        }
+        Value::Array(values) => Value::Array(
+            values
+                .into_iter()
+                .map(|v| interpolate_value(v, lookup))
+                .collect::<Result<_>>()?,
+        ),
+        Value::Object(values) => Value::Object(
+            values
+                .into_iter()
+                .map(|(k, v)| Ok((k, interpolate_value(v, lookup)?)))
+                .collect::<Result<_>>()?,
+        ),
+        other => other,
+    })
+}
+
+fn is_single_placeholder(value: &str) -> bool {
+    value.starts_with("${") && placeholder_end(value) == Some(value.len() - 1)
+}
+
+/// Keeps the substituted value a string, unless it is an unsigned number or a boolean.
+fn typed_value(value: String) -> Value {
+    if let Ok(number) = value.parse::<u64>() {
+        return Value::from(number);
+    }
+    match value.as_str() {
+        "true" => Value::Bool(true),
+        "false" => Value::Bool(false),
+        _ => Value::String(value),
+    }
+}
+
+/// Returns the index of the `}` which closes the placeholder at the start of the value.
+///
+/// Nested braces are skipped, so a default value could contain `{` and `}`, e.g., `${FILTER:-{"type":"a"}}`.
+fn placeholder_end(value: &str) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in value.char_indices().skip(2) {
+        match c {
+            '{' => depth += 1,
+            '}' if depth == 0 => return Some(i),
+            '}' => depth -= 1,
+            _ => {}
+        }
+    }
+    None
+}
+
+fn interpolate_string(value: &str, lookup: &dyn Fn(&str) -> Option<String>) -> Result<String> {
+    let mut result = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(start) = rest.find('$') {
+        result.push_str(&rest[..start]);
+        rest = &rest[start..];
+        if rest.starts_with("$${") {
+            // `$${` escapes a placeholder
+            result.push_str("${");
+            rest = &rest[3..];
+        } else if rest.starts_with("${") {
+            let end = placeholder_end(rest)
+                .with_context(|| format!("placeholder in \"{}\" is not closed", value))?;
+            result.push_str(resolve_placeholder(&rest[2..end], lookup)?.as_str());
+            rest = &rest[end + 1..];
+        } else {
+            result.push('$');
+            rest = &rest[1..];
+        }
+    }
+    result.push_str(rest);
+    Ok(result)
+}
+
+fn resolve_placeholder(
+    expression: &str,
+    lookup: &dyn Fn(&str) -> Option<String>,
+) -> Result<String> {
+    if let Some(path) = expression.strip_prefix(FILE_PREFIX) {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("failed to read secret file {}", path))?;
+        return Ok(content.trim_end_matches(&['\n', '\r'][..]).to_string());
+    }
+
+    let (name, default) = match expression.find(DEFAULT_SEPARATOR) {
+        Some(i) => (
+            &expression[..i],
+            Some(&expression[i + DEFAULT_SEPARATOR.len()..]),
+        ),
+        None => (expression, None),
+    };
+    if name.is_empty() {
+        bail!("placeholder ${{{}}} has no variable name", expression);
+    }
+
+    match (lookup(name), default) {
+        // only the default replaces an empty value, a set but empty variable is valid otherwise
+        (Some(value), Some(default)) if value.is_empty() => Ok(default.to_string()),
+        (Some(value), _) => Ok(value),
+        (None, Some(default)) => Ok(default.to_string()),
+        (None, None) => bail!("environment variable {} is not set", name),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cerk::kernel::Config;
+    use cerk_file_format::FileFormat;
+    use rstest::rstest;
+
+    fn lookup(name: &str) -> Option<String> {
+        match name {
+            "USER" => Some("guest".to_string()),
+            "HOST" => Some("127.0.0.1".to_string()),
+            "EMPTY" => Some("".to_string()),
+            "PORT" => Some("5672".to_string()),
+            "SECRET" => Some("a\"b\\c\nd: e".to_string()),
+            _ => None,
+        }
+    }
+
+    fn parse(format: FileFormat, content: &str) -> Result<Config> {
+        let value = interpolate_value(format.parse(content)?, &lookup)?;
+        Ok(serde_json::from_value(value)?)
+    }
+
+    fn map(entries: Vec<(&str, Config)>) -> Config {
+        Config::HashMap(
+            entries
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), value))
+                .collect(),
+        )
+    }
+
+    #[rstest(
+        value,
+        expected,
+        case("plain", "plain"),
+        case("${USER}", "guest"),
+        case("amqp://${USER}@${HOST}:5672/%2f", "amqp://guest@127.0.0.1:5672/%2f"),
+        case("${MISSING:-5672}", "5672"),
+        case("${HOST:-localhost}", "127.0.0.1"),
+        case("${EMPTY:-fallback}", "fallback"),
+        case("${EMPTY}", ""),
+        case("${MISSING:-}", ""),
+        case("${MISSING:-{\"type\":\"a\"}}", "{\"type\":\"a\"}"),
+        case("$${USER}", "${USER}"),
+        case("100$", "100$"),
+        case("${file:./src/test_data/secret.txt}", "s3cr3t")
+    )]
+    fn interpolate(value: &str, expected: &str) -> Result<()> {
+        assert_eq!(interpolate_string(value, &lookup)?, expected);
+        Ok(())
+    }
+
+    #[rstest(
+        value,
+        case("${MISSING}"),
+        case("${USER"),
+        case("${MISSING:-{}"),
+        case("${}"),
+        case("${file:./src/test_data/nonexisting.txt}")
+    )]
+    fn interpolate_fail(value: &str) {
+        assert!(interpolate_string(value, &lookup).is_err());
+    }
+
+    #[rstest(
+        format,
+        content,
+        case(
+            FileFormat::Json,
+            r#"{"uri": "amqp://${HOST}", "port": "${PORT}", "durable": "${DURABLE:-true}", "secret": "${SECRET}"}"#
+        ),
+        case(
+            FileFormat::Yaml,
+            "# port: ${UNSET}\nuri: amqp://${HOST}\nport: ${PORT}\ndurable: ${DURABLE:-true}\nsecret: ${SECRET}\n"
+        ),
+        case(
+            FileFormat::Toml,
+            "# port = \"${UNSET}\"\nuri = \"amqp://${HOST}\"\nport = \"${PORT}\"\ndurable = \"${DURABLE:-true}\"\nsecret = \"${SECRET}\"\n"
+        )
+    )]
+    fn interpolate_values(format: FileFormat, content: &str) -> Result<()> {
+        let expected = map(vec![
+            ("uri", Config::String("amqp://127.0.0.1".to_string())),
+            ("port", Config::U32(5672)),
+            ("durable", Config::Bool(true)),
+            ("secret", Config::String("a\"b\\c\nd: e".to_string())),
+        ]);
+        assert_eq!(parse(format, content)?, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn interpolate_only_values() -> Result<()> {
+        let config = parse(
+            FileFormat::Json,
+            r#"{"${USER}": ["${USER}", "port ${PORT}", 42]}"#,
+        )?;
+        let expected = map(vec![(
+            "${USER}",
+            Config::Vec(vec![
+                Config::String("guest".to_string()),
+                Config::String("port 5672".to_string()),
+                Config::U8(42),
+            ]),
+        )]);
+        assert_eq!(config, expected);
+        Ok(())
+    }
+}
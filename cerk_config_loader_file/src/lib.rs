@@ -17,10 +17,43 @@ A good overview is provided on [GitHub](https://github.com/ce-rust/cerk/).
 
 # This Component: File Based Config Loader
 
-This port loads configurations from a json file.
+This port loads configurations from a JSON, YAML or TOML file.
+The format is selected by the file extension: `.json`, `.yaml`/`.yml` or `.toml`.
 
 The file path could be set with the env variable `CONFIG_PATH`, default is `./config.json`.
 
+Hot reloading is enabled by setting the env variable `CONFIG_WATCH` to `true`, the file is then watched for changes after the `Init` event.
+Every event in the directory of the file, e.g., the swap of the `..data` symlink of a Kubernetes ConfigMap, re-reads the file and compares it with the last read content.
+On every change, the new configuration is compared with the last applied one and a `ConfigUpdated` event is only sent to the components with a changed configuration.
+Invalid files are rejected and the running configuration stays unchanged.
+
+## Interpolation
+
+String values in the config file could reference environment variables and secret files, which are substituted after the file is parsed and before the values are converted into `Config`:
+
+* `${ENV_VAR}` is replaced by the value of the environment variable `ENV_VAR`, loading the config fails if it is not set
+* `${ENV_VAR:-default}` is replaced by the value of `ENV_VAR` or by `default` if it is not set or empty, the default could contain braces
+* `${ENV_VAR}` of a set but empty variable is replaced by an empty value
+* `${file:/run/secrets/x}` is replaced by the content of the file `/run/secrets/x`, without trailing newlines
+* `$${` is an escaped `${` and is not substituted
+
+The substituted values do not have to be escaped for the file format, they could contain any character, e.g., quotes or newlines.
+Keys and comments are not interpolated.
+A string value which consists of a single placeholder becomes a number or a boolean if the substituted value is an unsigned number, `true` or `false`,
+e.g., `"prefetch_count": "${AMQP_PREFETCH_COUNT:-100}"`.
+
+```json
+{
+  "routing_rules": [],
+  "ports": {
+    "ampq-input": {
+      "uri": "amqp://${AMQP_USER:-guest}:${file:/run/secrets/amqp_password}@${AMQP_HOST}:5672/%2f",
+      "prefetch_count": "${AMQP_PREFETCH_COUNT:-100}"
+    }
+  }
+}
+```
+
 ## Example Config
 
 ```json
@@ -50,6 +83,26 @@ The file path could be set with the env variable `CONFIG_PATH`, default is `./co
 }
 ```
 
+## Example Config in YAML
+
+```yaml
+routing_rules:
+  - dummy-logger-output
+ports:
+  ampq-input:
+    uri: amqp://127.0.0.1:5672/%2f
+    consume_channels:
+      - name: test
+        ensure_queue: true
+        bind_to_exchange: test
+    publish_channels:
+      - name: test
+        ensure_exchange: true
+  dummy-logger-output: ~
+```
+
+TOML has no null value, so ports without configuration can not be expressed in a `.toml` file.
+
 ## Examples
 
  * [AMQP to Printer](https://github.com/ce-rust/cerk/tree/master/examples/examples/src/amqp_to_printer/)
@@ -63,6 +116,10 @@ extern crate log;
 
 mod config_loader_file;
 mod config_parser;
+mod config_watcher;
 mod file_reader;
+mod interpolation;
 
-pub use self::config_loader_file::{config_loader_file_start, CONFIG_LOADER_FILE};
+pub use self::config_loader_file::{
+    config_loader_file_start, read_component_configs_from_file, CONFIG_LOADER_FILE,
+};
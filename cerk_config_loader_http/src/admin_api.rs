@@ -0,0 +1,475 @@
+use anyhow::{bail, Context, Result};
+use cerk::kernel::Config;
+use cerk::runtime::InternalServerId;
+use cerk_router_rule_based::RoutingTable;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Error, Method, Request, Response, Server, StatusCode};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::runtime::Handle;
+
+/// The id of the router, its config contains the routing rules.
+pub const ROUTER_ID: &str = "router";
+
+/// The store behind the admin API, it holds the current configs and applies the changes.
+pub trait ConfigStore: Send + Sync {
+    /// Returns the current configs of all components, the routing rules are stored with the id `router`.
+    fn configs(&self) -> HashMap<InternalServerId, Config>;
+
+    /// Returns the source of every config value, if the store is able to track it.
+    /// The keys are the paths of the values, e.g., `amqp-input.uri`.
+    fn sources(&self) -> HashMap<String, String> {
+        HashMap::new()
+    }
+
+    /// Checks if the component could be configured, the configs of unknown components are rejected.
+    fn is_known(&self, id: &InternalServerId) -> bool {
+        self.configs().contains_key(id)
+    }
+
+    /// Checks if the config could be applied to the component without applying it.
+    fn validate(&self, _id: &InternalServerId, _config: &Config) -> Result<()> {
+        Ok(())
+    }
+
+    /// Applies the config to the component, e.g., by sending a `ConfigUpdated` event to the kernel.
+    fn apply(&self, id: InternalServerId, config: Config) -> Result<()>;
+}
+
+/// Checks that the routing rules are either a list of known port ids (broadcast router)
+/// or a string with a JSON encoded `RoutingTable` of known port ids (rule based router).
+pub fn validate_routing_rules(config: &Config, is_known: &dyn Fn(&str) -> bool) -> Result<()> {
+    match config {
+        Config::Vec(port_ids) => {
+            for port_id in port_ids {
+                match port_id {
+                    Config::String(port_id) if is_known(port_id) => {}
+                    Config::String(port_id) => bail!("unknown port {}", port_id),
+                    _ => bail!("the port ids have to be strings"),
+                }
+            }
+            Ok(())
+        }
+        Config::String(rules) => {
+            let table: RoutingTable = serde_json::from_str(rules)
+                .context("the routing rules are not a valid JSON routing table")?;
+            match table.keys().find(|port_id| !is_known(port_id)) {
+                Some(port_id) => bail!("unknown port {}", port_id),
+                None => Ok(()),
+            }
+        }
+        _ => bail!("the routing rules have to be a list of port ids or a string with JSON rules"),
+    }
+}
+
+/// Shared reference to a `ConfigStore`.
+pub type ArcConfigStore = Arc<dyn ConfigStore>;
+
+#[derive(Debug, PartialEq)]
+enum Route {
+    GetRouting,
+    PutRouting,
+    ValidateRouting,
+    GetPorts,
+    GetPort(InternalServerId),
+    PutPort(InternalServerId),
+    ValidatePort(InternalServerId),
+    GetSources,
+    NotFound,
+}
+
+#[derive(Serialize)]
+struct ErrorResponse {
+    error: String,
+}
+
+#[derive(Serialize)]
+struct ValidationResponse {
+    valid: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+fn route(method: &Method, path: &str) -> Route {
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+    match (method, segments.as_slice()) {
+        (&Method::GET, ["routing"]) => Route::GetRouting,
+        (&Method::PUT, ["routing"]) => Route::PutRouting,
+        (&Method::POST, ["validate", "routing"]) => Route::ValidateRouting,
+        (&Method::GET, ["ports"]) => Route::GetPorts,
+        (&Method::GET, ["sources"]) => Route::GetSources,
+        (&Method::GET, ["ports", id]) if !id.is_empty() => Route::GetPort(id.to_string()),
+        (&Method::PUT, ["ports", id]) if !id.is_empty() => Route::PutPort(id.to_string()),
+        (&Method::POST, ["validate", "ports", id]) if !id.is_empty() => {
+            Route::ValidatePort(id.to_string())
+        }
+        _ => Route::NotFound,
+    }
+}
+
+fn json_response<T: Serialize>(status: StatusCode, body: &T) -> Response<Body> {
+    match serde_json::to_vec(body) {
+        Ok(body) => Response::builder()
+            .status(status)
+            .header("Content-Type", "application/json")
+            .body(Body::from(body))
+            .unwrap(),
+        Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, format!("{:?}", e)),
+    }
+}
+
+fn error_response(status: StatusCode, error: String) -> Response<Body> {
+    let body = serde_json::to_vec(&ErrorResponse { error }).unwrap_or_default();
+    Response::builder()
+        .status(status)
+        .header("Content-Type", "application/json")
+        .body(Body::from(body))
+        .unwrap()
+}
+
+async fn parse_body(request: Request<Body>) -> Result<Config> {
+    let body = hyper::body::to_bytes(request.into_body())
+        .await
+        .context("failed to read request body")?;
+    serde_json::from_slice(&body).context("body is not a valid config")
+}
+
+/// Parses and validates the config, the error response has the status to return.
+fn validate(
+    store: &ArcConfigStore,
+    id: &InternalServerId,
+    config: Result<Config>,
+) -> Result<Config, (StatusCode, String)> {
+    if !store.is_known(id) {
+        return Err((StatusCode::NOT_FOUND, format!("unknown component {}", id)));
+    }
+    let config = config.map_err(|e| (StatusCode::BAD_REQUEST, format!("{:#}", e)))?;
+    store
+        .validate(id, &config)
+        .map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, format!("{:#}", e)))?;
+    Ok(config)
+}
+
+fn get(store: &ArcConfigStore, id: &str) -> Response<Body> {
+    match store.configs().get(id) {
+        Some(config) => json_response(StatusCode::OK, config),
+        None => error_response(StatusCode::NOT_FOUND, format!("no config for {}", id)),
+    }
+}
+
+fn put(store: &ArcConfigStore, id: InternalServerId, config: Result<Config>) -> Response<Body> {
+    let config = match validate(store, &id, config) {
+        Ok(config) => config,
+        Err((status, error)) => return error_response(status, error),
+    };
+    info!("apply new config for {} received by the admin API", id);
+    match store.apply(id.clone(), config.clone()) {
+        Ok(()) => json_response(StatusCode::OK, &config),
+        Err(e) => error_response(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("failed to apply config for {}: {:#}", id, e),
+        ),
+    }
+}
+
+fn validate_only(
+    store: &ArcConfigStore,
+    id: InternalServerId,
+    config: Result<Config>,
+) -> Response<Body> {
+    let (status, body) = match validate(store, &id, config) {
+        Ok(_) => (
+            StatusCode::OK,
+            ValidationResponse {
+                valid: true,
+                error: None,
+            },
+        ),
+        Err((status, error)) => (
+            status,
+            ValidationResponse {
+                valid: false,
+                error: Some(error),
+            },
+        ),
+    };
+    json_response(status, &body)
+}
+
+fn reject_router_as_port(id: &str) -> Option<Response<Body>> {
+    if id == ROUTER_ID {
+        Some(error_response(
+            StatusCode::BAD_REQUEST,
+            format!("{} is not a port, use /routing", ROUTER_ID),
+        ))
+    } else {
+        None
+    }
+}
+
+async fn handle_request(
+    request: Request<Body>,
+    store: ArcConfigStore,
+) -> Result<Response<Body>, Error> {
+    debug!(
+        "admin API received {} {}",
+        request.method(),
+        request.uri().path()
+    );
+    let response = match route(request.method(), request.uri().path()) {
+        Route::GetRouting => get(&store, ROUTER_ID),
+        Route::PutRouting => put(&store, ROUTER_ID.to_string(), parse_body(request).await),
+        Route::ValidateRouting => {
+            validate_only(&store, ROUTER_ID.to_string(), parse_body(request).await)
+        }
+        Route::GetPorts => {
+            let mut ports = store.configs();
+            ports.remove(ROUTER_ID);
+            json_response(StatusCode::OK, &ports)
+        }
+        Route::GetPort(id) => reject_router_as_port(&id).unwrap_or_else(|| get(&store, &id)),
+        Route::PutPort(id) => match reject_router_as_port(&id) {
+            Some(response) => response,
+            None => put(&store, id, parse_body(request).await),
+        },
+        Route::ValidatePort(id) => match reject_router_as_port(&id) {
+            Some(response) => response,
+            None => validate_only(&store, id, parse_body(request).await),
+        },
+        Route::GetSources => json_response(StatusCode::OK, &store.sources()),
+        Route::NotFound => error_response(StatusCode::NOT_FOUND, "unknown endpoint".to_string()),
+    };
+    Ok(response)
+}
+
+/// Starts the admin API on the given address in the tokio runtime of the handle.
+pub fn start_admin_server(
+    address: SocketAddr,
+    store: ArcConfigStore,
+    tokio: &Handle,
+) -> Result<()> {
+    let builder = tokio
+        .enter(|| Server::try_bind(&address))
+        .with_context(|| format!("failed to bind admin API to {}", address))?;
+
+    let make_svc = make_service_fn(move |_| {
+        let store = store.clone();
+        async move {
+            Ok::<_, Error>(service_fn(move |request| {
+                handle_request(request, store.clone())
+            }))
+        }
+    });
+
+    tokio.spawn(async move {
+        if let Err(e) = builder.serve(make_svc).await {
+            error!("admin API server error: {}", e);
+        }
+    });
+    info!("admin API is listening on {}", address);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::bail;
+    use std::sync::Mutex;
+
+    struct MemoryStore {
+        configs: Mutex<HashMap<InternalServerId, Config>>,
+    }
+
+    impl ConfigStore for MemoryStore {
+        fn configs(&self) -> HashMap<InternalServerId, Config> {
+            self.configs.lock().unwrap().clone()
+        }
+
+        fn validate(&self, id: &InternalServerId, config: &Config) -> Result<()> {
+            if let Config::U8(_) = config {
+                bail!("numbers are not allowed")
+            }
+            if id == ROUTER_ID {
+                validate_routing_rules(config, &|id| self.is_known(&id.to_string()))?;
+            }
+            Ok(())
+        }
+
+        fn apply(&self, id: InternalServerId, config: Config) -> Result<()> {
+            self.configs.lock().unwrap().insert(id, config);
+            Ok(())
+        }
+    }
+
+    fn store() -> ArcConfigStore {
+        Arc::new(MemoryStore {
+            configs: Mutex::new(
+                [
+                    (ROUTER_ID.to_string(), Config::Vec(vec![])),
+                    ("printer".to_string(), Config::Null),
+                ]
+                .iter()
+                .cloned()
+                .collect(),
+            ),
+        })
+    }
+
+    fn request(
+        store: &ArcConfigStore,
+        method: Method,
+        path: &str,
+        body: &str,
+    ) -> (StatusCode, String) {
+        let request = Request::builder()
+            .method(method)
+            .uri(path)
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        let mut runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async {
+            let response = handle_request(request, store.clone()).await.unwrap();
+            let status = response.status();
+            let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+            (status, String::from_utf8(body.to_vec()).unwrap())
+        })
+    }
+
+    #[test]
+    fn route_paths() {
+        assert_eq!(route(&Method::GET, "/routing"), Route::GetRouting);
+        assert_eq!(route(&Method::PUT, "/routing/"), Route::PutRouting);
+        assert_eq!(route(&Method::GET, "/ports"), Route::GetPorts);
+        assert_eq!(
+            route(&Method::PUT, "/ports/printer"),
+            Route::PutPort("printer".to_string())
+        );
+        assert_eq!(
+            route(&Method::POST, "/validate/ports/printer"),
+            Route::ValidatePort("printer".to_string())
+        );
+        assert_eq!(route(&Method::GET, "/sources"), Route::GetSources);
+        assert_eq!(route(&Method::DELETE, "/routing"), Route::NotFound);
+        assert_eq!(route(&Method::GET, "/ports/a/b"), Route::NotFound);
+    }
+
+    #[test]
+    fn get_configs() {
+        let store = store();
+        assert_eq!(
+            request(&store, Method::GET, "/routing", ""),
+            (StatusCode::OK, "[]".to_string())
+        );
+        assert_eq!(
+            request(&store, Method::GET, "/ports", ""),
+            (StatusCode::OK, r#"{"printer":null}"#.to_string())
+        );
+        assert_eq!(
+            request(&store, Method::GET, "/ports/printer", ""),
+            (StatusCode::OK, "null".to_string())
+        );
+        assert_eq!(
+            request(&store, Method::GET, "/ports/unknown", "").0,
+            StatusCode::NOT_FOUND
+        );
+    }
+
+    #[test]
+    fn put_configs() {
+        let store = store();
+        assert_eq!(
+            request(&store, Method::PUT, "/routing", r#"["printer"]"#).0,
+            StatusCode::OK
+        );
+        assert_eq!(
+            request(&store, Method::PUT, "/ports/printer", r#"{"prefix":"a"}"#).0,
+            StatusCode::OK
+        );
+        let configs = store.configs();
+        assert_eq!(
+            configs.get(ROUTER_ID),
+            Some(&Config::Vec(vec![Config::String("printer".to_string())]))
+        );
+        assert_eq!(
+            configs.get("printer"),
+            Some(&Config::HashMap(
+                [("prefix".to_string(), Config::String("a".to_string()))]
+                    .iter()
+                    .cloned()
+                    .collect()
+            ))
+        );
+    }
+
+    #[test]
+    fn put_invalid_configs() {
+        let store = store();
+        assert_eq!(
+            request(&store, Method::PUT, "/routing", "not json").0,
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            request(&store, Method::PUT, "/ports/printer", "42").0,
+            StatusCode::UNPROCESSABLE_ENTITY
+        );
+        assert_eq!(
+            request(&store, Method::PUT, "/ports/unknown", "null").0,
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            request(&store, Method::PUT, "/routing", r#"["unknown"]"#).0,
+            StatusCode::UNPROCESSABLE_ENTITY
+        );
+        assert_eq!(
+            request(&store, Method::PUT, "/ports/router", "[]").0,
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(store.configs().get("printer"), Some(&Config::Null));
+    }
+
+    #[test]
+    fn validate_routing() {
+        let is_known = |id: &str| id == "printer";
+        let port_ids = |ids: &[&str]| {
+            Config::Vec(
+                ids.iter()
+                    .map(|id| Config::String(id.to_string()))
+                    .collect(),
+            )
+        };
+        assert!(validate_routing_rules(&port_ids(&["printer"]), &is_known).is_ok());
+        let rules =
+            |rules: &str| validate_routing_rules(&Config::String(rules.to_string()), &is_known);
+        assert!(rules("{}").is_ok());
+        assert!(rules(r#"{"printer":{"Contains":["Type","order"]}}"#).is_ok());
+        assert!(rules(r#"{"rules":[]}"#).is_err());
+        assert!(rules(r#"{"unknown":{"Contains":["Type","order"]}}"#).is_err());
+        assert!(validate_routing_rules(&port_ids(&["printer", "unknown"]), &is_known).is_err());
+        assert!(validate_routing_rules(&Config::String("rules".to_string()), &is_known).is_err());
+        assert!(validate_routing_rules(&Config::Vec(vec![Config::U8(1)]), &is_known).is_err());
+        assert!(validate_routing_rules(&Config::Bool(true), &is_known).is_err());
+    }
+
+    #[test]
+    fn validate_configs() {
+        let store = store();
+        assert_eq!(
+            request(&store, Method::POST, "/validate/ports/printer", "true"),
+            (StatusCode::OK, r#"{"valid":true}"#.to_string())
+        );
+        assert_eq!(
+            request(&store, Method::POST, "/validate/routing", "42"),
+            (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                r#"{"valid":false,"error":"numbers are not allowed"}"#.to_string()
+            )
+        );
+        assert_eq!(
+            request(&store, Method::POST, "/validate/ports/unknown", "true").0,
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(store.configs().get("printer"), Some(&Config::Null));
+    }
+}
@@ -0,0 +1,210 @@
+use crate::admin_api::{start_admin_server, validate_routing_rules, ConfigStore, ROUTER_ID};
+use anyhow::Result;
+use cerk::kernel::{BrokerEvent, Config};
+use cerk::runtime::channel::{BoxedReceiver, BoxedSender};
+use cerk::runtime::{InternalServerFn, InternalServerFnRefStatic, InternalServerId};
+use cerk_config_loader_file::read_component_configs_from_file;
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+const DEFAULT_ADDRESS: &str = "127.0.0.1:3100";
+
+/// Holds the configs which were applied by the admin API and forwards them to the kernel.
+struct KernelConfigStore {
+    /// the ids of the ports which could be configured
+    port_ids: HashSet<InternalServerId>,
+    configs: Mutex<HashMap<InternalServerId, Config>>,
+    sender_to_kernel: Mutex<BoxedSender>,
+}
+
+impl ConfigStore for KernelConfigStore {
+    fn configs(&self) -> HashMap<InternalServerId, Config> {
+        self.configs.lock().unwrap().clone()
+    }
+
+    fn is_known(&self, id: &InternalServerId) -> bool {
+        id == ROUTER_ID || self.port_ids.contains(id)
+    }
+
+    fn validate(&self, id: &InternalServerId, config: &Config) -> Result<()> {
+        if id == ROUTER_ID {
+            validate_routing_rules(config, &|port_id| self.port_ids.contains(port_id))?;
+        }
+        Ok(())
+    }
+
+    fn apply(&self, id: InternalServerId, config: Config) -> Result<()> {
+        self.configs
+            .lock()
+            .unwrap()
+            .insert(id.clone(), config.clone());
+        self.sender_to_kernel
+            .lock()
+            .unwrap()
+            .send(BrokerEvent::ConfigUpdated(config, id));
+        Ok(())
+    }
+}
+
+fn parse_port_ids(port_ids: &str) -> HashSet<InternalServerId> {
+    port_ids
+        .split(',')
+        .map(|id| id.trim())
+        .filter(|id| !id.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Creates the store and applies the initial configs, so the API returns what is loaded.
+fn new_store(
+    mut port_ids: HashSet<InternalServerId>,
+    initial_configs: Vec<(InternalServerId, Config)>,
+    sender_to_kernel: BoxedSender,
+) -> Result<KernelConfigStore> {
+    port_ids.extend(
+        initial_configs
+            .iter()
+            .map(|(id, _)| id.clone())
+            .filter(|id| id != ROUTER_ID),
+    );
+    if port_ids.is_empty() {
+        warn!("CONFIG_ADMIN_PORTS is not set, only the routing rules could be configured");
+    }
+    let store = KernelConfigStore {
+        port_ids,
+        configs: Mutex::new(HashMap::new()),
+        sender_to_kernel: Mutex::new(sender_to_kernel),
+    };
+    for (id, config) in initial_configs {
+        store.apply(id, config)?;
+    }
+    Ok(store)
+}
+
+fn start(sender_to_kernel: BoxedSender, tokio: &tokio::runtime::Handle) -> Result<()> {
+    let address: SocketAddr = env::var("CONFIG_ADMIN_ADDRESS")
+        .unwrap_or(String::from(DEFAULT_ADDRESS))
+        .parse()?;
+    let port_ids = parse_port_ids(env::var("CONFIG_ADMIN_PORTS").unwrap_or_default().as_str());
+    let initial_configs = match env::var("CONFIG_PATH") {
+        Ok(path) => read_component_configs_from_file(path.as_str())?,
+        Err(_) => vec![],
+    };
+    let store = new_store(port_ids, initial_configs, sender_to_kernel)?;
+    start_admin_server(address, Arc::new(store), tokio)
+}
+
+/// This is the main function to start the config loader.
+pub fn config_loader_http_start(
+    id: InternalServerId,
+    inbox: BoxedReceiver,
+    sender_to_kernel: BoxedSender,
+) {
+    info!("start http config loader with id {}", id);
+    let tokio = tokio::runtime::Runtime::new().unwrap();
+    let mut sender_to_kernel = Some(sender_to_kernel);
+    loop {
+        match inbox.receive() {
+            BrokerEvent::Init => {
+                info!("{} initiated", id);
+                if let Some(sender_to_kernel) = sender_to_kernel.take() {
+                    if let Err(e) = start(sender_to_kernel, tokio.handle()) {
+                        error!("failed to start admin API {:?}", e);
+                    }
+                }
+            }
+            broker_event => warn!("event {} not implemented", broker_event),
+        }
+    }
+}
+
+/// This is the pointer for the main function to start the config loader.
+pub static CONFIG_LOADER_HTTP: InternalServerFnRefStatic =
+    &(config_loader_http_start as InternalServerFn);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cerk_runtime_threading::channel::new_channel_with_size;
+    use std::time::Duration;
+
+    #[test]
+    fn apply_sends_config_updated() {
+        let (send, receive) = new_channel_with_size(1);
+        let store = KernelConfigStore {
+            port_ids: parse_port_ids("printer"),
+            configs: Mutex::new(HashMap::new()),
+            sender_to_kernel: Mutex::new(send),
+        };
+
+        assert!(store
+            .apply("printer".to_string(), Config::Bool(true))
+            .is_ok());
+
+        assert_eq!(store.configs().get("printer"), Some(&Config::Bool(true)));
+        match receive.receive_timeout(Duration::from_millis(100)) {
+            Some(BrokerEvent::ConfigUpdated(config, id)) => {
+                assert_eq!(id, "printer");
+                assert_eq!(config, Config::Bool(true));
+            }
+            _ => panic!("expected ConfigUpdated"),
+        }
+    }
+
+    #[test]
+    fn seed_initial_configs() -> Result<()> {
+        let (send, receive) = new_channel_with_size(2);
+        let store = new_store(
+            parse_port_ids("printer"),
+            vec![
+                ("amqp-input".to_string(), Config::Null),
+                (
+                    ROUTER_ID.to_string(),
+                    Config::Vec(vec![Config::String("printer".to_string())]),
+                ),
+            ],
+            send,
+        )?;
+
+        assert!(store.is_known(&"printer".to_string()));
+        assert!(store.is_known(&"amqp-input".to_string()));
+        assert_eq!(store.configs().get("amqp-input"), Some(&Config::Null));
+        assert!(store.configs().contains_key(ROUTER_ID));
+        for _ in 0..2 {
+            assert!(matches!(
+                receive.receive_timeout(Duration::from_millis(100)),
+                Some(BrokerEvent::ConfigUpdated(..))
+            ));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn validate_known_ids() {
+        let (send, _receive) = new_channel_with_size(1);
+        let store = KernelConfigStore {
+            port_ids: parse_port_ids(" printer, amqp-output ,"),
+            configs: Mutex::new(HashMap::new()),
+            sender_to_kernel: Mutex::new(send),
+        };
+        assert_eq!(store.port_ids.len(), 2);
+        assert!(store.is_known(&"amqp-output".to_string()));
+        assert!(store.is_known(&ROUTER_ID.to_string()));
+        assert!(!store.is_known(&"unknown".to_string()));
+        let routing = |ids: &[&str]| {
+            Config::Vec(
+                ids.iter()
+                    .map(|id| Config::String(id.to_string()))
+                    .collect(),
+            )
+        };
+        assert!(store
+            .validate(&ROUTER_ID.to_string(), &routing(&["printer"]))
+            .is_ok());
+        assert!(store
+            .validate(&ROUTER_ID.to_string(), &routing(&["unknown"]))
+            .is_err());
+    }
+}
@@ -0,0 +1,73 @@
+/*!
+
+This is a package for [CERK](https://github.com/ce-rust/cerk).
+CERK is an open source [CloudEvents](https://github.com/cloudevents/spec) Router written in Rust with a MicroKernel architecture.
+
+# Introduction
+
+CERK lets you route your [CloudEvents](https://github.com/cloudevents/spec) between different different ports.
+Ports are transport layer bindings over which CloudEvents can be exchanged.
+It is built with modularity and portability in mind.
+
+# Components
+
+CERK comes with a couple of prefabricated components, but implementing custom components is easy.
+
+A good overview is provided on [GitHub](https://github.com/ce-rust/cerk/).
+
+# This Component: HTTP Admin Config Loader
+
+This config loader serves a local HTTP API to read and change the port configurations and routing rules at runtime.
+Every applied change is sent as `ConfigUpdated` event through the kernel to the component.
+
+The API is started after the `Init` event on the address set by the env variable `CONFIG_ADMIN_ADDRESS`, default is `127.0.0.1:3100`.
+The API has no authentication, so it should only be reachable from trusted hosts.
+
+Only the ports listed in the env variable `CONFIG_ADMIN_PORTS` could be configured, e.g., `CONFIG_ADMIN_PORTS=amqp-input,dummy-logger-output`.
+
+## Endpoints
+
+All bodies are JSON encoded configs, the same as the values in the config file of the [file based config loader](https://github.com/ce-rust/cerk/tree/master/cerk_config_loader_file/).
+
+| Method | Path                    | Description                                         |
+|--------|-------------------------|-----------------------------------------------------|
+| GET    | `/routing`              | returns the current routing rules                   |
+| PUT    | `/routing`              | applies new routing rules                           |
+| POST   | `/validate/routing`     | validates routing rules without applying them       |
+| GET    | `/ports`                | returns the current configs of all ports            |
+| GET    | `/ports/{id}`           | returns the current config of a port                |
+| PUT    | `/ports/{id}`           | applies a new config to a port                      |
+| POST   | `/validate/ports/{id}`  | validates a port config without applying it         |
+| GET    | `/sources`              | returns the source of every config value, if tracked |
+
+The initial configs are read at `Init` from the optional file set by the env variable `CONFIG_PATH`,
+in the format of the [file based config loader](https://github.com/ce-rust/cerk/tree/master/cerk_config_loader_file/).
+They are sent to the components and returned by the API, the ports of the file could be configured as well.
+Without `CONFIG_PATH`, the API starts without any config and only knows the configs which it applied.
+
+Requests for unknown components are rejected with `404 Not Found`, bodies which are no valid JSON with `400 Bad Request`
+and configs which could not be applied with `422 Unprocessable Entity`.
+The routing rules have to be either a list of the configured port ids (broadcast router)
+or a string with a JSON encoded `RoutingTable` of the configured port ids (rule based router).
+Port configs are not validated, because only the port itself knows its options.
+
+## Example
+
+```bash
+curl -X PUT http://127.0.0.1:3100/routing -d '["dummy-logger-output"]'
+```
+
+*/
+
+#![deny(missing_docs)]
+
+#[macro_use]
+extern crate log;
+
+mod admin_api;
+mod config_loader_http;
+
+pub use self::admin_api::{
+    start_admin_server, validate_routing_rules, ArcConfigStore, ConfigStore, ROUTER_ID,
+};
+pub use self::config_loader_http::{config_loader_http_start, CONFIG_LOADER_HTTP};
@@ -0,0 +1,18 @@
+# {{crate}}
+
+[![Build status](https://badge.buildkite.com/4494e29d5f2c47e3fe998af46dff78a447800a76a68024e392.svg?branch=master)](https://buildkite.com/ce-rust/cerk)
+[![Crates.io](https://img.shields.io/crates/v/cerk)](https://docs.rs/cerk_file_format/*/cerk_file_format/)
+[![Docs status](https://docs.rs/cerk/badge.svg)](https://docs.rs/cerk_file_format/)
+
+{{readme}}
+
+## Update Readme
+
+The original readme text is a Rust doc comment in the [lib.rs](./src/lib.rs) file
+
+1. `cargo install cargo-readme`
+2. `cargo readme  > README.md`
+
+## License
+
+{{license}}
@@ -0,0 +1,67 @@
+use anyhow::{bail, Result};
+use serde::de::DeserializeOwned;
+use std::path::Path;
+
+/// The format of a config file, selected by its file extension.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FileFormat {
+    /// `.json`
+    Json,
+    /// `.yaml` or `.yml`
+    Yaml,
+    /// `.toml`
+    Toml,
+}
+
+impl FileFormat {
+    /// Selects the format by the file extension of the path.
+    pub fn from_path(path: &str) -> Result<FileFormat> {
+        let extension = Path::new(path)
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_ascii_lowercase());
+        Ok(match extension.as_deref() {
+            Some("json") => FileFormat::Json,
+            Some("yaml") | Some("yml") => FileFormat::Yaml,
+            Some("toml") => FileFormat::Toml,
+            _ => bail!(
+                "unsupported file extension of {}, expected .json, .yaml, .yml or .toml",
+                path
+            ),
+        })
+    }
+
+    /// Deserializes the content of a file in this format.
+    pub fn parse<T: DeserializeOwned>(&self, content: &str) -> Result<T> {
+        Ok(match self {
+            FileFormat::Json => serde_json::from_str(content)?,
+            FileFormat::Yaml => serde_yaml::from_str(content)?,
+            FileFormat::Toml => toml::from_str(content)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest(
+        path,
+        format,
+        case("./config.json", FileFormat::Json),
+        case("./config.yaml", FileFormat::Yaml),
+        case("/etc/cerk/config.yml", FileFormat::Yaml),
+        case("config.YAML", FileFormat::Yaml),
+        case("config.toml", FileFormat::Toml)
+    )]
+    fn from_path(path: &str, format: FileFormat) -> Result<()> {
+        assert_eq!(FileFormat::from_path(path)?, format);
+        Ok(())
+    }
+
+    #[rstest(path, case("./config"), case("./config.ini"), case("./json"))]
+    fn from_path_unsupported(path: &str) {
+        assert!(FileFormat::from_path(path).is_err());
+    }
+}
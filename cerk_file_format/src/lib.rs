@@ -0,0 +1,36 @@
+/*!
+
+This is a package for [CERK](https://github.com/ce-rust/cerk).
+CERK is an open source [CloudEvents](https://github.com/cloudevents/spec) Router written in Rust with a MicroKernel architecture.
+
+# Introduction
+
+CERK lets you route your [CloudEvents](https://github.com/cloudevents/spec) between different different ports.
+Ports are transport layer bindings over which CloudEvents can be exchanged.
+It is built with modularity and portability in mind.
+
+# Components
+
+CERK comes with a couple of prefabricated components, but implementing custom components is easy.
+
+A good overview is provided on [GitHub](https://github.com/ce-rust/cerk/).
+
+# This Crate: File Format
+
+This crate contains the file formats which are shared by the file loaders, `cerk_loader_file` and `cerk_config_loader_file`.
+
+The format is selected by the file extension:
+
+| Extension         | Format |
+|-------------------|--------|
+| `.json`           | JSON   |
+| `.yaml` or `.yml` | YAML   |
+| `.toml`           | TOML   |
+
+*/
+
+#![deny(missing_docs)]
+
+mod file_format;
+
+pub use self::file_format::FileFormat;
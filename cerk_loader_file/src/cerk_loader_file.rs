@@ -1,15 +1,18 @@
-use crate::config_parser::parse_json_to_start_options;
+use crate::config_parser::parse_to_start_options;
 use crate::file_reader::read_file;
 use crate::start_links::ComponentStartLinks;
 use anyhow::{Context, Result};
 use cerk::kernel::{bootstrap, StartOptions};
+use cerk_file_format::FileFormat;
 use std::env;
 
 /// Starts cerk with a ComponentStartLinks set and a init config provided in the given path.
+/// The file format (JSON, YAML or TOML) is selected by the file extension.
 pub fn load_by_path<'a>(path: String, links: ComponentStartLinks<'static>) -> Result<StartOptions> {
     info!("loading loader config from {}", path);
+    let format = FileFormat::from_path(path.as_str())?;
     let content = read_file(path.as_str())?;
-    parse_json_to_start_options(content, links)
+    parse_to_start_options(content, format, links)
         .with_context(|| format!("failed to parse file {}", path))
 }
 
@@ -1,6 +1,7 @@
 use crate::start_links::ComponentStartLinks;
 use anyhow::{Context, Result};
 use cerk::kernel::{ScheduleInternalServer, ScheduleInternalServerStatic, StartOptions};
+use cerk_file_format::FileFormat;
 use serde::Deserialize;
 use std::collections::HashMap;
 
@@ -13,9 +14,8 @@ struct Configuration {
     ports: HashMap<String, String>,
 }
 
-fn parse_json_to_config(content: String) -> Result<Configuration> {
-    let config = serde_json::from_str(content.as_str())?;
-    Ok(config)
+fn parse_to_config(content: String, format: FileFormat) -> Result<Configuration> {
+    format.parse(content.as_str())
 }
 
 fn get_link<'a, T>(name: &String, links: &'a HashMap<String, T>) -> Result<&'a T> {
@@ -60,11 +60,12 @@ fn parse_config_to_start_options(
     Ok(config)
 }
 
-pub fn parse_json_to_start_options<'a>(
+pub fn parse_to_start_options<'a>(
     config_content: String,
+    format: FileFormat,
     links: ComponentStartLinks<'static>,
 ) -> Result<StartOptions> {
-    let config = parse_json_to_config(config_content)?;
+    let config = parse_to_config(config_content, format)?;
     parse_config_to_start_options(&links, &config)
 }
 
@@ -87,7 +88,7 @@ mod tests {
             }
         }
         "#;
-        let config = parse_json_to_config(json.to_string())?;
+        let config = parse_to_config(json.to_string(), FileFormat::Json)?;
         assert_eq!(config.scheduler, "myschedulertype");
         assert_eq!(config.router, "myroutertype");
         assert_eq!(config.config_loader, "myconfig_loadertype");
@@ -97,6 +98,39 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn parse_yaml_to_config_test() -> Result<()> {
+        let yaml = r#"
+scheduler: myschedulertype
+router: myroutertype
+config_loader: myconfig_loadertype
+ports:
+  myport: myporttype
+"#;
+        let config = parse_to_config(yaml.to_string(), FileFormat::Yaml)?;
+        assert_eq!(config.scheduler, "myschedulertype");
+        assert_eq!(config.ports.get("myport"), Some(&"myporttype".to_string()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_toml_to_config_test() -> Result<()> {
+        let toml = r#"
+scheduler = "myschedulertype"
+router = "myroutertype"
+config_loader = "myconfig_loadertype"
+
+[ports]
+myport = "myporttype"
+"#;
+        let config = parse_to_config(toml.to_string(), FileFormat::Toml)?;
+        assert_eq!(config.scheduler, "myschedulertype");
+        assert_eq!(config.ports.get("myport"), Some(&"myporttype".to_string()));
+
+        Ok(())
+    }
+
     fn dummy_scheduler(_: StartOptions, _: KernelFn) {}
 
     fn dummy_router(_: InternalServerId, _: BoxedReceiver, _: BoxedSender) {}
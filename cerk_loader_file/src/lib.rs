@@ -21,6 +21,7 @@ The cerk_loader_file link the different modules together and pass it to the `boo
 
 It uses a `ComponentStartLinks` file with all links to the start functions and a configuration file.
 The configuration file could be passed by the env variable `$INIT_PATH` or just use the path `./init.json`.
+The file could be written in JSON, YAML or TOML, the format is selected by the file extension (`.json`, `.yaml`/`.yml` or `.toml`).
 
 
 ### Example Config
@@ -36,6 +37,16 @@ The configuration file could be passed by the env variable `$INIT_PATH` or just
 }
 ```
 
+### Example Config in YAML
+
+```yaml
+scheduler: SCHEDULER
+router: ROUTER
+config_loader: CONFIG_LOADER
+ports:
+  myport: PORT
+```
+
 #### Example ComponentStartLinks
 
 ```no_run
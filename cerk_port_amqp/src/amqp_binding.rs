@@ -0,0 +1,210 @@
+use amq_protocol_types::{AMQPValue, FieldTable, LongString, ShortString};
+use anyhow::{Context, Result};
+pub use cerk_port_common::content_mode::ContentMode;
+use chrono::{TimeZone, Utc};
+use cloudevents::binding::http::to_event;
+use cloudevents::Event;
+use http::header::{HeaderName, HeaderValue, CONTENT_TYPE};
+use http::{HeaderMap, Request};
+use lapin::BasicProperties;
+use std::convert::TryFrom;
+
+const AMQP_HEADER_PREFIX: &str = "cloudEvents_";
+/// The prefix of the first binding version, which is still accepted for incoming messages.
+const AMQP_HEADER_PREFIX_COLON: &str = "cloudEvents:";
+const HTTP_HEADER_PREFIX: &str = "ce-";
+const STRUCTURED_CONTENT_TYPE: &str = "application/cloudevents+json; charset=UTF-8";
+const PERSISTENT: u8 = 2;
+
+fn attribute_name(header: &str) -> Option<&str> {
+    header
+        .strip_prefix(AMQP_HEADER_PREFIX)
+        .or_else(|| header.strip_prefix(AMQP_HEADER_PREFIX_COLON))
+}
+
+/// Converts a header to the string representation of the attribute.
+fn attribute_value(value: &AMQPValue) -> Result<String> {
+    Ok(match value {
+        AMQPValue::LongString(s) => s.as_str().to_string(),
+        AMQPValue::ShortString(s) => s.as_str().to_string(),
+        AMQPValue::Boolean(v) => v.to_string(),
+        AMQPValue::ShortShortInt(v) => v.to_string(),
+        AMQPValue::ShortShortUInt(v) => v.to_string(),
+        AMQPValue::ShortInt(v) => v.to_string(),
+        AMQPValue::ShortUInt(v) => v.to_string(),
+        AMQPValue::LongInt(v) => v.to_string(),
+        AMQPValue::LongUInt(v) => v.to_string(),
+        AMQPValue::LongLongInt(v) => v.to_string(),
+        AMQPValue::Timestamp(seconds) => Utc
+            .timestamp_opt(*seconds as i64, 0)
+            .single()
+            .context("invalid timestamp")?
+            .to_rfc3339(),
+        value => bail!("unsupported attribute value {:?}", value),
+    })
+}
+
+/// Converts the event to the body and properties of an AMQP message according to the [AMQP protocol binding](https://github.com/cloudevents/spec/blob/master/amqp-protocol-binding.md).
+///
+/// The binary content mode reuses the HTTP binding, the `ce-` headers become `cloudEvents_` headers.
+pub fn to_message(event: &Event, content_mode: ContentMode) -> Result<(Vec<u8>, BasicProperties)> {
+    let properties = BasicProperties::default().with_delivery_mode(PERSISTENT);
+    match content_mode {
+        ContentMode::Binary => {
+            let request = Request::<Option<Vec<u8>>>::try_from(event.clone())
+                .map_err(|e| anyhow!("failed to serialize CloudEvent: {}", e))?;
+            let (parts, body) = request.into_parts();
+            let mut headers = FieldTable::default();
+            let mut properties = properties;
+            for (name, value) in parts.headers.iter() {
+                let value = value.to_str()?.to_string();
+                match name.as_str().strip_prefix(HTTP_HEADER_PREFIX) {
+                    Some(attribute) => headers.insert(
+                        ShortString::from(format!("{}{}", AMQP_HEADER_PREFIX, attribute)),
+                        AMQPValue::LongString(LongString::from(value)),
+                    ),
+                    None if name == CONTENT_TYPE => {
+                        properties = properties.with_content_type(ShortString::from(value))
+                    }
+                    None => {}
+                }
+            }
+            Ok((body.unwrap_or_default(), properties.with_headers(headers)))
+        }
+        ContentMode::Structured => Ok((
+            serde_json::to_vec(event)?,
+            properties.with_content_type(ShortString::from(STRUCTURED_CONTENT_TYPE)),
+        )),
+    }
+}
+
+/// Converts an AMQP message to an event.
+///
+/// Messages with `cloudEvents_` or `cloudEvents:` headers are in the binary content mode, all others have to be in the structured content mode.
+pub fn from_message(data: &[u8], properties: &BasicProperties) -> Result<Event> {
+    let attributes: Vec<(&str, &AMQPValue)> = properties
+        .headers()
+        .iter()
+        .flat_map(|headers| headers.inner().iter())
+        .filter_map(|(name, value)| attribute_name(name.as_str()).map(|a| (a, value)))
+        .collect();
+    if attributes.is_empty() {
+        return serde_json::from_slice::<Event>(data)
+            .context("failed to parse structured CloudEvent");
+    }
+    let mut header_map = HeaderMap::new();
+    if let Some(content_type) = properties.content_type() {
+        header_map.insert(CONTENT_TYPE, HeaderValue::from_str(content_type.as_str())?);
+    }
+    for (attribute, value) in attributes {
+        header_map.insert(
+            HeaderName::from_bytes(format!("{}{}", HTTP_HEADER_PREFIX, attribute).as_bytes())?,
+            HeaderValue::from_str(&attribute_value(value)?)?,
+        );
+    }
+    to_event(&header_map, data.to_vec()).context("failed to parse binary CloudEvent")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cloudevents::{AttributesReader, Data, EventBuilder, EventBuilderV10};
+
+    fn event() -> Event {
+        EventBuilderV10::new()
+            .id("1")
+            .ty("test")
+            .source("http://example.com")
+            .extension("tenant", "a")
+            .data("application/octet-stream", vec![0xff, 0x00, 0xfe])
+            .build()
+            .unwrap()
+    }
+
+    fn header<'a>(properties: &'a BasicProperties, name: &str) -> Option<&'a AMQPValue> {
+        properties
+            .headers()
+            .as_ref()
+            .and_then(|headers| headers.inner().get(name))
+    }
+
+    #[test]
+    fn binary_round_trip() -> Result<()> {
+        let (body, properties) = to_message(&event(), ContentMode::Binary)?;
+        assert_eq!(body, vec![0xff, 0x00, 0xfe]);
+        assert_eq!(
+            properties.content_type().as_ref().map(|c| c.as_str()),
+            Some("application/octet-stream")
+        );
+        assert_eq!(
+            header(&properties, "cloudEvents_tenant"),
+            Some(&AMQPValue::LongString(LongString::from("a")))
+        );
+        assert_eq!(from_message(&body, &properties)?, event());
+        Ok(())
+    }
+
+    #[test]
+    fn structured_round_trip() -> Result<()> {
+        let (body, properties) = to_message(&event(), ContentMode::Structured)?;
+        assert!(properties.headers().is_none());
+        assert_eq!(
+            properties.content_type().as_ref().map(|c| c.as_str()),
+            Some(STRUCTURED_CONTENT_TYPE)
+        );
+        assert_eq!(from_message(&body, &properties)?, event());
+        // messages of older publishers have no content type
+        assert_eq!(from_message(&body, &BasicProperties::default())?, event());
+        Ok(())
+    }
+
+    #[test]
+    fn binary_message_with_typed_headers() -> Result<()> {
+        let mut headers = FieldTable::default();
+        headers.insert(
+            "cloudEvents:specversion".into(),
+            AMQPValue::LongString("1.0".into()),
+        );
+        headers.insert("cloudEvents:id".into(), AMQPValue::LongString("1".into()));
+        headers.insert(
+            "cloudEvents:type".into(),
+            AMQPValue::ShortString("test".into()),
+        );
+        headers.insert(
+            "cloudEvents:source".into(),
+            AMQPValue::LongString("http://example.com".into()),
+        );
+        headers.insert(
+            "cloudEvents:time".into(),
+            AMQPValue::Timestamp(1_600_000_000),
+        );
+        headers.insert("cloudEvents:attempt".into(), AMQPValue::LongInt(2));
+        headers.insert("x-death".into(), AMQPValue::Boolean(true));
+        let properties = BasicProperties::default()
+            .with_headers(headers)
+            .with_content_type("text/plain".into());
+
+        let event = from_message(b"hello", &properties)?;
+        assert_eq!(event.id(), "1");
+        assert_eq!(
+            event.time(),
+            Some(&Utc.timestamp_opt(1_600_000_000, 0).unwrap())
+        );
+        assert_eq!(
+            event.extension("attempt").map(|a| a.to_string()),
+            Some("2".to_string())
+        );
+        assert_eq!(event.data(), Some(&Data::Binary(b"hello".to_vec())));
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_messages() {
+        assert!(from_message(&[0xff], &BasicProperties::default()).is_err());
+        let mut headers = FieldTable::default();
+        headers.insert("cloudEvents_id".into(), AMQPValue::LongString("1".into()));
+        let properties = BasicProperties::default().with_headers(headers);
+        assert!(from_message(b"", &properties).is_err());
+        assert!(ContentMode::parse("batched").is_err());
+    }
+}
@@ -6,19 +6,24 @@ Helpers for the [lapin crate](https://crates.io/crates/lapin)
 
 use amq_protocol::protocol::{AMQPErrorKind, AMQPSoftError};
 use amq_protocol_types::FieldTable;
-use anyhow::Result;
+use anyhow::{Context, Result};
 use lapin::options::{
     ExchangeDeclareOptions, ExchangeDeleteOptions, QueueDeclareOptions, QueueDeleteOptions,
 };
 use lapin::{Channel, Connection, ExchangeKind, Queue};
 
-/// same as queue_declare but on PRECONDITIONFAILED it does recreate the queue
+/// same as queue_declare but on PRECONDITIONFAILED it does recreate the queue if `recreate` is set
+///
+/// The broker closes the channel on PRECONDITIONFAILED, so it is replaced by a new channel,
+/// which has none of the options of the previous one, e.g., `basic_qos`.
+/// Without `recreate`, it fails because the existing queue was declared with other arguments.
 pub async fn assert_queue(
     connection: &Connection,
     channel: &mut Channel,
     queue: &str,
     options: QueueDeclareOptions,
     arguments: FieldTable,
+    recreate: bool,
 ) -> Result<Queue> {
     let queue_declare = |c: &Channel| c.queue_declare(queue, options.clone(), arguments.clone());
     let mut result = queue_declare(channel).await;
@@ -27,6 +32,14 @@ pub async fn assert_queue(
             match soft_error {
                 AMQPSoftError::PRECONDITIONFAILED => {
                     *channel = connection.create_channel().await?;
+                    if !recreate {
+                        return result.with_context(|| {
+                            format!(
+                                "queue {} exists with other arguments, delete it or set recreate_queue",
+                                queue
+                            )
+                        });
+                    }
                     channel
                         .queue_delete(queue, QueueDeleteOptions::default())
                         .await?;
@@ -39,7 +52,9 @@ pub async fn assert_queue(
     Ok(result?)
 }
 
-/// same as exchange_declare but on PRECONDITIONFAILED it does recreate the queue
+/// same as exchange_declare but on PRECONDITIONFAILED it does recreate the exchange
+///
+/// The channel is replaced like in [`assert_queue`].
 pub async fn assert_exchange(
     connection: &Connection,
     channel: &mut Channel,
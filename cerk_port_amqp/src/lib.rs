@@ -23,11 +23,114 @@ The port is implemented with [lapin](https://github.com/CleverCloud/lapin).
 
 ## Content Modes
 
-The port supports the structured content mode with the JSON event format.
-However, it does not support the binary content mode.
+The port supports the structured and the binary content mode.
+In the structured content mode, the event is published as JSON body with the content type `application/cloudevents+json`.
+In the binary content mode, the attributes are published as `cloudEvents_` headers and the data as body with its own content type,
+which allows non-UTF-8 data.
+
+The content mode of the published events is configured per publish channel.
+The content mode of consumed messages is detected automatically: messages with `cloudEvents_` (or `cloudEvents:`) headers are read in the binary content mode.
 
 <https://github.com/cloudevents/spec/blob/master/amqp-protocol-binding.md#2-use-of-cloudevents-attributes>
 
+## Configurations
+
+The port expects a `Config::HashMap` with the following options:
+
+| Name                         | Type   | Description                                                                  |
+|------------------------------|--------|------------------------------------------------------------------------------|
+| `uri`                        | String | the AMQP URI, e.g., `amqp://127.0.0.1:5672/%2f` or `amqps://broker:5671/%2f` |
+| `username`                   | String | the username, overrides the one of the URI                                   |
+| `username_file`              | String | the path of a file containing the username                                   |
+| `password`                   | String | the password, overrides the one of the URI                                   |
+| `password_file`              | String | the path of a file containing the password                                   |
+| `auth_mechanism`             | String | `plain`, `amqplain` or `external`, overrides the one of the URI              |
+| `tls_ca_path`                | String | the PEM encoded CA certificates to verify the broker (requires `amqps`)      |
+| `tls_identity_path`          | String | the PKCS #12 encoded client certificate and key (requires `amqps`)           |
+| `tls_identity_password`      | String | the password of the client identity                                          |
+| `tls_identity_password_file` | String | the path of a file containing the password of the client identity            |
+| `consume_channels`           | Vec    | the queues to consume, see below                                             |
+| `publish_channels`           | Vec    | the exchanges to publish to, see below                                       |
+
+Credentials in separate options or files keep them out of the URI, e.g., to mount them as secrets.
+A trailing newline of a file is ignored.
+Without `tls_ca_path`, the broker certificate is verified with the CA certificates of the system.
+With the `external` mechanism, the broker authenticates the port by its client certificate (SASL EXTERNAL),
+which requires `tls_identity_path`.
+
+### Consume Channels
+
+| Name                 | Type    | Default                         | Description                                                         |
+|----------------------|---------|---------------------------------|---------------------------------------------------------------------|
+| `name`               | String  |                                 | the name of the queue                                               |
+| `ensure_queue`       | bool    | `false`                         | declare the queue                                                   |
+| `ensure_dlx`         | bool    | `ensure_queue`                  | declare the dead letter exchange and queue                          |
+| `dlx_exchange`       | String  | `{name}-dlx`                    | the dead letter exchange of the queue                               |
+| `dlx_queue`          | String  | `dlx_exchange`                  | the queue bound to the dead letter exchange (requires `ensure_dlx`) |
+| `delivery_limit`     | u32     | `3` with a dead letter exchange | the number of deliveries before a message is dead-lettered          |
+| `queue_type`         | String  |                                 | `classic` or `quorum`                                               |
+| `message_ttl`        | u32     |                                 | the time to live of the messages in milliseconds                    |
+| `expires`            | u32     |                                 | the time in milliseconds after which an unused queue is deleted     |
+| `max_length`         | u32     |                                 | the maximum number of messages in the queue                         |
+| `max_length_bytes`   | u32     |                                 | the maximum size of the messages in the queue in bytes              |
+| `queue_arguments`    | HashMap |                                 | further `x-` arguments of the queue, e.g., `x-overflow`             |
+| `recreate_queue`     | bool    | `false`                         | delete and declare the queue again if its arguments changed         |
+| `bind_to_exchange`   | String  |                                 | bind the queue to the exchange (requires `ensure_queue`)            |
+| `binding_key`        | String  | `""`                            | the binding key, e.g., `created.#` for a topic exchange             |
+| `delivery_guarantee` | u8      | `0`                             | the delivery guarantee of the consumed events                       |
+| `prefetch_count`     | u32     | `30`                            | the maximum number of unacknowledged deliveries                     |
+
+The queue arguments are only applied if the port declares the queue.
+If `dlx_exchange` is set without `ensure_dlx`, the exchange has to exist already.
+The values of `queue_arguments` take precedence over the options above.
+Be aware that RabbitMQ does not allow to change the arguments of an existing queue,
+therefore, the port fails to connect if the arguments of an existing queue differ.
+With `recreate_queue`, which requires `ensure_queue`, it deletes and declares the queue again instead, the messages in the queue are lost.
+The dead letter queue is never deleted.
+
+### Publish Channels
+
+| Name                 | Type   | Default      | Description                                                         |
+|----------------------|--------|--------------|---------------------------------------------------------------------|
+| `name`               | String |              | the name of the exchange                                            |
+| `ensure_exchange`    | bool   | `false`      | declare the exchange                                                |
+| `exchange_type`      | String | `fanout`     | `fanout`, `direct`, `topic` or `headers`                            |
+| `routing_key`        | String | `""`         | the routing key template, e.g., `{type}.{source}`                   |
+| `filter`             | String |              | JSON encoded routing rules, only matching events are published      |
+| `content_mode`       | String | `structured` | `structured` or `binary`                                            |
+| `publish_timeout`    | u32    | `5000`       | the time to wait for the confirmation of the broker in milliseconds |
+| `delivery_guarantee` | u8     | `0`          | the delivery guarantee of the published events                      |
+
+The placeholders of the routing key are replaced with the attributes or extensions of the event,
+e.g., `{type}.{source}` results in `created.orders` for an event with the type `created` and the source `orders`.
+The publishing of an event fails if it has no value for a placeholder.
+
+The filter uses the same format as the routing rules of the [rule-based router](https://github.com/ce-rust/cerk/tree/master/cerk_router_rule_based/),
+e.g., `{"Exact":["Type","created"]}`.
+An event is only published to the exchanges whose filter it matches.
+
+## Publish Errors
+
+If an event requires an acknowledgment, the port reports the outcome of the publishing to the router.
+Failures which can succeed on a retry are reported as `TransientError`:
+a missing connection, e.g., before a valid config was received, a closed connection or channel, a negative acknowledgment (nack) of the broker, or a missing confirmation within `publish_timeout`.
+Failures which fail again on a retry are reported as `PermanentError`:
+an unroutable event, which the broker returns because it is published as `mandatory`,
+an event which can not be serialized or has no value for a placeholder of the routing key,
+and an event which the broker refuses because it is too large or not permitted.
+Confirmations and returned events are only evaluated on channels with the delivery guarantee `2` (at least once).
+
+## Reconnection
+
+The port monitors the connection and its channels.
+If one of them is closed, e.g., because the broker restarted, the port reconnects automatically.
+The first attempt is made immediately, the following ones with an exponential backoff from 500ms up to 30s.
+Each attempt declares the configured exchanges and queues again and resumes the consumers.
+
+Unacknowledged deliveries of the lost connection are requeued by the broker and consumed again after the reconnect,
+so the results which the router reports for them afterwards are ignored.
+While the port reconnects, the health check reports it as unhealthy and outgoing events are rejected with a `TransientError`.
+
 ## Examples
 
  * [Sequence to AMQP to Printer](https://github.com/ce-rust/cerk/tree/master/examples/examples/src/sequence_to_amqp_to_printer/)
@@ -43,7 +146,11 @@ extern crate log;
 #[macro_use]
 extern crate anyhow;
 
+mod amqp_binding;
+mod connection_options;
 pub mod lapin_helper;
 mod port_amqp;
+mod publish_error;
+mod queue_arguments;
 
 pub use self::port_amqp::{port_amqp_start, PORT_AMQP};
@@ -1,7 +1,9 @@
+use crate::amqp_binding::{from_message, to_message, ContentMode};
+use crate::connection_options::{try_get_connection_options, ConnectionOptions};
 use crate::lapin_helper::{assert_exchange, assert_queue};
-use amq_protocol_types::LongString;
-use amq_protocol_types::ShortString;
-use amq_protocol_types::{AMQPValue, LongLongUInt, ShortUInt};
+use crate::publish_error::{check_confirmation, PublishError};
+use crate::queue_arguments::{try_get_queue_options, DeadLetterOptions, QueueOptions};
+use amq_protocol_types::LongLongUInt;
 use anyhow::{Context, Result};
 use async_std::future::timeout;
 use cerk::kernel::{
@@ -11,6 +13,8 @@ use cerk::kernel::{
 };
 use cerk::runtime::channel::{BoxedReceiver, BoxedSender};
 use cerk::runtime::{InternalServerFn, InternalServerFnRefStatic, InternalServerId};
+use cerk_port_common::template::render_template;
+use cerk_router_rule_based::RoutingRules;
 use cloudevents::{AttributesReader, Event};
 use futures_lite::future;
 use futures_lite::stream::StreamExt;
@@ -22,12 +26,20 @@ use lapin::{
 use std::collections::HashMap;
 use std::convert::TryFrom;
 use std::sync::{Arc, Mutex};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 /// The default prefetch count per channel.
 /// The channel to the kernel has a size of 50 so it should be smaller then that.
 const DEFAULT_PREFETCH_COUNT: u16 = 30;
 
+/// The default time to wait for the confirmation of a published event.
+const DEFAULT_PUBLISH_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The delay before the second reconnect attempt, the first attempt is made immediately.
+const RECONNECT_MIN_BACKOFF: Duration = Duration::from_millis(500);
+/// The backoff between reconnect attempts is doubled until it reaches this maximum.
+const RECONNECT_MAX_BACKOFF: Duration = Duration::from_secs(30);
+
 struct PendingDelivery {
     consume_channel_id: String,
     delivery_tag: LongLongUInt,
@@ -38,8 +50,9 @@ type PendingDeliveries = HashMap<CloudEventMessageRoutingId, PendingDelivery>;
 struct AmqpConsumeOptions {
     channel: Option<Channel>,
     ensure_queue: bool,
-    ensure_dlx: bool,
+    queue_options: QueueOptions,
     bind_to_exchange: Option<String>,
+    binding_key: String,
     delivery_guarantee: DeliveryGuarantee,
     prefetch_count: u16,
 }
@@ -47,11 +60,18 @@ struct AmqpConsumeOptions {
 struct AmqpPublishOptions {
     channel: Option<Channel>,
     ensure_exchange: bool,
+    exchange_kind: ExchangeKind,
+    /// template of the routing key, see [`render_template`]
+    routing_key: String,
+    /// only the events which match the filter are published to the exchange
+    filter: Option<RoutingRules>,
+    content_mode: ContentMode,
+    publish_timeout: Duration,
     delivery_guarantee: DeliveryGuarantee,
 }
 
 struct AmqpOptions {
-    uri: String,
+    connection: ConnectionOptions,
     consume_channels: HashMap<String, AmqpConsumeOptions>,
     publish_channels: HashMap<String, AmqpPublishOptions>,
 }
@@ -63,17 +83,38 @@ fn try_get_delivery_option(config: &HashMap<String, Config>) -> Result<DeliveryG
     })
 }
 
+fn try_get_exchange_kind(config: &HashMap<String, Config>) -> Result<ExchangeKind> {
+    Ok(match config.get("exchange_type") {
+        Some(Config::String(kind)) => match kind.as_str() {
+            "fanout" => ExchangeKind::Fanout,
+            "direct" => ExchangeKind::Direct,
+            "topic" => ExchangeKind::Topic,
+            "headers" => ExchangeKind::Headers,
+            _ => bail!("exchange_type {} is not supported", kind),
+        },
+        Some(_) => bail!("exchange_type has to be of type String"),
+        None => ExchangeKind::Fanout,
+    })
+}
+
+fn try_get_filter(config: &HashMap<String, Config>) -> Result<Option<RoutingRules>> {
+    Ok(match config.get("filter") {
+        Some(Config::String(filter)) => Some(
+            serde_json::from_str::<RoutingRules>(filter)
+                .with_context(|| format!("filter {} is invalid", filter))?,
+        ),
+        Some(_) => bail!("filter has to be of type String"),
+        None => None,
+    })
+}
+
 fn build_config(id: &InternalServerId, config: &Config) -> Result<AmqpOptions> {
     match config {
         Config::HashMap(config_map) => {
-            let mut options = if let Some(Config::String(uri)) = config_map.get("uri") {
-                AmqpOptions {
-                    uri: uri.to_string(),
-                    consume_channels: HashMap::new(),
-                    publish_channels: HashMap::new(),
-                }
-            } else {
-                bail!("No uri option")
+            let mut options = AmqpOptions {
+                connection: try_get_connection_options(config)?,
+                consume_channels: HashMap::new(),
+                publish_channels: HashMap::new(),
             };
 
             if let Some(Config::Vec(ref consumers)) = config_map.get("consume_channels") {
@@ -83,13 +124,26 @@ fn build_config(id: &InternalServerId, config: &Config) -> Result<AmqpOptions> {
                             Some(Config::Bool(b)) => *b,
                             _ => false,
                         };
+                        let name = match consumer.get("name") {
+                            Some(Config::String(name)) => name,
+                            _ => bail!("consume_channels name is not set"),
+                        };
                         let consumer_options = AmqpConsumeOptions {
                             ensure_queue,
-                            ensure_dlx: ensure_queue,
+                            queue_options: try_get_queue_options(
+                                name,
+                                consumer_config,
+                                ensure_queue,
+                            )
+                            .with_context(|| format!("consume channel {} is invalid", name))?,
                             bind_to_exchange: match consumer.get("bind_to_exchange") {
                                 Some(Config::String(s)) => Some(s.to_string()),
                                 _ => None,
                             },
+                            binding_key: match consumer.get("binding_key") {
+                                Some(Config::String(s)) => s.to_string(),
+                                _ => String::new(),
+                            },
                             delivery_guarantee: try_get_delivery_option(consumer)?,
                             channel: None,
                             prefetch_count: consumer_config
@@ -98,13 +152,9 @@ fn build_config(id: &InternalServerId, config: &Config) -> Result<AmqpOptions> {
                                 .unwrap_or(DEFAULT_PREFETCH_COUNT),
                         };
 
-                        if let Some(Config::String(name)) = consumer.get("name") {
-                            options
-                                .consume_channels
-                                .insert(name.to_string(), consumer_options);
-                        } else {
-                            bail!("consume_channels name is not set")
-                        }
+                        options
+                            .consume_channels
+                            .insert(name.to_string(), consumer_options);
                     } else {
                         bail!("consume_channels entries have to be of type HashMap")
                     }
@@ -119,6 +169,20 @@ fn build_config(id: &InternalServerId, config: &Config) -> Result<AmqpOptions> {
                                 Some(Config::Bool(b)) => *b,
                                 _ => false,
                             },
+                            exchange_kind: try_get_exchange_kind(publisher)?,
+                            routing_key: match publisher.get("routing_key") {
+                                Some(Config::String(s)) => s.to_string(),
+                                _ => String::new(),
+                            },
+                            filter: try_get_filter(publisher)?,
+                            content_mode: match publisher.get("content_mode") {
+                                Some(Config::String(s)) => ContentMode::parse(s)?,
+                                _ => ContentMode::Structured,
+                            },
+                            publish_timeout: publisher_config
+                                .get_op_val_u32("publish_timeout")?
+                                .map(|v| Duration::from_millis(v as u64))
+                                .unwrap_or(DEFAULT_PUBLISH_TIMEOUT),
                             delivery_guarantee: try_get_delivery_option(publisher)?,
                             channel: None,
                         };
@@ -142,21 +206,57 @@ fn build_config(id: &InternalServerId, config: &Config) -> Result<AmqpOptions> {
     }
 }
 
+/// Tracks the recovery of a lost connection.
+struct Recovery {
+    /// the number of failed reconnect attempts
+    attempt: u32,
+    next_attempt: Instant,
+}
+
+impl Recovery {
+    fn new() -> Self {
+        Recovery {
+            attempt: 0,
+            next_attempt: Instant::now(),
+        }
+    }
+
+    /// The delay after the given number of failed attempts.
+    fn backoff(attempt: u32) -> Duration {
+        RECONNECT_MIN_BACKOFF
+            .saturating_mul(1 << attempt.saturating_sub(1).min(16))
+            .min(RECONNECT_MAX_BACKOFF)
+    }
+
+    fn failed(&mut self) {
+        self.attempt += 1;
+        self.next_attempt = Instant::now() + Recovery::backoff(self.attempt);
+    }
+
+    fn is_due(&self) -> bool {
+        Instant::now() >= self.next_attempt
+    }
+}
+
+/// Declares the topology and starts the consumers on a new connection.
+///
+/// `generation` identifies the connection, it is part of the routing ids of the consumed events,
+/// so the delivery tags of a closed connection are never mixed up with the ones of the new connection.
 fn setup_connection(
     id: InternalServerId,
     sender_to_kernel: BoxedSender,
-    _connection: &Option<Connection>, // todo reuse connection, if there is already one
-    config: Config,
+    mut config: AmqpOptions,
+    generation: u64,
     pending_deliveries: Arc<Mutex<HashMap<String, PendingDelivery>>>,
 ) -> Result<(Connection, AmqpOptions)> {
-    let mut config = match build_config(&id, &config) {
-        Ok(c) => c,
-        Err(e) => std::panic::panic_any(e),
-    };
-
     async_global_executor::block_on(async {
-        let setup =
-            setup_connection_async(&id, &sender_to_kernel, &pending_deliveries, &mut config);
+        let setup = setup_connection_async(
+            &id,
+            &sender_to_kernel,
+            generation,
+            &pending_deliveries,
+            &mut config,
+        );
         let result = timeout(Duration::from_secs(1), setup)
             .await
             .map_err(|_| anyhow!("setup_connection timed out"))??;
@@ -167,12 +267,14 @@ fn setup_connection(
 async fn setup_connection_async(
     id: &String,
     sender_to_kernel: &BoxedSender,
+    generation: u64,
     pending_deliveries: &Arc<Mutex<HashMap<String, PendingDelivery>>>,
     config: &mut AmqpOptions,
 ) -> Result<Connection> {
-    let connection = Connection::connect(
-        &config.uri,
+    let connection = Connection::connect_uri_with_identity(
+        config.connection.uri.clone(),
         ConnectionProperties::default().with_default_executor(8),
+        config.connection.tls.as_ref(),
     )
     .await?;
 
@@ -191,8 +293,8 @@ async fn setup_connection_async(
             &connection,
             id,
             sender_to_kernel,
+            generation,
             pending_deliveries,
-            &connection,
             name,
             channel_options,
         )
@@ -207,44 +309,27 @@ async fn setup_consume_channel(
     connection: &Connection,
     id: &String,
     sender_to_kernel: &BoxedSender,
+    generation: u64,
     pending_deliveries: &Arc<Mutex<HashMap<String, PendingDelivery>>>,
-    conn: &Connection,
-    name: &String,
+    name: &str,
     channel_options: &mut AmqpConsumeOptions,
 ) -> Result<Channel> {
-    let mut channel = conn.create_channel().await?;
-    if channel_options.delivery_guarantee.requires_acknowledgment() {
-        channel
-            .confirm_select(ConfirmSelectOptions { nowait: false })
-            .await?;
+    let mut channel = connection.create_channel().await?;
+    if let Some(dead_letter) = &channel_options.queue_options.dead_letter {
+        setup_dlx(connection, dead_letter, &mut channel)
+            .await
+            .context("failed to setup dlx")?;
     }
-    channel
-        .basic_qos(channel_options.prefetch_count, BasicQosOptions::default())
-        .await?;
     if channel_options.ensure_queue {
-        let mut queue_args = FieldTable::default();
-        if channel_options.ensure_dlx {
-            let dlx = setup_dlx(conn, &name, &mut channel)
-                .await
-                .context("failed to setup dlx")?;
-            queue_args.insert(
-                ShortString::from("x-dead-letter-exchange"),
-                AMQPValue::LongString(LongString::from(dlx)),
-            );
-            queue_args.insert(
-                ShortString::from("x-delivery-limit"),
-                AMQPValue::ShortUInt(ShortUInt::from_be(3)),
-            );
-        }
-
         let mut queue_options = QueueDeclareOptions::default();
         queue_options.durable = true;
         let queue = assert_queue(
             connection,
             &mut channel,
-            name.as_str(),
+            name,
             queue_options,
-            queue_args,
+            channel_options.queue_options.arguments.clone(),
+            channel_options.queue_options.recreate,
         )
         .await?;
         info!("Declared queue {:?}", queue);
@@ -252,19 +337,28 @@ async fn setup_consume_channel(
         if let Some(exchange) = &channel_options.bind_to_exchange {
             channel
                 .queue_bind(
-                    name.as_str(),
+                    name,
                     exchange.as_str(),
-                    "",
+                    channel_options.binding_key.as_str(),
                     QueueBindOptions::default(),
                     FieldTable::default(),
                 )
                 .await?;
         }
     }
+    // the declarations above might have replaced the channel, so it is configured afterwards
+    if channel_options.delivery_guarantee.requires_acknowledgment() {
+        channel
+            .confirm_select(ConfirmSelectOptions { nowait: false })
+            .await?;
+    }
+    channel
+        .basic_qos(channel_options.prefetch_count, BasicQosOptions::default())
+        .await?;
 
     let mut consumer = channel
         .basic_consume(
-            name.as_str(),
+            name,
             format!("cerk-{}", id.clone()).as_str(),
             BasicConsumeOptions::default(),
             FieldTable::default(),
@@ -274,7 +368,7 @@ async fn setup_consume_channel(
     let cloned_sender = sender_to_kernel.clone_boxed();
     let cloned_id = id.clone();
     let cloned_delivery_guarantee = channel_options.delivery_guarantee.clone();
-    let cloned_name = name.clone();
+    let cloned_name = name.to_string();
     let weak_clone = pending_deliveries.clone();
     async_global_executor::spawn(async move {
         info!("will consume");
@@ -284,10 +378,10 @@ async fn setup_consume_channel(
                 Ok((channel, delivery)) => {
                     match receive_message(
                         &cloned_name,
-                        channel,
                         delivery,
                         cloned_sender,
                         &cloned_id,
+                        generation,
                         weak_clone.clone(),
                         &cloned_delivery_guarantee,
                     ) {
@@ -314,9 +408,14 @@ async fn setup_consume_channel(
                         }
                     }
                 }
-                Err(e) => error!("error in consumer: {:?}", e),
+                Err(e) => error!(
+                    "{} error in consumer of {}: {:?}",
+                    &cloned_id, &cloned_name, e
+                ),
             }
         }
+        // the main loop detects the closed channel and reconnects
+        info!("{} consumer of {} stopped", &cloned_id, &cloned_name);
     })
     .detach();
 
@@ -325,10 +424,9 @@ async fn setup_consume_channel(
 
 async fn setup_dlx(
     connection: &Connection,
-    name: &&String,
+    dead_letter: &DeadLetterOptions,
     channel: &mut Channel,
-) -> Result<String> {
-    let dlx_name = format!("{}-dlx", &name);
+) -> Result<()> {
     let mut exchange_options = ExchangeDeclareOptions::default();
     exchange_options.durable = true;
     let mut queue_options = QueueDeclareOptions::default();
@@ -337,7 +435,7 @@ async fn setup_dlx(
     assert_exchange(
         connection,
         channel,
-        dlx_name.as_str(),
+        dead_letter.exchange.as_str(),
         ExchangeKind::Fanout,
         exchange_options,
         FieldTable::default(),
@@ -346,21 +444,23 @@ async fn setup_dlx(
     assert_queue(
         connection,
         channel,
-        dlx_name.as_str(),
+        dead_letter.queue.as_str(),
         queue_options,
         FieldTable::default(),
+        // the dead-lettered messages are never deleted
+        false,
     )
     .await?;
     channel
         .queue_bind(
-            dlx_name.as_str(),
-            dlx_name.as_str(),
+            dead_letter.queue.as_str(),
+            dead_letter.exchange.as_str(),
             "",
             QueueBindOptions::default(),
             FieldTable::default(),
         )
         .await?;
-    Ok(dlx_name)
+    Ok(())
 }
 
 async fn setup_publish_channel(
@@ -369,23 +469,24 @@ async fn setup_publish_channel(
     channel_options: &mut AmqpPublishOptions,
 ) -> Result<Channel> {
     let mut channel = conn.create_channel().await?;
-    if channel_options.delivery_guarantee.requires_acknowledgment() {
-        channel
-            .confirm_select(ConfirmSelectOptions { nowait: false })
-            .await?;
-    }
     if channel_options.ensure_exchange {
         assert_exchange(
             conn,
             &mut channel,
             name.as_str(),
-            ExchangeKind::Fanout,
+            channel_options.exchange_kind.clone(),
             ExchangeDeclareOptions::default(),
             FieldTable::default(),
         )
         .await?;
         info!("Declared exchange {}", &name);
     }
+    // assert_exchange might have replaced the channel, so it is configured afterwards
+    if channel_options.delivery_guarantee.requires_acknowledgment() {
+        channel
+            .confirm_select(ConfirmSelectOptions { nowait: false })
+            .await?;
+    }
     Ok(channel)
 }
 
@@ -400,20 +501,19 @@ async fn setup_publish_channel(
 ///
 fn receive_message(
     name: &String,
-    channel: &Channel,
     delivery: &Delivery,
     sender: BoxedSender,
     id: &String,
+    generation: u64,
     pending_deliveries: Arc<Mutex<HashMap<String, PendingDelivery>>>,
     delivery_guarantee: &DeliveryGuarantee,
 ) -> Result<bool> {
-    debug!("{} received CloudEvent on queue {}", id, channel.id());
-    let payload_str = std::str::from_utf8(&delivery.data).unwrap();
+    debug!("{} received CloudEvent on queue {}", id, name);
     let send_immediate_ack: bool;
-    match serde_json::from_str::<Event>(&payload_str) {
+    match from_message(&delivery.data, &delivery.properties) {
         Ok(cloud_event) => {
             debug!("{} deserialized event successfully", id);
-            let routing_id = get_event_id(&cloud_event, &delivery.delivery_tag);
+            let routing_id = get_event_id(&cloud_event, generation, &delivery.delivery_tag);
             if delivery_guarantee.requires_acknowledgment() {
                 info!(
                     "pending_deliveries size: {}",
@@ -451,62 +551,142 @@ fn receive_message(
             }));
         }
         Err(err) => {
-            bail!("{} while converting string to CloudEvent: {:?}", id, err);
+            bail!("{} while converting message to CloudEvent: {:?}", id, err);
         }
     }
 
     Ok(send_immediate_ack)
 }
 
-fn get_event_id(cloud_event: &Event, delivery_tag: &LongLongUInt) -> String {
-    format!("{}--{}", cloud_event.id(), delivery_tag)
+fn get_event_id(cloud_event: &Event, generation: u64, delivery_tag: &LongLongUInt) -> String {
+    format!("{}--{}--{}", cloud_event.id(), generation, delivery_tag)
+}
+
+/// Checks that the connection and all its channels are open.
+fn is_connected(connection: &Connection, configuration: &AmqpOptions) -> bool {
+    let channels = configuration
+        .publish_channels
+        .values()
+        .map(|options| options.channel.as_ref())
+        .chain(
+            configuration
+                .consume_channels
+                .values()
+                .map(|options| options.channel.as_ref()),
+        );
+    connection.status().connected()
+        && channels
+            .into_iter()
+            .all(|channel| channel.is_some_and(|c| c.status().connected()))
+}
+
+/// Closes the connection, the broker requeues all unacknowledged deliveries of its channels.
+fn close_connection(id: &InternalServerId, connection: Connection) {
+    if !connection.status().connected() {
+        return;
+    }
+    let result = async_global_executor::block_on(timeout(
+        Duration::from_secs(1),
+        connection.close(200, "closed by cerk"),
+    ));
+    match result {
+        Ok(Ok(())) => debug!("{} closed the connection", id),
+        Ok(Err(e)) => warn!("{} failed to close the connection: {:?}", id, e),
+        Err(_) => warn!("{} timed out while closing the connection", id),
+    }
 }
 
-async fn send_cloud_event(cloud_event: &Event, configurations: &AmqpOptions) -> Result<()> {
-    let payload = serde_json::to_string(cloud_event).unwrap();
+/// Forgets the pending deliveries of a closed connection.
+///
+/// Their delivery tags are only valid on the closed channels.
+/// The broker redelivers these messages to the new consumers,
+/// the outcome which the kernel sends later for the old routing ids is ignored.
+fn reconcile_pending_deliveries(
+    id: &InternalServerId,
+    pending_deliveries: &Arc<Mutex<HashMap<String, PendingDelivery>>>,
+) {
+    let mut pending_deliveries = pending_deliveries.lock().unwrap();
+    if !pending_deliveries.is_empty() {
+        warn!(
+            "{} dropped {} pending deliveries of the closed connection, they will be redelivered by the broker",
+            id,
+            pending_deliveries.len()
+        );
+        pending_deliveries.clear();
+    }
+}
+
+async fn send_cloud_event(
+    cloud_event: &Event,
+    configurations: &AmqpOptions,
+) -> Result<(), PublishError> {
     for (name, options) in configurations.publish_channels.iter() {
-        let result = match options.channel {
-            Some(ref channel) => {
-                let result = publish_cloud_event(&payload, &name, channel).await;
-                if let Ok(result) = result {
-                    if !options.delivery_guarantee.requires_acknowledgment() || result.is_ack() {
-                        Ok(())
-                    } else {
-                        bail!("Message was not acknowledged, but channel delivery_guarantee requires it: {:?}", result)
-                    }
-                } else {
-                    Err(anyhow!("message was not sent successful"))
-                }
+        if let Some(filter) = options.filter.as_ref() {
+            if !filter.matches(cloud_event) {
+                debug!(
+                    "event {} does not match the filter of {}",
+                    cloud_event.id(),
+                    name
+                );
+                continue;
             }
-            None => Err(anyhow!("channel to exchange is closed")),
-        };
-        result?
+        }
+        let routing_key = render_template(&options.routing_key, cloud_event)
+            .with_context(|| format!("failed to render the routing key for {}", name))
+            .map_err(PublishError::Permanent)?;
+        let (payload, properties) = to_message(cloud_event, options.content_mode)
+            .context("failed to serialize the CloudEvent")
+            .map_err(PublishError::Permanent)?;
+        let channel = options.channel.as_ref().ok_or_else(|| {
+            PublishError::Transient(anyhow!("channel to exchange {} is closed", name))
+        })?;
+        let confirmation = publish_cloud_event(
+            payload,
+            properties,
+            name,
+            &routing_key,
+            channel,
+            options.publish_timeout,
+        )
+        .await?;
+        check_confirmation(
+            confirmation,
+            options.delivery_guarantee.requires_acknowledgment(),
+        )?;
     }
     Ok(())
 }
 
 async fn publish_cloud_event(
-    payload: &String,
-    name: &String,
+    payload: Vec<u8>,
+    properties: BasicProperties,
+    name: &str,
+    routing_key: &str,
     channel: &Channel,
-) -> Result<Confirmation> {
-    let confirmation = channel
-        .basic_publish(
-            name.as_str(),
-            "",
-            BasicPublishOptions {
-                mandatory: true,
-                immediate: false,
-            },
-            Vec::from(payload.as_str()),
-            BasicProperties::default()
-                .with_delivery_mode(2) //persistent
-                .with_content_type(ShortString::from(
-                    "application/cloudevents+json; charset=UTF-8",
-                )),
-        )
-        .await?
-        .await?;
+    publish_timeout: Duration,
+) -> Result<Confirmation, PublishError> {
+    let publish = async {
+        channel
+            .basic_publish(
+                name,
+                routing_key,
+                BasicPublishOptions {
+                    mandatory: true,
+                    immediate: false,
+                },
+                payload,
+                properties,
+            )
+            .await?
+            .await
+    };
+    let confirmation = timeout(publish_timeout, publish).await.map_err(|_| {
+        PublishError::Transient(anyhow!(
+            "the confirmation of {} timed out after {:?}",
+            name,
+            publish_timeout
+        ))
+    })??;
     Ok(confirmation)
 }
 
@@ -565,12 +745,50 @@ async fn ack_nack_pending_event(
     Ok(())
 }
 
+/// Replaces the current connection with a new one.
+///
+/// The previous connection is closed first, so its consumers do not receive any further messages.
+/// The config is built before, so an invalid config keeps the current connection.
+fn reconnect(
+    id: &InternalServerId,
+    sender_to_kernel: &BoxedSender,
+    config: &Config,
+    generation: &mut u64,
+    pending_deliveries: &Arc<Mutex<HashMap<String, PendingDelivery>>>,
+    connection_option: &mut Option<Connection>,
+    configuration_option: &mut Option<AmqpOptions>,
+) -> Result<()> {
+    let options = build_config(id, config)?;
+    if let Some(connection) = connection_option.take() {
+        close_connection(id, connection);
+    }
+    *configuration_option = None;
+    reconcile_pending_deliveries(id, pending_deliveries);
+    *generation += 1;
+    let (connection, configuration) = setup_connection(
+        id.clone(),
+        sender_to_kernel.clone_boxed(),
+        options,
+        *generation,
+        pending_deliveries.clone(),
+    )?;
+    *connection_option = Some(connection);
+    *configuration_option = Some(configuration);
+    Ok(())
+}
+
 fn check_health(
     event: HealthCheckRequest,
     send_to_kernel: &BoxedSender,
     connection: &Option<Connection>,
+    recovery: &Option<Recovery>,
 ) {
-    let status = if let Some(c) = connection {
+    let status = if let Some(recovery) = recovery {
+        HealthCheckStatus::Unhealthy(format!(
+            "Connection lost, {} reconnect attempts failed",
+            recovery.attempt
+        ))
+    } else if let Some(c) = connection {
         if c.status().connected() {
             HealthCheckStatus::Healthy
         } else {
@@ -594,87 +812,168 @@ pub fn port_amqp_start(id: InternalServerId, inbox: BoxedReceiver, sender_to_ker
     let pending_deliveries: PendingDeliveries = HashMap::new();
     let arc_pending_deliveries: Arc<Mutex<HashMap<String, PendingDelivery>>> =
         Arc::new(Mutex::new(pending_deliveries));
+    // the last config, it is used to reconnect
+    let mut config_option: Option<Config> = None;
+    let mut recovery: Option<Recovery> = None;
+    let mut generation: u64 = 0;
 
     info!("start amqp port with id {}", id);
 
     loop {
-        match inbox.receive() {
-            BrokerEvent::Init => {
-                info!("{} initiated", id);
-            }
-            BrokerEvent::ConfigUpdated(config, _) => {
-                info!("{} received ConfigUpdated", &id);
-                let result = setup_connection(
-                    id.clone(),
-                    sender_to_kernel.clone_boxed(),
-                    &connection_option,
-                    config,
-                    arc_pending_deliveries.clone(),
-                );
-                match result {
-                    Ok(as_ok) => {
-                        connection_option = Some(as_ok.0);
-                        configuration_option = Some(as_ok.1);
-                    }
-                    Err(e) => {
-                        warn!("{} was not able to establish a connection: {:?}", &id, e);
-                        connection_option = None;
-                        configuration_option = None;
+        if let Some(broker_event) = inbox.receive_timeout(Duration::from_millis(100)) {
+            match broker_event {
+                BrokerEvent::Init => {
+                    info!("{} initiated", id);
+                }
+                BrokerEvent::ConfigUpdated(config, _) => {
+                    info!("{} received ConfigUpdated", &id);
+                    if let Err(e) = build_config(&id, &config) {
+                        error!(
+                            "{} keeps the current connection, the config is invalid: {:?}",
+                            &id, e
+                        );
+                    } else {
+                        let result = reconnect(
+                            &id,
+                            &sender_to_kernel,
+                            &config,
+                            &mut generation,
+                            &arc_pending_deliveries,
+                            &mut connection_option,
+                            &mut configuration_option,
+                        );
+                        config_option = Some(config);
+                        match result {
+                            Ok(()) => recovery = None,
+                            Err(e) => {
+                                warn!("{} was not able to establish a connection: {:?}", &id, e);
+                                let mut r = Recovery::new();
+                                r.failed();
+                                recovery = Some(r);
+                            }
+                        }
                     }
                 }
-            }
-            BrokerEvent::OutgoingCloudEvent(event) => {
-                let OutgoingCloudEvent {
-                    routing_id,
-                    cloud_event,
-                    destination_id: _,
-                    args,
-                } = event;
-                debug!("{} CloudEvent received", &id);
-                if let Some(configuration) = configuration_option.as_ref() {
-                    let result = future::block_on(send_cloud_event(&cloud_event, configuration));
-                    let result = match result {
-                        Ok(_) => {
-                            info!("sent cloud event to queue");
-                            ProcessingResult::Successful
+                BrokerEvent::OutgoingCloudEvent(event) => {
+                    let OutgoingCloudEvent {
+                        routing_id,
+                        cloud_event,
+                        destination_id: _,
+                        args,
+                    } = event;
+                    debug!("{} CloudEvent received", &id);
+                    if recovery.is_some() {
+                        warn!(
+                            "{} received CloudEvent while reconnecting - message will not be delivered",
+                            &id
+                        );
+                        if args.delivery_guarantee.requires_acknowledgment() {
+                            sender_to_kernel.send(BrokerEvent::OutgoingCloudEventProcessed(
+                                OutgoingCloudEventProcessed {
+                                    sender_id: id.clone(),
+                                    routing_id,
+                                    result: ProcessingResult::TransientError,
+                                },
+                            ));
                         }
-                        Err(e) => {
-                            error!("{} was not able to send CloudEvent {}", &id, e);
-                            // todo transient or permanent?
-                            ProcessingResult::PermanentError
+                    } else if let Some(configuration) = configuration_option.as_ref() {
+                        let result =
+                            future::block_on(send_cloud_event(&cloud_event, configuration));
+                        let result = match result {
+                            Ok(_) => {
+                                info!("sent cloud event to queue");
+                                ProcessingResult::Successful
+                            }
+                            Err(e) => {
+                                error!("{} was not able to send CloudEvent {}", &id, e);
+                                e.processing_result()
+                            }
+                        };
+                        if args.delivery_guarantee.requires_acknowledgment() {
+                            sender_to_kernel.send(BrokerEvent::OutgoingCloudEventProcessed(
+                                OutgoingCloudEventProcessed {
+                                    sender_id: id.clone(),
+                                    routing_id,
+                                    result,
+                                },
+                            ));
+                        }
+                    } else {
+                        error!(
+                            "{} received CloudEvent before connection was set up - message will not be delivered",
+                            &id
+                        );
+                        // the event can be delivered as soon as a valid config is received
+                        if args.delivery_guarantee.requires_acknowledgment() {
+                            sender_to_kernel.send(BrokerEvent::OutgoingCloudEventProcessed(
+                                OutgoingCloudEventProcessed {
+                                    sender_id: id.clone(),
+                                    routing_id,
+                                    result: ProcessingResult::TransientError,
+                                },
+                            ));
                         }
-                    };
-                    if args.delivery_guarantee.requires_acknowledgment() {
-                        sender_to_kernel.send(BrokerEvent::OutgoingCloudEventProcessed(
-                            OutgoingCloudEventProcessed {
-                                sender_id: id.clone(),
-                                routing_id,
-                                result,
-                            },
-                        ));
                     }
-                } else {
-                    error!("received CloudEvent before connection was  set up - message will not be delivered")
                 }
+                BrokerEvent::IncomingCloudEventProcessed(event_id, result) => {
+                    let mut pending_deliveries = arc_pending_deliveries.lock().unwrap();
+                    let result = future::block_on(ack_nack_pending_event(
+                        &configuration_option,
+                        &mut pending_deliveries,
+                        &event_id,
+                        result,
+                    ));
+                    match result {
+                        Ok(()) => debug!("IncomingCloudEventProcessed was ack/nack successful"),
+                        Err(err) => warn!("IncomingCloudEventProcessed was not ack/nack {:?}", err),
+                    };
+                    pending_deliveries.remove_entry(&event_id);
+                }
+                BrokerEvent::HealthCheckRequest(event) => {
+                    check_health(event, &sender_to_kernel, &connection_option, &recovery)
+                }
+                broker_event => warn!("event {} not implemented", broker_event),
             }
-            BrokerEvent::IncomingCloudEventProcessed(event_id, result) => {
-                let mut pending_deliveries = arc_pending_deliveries.lock().unwrap();
-                let result = future::block_on(ack_nack_pending_event(
-                    &configuration_option,
-                    &mut pending_deliveries,
-                    &event_id,
-                    result,
-                ));
-                match result {
-                    Ok(()) => debug!("IncomingCloudEventProcessed was ack/nack successful"),
-                    Err(err) => warn!("IncomingCloudEventProcessed was not ack/nack {:?}", err),
-                };
-                pending_deliveries.remove_entry(&event_id);
+        }
+
+        if recovery.is_none() {
+            if let (Some(connection), Some(configuration)) =
+                (connection_option.as_ref(), configuration_option.as_ref())
+            {
+                if !is_connected(connection, configuration) {
+                    warn!("{} lost the connection, start to reconnect", &id);
+                    recovery = Some(Recovery::new());
+                }
             }
-            BrokerEvent::HealthCheckRequest(event) => {
-                check_health(event, &sender_to_kernel, &connection_option)
+        }
+        if let (Some(r), Some(config)) = (recovery.as_mut(), config_option.as_ref()) {
+            if r.is_due() {
+                let result = reconnect(
+                    &id,
+                    &sender_to_kernel,
+                    config,
+                    &mut generation,
+                    &arc_pending_deliveries,
+                    &mut connection_option,
+                    &mut configuration_option,
+                );
+                match result {
+                    Ok(()) => {
+                        info!("{} reconnected after {} failed attempts", &id, r.attempt);
+                        recovery = None;
+                    }
+                    Err(e) => {
+                        r.failed();
+                        warn!(
+                            "{} reconnect attempt {} failed, retry in {:?}: {:?}",
+                            &id,
+                            r.attempt,
+                            Recovery::backoff(r.attempt),
+                            e
+                        );
+                    }
+                }
             }
-            broker_event => warn!("event {} not implemented", broker_event),
         }
     }
 }
@@ -685,6 +984,10 @@ pub static PORT_AMQP: InternalServerFnRefStatic = &(port_amqp_start as InternalS
 #[cfg(test)]
 mod tests {
     use super::*;
+    use cerk_router_rule_based::CloudEventFields;
+    use cerk_runtime_threading::channel::new_channel_with_size;
+    use cloudevents::{EventBuilder, EventBuilderV10};
+    use std::thread;
 
     #[test]
     fn minimal_config() -> Result<()> {
@@ -694,7 +997,203 @@ mod tests {
             &"an-id".to_string(),
             &Config::HashMap(map.iter().cloned().collect()),
         )?;
-        assert_eq!(config.uri, uri);
+        assert_eq!(config.connection.uri.authority.host, "127.0.0.1");
+        assert_eq!(config.connection.uri.authority.port, 5672);
         Ok(())
     }
+
+    #[test]
+    fn publish_channel_routing_config() -> Result<()> {
+        let publisher = [
+            ("name".to_string(), Config::String("events".to_string())),
+            (
+                "exchange_type".to_string(),
+                Config::String("topic".to_string()),
+            ),
+            (
+                "routing_key".to_string(),
+                Config::String("{type}.{source}".to_string()),
+            ),
+            (
+                "filter".to_string(),
+                Config::String(r#"{"Exact":["Type","created"]}"#.to_string()),
+            ),
+            (
+                "content_mode".to_string(),
+                Config::String("binary".to_string()),
+            ),
+        ];
+        let consumer = [
+            ("name".to_string(), Config::String("created".to_string())),
+            (
+                "bind_to_exchange".to_string(),
+                Config::String("events".to_string()),
+            ),
+            (
+                "binding_key".to_string(),
+                Config::String("created.#".to_string()),
+            ),
+        ];
+        let map = [
+            (
+                "uri".to_string(),
+                Config::String("amqp://127.0.0.1:5672/%2f".to_string()),
+            ),
+            (
+                "publish_channels".to_string(),
+                Config::Vec(vec![Config::HashMap(publisher.iter().cloned().collect())]),
+            ),
+            (
+                "consume_channels".to_string(),
+                Config::Vec(vec![Config::HashMap(consumer.iter().cloned().collect())]),
+            ),
+        ];
+        let config = build_config(
+            &"an-id".to_string(),
+            &Config::HashMap(map.iter().cloned().collect()),
+        )?;
+
+        let publisher = &config.publish_channels["events"];
+        assert_eq!(publisher.exchange_kind, ExchangeKind::Topic);
+        assert_eq!(publisher.routing_key, "{type}.{source}");
+        assert_eq!(
+            publisher.filter,
+            Some(RoutingRules::Exact(
+                CloudEventFields::Type,
+                Some("created".to_string())
+            ))
+        );
+        assert_eq!(publisher.content_mode, ContentMode::Binary);
+        assert_eq!(config.consume_channels["created"].binding_key, "created.#");
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_publish_channel_config() {
+        let build = |key: &str, value: &str| {
+            let publisher = [
+                ("name".to_string(), Config::String("events".to_string())),
+                (key.to_string(), Config::String(value.to_string())),
+            ];
+            let map = [
+                (
+                    "uri".to_string(),
+                    Config::String("amqp://127.0.0.1:5672/%2f".to_string()),
+                ),
+                (
+                    "publish_channels".to_string(),
+                    Config::Vec(vec![Config::HashMap(publisher.iter().cloned().collect())]),
+                ),
+            ];
+            build_config(
+                &"an-id".to_string(),
+                &Config::HashMap(map.iter().cloned().collect()),
+            )
+        };
+        assert!(build("exchange_type", "x-unknown").is_err());
+        assert!(build("filter", "{}").is_err());
+        assert!(build("content_mode", "batched").is_err());
+    }
+
+    #[test]
+    fn reconnect_backoff() {
+        assert_eq!(Recovery::backoff(1), Duration::from_millis(500));
+        assert_eq!(Recovery::backoff(2), Duration::from_secs(1));
+        assert_eq!(Recovery::backoff(4), Duration::from_secs(4));
+        assert_eq!(Recovery::backoff(7), RECONNECT_MAX_BACKOFF);
+        assert_eq!(Recovery::backoff(u32::MAX), RECONNECT_MAX_BACKOFF);
+    }
+
+    #[test]
+    fn unhealthy_while_reconnecting() {
+        let (send_to_port, recv) = new_channel_with_size(1);
+        let (send, recv_from_port) = new_channel_with_size(1);
+        thread::spawn(move || {
+            PORT_AMQP("amqp".to_string(), recv, send);
+        });
+        // nothing listens on the discard port
+        let map = [(
+            "uri".to_string(),
+            Config::String("amqp://127.0.0.1:9/%2f".to_string()),
+        )];
+        send_to_port.send(BrokerEvent::ConfigUpdated(
+            Config::HashMap(map.iter().cloned().collect()),
+            "amqp".to_string(),
+        ));
+
+        send_to_port.send(BrokerEvent::HealthCheckRequest(HealthCheckRequest {
+            id: "1".to_string(),
+            sender_id: "test".to_string(),
+            destination_id: "amqp".to_string(),
+        }));
+        match recv_from_port.receive_timeout(Duration::from_secs(5)) {
+            Some(BrokerEvent::HealthCheckResponse(response)) => match response.status {
+                HealthCheckStatus::Unhealthy(reason) => assert!(reason.contains("reconnect")),
+                status => panic!("expected Unhealthy, got {:?}", status),
+            },
+            _ => panic!("expected HealthCheckResponse"),
+        }
+
+        send_to_port.send(BrokerEvent::OutgoingCloudEvent(OutgoingCloudEvent {
+            routing_id: "1".to_string(),
+            cloud_event: EventBuilderV10::new()
+                .id("1")
+                .ty("test")
+                .source("http://example.com")
+                .build()
+                .unwrap(),
+            destination_id: "amqp".to_string(),
+            args: CloudEventRoutingArgs {
+                delivery_guarantee: DeliveryGuarantee::AtLeastOnce,
+            },
+        }));
+        match recv_from_port.receive_timeout(Duration::from_secs(5)) {
+            Some(BrokerEvent::OutgoingCloudEventProcessed(processed)) => {
+                assert_eq!(processed.result, ProcessingResult::TransientError)
+            }
+            _ => panic!("expected OutgoingCloudEventProcessed"),
+        }
+    }
+
+    #[test]
+    fn invalid_config_does_not_stop_the_port() {
+        let (send_to_port, recv) = new_channel_with_size(1);
+        let (send, recv_from_port) = new_channel_with_size(1);
+        thread::spawn(move || {
+            PORT_AMQP("amqp".to_string(), recv, send);
+        });
+        send_to_port.send(BrokerEvent::ConfigUpdated(Config::Null, "amqp".to_string()));
+        send_to_port.send(BrokerEvent::OutgoingCloudEvent(OutgoingCloudEvent {
+            routing_id: "1".to_string(),
+            cloud_event: EventBuilderV10::new()
+                .id("1")
+                .ty("test")
+                .source("http://example.com")
+                .build()
+                .unwrap(),
+            destination_id: "amqp".to_string(),
+            args: CloudEventRoutingArgs {
+                delivery_guarantee: DeliveryGuarantee::AtLeastOnce,
+            },
+        }));
+        match recv_from_port.receive_timeout(Duration::from_secs(5)) {
+            Some(BrokerEvent::OutgoingCloudEventProcessed(processed)) => {
+                assert_eq!(processed.result, ProcessingResult::TransientError)
+            }
+            _ => panic!("expected OutgoingCloudEventProcessed"),
+        }
+
+        send_to_port.send(BrokerEvent::HealthCheckRequest(HealthCheckRequest {
+            id: "1".to_string(),
+            sender_id: "test".to_string(),
+            destination_id: "amqp".to_string(),
+        }));
+        match recv_from_port.receive_timeout(Duration::from_secs(5)) {
+            Some(BrokerEvent::HealthCheckResponse(response)) => assert_eq!(
+                response.status,
+                HealthCheckStatus::Unhealthy("Not connected".to_string())
+            ),
+            _ => panic!("expected HealthCheckResponse"),
+        }
+    }
 }
@@ -0,0 +1,132 @@
+use amq_protocol::protocol::{AMQPErrorKind, AMQPSoftError};
+use cerk::kernel::ProcessingResult;
+use lapin::publisher_confirm::Confirmation;
+use std::fmt;
+
+/// The reason why an event could not be published.
+#[derive(Debug)]
+pub enum PublishError {
+    /// publishing the event again can succeed, e.g., after a reconnect
+    Transient(anyhow::Error),
+    /// publishing the event again fails as well, e.g., because it is unroutable
+    Permanent(anyhow::Error),
+}
+
+impl PublishError {
+    pub fn processing_result(&self) -> ProcessingResult {
+        match self {
+            PublishError::Transient(_) => ProcessingResult::TransientError,
+            PublishError::Permanent(_) => ProcessingResult::PermanentError,
+        }
+    }
+}
+
+impl fmt::Display for PublishError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PublishError::Transient(e) => write!(f, "transient error: {:#}", e),
+            PublishError::Permanent(e) => write!(f, "permanent error: {:#}", e),
+        }
+    }
+}
+
+impl From<lapin::Error> for PublishError {
+    /// Errors of the connection or the channel are transient,
+    /// errors which are caused by the message itself are permanent.
+    fn from(error: lapin::Error) -> Self {
+        let permanent = match &error {
+            lapin::Error::SerialisationError(_) => true,
+            lapin::Error::ProtocolError(e) => matches!(
+                e.kind(),
+                AMQPErrorKind::Soft(AMQPSoftError::CONTENTTOOLARGE)
+                    | AMQPErrorKind::Soft(AMQPSoftError::ACCESSREFUSED)
+            ),
+            _ => false,
+        };
+        if permanent {
+            PublishError::Permanent(error.into())
+        } else {
+            PublishError::Transient(error.into())
+        }
+    }
+}
+
+/// Checks the confirmation of the broker.
+///
+/// Messages which the broker returns are unroutable, because they are published as `mandatory`.
+/// Without `requires_acknowledgment`, the channel is not in confirm mode
+/// and a missing confirmation is accepted.
+pub fn check_confirmation(
+    confirmation: Confirmation,
+    requires_acknowledgment: bool,
+) -> Result<(), PublishError> {
+    match confirmation {
+        Confirmation::Ack(Some(returned)) | Confirmation::Nack(Some(returned)) => {
+            Err(PublishError::Permanent(anyhow!(
+                "the message is unroutable: {} {}",
+                returned.reply_code,
+                returned.reply_text
+            )))
+        }
+        Confirmation::Ack(None) => Ok(()),
+        _ if !requires_acknowledgment => Ok(()),
+        Confirmation::Nack(None) => Err(PublishError::Transient(anyhow!(
+            "the broker did not acknowledge the message"
+        ))),
+        Confirmation::NotRequested => Err(PublishError::Transient(anyhow!(
+            "the message was not confirmed, but the delivery guarantee requires it"
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use amq_protocol::protocol::AMQPError;
+    use std::io;
+    use std::sync::Arc;
+
+    #[test]
+    fn classify_lapin_errors() {
+        let error = |kind| lapin::Error::ProtocolError(AMQPError::new(kind, "error".into()));
+        for (error, result) in [
+            (
+                lapin::Error::IOError(Arc::new(io::Error::from(io::ErrorKind::BrokenPipe))),
+                ProcessingResult::TransientError,
+            ),
+            (
+                lapin::Error::InvalidChannelState(lapin::ChannelState::Closed),
+                ProcessingResult::TransientError,
+            ),
+            (
+                error(AMQPErrorKind::Soft(AMQPSoftError::NOTFOUND)),
+                ProcessingResult::TransientError,
+            ),
+            (
+                error(AMQPErrorKind::Soft(AMQPSoftError::CONTENTTOOLARGE)),
+                ProcessingResult::PermanentError,
+            ),
+            (
+                error(AMQPErrorKind::Soft(AMQPSoftError::ACCESSREFUSED)),
+                ProcessingResult::PermanentError,
+            ),
+        ] {
+            assert_eq!(PublishError::from(error).processing_result(), result);
+        }
+    }
+
+    #[test]
+    fn check_confirmations() {
+        assert!(check_confirmation(Confirmation::Ack(None), true).is_ok());
+        assert!(check_confirmation(Confirmation::NotRequested, false).is_ok());
+        assert!(check_confirmation(Confirmation::Nack(None), false).is_ok());
+        for confirmation in [Confirmation::Nack(None), Confirmation::NotRequested] {
+            assert_eq!(
+                check_confirmation(confirmation, true)
+                    .unwrap_err()
+                    .processing_result(),
+                ProcessingResult::TransientError
+            );
+        }
+    }
+}
@@ -0,0 +1,287 @@
+use amq_protocol_types::{AMQPValue, FieldTable, LongString, ShortString};
+use anyhow::{Context, Result};
+use cerk::kernel::{Config, ConfigHelpers};
+
+/// The delivery limit of dead-lettered queues, if none is configured.
+pub const DEFAULT_DELIVERY_LIMIT: u32 = 3;
+
+/// The dead letter exchange and queue which are declared by the port.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeadLetterOptions {
+    /// the name of the fanout exchange
+    pub exchange: String,
+    /// the name of the queue which is bound to the exchange
+    pub queue: String,
+}
+
+/// The options to declare a consumed queue.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueueOptions {
+    /// the dead letter exchange and queue which have to be declared
+    pub dead_letter: Option<DeadLetterOptions>,
+    /// the `x-` arguments of the queue
+    pub arguments: FieldTable,
+    /// delete and declare the queue again, if it exists with other arguments
+    pub recreate: bool,
+}
+
+fn insert_u32(
+    arguments: &mut FieldTable,
+    config: &Config,
+    key: &'static str,
+    argument: &str,
+) -> Result<()> {
+    if let Some(value) = config
+        .get_op_val_u32(key)
+        .with_context(|| format!("{} has to be of type u32", key))?
+    {
+        arguments.insert(
+            ShortString::from(argument),
+            AMQPValue::LongLongInt(value as i64),
+        );
+    }
+    Ok(())
+}
+
+fn to_argument_value(name: &str, value: &Config) -> Result<AMQPValue> {
+    Ok(match value {
+        Config::String(s) => AMQPValue::LongString(LongString::from(s.as_str())),
+        Config::Bool(b) => AMQPValue::Boolean(*b),
+        Config::U8(v) => AMQPValue::LongLongInt(*v as i64),
+        Config::U32(v) => AMQPValue::LongLongInt(*v as i64),
+        _ => bail!("queue argument {} has an unsupported type", name),
+    })
+}
+
+/// Reads the dead-lettering and the `x-` arguments of a consume channel.
+///
+/// `ensure_dlx` defaults to `ensure_queue`, the dead letter exchange defaults to `{name}-dlx`
+/// and the dead letter queue to the name of the dead letter exchange.
+/// The arguments of `queue_arguments` override the ones derived from the other options.
+pub fn try_get_queue_options(
+    name: &str,
+    config: &Config,
+    ensure_queue: bool,
+) -> Result<QueueOptions> {
+    let mut arguments = FieldTable::default();
+
+    let recreate = match config.get_op_val_config("recreate_queue")? {
+        Some(Config::Bool(b)) => *b,
+        Some(_) => bail!("recreate_queue has to be of type Bool"),
+        None => false,
+    };
+    if recreate && !ensure_queue {
+        bail!("recreate_queue requires ensure_queue");
+    }
+
+    let ensure_dlx = match config.get_op_val_config("ensure_dlx")? {
+        Some(Config::Bool(b)) => *b,
+        Some(_) => bail!("ensure_dlx has to be of type Bool"),
+        None => ensure_queue,
+    };
+    let dlx_exchange = config
+        .get_op_val_string("dlx_exchange")
+        .context("dlx_exchange has to be of type String")?;
+    let dlx_queue = config
+        .get_op_val_string("dlx_queue")
+        .context("dlx_queue has to be of type String")?;
+    // without ensure_dlx, the configured dead letter exchange has to exist already
+    let (dead_letter, dead_letter_exchange) = if ensure_dlx {
+        let exchange = dlx_exchange.unwrap_or_else(|| format!("{}-dlx", name));
+        let dead_letter = DeadLetterOptions {
+            queue: dlx_queue.unwrap_or_else(|| exchange.clone()),
+            exchange: exchange.clone(),
+        };
+        (Some(dead_letter), Some(exchange))
+    } else if dlx_queue.is_some() {
+        bail!("dlx_queue requires ensure_dlx")
+    } else {
+        (None, dlx_exchange)
+    };
+    if let Some(exchange) = dead_letter_exchange {
+        arguments.insert(
+            ShortString::from("x-dead-letter-exchange"),
+            AMQPValue::LongString(LongString::from(exchange)),
+        );
+        let delivery_limit = config
+            .get_op_val_u32("delivery_limit")
+            .context("delivery_limit has to be of type u32")?
+            .unwrap_or(DEFAULT_DELIVERY_LIMIT);
+        arguments.insert(
+            ShortString::from("x-delivery-limit"),
+            AMQPValue::LongLongInt(delivery_limit as i64),
+        );
+    } else {
+        insert_u32(&mut arguments, config, "delivery_limit", "x-delivery-limit")?;
+    }
+
+    if let Some(queue_type) = config
+        .get_op_val_string("queue_type")
+        .context("queue_type has to be of type String")?
+    {
+        match queue_type.as_str() {
+            "classic" | "quorum" => arguments.insert(
+                ShortString::from("x-queue-type"),
+                AMQPValue::LongString(LongString::from(queue_type)),
+            ),
+            _ => bail!("queue_type {} is not supported", queue_type),
+        }
+    }
+    insert_u32(&mut arguments, config, "message_ttl", "x-message-ttl")?;
+    insert_u32(&mut arguments, config, "expires", "x-expires")?;
+    insert_u32(&mut arguments, config, "max_length", "x-max-length")?;
+    insert_u32(
+        &mut arguments,
+        config,
+        "max_length_bytes",
+        "x-max-length-bytes",
+    )?;
+
+    match config.get_op_val_config("queue_arguments")? {
+        Some(Config::HashMap(queue_arguments)) => {
+            for (argument, value) in queue_arguments.iter() {
+                if !argument.starts_with("x-") {
+                    bail!("queue argument {} does not start with x-", argument);
+                }
+                arguments.insert(
+                    ShortString::from(argument.as_str()),
+                    to_argument_value(argument, value)?,
+                );
+            }
+        }
+        Some(_) => bail!("queue_arguments has to be of type HashMap"),
+        None => {}
+    }
+
+    Ok(QueueOptions {
+        dead_letter,
+        arguments,
+        recreate,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(entries: Vec<(&str, Config)>) -> Config {
+        Config::HashMap(
+            entries
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), value))
+                .collect(),
+        )
+    }
+
+    fn argument<'a>(options: &'a QueueOptions, name: &str) -> Option<&'a AMQPValue> {
+        options.arguments.inner().get(name)
+    }
+
+    #[test]
+    fn default_dead_lettering() -> Result<()> {
+        let options = try_get_queue_options("orders", &config(vec![]), true)?;
+        assert_eq!(
+            options.dead_letter,
+            Some(DeadLetterOptions {
+                exchange: "orders-dlx".to_string(),
+                queue: "orders-dlx".to_string(),
+            })
+        );
+        assert_eq!(
+            argument(&options, "x-dead-letter-exchange"),
+            Some(&AMQPValue::LongString("orders-dlx".into()))
+        );
+        assert_eq!(
+            argument(&options, "x-delivery-limit"),
+            Some(&AMQPValue::LongLongInt(3))
+        );
+
+        let options = try_get_queue_options("orders", &config(vec![]), false)?;
+        assert_eq!(
+            options,
+            QueueOptions {
+                dead_letter: None,
+                arguments: FieldTable::default(),
+                recreate: false,
+            }
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn configured_arguments() -> Result<()> {
+        let queue_arguments = config(vec![
+            ("x-overflow", Config::String("reject-publish".to_string())),
+            ("x-message-ttl", Config::U32(1000)),
+        ]);
+        let options = try_get_queue_options(
+            "orders",
+            &config(vec![
+                ("ensure_dlx", Config::Bool(false)),
+                ("dlx_exchange", Config::String("dead".to_string())),
+                ("delivery_limit", Config::U8(5)),
+                ("queue_type", Config::String("quorum".to_string())),
+                ("message_ttl", Config::U32(60000)),
+                ("max_length", Config::U32(100)),
+                ("queue_arguments", queue_arguments),
+            ]),
+            true,
+        )?;
+        assert_eq!(options.dead_letter, None);
+        for (name, value) in [
+            (
+                "x-dead-letter-exchange",
+                AMQPValue::LongString("dead".into()),
+            ),
+            ("x-delivery-limit", AMQPValue::LongLongInt(5)),
+            ("x-queue-type", AMQPValue::LongString("quorum".into())),
+            ("x-message-ttl", AMQPValue::LongLongInt(1000)),
+            ("x-max-length", AMQPValue::LongLongInt(100)),
+            ("x-overflow", AMQPValue::LongString("reject-publish".into())),
+        ] {
+            assert_eq!(argument(&options, name), Some(&value), "{}", name);
+        }
+        assert_eq!(argument(&options, "x-expires"), None);
+        assert!(!options.recreate);
+
+        let options = try_get_queue_options(
+            "orders",
+            &config(vec![
+                ("dlx_exchange", Config::String("dead".to_string())),
+                ("dlx_queue", Config::String("dead-orders".to_string())),
+                ("recreate_queue", Config::Bool(true)),
+            ]),
+            true,
+        )?;
+        assert!(options.recreate);
+        assert_eq!(
+            options.dead_letter,
+            Some(DeadLetterOptions {
+                exchange: "dead".to_string(),
+                queue: "dead-orders".to_string(),
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_arguments() {
+        let build = |key: &str, value: Config| {
+            try_get_queue_options("orders", &config(vec![(key, value)]), false)
+        };
+        assert!(build("queue_type", Config::String("stream-ish".to_string())).is_err());
+        assert!(build("message_ttl", Config::String("1s".to_string())).is_err());
+        assert!(build("dlx_queue", Config::String("dead".to_string())).is_err());
+        assert!(build("recreate_queue", Config::Bool(true)).is_err());
+        assert!(build(
+            "queue_arguments",
+            config(vec![("overflow", Config::String("drop-head".to_string()))])
+        )
+        .is_err());
+        assert!(build(
+            "queue_arguments",
+            config(vec![("x-args", Config::Vec(vec![]))])
+        )
+        .is_err());
+    }
+}
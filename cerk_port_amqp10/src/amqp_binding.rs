@@ -0,0 +1,267 @@
+use crate::codec::AmqpValue;
+use anyhow::{Context, Result};
+pub use cerk_port_common::content_mode::ContentMode;
+use chrono::{TimeZone, Utc};
+use cloudevents::binding::http::to_event;
+use cloudevents::Event;
+use http::header::{HeaderName, HeaderValue, CONTENT_TYPE};
+use http::{HeaderMap, Request};
+use std::convert::TryFrom;
+use uuid::Uuid;
+
+const AMQP_PROPERTY_PREFIX: &str = "cloudEvents:";
+/// The prefix of the later binding versions, which is also valid in JMS selectors.
+const AMQP_PROPERTY_PREFIX_UNDERSCORE: &str = "cloudEvents_";
+const HTTP_HEADER_PREFIX: &str = "ce-";
+const STRUCTURED_CONTENT_TYPE: &str = "application/cloudevents+json; charset=utf-8";
+
+const HEADER: u64 = 0x70;
+const DELIVERY_ANNOTATIONS: u64 = 0x71;
+const MESSAGE_ANNOTATIONS: u64 = 0x72;
+const PROPERTIES: u64 = 0x73;
+const APPLICATION_PROPERTIES: u64 = 0x74;
+const DATA: u64 = 0x75;
+const AMQP_VALUE: u64 = 0x77;
+const FOOTER: u64 = 0x78;
+/// The position of the `content-type` field in the properties section.
+const CONTENT_TYPE_FIELD: usize = 6;
+
+/// The parts of an AMQP message which are used by the binding.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AmqpMessage {
+    pub content_type: Option<String>,
+    pub application_properties: Vec<(String, AmqpValue)>,
+    pub body: Vec<u8>,
+}
+
+impl AmqpMessage {
+    /// Encodes the message as durable message with a single data section.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        AmqpValue::described(HEADER, AmqpValue::List(vec![AmqpValue::Bool(true)])).encode(&mut buf);
+        if let Some(ref content_type) = self.content_type {
+            let mut properties = vec![AmqpValue::Null; CONTENT_TYPE_FIELD];
+            properties.push(AmqpValue::Symbol(content_type.clone()));
+            AmqpValue::described(PROPERTIES, AmqpValue::List(properties)).encode(&mut buf);
+        }
+        if !self.application_properties.is_empty() {
+            let properties = self
+                .application_properties
+                .iter()
+                .map(|(name, value)| (AmqpValue::String(name.clone()), value.clone()))
+                .collect();
+            AmqpValue::described(APPLICATION_PROPERTIES, AmqpValue::Map(properties))
+                .encode(&mut buf);
+        }
+        AmqpValue::described(DATA, AmqpValue::Binary(self.body.clone())).encode(&mut buf);
+        buf
+    }
+
+    /// Decodes the sections of a message, the body has to consist of data sections or a binary or string value.
+    pub fn decode(mut buf: &[u8]) -> Result<AmqpMessage> {
+        let mut message = AmqpMessage {
+            content_type: None,
+            application_properties: vec![],
+            body: vec![],
+        };
+        while !buf.is_empty() {
+            let section = AmqpValue::decode(&mut buf)?;
+            let (code, value) = match section {
+                AmqpValue::Described(ref descriptor, ref value) => (
+                    descriptor.as_u64().context("invalid section descriptor")?,
+                    value.as_ref(),
+                ),
+                value => bail!("expected message section, but received {:?}", value),
+            };
+            match (code, value) {
+                (HEADER, _)
+                | (DELIVERY_ANNOTATIONS, _)
+                | (MESSAGE_ANNOTATIONS, _)
+                | (FOOTER, _) => {}
+                (PROPERTIES, AmqpValue::List(fields)) => {
+                    message.content_type = fields
+                        .get(CONTENT_TYPE_FIELD)
+                        .and_then(|c| c.as_str())
+                        .map(|c| c.to_string());
+                }
+                (APPLICATION_PROPERTIES, AmqpValue::Map(properties)) => {
+                    for (name, value) in properties {
+                        let name = name.as_str().context("application property without name")?;
+                        message
+                            .application_properties
+                            .push((name.to_string(), value.clone()));
+                    }
+                }
+                (DATA, AmqpValue::Binary(data)) => message.body.extend_from_slice(data),
+                (AMQP_VALUE, AmqpValue::Binary(data)) => message.body = data.clone(),
+                (AMQP_VALUE, AmqpValue::String(data)) => message.body = data.clone().into_bytes(),
+                (code, _) => bail!("unsupported message section {:#x}", code),
+            }
+        }
+        Ok(message)
+    }
+}
+
+/// Converts an application property to the string representation of the attribute.
+fn attribute_value(value: &AmqpValue) -> Result<String> {
+    Ok(match value {
+        AmqpValue::String(s) | AmqpValue::Symbol(s) => s.clone(),
+        AmqpValue::Bool(v) => v.to_string(),
+        AmqpValue::Ubyte(_) | AmqpValue::Ushort(_) | AmqpValue::Uint(_) | AmqpValue::Ulong(_) => {
+            value.as_u64().unwrap_or_default().to_string()
+        }
+        AmqpValue::Byte(v) => v.to_string(),
+        AmqpValue::Short(v) => v.to_string(),
+        AmqpValue::Int(v) => v.to_string(),
+        AmqpValue::Long(v) => v.to_string(),
+        AmqpValue::Timestamp(ms) => Utc
+            .timestamp_millis_opt(*ms)
+            .single()
+            .context("invalid timestamp")?
+            .to_rfc3339(),
+        AmqpValue::Uuid(bytes) => Uuid::from_bytes(*bytes).to_string(),
+        value => bail!("unsupported attribute value {:?}", value),
+    })
+}
+
+/// Converts the event to an AMQP message according to the [AMQP protocol binding](https://github.com/cloudevents/spec/blob/master/amqp-protocol-binding.md).
+///
+/// The binary content mode reuses the HTTP binding, the `ce-` headers become `cloudEvents:` application properties.
+pub fn to_message(event: Event, content_mode: ContentMode) -> Result<AmqpMessage> {
+    match content_mode {
+        ContentMode::Binary => {
+            let request = Request::<Option<Vec<u8>>>::try_from(event)
+                .map_err(|e| anyhow!("failed to serialize CloudEvent: {}", e))?;
+            let (parts, body) = request.into_parts();
+            let mut message = AmqpMessage {
+                content_type: None,
+                application_properties: vec![],
+                body: body.unwrap_or_default(),
+            };
+            for (name, value) in parts.headers.iter() {
+                let value = value.to_str()?.to_string();
+                match name.as_str().strip_prefix(HTTP_HEADER_PREFIX) {
+                    Some(attribute) => message.application_properties.push((
+                        format!("{}{}", AMQP_PROPERTY_PREFIX, attribute),
+                        AmqpValue::String(value),
+                    )),
+                    None if name == CONTENT_TYPE => message.content_type = Some(value),
+                    None => {}
+                }
+            }
+            Ok(message)
+        }
+        ContentMode::Structured => Ok(AmqpMessage {
+            content_type: Some(STRUCTURED_CONTENT_TYPE.to_string()),
+            application_properties: vec![],
+            body: serde_json::to_vec(&event)?,
+        }),
+    }
+}
+
+/// Converts an AMQP message in binary or structured content mode to an event.
+pub fn from_message(message: &AmqpMessage) -> Result<Event> {
+    let mut header_map = HeaderMap::new();
+    if let Some(ref content_type) = message.content_type {
+        header_map.insert(CONTENT_TYPE, HeaderValue::from_str(content_type)?);
+    }
+    for (name, value) in message.application_properties.iter() {
+        let attribute = name
+            .strip_prefix(AMQP_PROPERTY_PREFIX)
+            .or_else(|| name.strip_prefix(AMQP_PROPERTY_PREFIX_UNDERSCORE));
+        if let Some(attribute) = attribute {
+            header_map.insert(
+                HeaderName::from_bytes(format!("{}{}", HTTP_HEADER_PREFIX, attribute).as_bytes())?,
+                HeaderValue::from_str(&attribute_value(value)?)?,
+            );
+        }
+    }
+    to_event(&header_map, message.body.clone()).context("failed to parse CloudEvent")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cloudevents::{AttributesReader, EventBuilder, EventBuilderV10};
+
+    fn event() -> Event {
+        EventBuilderV10::new()
+            .id("1")
+            .ty("test")
+            .source("http://example.com")
+            .extension("tenant", "a")
+            .data("text/plain", b"hello".to_vec())
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn binary_round_trip() -> Result<()> {
+        let message = to_message(event(), ContentMode::Binary)?;
+        assert_eq!(message.content_type, Some("text/plain".to_string()));
+        assert_eq!(message.body, b"hello".to_vec());
+        assert!(message.application_properties.contains(&(
+            "cloudEvents:id".to_string(),
+            AmqpValue::String("1".to_string())
+        )));
+        let decoded = AmqpMessage::decode(&message.encode())?;
+        assert_eq!(decoded, message);
+        assert_eq!(from_message(&decoded)?, event());
+        Ok(())
+    }
+
+    #[test]
+    fn structured_round_trip() -> Result<()> {
+        let message = to_message(event(), ContentMode::Structured)?;
+        assert!(message.application_properties.is_empty());
+        assert_eq!(
+            from_message(&AmqpMessage::decode(&message.encode())?)?,
+            event()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn typed_application_properties() -> Result<()> {
+        let message = AmqpMessage {
+            content_type: None,
+            application_properties: vec![
+                (
+                    "cloudEvents_specversion".to_string(),
+                    AmqpValue::String("1.0".to_string()),
+                ),
+                ("cloudEvents_id".to_string(), AmqpValue::Ulong(7)),
+                (
+                    "cloudEvents_type".to_string(),
+                    AmqpValue::Symbol("test".to_string()),
+                ),
+                (
+                    "cloudEvents_source".to_string(),
+                    AmqpValue::String("http://example.com".to_string()),
+                ),
+                (
+                    "cloudEvents_time".to_string(),
+                    AmqpValue::Timestamp(1_600_000_000_000),
+                ),
+                ("other".to_string(), AmqpValue::Binary(vec![1])),
+            ],
+            body: vec![],
+        };
+        let event = from_message(&message)?;
+        assert_eq!(event.id(), "7");
+        assert_eq!(
+            event.time().map(|t| t.timestamp_millis()),
+            Some(1_600_000_000_000)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_message() {
+        let mut body = Vec::new();
+        AmqpValue::described(DATA, AmqpValue::Binary(b"hello".to_vec())).encode(&mut body);
+        let message = AmqpMessage::decode(&body).unwrap();
+        assert!(from_message(&message).is_err());
+        assert!(AmqpMessage::decode(&[0x41]).is_err());
+    }
+}
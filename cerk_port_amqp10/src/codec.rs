@@ -0,0 +1,579 @@
+use anyhow::{Context, Result};
+use std::convert::TryInto;
+
+/// The max nesting of described and compound values, so a forged value can not overflow the stack.
+const MAX_DEPTH: usize = 32;
+/// The max count of an array of values without any bytes, e.g., nulls, as it is not limited by the size of the array.
+const MAX_EMPTY_VALUES: usize = 255;
+
+/// A value of the [AMQP 1.0 type system](http://docs.oasis-open.org/amqp/core/v1.0/os/amqp-core-types-v1.0-os.html).
+#[derive(Debug, Clone, PartialEq)]
+pub enum AmqpValue {
+    Null,
+    Bool(bool),
+    Ubyte(u8),
+    Ushort(u16),
+    Uint(u32),
+    Ulong(u64),
+    Byte(i8),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    /// milliseconds since the unix epoch
+    Timestamp(i64),
+    Uuid([u8; 16]),
+    Binary(Vec<u8>),
+    String(String),
+    Symbol(String),
+    List(Vec<AmqpValue>),
+    Map(Vec<(AmqpValue, AmqpValue)>),
+    Array(Vec<AmqpValue>),
+    Described(Box<AmqpValue>, Box<AmqpValue>),
+}
+
+fn take<'a>(buf: &mut &'a [u8], n: usize) -> Result<&'a [u8]> {
+    if buf.len() < n {
+        bail!("unexpected end of data");
+    }
+    let (head, tail) = buf.split_at(n);
+    *buf = tail;
+    Ok(head)
+}
+
+fn take_u8(buf: &mut &[u8]) -> Result<u8> {
+    Ok(take(buf, 1)?[0])
+}
+
+fn take_array<const N: usize>(buf: &mut &[u8]) -> Result<[u8; N]> {
+    Ok(take(buf, N)?.try_into()?)
+}
+
+fn take_u32(buf: &mut &[u8]) -> Result<u32> {
+    Ok(u32::from_be_bytes(take_array(buf)?))
+}
+
+/// Encodes the values of a list or map, the size includes the count.
+fn encode_compound(buf: &mut Vec<u8>, small: u8, large: u8, count: usize, items: &[u8]) {
+    if items.len() < 255 && count < 256 {
+        buf.push(small);
+        buf.push(items.len() as u8 + 1);
+        buf.push(count as u8);
+    } else {
+        buf.push(large);
+        buf.extend_from_slice(&(items.len() as u32 + 4).to_be_bytes());
+        buf.extend_from_slice(&(count as u32).to_be_bytes());
+    }
+    buf.extend_from_slice(items);
+}
+
+fn encode_variable(buf: &mut Vec<u8>, small: u8, large: u8, bytes: &[u8]) {
+    if bytes.len() < 256 {
+        buf.push(small);
+        buf.push(bytes.len() as u8);
+    } else {
+        buf.push(large);
+        buf.extend_from_slice(&(bytes.len() as u32).to_be_bytes());
+    }
+    buf.extend_from_slice(bytes);
+}
+
+impl AmqpValue {
+    /// Creates a described value with a numeric descriptor, e.g., a performative or a message section.
+    pub fn described(code: u64, value: AmqpValue) -> AmqpValue {
+        AmqpValue::Described(Box::new(AmqpValue::Ulong(code)), Box::new(value))
+    }
+
+    /// Returns the numeric descriptor of a described value.
+    pub fn descriptor(&self) -> Option<u64> {
+        match self {
+            AmqpValue::Described(descriptor, _) => descriptor.as_u64(),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            AmqpValue::String(s) | AmqpValue::Symbol(s) => Some(s.as_str()),
+            _ => None,
+        }
+    }
+
+    pub fn as_u64(&self) -> Option<u64> {
+        match *self {
+            AmqpValue::Ubyte(v) => Some(v as u64),
+            AmqpValue::Ushort(v) => Some(v as u64),
+            AmqpValue::Uint(v) => Some(v as u64),
+            AmqpValue::Ulong(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    pub fn as_u32(&self) -> Option<u32> {
+        self.as_u64().and_then(|v| v.try_into().ok())
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match *self {
+            AmqpValue::Bool(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// Encodes the value with the most compact constructor.
+    pub fn encode(&self, buf: &mut Vec<u8>) {
+        match self {
+            AmqpValue::Null => buf.push(0x40),
+            AmqpValue::Bool(true) => buf.push(0x41),
+            AmqpValue::Bool(false) => buf.push(0x42),
+            AmqpValue::Uint(0) => buf.push(0x43),
+            AmqpValue::Uint(v) if *v < 256 => buf.extend_from_slice(&[0x52, *v as u8]),
+            AmqpValue::Ulong(0) => buf.push(0x44),
+            AmqpValue::Ulong(v) if *v < 256 => buf.extend_from_slice(&[0x53, *v as u8]),
+            AmqpValue::Int(v) if i8::try_from(*v).is_ok() => {
+                buf.extend_from_slice(&[0x54, *v as i8 as u8])
+            }
+            AmqpValue::Long(v) if i8::try_from(*v).is_ok() => {
+                buf.extend_from_slice(&[0x55, *v as i8 as u8])
+            }
+            AmqpValue::Binary(b) => encode_variable(buf, 0xa0, 0xb0, b),
+            AmqpValue::String(s) => encode_variable(buf, 0xa1, 0xb1, s.as_bytes()),
+            AmqpValue::Symbol(s) => encode_variable(buf, 0xa3, 0xb3, s.as_bytes()),
+            AmqpValue::List(items) if items.is_empty() => buf.push(0x45),
+            AmqpValue::List(items) => {
+                let mut encoded = Vec::new();
+                for item in items {
+                    item.encode(&mut encoded);
+                }
+                encode_compound(buf, 0xc0, 0xd0, items.len(), &encoded);
+            }
+            AmqpValue::Map(pairs) => {
+                let mut encoded = Vec::new();
+                for (key, value) in pairs {
+                    key.encode(&mut encoded);
+                    value.encode(&mut encoded);
+                }
+                encode_compound(buf, 0xc1, 0xd1, pairs.len() * 2, &encoded);
+            }
+            AmqpValue::Described(descriptor, value) => {
+                buf.push(0x00);
+                descriptor.encode(buf);
+                value.encode(buf);
+            }
+            value => {
+                let (code, body) = value.encode_wide();
+                buf.push(code);
+                buf.extend_from_slice(&body);
+            }
+        }
+    }
+
+    /// Encodes the value with the fixed size constructor, which is required for array elements.
+    fn encode_wide(&self) -> (u8, Vec<u8>) {
+        let with_size = |items: &[u8], count: usize| {
+            let mut body = Vec::new();
+            body.extend_from_slice(&(items.len() as u32 + 4).to_be_bytes());
+            body.extend_from_slice(&(count as u32).to_be_bytes());
+            body.extend_from_slice(items);
+            body
+        };
+        let with_length = |bytes: &[u8]| {
+            let mut body = (bytes.len() as u32).to_be_bytes().to_vec();
+            body.extend_from_slice(bytes);
+            body
+        };
+        match self {
+            AmqpValue::Null => (0x40, vec![]),
+            AmqpValue::Bool(v) => (0x56, vec![*v as u8]),
+            AmqpValue::Ubyte(v) => (0x50, vec![*v]),
+            AmqpValue::Ushort(v) => (0x60, v.to_be_bytes().to_vec()),
+            AmqpValue::Uint(v) => (0x70, v.to_be_bytes().to_vec()),
+            AmqpValue::Ulong(v) => (0x80, v.to_be_bytes().to_vec()),
+            AmqpValue::Byte(v) => (0x51, v.to_be_bytes().to_vec()),
+            AmqpValue::Short(v) => (0x61, v.to_be_bytes().to_vec()),
+            AmqpValue::Int(v) => (0x71, v.to_be_bytes().to_vec()),
+            AmqpValue::Long(v) => (0x81, v.to_be_bytes().to_vec()),
+            AmqpValue::Float(v) => (0x72, v.to_be_bytes().to_vec()),
+            AmqpValue::Double(v) => (0x82, v.to_be_bytes().to_vec()),
+            AmqpValue::Timestamp(v) => (0x83, v.to_be_bytes().to_vec()),
+            AmqpValue::Uuid(v) => (0x98, v.to_vec()),
+            AmqpValue::Binary(v) => (0xb0, with_length(v)),
+            AmqpValue::String(v) => (0xb1, with_length(v.as_bytes())),
+            AmqpValue::Symbol(v) => (0xb3, with_length(v.as_bytes())),
+            AmqpValue::List(items) => {
+                let mut encoded = Vec::new();
+                for item in items {
+                    item.encode(&mut encoded);
+                }
+                (0xd0, with_size(&encoded, items.len()))
+            }
+            AmqpValue::Map(pairs) => {
+                let mut encoded = Vec::new();
+                for (key, value) in pairs {
+                    key.encode(&mut encoded);
+                    value.encode(&mut encoded);
+                }
+                (0xd1, with_size(&encoded, pairs.len() * 2))
+            }
+            AmqpValue::Array(items) => {
+                let mut encoded = Vec::new();
+                match items.first().map(|item| item.encode_wide().0) {
+                    Some(code) => encoded.push(code),
+                    // an empty array needs a constructor, too
+                    None => encoded.push(0x40),
+                }
+                for item in items {
+                    encoded.extend_from_slice(&item.encode_wide().1);
+                }
+                (0xf0, with_size(&encoded, items.len()))
+            }
+            AmqpValue::Described(_, _) => {
+                let mut encoded = Vec::new();
+                self.encode(&mut encoded);
+                (encoded[0], encoded[1..].to_vec())
+            }
+        }
+    }
+
+    /// Decodes the next value and advances the buffer.
+    pub fn decode(buf: &mut &[u8]) -> Result<AmqpValue> {
+        AmqpValue::decode_nested(buf, 0)
+    }
+
+    fn decode_nested(buf: &mut &[u8], depth: usize) -> Result<AmqpValue> {
+        if depth > MAX_DEPTH {
+            bail!("values are nested deeper than {}", MAX_DEPTH);
+        }
+        let code = take_u8(buf)?;
+        AmqpValue::decode_with_constructor(code, buf, depth)
+    }
+
+    fn decode_with_constructor(code: u8, buf: &mut &[u8], depth: usize) -> Result<AmqpValue> {
+        Ok(match code {
+            0x00 => {
+                let descriptor = AmqpValue::decode_nested(buf, depth + 1)?;
+                let value = AmqpValue::decode_nested(buf, depth + 1)?;
+                AmqpValue::Described(Box::new(descriptor), Box::new(value))
+            }
+            0x40 => AmqpValue::Null,
+            0x41 => AmqpValue::Bool(true),
+            0x42 => AmqpValue::Bool(false),
+            0x56 => AmqpValue::Bool(take_u8(buf)? != 0),
+            0x50 => AmqpValue::Ubyte(take_u8(buf)?),
+            0x60 => AmqpValue::Ushort(u16::from_be_bytes(take_array(buf)?)),
+            0x43 => AmqpValue::Uint(0),
+            0x52 => AmqpValue::Uint(take_u8(buf)? as u32),
+            0x70 => AmqpValue::Uint(take_u32(buf)?),
+            0x44 => AmqpValue::Ulong(0),
+            0x53 => AmqpValue::Ulong(take_u8(buf)? as u64),
+            0x80 => AmqpValue::Ulong(u64::from_be_bytes(take_array(buf)?)),
+            0x51 => AmqpValue::Byte(take_u8(buf)? as i8),
+            0x61 => AmqpValue::Short(i16::from_be_bytes(take_array(buf)?)),
+            0x54 => AmqpValue::Int(take_u8(buf)? as i8 as i32),
+            0x71 => AmqpValue::Int(i32::from_be_bytes(take_array(buf)?)),
+            0x55 => AmqpValue::Long(take_u8(buf)? as i8 as i64),
+            0x81 => AmqpValue::Long(i64::from_be_bytes(take_array(buf)?)),
+            0x72 => AmqpValue::Float(f32::from_be_bytes(take_array(buf)?)),
+            0x82 => AmqpValue::Double(f64::from_be_bytes(take_array(buf)?)),
+            0x73 => {
+                let c = char::from_u32(take_u32(buf)?).context("invalid char")?;
+                AmqpValue::String(c.to_string())
+            }
+            0x83 => AmqpValue::Timestamp(i64::from_be_bytes(take_array(buf)?)),
+            0x98 => AmqpValue::Uuid(take_array(buf)?),
+            0xa0 | 0xa1 | 0xa3 | 0xb0 | 0xb1 | 0xb3 => {
+                let length = if code & 0xf0 == 0xa0 {
+                    take_u8(buf)? as usize
+                } else {
+                    take_u32(buf)? as usize
+                };
+                let bytes = take(buf, length)?.to_vec();
+                match code & 0x0f {
+                    0x00 => AmqpValue::Binary(bytes),
+                    0x01 => AmqpValue::String(String::from_utf8(bytes)?),
+                    _ => AmqpValue::Symbol(String::from_utf8(bytes)?),
+                }
+            }
+            0x45 => AmqpValue::List(vec![]),
+            0xc0 | 0xc1 | 0xd0 | 0xd1 | 0xe0 | 0xf0 => {
+                let small = code & 0xf0 != 0xd0 && code != 0xf0;
+                let mut items = if small {
+                    let size = take_u8(buf)? as usize;
+                    take(buf, size)?
+                } else {
+                    let size = take_u32(buf)? as usize;
+                    take(buf, size)?
+                };
+                let count = if small {
+                    take_u8(&mut items)? as usize
+                } else {
+                    take_u32(&mut items)? as usize
+                };
+                match code {
+                    0xc0 | 0xd0 => AmqpValue::List(
+                        (0..count)
+                            .map(|_| AmqpValue::decode_nested(&mut items, depth + 1))
+                            .collect::<Result<_>>()?,
+                    ),
+                    0xc1 | 0xd1 => AmqpValue::Map(
+                        (0..count / 2)
+                            .map(|_| {
+                                Ok((
+                                    AmqpValue::decode_nested(&mut items, depth + 1)?,
+                                    AmqpValue::decode_nested(&mut items, depth + 1)?,
+                                ))
+                            })
+                            .collect::<Result<_>>()?,
+                    ),
+                    _ => {
+                        let mut constructor = take_u8(&mut items)?;
+                        let descriptor = if constructor == 0x00 {
+                            let descriptor = AmqpValue::decode_nested(&mut items, depth + 1)?;
+                            constructor = take_u8(&mut items)?;
+                            Some(descriptor)
+                        } else {
+                            None
+                        };
+                        if matches!(constructor, 0x40..=0x45) && count > MAX_EMPTY_VALUES {
+                            bail!("array of {} values without bytes", count);
+                        }
+                        AmqpValue::Array(
+                            (0..count)
+                                .map(|_| {
+                                    let value = AmqpValue::decode_with_constructor(
+                                        constructor,
+                                        &mut items,
+                                        depth + 1,
+                                    )?;
+                                    Ok(match descriptor {
+                                        Some(ref descriptor) => AmqpValue::Described(
+                                            Box::new(descriptor.clone()),
+                                            Box::new(value),
+                                        ),
+                                        None => value,
+                                    })
+                                })
+                                .collect::<Result<_>>()?,
+                        )
+                    }
+                }
+            }
+            code => bail!("unsupported type code 0x{:02x}", code),
+        })
+    }
+}
+
+/// A deterministic pseudo random generator (xorshift), so the fuzz tests are reproducible without further dependencies.
+#[cfg(test)]
+pub(crate) struct XorShift(u64);
+
+#[cfg(test)]
+impl XorShift {
+    pub(crate) fn new(seed: u64) -> Self {
+        XorShift(seed.max(1))
+    }
+
+    pub(crate) fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// Returns a number in `0..n`.
+    pub(crate) fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    /// Replaces, inserts or removes a few bytes or truncates the data.
+    pub(crate) fn mutate(&mut self, data: &mut Vec<u8>) {
+        for _ in 0..=self.below(4) {
+            match self.below(4) {
+                0 if !data.is_empty() => {
+                    let index = self.below(data.len());
+                    data[index] = self.next() as u8;
+                }
+                1 => {
+                    let index = self.below(data.len() + 1);
+                    data.insert(index, self.next() as u8);
+                }
+                2 if !data.is_empty() => {
+                    data.remove(self.below(data.len()));
+                }
+                _ => data.truncate(self.below(data.len() + 1)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn random_value(rng: &mut XorShift, depth: usize) -> AmqpValue {
+        let string = |rng: &mut XorShift| {
+            let length = rng.below(300);
+            (0..length)
+                .map(|_| (b'a' + rng.below(26) as u8) as char)
+                .collect()
+        };
+        match rng.below(if depth < 3 { 20 } else { 16 }) {
+            0 => AmqpValue::Null,
+            1 => AmqpValue::Bool(rng.next() & 1 == 0),
+            2 => AmqpValue::Ubyte(rng.next() as u8),
+            3 => AmqpValue::Ushort(rng.next() as u16),
+            4 => AmqpValue::Uint(rng.next() as u32 >> rng.below(32)),
+            5 => AmqpValue::Ulong(rng.next() >> rng.below(64)),
+            6 => AmqpValue::Byte(rng.next() as i8),
+            7 => AmqpValue::Short(rng.next() as i16),
+            8 => AmqpValue::Int(rng.next() as i32 >> rng.below(32)),
+            9 => AmqpValue::Long(rng.next() as i64 >> rng.below(64)),
+            // the floats are integral, so they are never NaN
+            10 => AmqpValue::Double(rng.next() as i32 as f64),
+            11 => AmqpValue::Timestamp(rng.next() as i64),
+            12 => AmqpValue::Uuid((rng.next() as u128).to_be_bytes()),
+            13 => AmqpValue::Binary((0..rng.below(300)).map(|_| rng.next() as u8).collect()),
+            14 => AmqpValue::String(string(rng)),
+            15 => AmqpValue::Symbol(string(rng)),
+            16 => AmqpValue::List(
+                (0..rng.below(5))
+                    .map(|_| random_value(rng, depth + 1))
+                    .collect(),
+            ),
+            17 => AmqpValue::Map(
+                (0..rng.below(5))
+                    .map(|_| (AmqpValue::Symbol(string(rng)), random_value(rng, depth + 1)))
+                    .collect(),
+            ),
+            18 => AmqpValue::Array(
+                (0..rng.below(5))
+                    .map(|_| AmqpValue::Uint(rng.next() as u32))
+                    .collect(),
+            ),
+            _ => AmqpValue::described(rng.next() >> 32, random_value(rng, depth + 1)),
+        }
+    }
+
+    fn round_trip(value: AmqpValue) -> Result<()> {
+        let mut encoded = Vec::new();
+        value.encode(&mut encoded);
+        let mut buf = encoded.as_slice();
+        assert_eq!(AmqpValue::decode(&mut buf)?, value);
+        assert!(buf.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn round_trip_values() -> Result<()> {
+        round_trip(AmqpValue::Null)?;
+        round_trip(AmqpValue::Bool(false))?;
+        round_trip(AmqpValue::Uint(0))?;
+        round_trip(AmqpValue::Uint(7))?;
+        round_trip(AmqpValue::Uint(70000))?;
+        round_trip(AmqpValue::Ulong(u64::MAX))?;
+        round_trip(AmqpValue::Int(-1))?;
+        round_trip(AmqpValue::Long(-100000))?;
+        round_trip(AmqpValue::Double(1.5))?;
+        round_trip(AmqpValue::Timestamp(1600000000000))?;
+        round_trip(AmqpValue::Uuid([7; 16]))?;
+        round_trip(AmqpValue::String("a".repeat(300)))?;
+        round_trip(AmqpValue::Symbol("cloudEvents:id".to_string()))?;
+        round_trip(AmqpValue::Binary(vec![1, 2, 3]))?;
+        round_trip(AmqpValue::List(vec![]))?;
+        round_trip(AmqpValue::List(vec![
+            AmqpValue::Null,
+            AmqpValue::Binary(vec![0; 300]),
+        ]))?;
+        round_trip(AmqpValue::Map(vec![(
+            AmqpValue::String("key".to_string()),
+            AmqpValue::Bool(true),
+        )]))?;
+        round_trip(AmqpValue::Array(vec![
+            AmqpValue::Symbol("ANONYMOUS".to_string()),
+            AmqpValue::Symbol("PLAIN".to_string()),
+        ]))?;
+        round_trip(AmqpValue::described(
+            0x10,
+            AmqpValue::List(vec![AmqpValue::String("container".to_string())]),
+        ))?;
+        Ok(())
+    }
+
+    #[test]
+    fn decode_described_array() -> Result<()> {
+        // an array of two described uints with the descriptor 0x24
+        let encoded = [0xe0, 0x07, 0x02, 0x00, 0x53, 0x24, 0x52, 0x01, 0x02];
+        assert_eq!(
+            AmqpValue::decode(&mut &encoded[..])?,
+            AmqpValue::Array(vec![
+                AmqpValue::described(0x24, AmqpValue::Uint(1)),
+                AmqpValue::described(0x24, AmqpValue::Uint(2)),
+            ])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn reject_invalid_data() {
+        assert!(AmqpValue::decode(&mut &[0xa1, 0x05, b'a'][..]).is_err());
+        assert!(AmqpValue::decode(&mut &[0xff][..]).is_err());
+    }
+
+    #[test]
+    fn round_trip_random_values() -> Result<()> {
+        let mut rng = XorShift::new(0x5eed);
+        for _ in 0..2000 {
+            round_trip(random_value(&mut rng, 0))?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn decode_mutated_values_without_panicking() {
+        let mut rng = XorShift::new(0xf00d);
+        for _ in 0..20000 {
+            let mut encoded = Vec::new();
+            random_value(&mut rng, 0).encode(&mut encoded);
+            rng.mutate(&mut encoded);
+            // a successfully decoded value is encoded and decoded the same way again
+            if let Ok(value) = AmqpValue::decode(&mut encoded.as_slice()) {
+                let mut reencoded = Vec::new();
+                value.encode(&mut reencoded);
+                let mut again = Vec::new();
+                AmqpValue::decode(&mut reencoded.as_slice())
+                    .unwrap()
+                    .encode(&mut again);
+                assert_eq!(again, reencoded);
+            }
+        }
+    }
+
+    #[test]
+    fn decode_random_data_without_panicking() {
+        let mut rng = XorShift::new(0xbeef);
+        for _ in 0..20000 {
+            let data: Vec<u8> = (0..rng.below(64)).map(|_| rng.next() as u8).collect();
+            let _ = AmqpValue::decode(&mut data.as_slice());
+        }
+    }
+
+    #[test]
+    fn reject_forged_nesting_and_counts() {
+        // described values nested until the stack overflows
+        assert!(AmqpValue::decode(&mut &[0x00; 100_000][..]).is_err());
+        let mut nested_lists = Vec::new();
+        for _ in 0..100_000 {
+            nested_lists.extend_from_slice(&[0xc0, 0xff, 0x01]);
+        }
+        assert!(AmqpValue::decode(&mut nested_lists.as_slice()).is_err());
+        // an array of u32::MAX nulls, which has only a few bytes
+        let nulls = [0xf0, 0, 0, 0, 5, 0xff, 0xff, 0xff, 0xff, 0x40];
+        assert!(AmqpValue::decode(&mut &nulls[..]).is_err());
+        let few_nulls = [0xe0, 0x02, 0x03, 0x40];
+        assert_eq!(
+            AmqpValue::decode(&mut &few_nulls[..]).unwrap(),
+            AmqpValue::Array(vec![AmqpValue::Null; 3])
+        );
+    }
+}
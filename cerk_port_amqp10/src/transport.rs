@@ -0,0 +1,258 @@
+use crate::codec::AmqpValue;
+use anyhow::{Context, Result};
+use std::io::{Read, Write};
+
+pub const AMQP_HEADER: [u8; 8] = *b"AMQP\x00\x01\x00\x00";
+pub const SASL_HEADER: [u8; 8] = *b"AMQP\x03\x01\x00\x00";
+
+const FRAME_TYPE_AMQP: u8 = 0x00;
+const FRAME_TYPE_SASL: u8 = 0x01;
+/// The size of the frame header without extended header.
+const FRAME_HEADER_SIZE: u32 = 8;
+/// The smallest max frame size every peer has to accept.
+pub const MIN_MAX_FRAME_SIZE: u32 = 512;
+
+pub const SASL_MECHANISMS: u64 = 0x40;
+pub const SASL_INIT: u64 = 0x41;
+pub const SASL_OUTCOME: u64 = 0x44;
+pub const OPEN: u64 = 0x10;
+pub const BEGIN: u64 = 0x11;
+pub const ATTACH: u64 = 0x12;
+pub const FLOW: u64 = 0x13;
+pub const TRANSFER: u64 = 0x14;
+pub const DISPOSITION: u64 = 0x15;
+pub const DETACH: u64 = 0x16;
+pub const END: u64 = 0x17;
+pub const CLOSE: u64 = 0x18;
+pub const ERROR: u64 = 0x1d;
+pub const ACCEPTED: u64 = 0x24;
+pub const REJECTED: u64 = 0x25;
+pub const RELEASED: u64 = 0x26;
+pub const MODIFIED: u64 = 0x27;
+pub const SOURCE: u64 = 0x28;
+pub const TARGET: u64 = 0x29;
+
+/// A performative is a described list, the fields are identified by their position.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Performative {
+    pub code: u64,
+    pub fields: Vec<AmqpValue>,
+}
+
+impl Performative {
+    pub fn new(code: u64, fields: Vec<AmqpValue>) -> Self {
+        Performative { code, fields }
+    }
+
+    /// Returns the field at the position, missing fields are null.
+    pub fn field(&self, index: usize) -> &AmqpValue {
+        self.fields.get(index).unwrap_or(&AmqpValue::Null)
+    }
+
+    /// Returns a readable description of the error in the field at the position, e.g., of a `close` or `detach`.
+    pub fn error(&self, index: usize) -> Option<String> {
+        match self.field(index) {
+            AmqpValue::Described(descriptor, error) if descriptor.as_u64() == Some(ERROR) => {
+                match error.as_ref() {
+                    AmqpValue::List(fields) => Some(format!(
+                        "{} {}",
+                        fields.first().and_then(|c| c.as_str()).unwrap_or_default(),
+                        fields.get(1).and_then(|d| d.as_str()).unwrap_or_default()
+                    )),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    fn encode(&self, buf: &mut Vec<u8>) {
+        AmqpValue::described(self.code, AmqpValue::List(self.fields.clone())).encode(buf)
+    }
+
+    fn decode(buf: &mut &[u8]) -> Result<Self> {
+        match AmqpValue::decode(buf)? {
+            AmqpValue::Described(descriptor, fields) => Ok(Performative {
+                code: descriptor
+                    .as_u64()
+                    .context("invalid performative descriptor")?,
+                fields: match *fields {
+                    AmqpValue::List(fields) => fields,
+                    fields => bail!("invalid performative fields {:?}", fields),
+                },
+            }),
+            value => bail!("expected performative, but received {:?}", value),
+        }
+    }
+}
+
+/// A frame, the performative is missing for heartbeats.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub channel: u16,
+    pub performative: Option<Performative>,
+    pub payload: Vec<u8>,
+}
+
+fn encode_frame(
+    frame_type: u8,
+    channel: u16,
+    performative: Option<&Performative>,
+    payload: &[u8],
+) -> Vec<u8> {
+    let mut body = Vec::new();
+    if let Some(performative) = performative {
+        performative.encode(&mut body);
+    }
+    body.extend_from_slice(payload);
+    let mut frame = (body.len() as u32 + FRAME_HEADER_SIZE)
+        .to_be_bytes()
+        .to_vec();
+    // data offset in 4 byte words
+    frame.push(2);
+    frame.push(frame_type);
+    frame.extend_from_slice(&channel.to_be_bytes());
+    frame.extend_from_slice(&body);
+    frame
+}
+
+/// Encodes an AMQP frame.
+pub fn amqp_frame(channel: u16, performative: Option<&Performative>, payload: &[u8]) -> Vec<u8> {
+    encode_frame(FRAME_TYPE_AMQP, channel, performative, payload)
+}
+
+/// Encodes a SASL frame.
+pub fn sasl_frame(performative: &Performative) -> Vec<u8> {
+    encode_frame(FRAME_TYPE_SASL, 0, Some(performative), &[])
+}
+
+/// Returns the space for the payload of a transfer frame with the performative.
+pub fn payload_capacity(max_frame_size: u32, performative: &Performative) -> usize {
+    let mut encoded = Vec::new();
+    performative.encode(&mut encoded);
+    (max_frame_size as usize).saturating_sub(FRAME_HEADER_SIZE as usize + encoded.len())
+}
+
+/// Reads the protocol header and fails if it is not the expected one.
+pub fn read_header(reader: &mut impl Read, expected: &[u8; 8]) -> Result<()> {
+    let mut header = [0; 8];
+    reader.read_exact(&mut header)?;
+    if &header != expected {
+        bail!("unexpected protocol header {:?}", header);
+    }
+    Ok(())
+}
+
+/// Reads a frame, which is at most `max_frame_size` bytes large.
+///
+/// The size is checked before the frame is read, so the peer can not make the port allocate an arbitrary amount of memory.
+pub fn read_frame(reader: &mut impl Read, max_frame_size: u32) -> Result<Frame> {
+    let mut header = [0; 8];
+    reader.read_exact(&mut header)?;
+    let size = u32::from_be_bytes([header[0], header[1], header[2], header[3]]);
+    let data_offset = header[4] as u32 * 4;
+    if size > max_frame_size {
+        bail!(
+            "frame size {} exceeds the max frame size {}",
+            size,
+            max_frame_size
+        );
+    }
+    if size < data_offset || data_offset < FRAME_HEADER_SIZE {
+        bail!(
+            "invalid frame size {} with data offset {}",
+            size,
+            data_offset
+        );
+    }
+    let mut rest = vec![0; (size - FRAME_HEADER_SIZE) as usize];
+    reader.read_exact(&mut rest)?;
+    let mut body = &rest[(data_offset - FRAME_HEADER_SIZE) as usize..];
+    let performative = if body.is_empty() {
+        None
+    } else {
+        Some(Performative::decode(&mut body)?)
+    };
+    Ok(Frame {
+        channel: u16::from_be_bytes([header[6], header[7]]),
+        performative,
+        payload: body.to_vec(),
+    })
+}
+
+pub fn write_all(writer: &mut impl Write, bytes: &[u8]) -> Result<()> {
+    writer.write_all(bytes)?;
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::XorShift;
+
+    #[test]
+    fn round_trip_frames() -> Result<()> {
+        let transfer = Performative::new(TRANSFER, vec![AmqpValue::Uint(1), AmqpValue::Uint(2)]);
+        let mut encoded = amqp_frame(3, Some(&transfer), b"payload");
+        encoded.extend(amqp_frame(0, None, &[]));
+        let mut reader = encoded.as_slice();
+        assert_eq!(
+            read_frame(&mut reader, MIN_MAX_FRAME_SIZE)?,
+            Frame {
+                channel: 3,
+                performative: Some(transfer),
+                payload: b"payload".to_vec(),
+            }
+        );
+        let heartbeat = read_frame(&mut reader, MIN_MAX_FRAME_SIZE)?;
+        assert_eq!(heartbeat.performative, None);
+        assert!(reader.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn reject_frames_exceeding_the_max_frame_size() {
+        let encoded = amqp_frame(0, None, &[0; MIN_MAX_FRAME_SIZE as usize]);
+        assert!(read_frame(&mut encoded.as_slice(), MIN_MAX_FRAME_SIZE).is_err());
+        // a forged size is rejected before the frame is read
+        let mut forged = u32::MAX.to_be_bytes().to_vec();
+        forged.extend_from_slice(&[2, 0, 0, 0]);
+        let error = read_frame(&mut forged.as_slice(), MIN_MAX_FRAME_SIZE).unwrap_err();
+        assert!(error.to_string().contains("exceeds the max frame size"));
+    }
+
+    #[test]
+    fn describe_errors() {
+        let close = Performative::new(
+            CLOSE,
+            vec![AmqpValue::described(
+                ERROR,
+                AmqpValue::List(vec![
+                    AmqpValue::Symbol("amqp:not-found".to_string()),
+                    AmqpValue::String("queue not found".to_string()),
+                ]),
+            )],
+        );
+        assert_eq!(
+            close.error(0),
+            Some("amqp:not-found queue not found".to_string())
+        );
+        assert_eq!(Performative::new(CLOSE, vec![]).error(0), None);
+    }
+
+    #[test]
+    fn read_mutated_frames_without_panicking() {
+        let mut rng = XorShift::new(0xcafe);
+        let transfer = Performative::new(
+            TRANSFER,
+            vec![AmqpValue::Uint(1), AmqpValue::Uint(2), AmqpValue::Null],
+        );
+        for _ in 0..20000 {
+            let payload: Vec<u8> = (0..rng.below(100)).map(|_| rng.next() as u8).collect();
+            let mut encoded = amqp_frame(rng.next() as u16, Some(&transfer), &payload);
+            rng.mutate(&mut encoded);
+            let _ = read_frame(&mut encoded.as_slice(), MIN_MAX_FRAME_SIZE);
+        }
+    }
+}
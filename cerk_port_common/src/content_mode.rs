@@ -0,0 +1,35 @@
+use anyhow::{bail, Result};
+
+/// The content mode of the published messages, as defined by the CloudEvents protocol bindings.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ContentMode {
+    /// the attributes are sent as metadata of the message, e.g., headers, and the data as payload
+    Binary,
+    /// the event is sent as JSON payload
+    Structured,
+}
+
+impl ContentMode {
+    /// Parses the `content_mode` config value, which is either `binary` or `structured`.
+    pub fn parse(name: &str) -> Result<ContentMode> {
+        match name {
+            "binary" => Ok(ContentMode::Binary),
+            "structured" => Ok(ContentMode::Structured),
+            _ => bail!("content_mode {} is not supported", name),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_content_modes() -> Result<()> {
+        assert_eq!(ContentMode::parse("binary")?, ContentMode::Binary);
+        assert_eq!(ContentMode::parse("structured")?, ContentMode::Structured);
+        assert!(ContentMode::parse("Binary").is_err());
+        assert!(ContentMode::parse("").is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,38 @@
+/*!
+
+This is a package for [CERK](https://github.com/ce-rust/cerk).
+CERK is an open source [CloudEvents](https://github.com/cloudevents/spec) Router written in Rust with a MicroKernel architecture.
+
+# Introduction
+
+CERK lets you route your [CloudEvents](https://github.com/cloudevents/spec) between different different ports.
+Ports are transport layer bindings over which CloudEvents can be exchanged.
+It is built with modularity and portability in mind.
+
+# Components
+
+CERK comes with a couple of prefabricated components, but implementing custom components is easy.
+
+A good overview is provided on [GitHub](https://github.com/ce-rust/cerk/).
+
+# This Crate: Common Port Code
+
+This crate contains the code which is shared by several ports, it does not provide a port itself.
+
+* `content_mode`: the content modes of the CloudEvents protocol bindings
+* `template`: renders templates with `{attribute}` placeholders, e.g., the routing keys of the AMQP port
+* `mqtt_connection_options`: reads the credentials, TLS and status options of the MQTT ports
+* `mqtt_topics`: reads the subscriptions of the MQTT ports, matches their topic filters and renders the topics
+
+*/
+
+#![deny(missing_docs)]
+
+/// The content modes of the published messages.
+pub mod content_mode;
+/// The options to connect the MQTT ports to the broker.
+pub mod mqtt_connection_options;
+/// The subscriptions and topics of the MQTT ports.
+pub mod mqtt_topics;
+/// The templates with `{attribute}` placeholders.
+pub mod template;
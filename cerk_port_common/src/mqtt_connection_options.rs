@@ -0,0 +1,209 @@
+use anyhow::{bail, Context, Result};
+use cerk::kernel::{Config, ConfigHelpers};
+use std::time::Duration;
+
+/// The scheme of hosts which are connected with TLS.
+pub const TLS_SCHEME: &str = "ssl://";
+/// The QoS level of the status messages.
+pub const STATUS_QOS: u8 = 1;
+
+const DEFAULT_STATUS_ONLINE: &str = "online";
+const DEFAULT_STATUS_OFFLINE: &str = "offline";
+
+/// The PEM encoded files to connect with TLS.
+#[derive(Clone, PartialEq)]
+pub struct TlsOptions {
+    /// the CA bundle to verify the broker, the system certificates are used if it is not set
+    pub ca_path: Option<String>,
+    /// the certificate of the client
+    pub cert_path: Option<String>,
+    /// the private key of the client
+    pub key_path: Option<String>,
+    /// the password of the private key
+    pub key_password: Option<String>,
+}
+
+/// The retained status of the port, the broker publishes the offline status as last will.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StatusOptions {
+    /// the topic of the retained status messages
+    pub topic: String,
+    /// the status published after the port has connected
+    pub online: String,
+    /// the status published on disconnect and as last will
+    pub offline: String,
+}
+
+/// The options to connect to the broker.
+///
+/// It does not implement `Debug`, so the credentials can not end up in the logs.
+#[derive(Clone)]
+pub struct ConnectionOptions {
+    /// the user name to authenticate at the broker
+    pub username: Option<String>,
+    /// the password of the user
+    pub password: Option<String>,
+    /// the keep alive interval, the port's default is used if it is not set
+    pub keep_alive: Option<Duration>,
+    /// the TLS options, set for `ssl://` hosts
+    pub tls: Option<TlsOptions>,
+    /// the retained status of the port
+    pub status: Option<StatusOptions>,
+}
+
+fn get_string(config: &Config, key: &'static str) -> Result<Option<String>> {
+    config
+        .get_op_val_string(key)
+        .with_context(|| format!("{} has to be of type String", key))
+}
+
+fn try_get_tls_options(host: &str, config: &Config) -> Result<Option<TlsOptions>> {
+    let tls = TlsOptions {
+        ca_path: get_string(config, "tls_ca_path")?,
+        cert_path: get_string(config, "tls_cert_path")?,
+        key_path: get_string(config, "tls_key_path")?,
+        key_password: get_string(config, "tls_key_password")?,
+    };
+    if tls.cert_path.is_some() != tls.key_path.is_some() {
+        bail!("tls_cert_path and tls_key_path must be set together");
+    }
+    if tls.key_password.is_some() && tls.key_path.is_none() {
+        bail!("tls_key_password requires tls_key_path");
+    }
+    if host.starts_with(TLS_SCHEME) {
+        Ok(Some(tls))
+    } else if tls
+        == (TlsOptions {
+            ca_path: None,
+            cert_path: None,
+            key_path: None,
+            key_password: None,
+        })
+    {
+        Ok(None)
+    } else {
+        bail!("the TLS options require an {} host", TLS_SCHEME)
+    }
+}
+
+fn try_get_status_options(config: &Config) -> Result<Option<StatusOptions>> {
+    let online = get_string(config, "status_online")?;
+    let offline = get_string(config, "status_offline")?;
+    match get_string(config, "status_topic")? {
+        Some(topic) => {
+            if topic.is_empty() || topic.contains('+') || topic.contains('#') {
+                bail!("the status_topic {:?} is not a valid topic name", topic);
+            }
+            Ok(Some(StatusOptions {
+                topic,
+                online: online.unwrap_or_else(|| DEFAULT_STATUS_ONLINE.to_string()),
+                offline: offline.unwrap_or_else(|| DEFAULT_STATUS_OFFLINE.to_string()),
+            }))
+        }
+        None if online.is_some() || offline.is_some() => {
+            bail!("status_online and status_offline require status_topic")
+        }
+        None => Ok(None),
+    }
+}
+
+/// Reads the credentials, the keep alive interval, the TLS options and the status.
+///
+/// TLS is enabled by an `ssl://` host.
+pub fn try_get_connection_options(host: &str, config: &Config) -> Result<ConnectionOptions> {
+    let username = get_string(config, "username")?;
+    let password = get_string(config, "password")?;
+    if password.is_some() && username.is_none() {
+        bail!("password requires username");
+    }
+    let keep_alive = config
+        .get_op_val_u32("keep_alive")
+        .context("keep_alive has to be of type u32")?
+        .map(|seconds| Duration::from_secs(seconds as u64));
+    Ok(ConnectionOptions {
+        username,
+        password,
+        keep_alive,
+        tls: try_get_tls_options(host, config)?,
+        status: try_get_status_options(config)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(entries: Vec<(&str, Config)>) -> Config {
+        Config::HashMap(
+            entries
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), value))
+                .collect(),
+        )
+    }
+
+    fn string(value: &str) -> Config {
+        Config::String(value.to_string())
+    }
+
+    #[test]
+    fn full_connection_options() -> Result<()> {
+        let options = try_get_connection_options(
+            "ssl://broker:8883",
+            &config(vec![
+                ("username", string("cerk")),
+                ("password", string("s3cret")),
+                ("keep_alive", Config::U32(30)),
+                ("tls_ca_path", string("/etc/ssl/ca.pem")),
+                ("tls_cert_path", string("/etc/ssl/client.pem")),
+                ("tls_key_path", string("/etc/ssl/client.key")),
+                ("status_topic", string("gateways/cerk/status")),
+                ("status_offline", string("lost")),
+            ]),
+        )?;
+        assert_eq!(options.username, Some("cerk".to_string()));
+        assert_eq!(options.password, Some("s3cret".to_string()));
+        assert_eq!(options.keep_alive, Some(Duration::from_secs(30)));
+        let tls = options.tls.unwrap();
+        assert_eq!(tls.ca_path, Some("/etc/ssl/ca.pem".to_string()));
+        assert_eq!(tls.key_password, None);
+        assert_eq!(
+            options.status,
+            Some(StatusOptions {
+                topic: "gateways/cerk/status".to_string(),
+                online: "online".to_string(),
+                offline: "lost".to_string(),
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn default_connection_options() -> Result<()> {
+        let options = try_get_connection_options("tcp://broker:1883", &config(vec![]))?;
+        assert!(options.username.is_none());
+        assert!(options.keep_alive.is_none());
+        assert!(options.tls.is_none());
+        assert!(options.status.is_none());
+        // the system certificates are used without tls_ca_path
+        let options = try_get_connection_options("ssl://broker:8883", &config(vec![]))?;
+        assert!(options.tls.unwrap().ca_path.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_connection_options() {
+        let build = |host: &str, entries| try_get_connection_options(host, &config(entries));
+        assert!(build("tcp://broker", vec![("password", string("s3cret"))]).is_err());
+        assert!(build("tcp://broker", vec![("tls_ca_path", string("ca.pem"))]).is_err());
+        assert!(build(
+            "ssl://broker",
+            vec![("tls_cert_path", string("client.pem"))]
+        )
+        .is_err());
+        assert!(build("ssl://broker", vec![("tls_key_password", string("s3cret"))]).is_err());
+        assert!(build("tcp://broker", vec![("keep_alive", string("30s"))]).is_err());
+        assert!(build("tcp://broker", vec![("status_online", string("up"))]).is_err());
+        assert!(build("tcp://broker", vec![("status_topic", string("status/#"))]).is_err());
+    }
+}
@@ -0,0 +1,321 @@
+use crate::template::render_template;
+use anyhow::{bail, Context, Result};
+use cerk::kernel::{Config, ConfigHelpers, DeliveryGuarantee};
+use cloudevents::Event;
+use std::convert::TryFrom;
+
+const SINGLE_LEVEL_WILDCARD: &str = "+";
+const MULTI_LEVEL_WILDCARD: &str = "#";
+
+/// A topic filter the port subscribes to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MqttSubscription {
+    /// the topic filter, wildcards are allowed
+    pub topic: String,
+    /// the QoS level of the subscription
+    pub qos: u8,
+    /// the delivery guarantee of the received events
+    pub delivery_guarantee: DeliveryGuarantee,
+}
+
+/// Checks that the wildcards only fill whole levels and that `#` is the last level.
+fn validate_topic_filter(filter: &str) -> Result<()> {
+    if filter.is_empty() {
+        bail!("the topic filter must not be empty");
+    }
+    let levels: Vec<&str> = filter.split('/').collect();
+    for (i, level) in levels.iter().enumerate() {
+        match *level {
+            SINGLE_LEVEL_WILDCARD => {}
+            MULTI_LEVEL_WILDCARD if i == levels.len() - 1 => {}
+            level
+                if level.contains(SINGLE_LEVEL_WILDCARD)
+                    || level.contains(MULTI_LEVEL_WILDCARD) =>
+            {
+                bail!("the topic filter {} has an invalid wildcard", filter)
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn build_subscription(
+    topic: String,
+    qos: Option<u8>,
+    delivery_guarantee: Option<&Config>,
+    max_qos: u8,
+) -> Result<MqttSubscription> {
+    validate_topic_filter(&topic)?;
+    let qos = qos.unwrap_or(0);
+    if qos > max_qos {
+        bail!("the qos {} of {} is not supported", qos, topic);
+    }
+    let delivery_guarantee = match delivery_guarantee {
+        Some(config) => DeliveryGuarantee::try_from(config)
+            .with_context(|| format!("invalid delivery_guarantee of {}", topic))?,
+        None if qos > 0 => DeliveryGuarantee::AtLeastOnce,
+        None => DeliveryGuarantee::BestEffort,
+    };
+    if qos == 0 && delivery_guarantee.requires_acknowledgment() {
+        bail!(
+            "the subscription {} requires at least qos 1 for the delivery guarantee {:?}",
+            topic,
+            delivery_guarantee
+        );
+    }
+    Ok(MqttSubscription {
+        topic,
+        qos,
+        delivery_guarantee,
+    })
+}
+
+/// Reads the `subscriptions` and the single `subscribe_topic` with its `subscribe_qos`.
+pub fn try_get_subscriptions(config: &Config, max_qos: u8) -> Result<Vec<MqttSubscription>> {
+    let mut subscriptions = Vec::new();
+    if let Some(topic) = config
+        .get_op_val_string("subscribe_topic")
+        .context("subscribe_topic has to be of type String")?
+    {
+        let qos = config
+            .get_op_val_u8("subscribe_qos")
+            .context("subscribe_qos has to be of type U8")?;
+        subscriptions.push(build_subscription(topic, qos, None, max_qos)?);
+    }
+    for subscription in config.get_op_val_vec("subscriptions")?.unwrap_or_default() {
+        let topic = subscription
+            .get_op_val_string("topic")
+            .context("subscriptions topic has to be of type String")?
+            .context("subscriptions topic is not set")?;
+        let qos = subscription
+            .get_op_val_u8("qos")
+            .context("subscriptions qos has to be of type U8")?;
+        subscriptions.push(build_subscription(
+            topic,
+            qos,
+            subscription.get_op_val_config("delivery_guarantee")?,
+            max_qos,
+        )?);
+    }
+    Ok(subscriptions)
+}
+
+/// Returns the levels of the topic which are matched by the wildcards of the filter,
+/// or `None` if the filter does not match the topic.
+fn matched_levels<'a>(filter: &str, topic: &'a str) -> Option<Vec<&'a str>> {
+    let levels: Vec<&str> = topic.split('/').collect();
+    // topics starting with `$` are not matched by a leading wildcard
+    if topic.starts_with('$')
+        && (filter.starts_with(SINGLE_LEVEL_WILDCARD) || filter.starts_with(MULTI_LEVEL_WILDCARD))
+    {
+        return None;
+    }
+    let mut matched = Vec::new();
+    for (i, level) in filter.split('/').enumerate() {
+        match level {
+            // `#` matches the parent level as well, e.g., `sport/#` matches `sport`
+            MULTI_LEVEL_WILDCARD => {
+                matched.extend_from_slice(levels.get(i..).unwrap_or_default());
+                return Some(matched);
+            }
+            SINGLE_LEVEL_WILDCARD => matched.push(*levels.get(i)?),
+            level => {
+                if levels.get(i) != Some(&level) {
+                    return None;
+                }
+            }
+        }
+    }
+    if filter.split('/').count() == levels.len() {
+        Some(matched)
+    } else {
+        None
+    }
+}
+
+/// Finds the first subscription which matches the topic and
+/// returns it with the levels matched by the wildcards, joined by `/`.
+///
+/// For the filter `devices/+/events` and the topic `devices/sensor-1/events`, `sensor-1` is returned.
+pub fn find_subscription<'a>(
+    subscriptions: &'a [MqttSubscription],
+    topic: &str,
+) -> Option<(&'a MqttSubscription, Option<String>)> {
+    subscriptions.iter().find_map(|subscription| {
+        matched_levels(&subscription.topic, topic).map(|levels| {
+            let subject = if levels.is_empty() {
+                None
+            } else {
+                Some(levels.join("/"))
+            };
+            (subscription, subject)
+        })
+    })
+}
+
+/// Replaces the `{attribute}` placeholders of the topic template with the attributes of the event.
+///
+/// For the template `devices/{subject}/events` and an event with the subject `sensor-1`,
+/// `devices/sensor-1/events` is returned.
+/// The attributes may contain `/` to fill several levels, but no wildcards.
+pub fn render_topic(template: &str, event: &Event) -> Result<String> {
+    let rendered = render_template(template, event)?;
+    if rendered.is_empty()
+        || rendered.contains(SINGLE_LEVEL_WILDCARD)
+        || rendered.contains(MULTI_LEVEL_WILDCARD)
+        || rendered.contains('\0')
+    {
+        bail!("the topic {:?} is not a valid topic name", rendered);
+    }
+    Ok(rendered)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cloudevents::{EventBuilder, EventBuilderV10};
+
+    fn entry(values: &[(&str, Config)]) -> Config {
+        Config::HashMap(
+            values
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.clone()))
+                .collect(),
+        )
+    }
+
+    fn event(subject: &str) -> Event {
+        EventBuilderV10::new()
+            .id("1")
+            .ty("created")
+            .source("devices")
+            .subject(subject)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn build_subscriptions() -> Result<()> {
+        let config = entry(&[
+            ("subscribe_topic", Config::String("inbox".to_string())),
+            (
+                "subscriptions",
+                Config::Vec(vec![
+                    entry(&[
+                        ("topic", Config::String("devices/+/events".to_string())),
+                        ("qos", Config::U8(1)),
+                    ]),
+                    entry(&[
+                        ("topic", Config::String("metrics/#".to_string())),
+                        ("qos", Config::U8(1)),
+                        ("delivery_guarantee", Config::U8(0)),
+                    ]),
+                ]),
+            ),
+        ]);
+        let subscriptions = try_get_subscriptions(&config, 2)?;
+        assert_eq!(
+            subscriptions,
+            vec![
+                MqttSubscription {
+                    topic: "inbox".to_string(),
+                    qos: 0,
+                    delivery_guarantee: DeliveryGuarantee::BestEffort,
+                },
+                MqttSubscription {
+                    topic: "devices/+/events".to_string(),
+                    qos: 1,
+                    delivery_guarantee: DeliveryGuarantee::AtLeastOnce,
+                },
+                MqttSubscription {
+                    topic: "metrics/#".to_string(),
+                    qos: 1,
+                    delivery_guarantee: DeliveryGuarantee::BestEffort,
+                },
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn build_invalid_subscriptions() {
+        let subscription = |values: &[(&str, Config)]| {
+            try_get_subscriptions(
+                &entry(&[("subscriptions", Config::Vec(vec![entry(values)]))]),
+                1,
+            )
+        };
+        assert!(subscription(&[("qos", Config::U8(1))]).is_err());
+        for topic in ["", "devices/#/events", "devices/sensor+"] {
+            assert!(subscription(&[("topic", Config::String(topic.to_string()))]).is_err());
+        }
+        assert!(subscription(&[
+            ("topic", Config::String("devices".to_string())),
+            ("qos", Config::U8(2)),
+        ])
+        .is_err());
+        // at least once requires qos 1
+        assert!(subscription(&[
+            ("topic", Config::String("devices".to_string())),
+            ("delivery_guarantee", Config::U8(2)),
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn match_wildcards() {
+        let subscriptions = try_get_subscriptions(
+            &entry(&[(
+                "subscriptions",
+                Config::Vec(
+                    ["devices/+/events", "metrics/#", "status"]
+                        .iter()
+                        .map(|topic| entry(&[("topic", Config::String(topic.to_string()))]))
+                        .collect(),
+                ),
+            )]),
+            2,
+        )
+        .unwrap();
+        let subject = |topic: &str| {
+            find_subscription(&subscriptions, topic)
+                .map(|(subscription, subject)| (subscription.topic.as_str(), subject))
+        };
+        assert_eq!(
+            subject("devices/sensor-1/events"),
+            Some(("devices/+/events", Some("sensor-1".to_string())))
+        );
+        assert_eq!(
+            subject("metrics/eu/cpu"),
+            Some(("metrics/#", Some("eu/cpu".to_string())))
+        );
+        assert_eq!(subject("metrics"), Some(("metrics/#", None)));
+        assert_eq!(subject("status"), Some(("status", None)));
+        assert_eq!(subject("devices/sensor-1"), None);
+        assert_eq!(subject("devices/sensor-1/events/raw"), None);
+        assert_eq!(matched_levels("#", "$SYS/uptime"), None);
+        assert_eq!(
+            matched_levels("$SYS/#", "$SYS/uptime"),
+            Some(vec!["uptime"])
+        );
+    }
+
+    #[test]
+    fn render_topics() -> Result<()> {
+        assert_eq!(render_topic("outbox", &event("a"))?, "outbox");
+        assert_eq!(
+            render_topic("devices/{subject}/events", &event("sensor-1"))?,
+            "devices/sensor-1/events"
+        );
+        assert_eq!(
+            render_topic("{source}/{subject}", &event("eu/cpu"))?,
+            "devices/eu/cpu"
+        );
+        assert!(render_topic("devices/{subject}", &event("+")).is_err());
+        assert!(render_topic("devices/{dataschema}", &event("a")).is_err());
+        assert!(render_topic("devices/{subject", &event("a")).is_err());
+        assert!(render_topic("{subject}", &event("")).is_err());
+        Ok(())
+    }
+}
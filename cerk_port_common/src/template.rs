@@ -0,0 +1,73 @@
+use anyhow::{Context, Result};
+use cloudevents::event::AttributeValue;
+use cloudevents::Event;
+
+fn attribute(event: &Event, name: &str) -> Option<String> {
+    event
+        .iter()
+        .find(|(attribute, _)| *attribute == name)
+        .map(|(_, value)| match value {
+            AttributeValue::Time(time) => time.to_rfc3339(),
+            value => value.to_string(),
+        })
+}
+
+/// Replaces the `{attribute}` placeholders of the template with the attributes of the event.
+///
+/// For the template `{type}.{source}` and an event with the type `created` and the source `orders`,
+/// `created.orders` is returned.
+/// Extensions can be used as well, e.g., `{tenant}.events`.
+pub fn render_template(template: &str, event: &Event) -> Result<String> {
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        rendered.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find('}')
+            .with_context(|| format!("the template {} has an unclosed placeholder", template))?;
+        let name = &rest[start + 1..start + end];
+        let value = attribute(event, name)
+            .with_context(|| format!("the event has no attribute {}", name))?;
+        rendered.push_str(&value);
+        rest = &rest[start + end + 1..];
+    }
+    rendered.push_str(rest);
+    Ok(rendered)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cloudevents::{EventBuilder, EventBuilderV10};
+
+    fn event() -> Event {
+        EventBuilderV10::new()
+            .id("1")
+            .ty("created")
+            .source("orders")
+            .extension("tenant", "a")
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn render_attributes() -> Result<()> {
+        assert_eq!(render_template("", &event())?, "");
+        assert_eq!(render_template("static", &event())?, "static");
+        assert_eq!(
+            render_template("{type}.{source}", &event())?,
+            "created.orders"
+        );
+        assert_eq!(
+            render_template("events.{tenant}.{id}", &event())?,
+            "events.a.1"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn render_invalid_templates() {
+        assert!(render_template("{subject}", &event()).is_err());
+        assert!(render_template("{type", &event()).is_err());
+    }
+}
@@ -0,0 +1,77 @@
+/*!
+
+This is a package for [CERK](https://github.com/ce-rust/cerk).
+CERK is an open source [CloudEvents](https://github.com/cloudevents/spec) Router written in Rust with a MicroKernel architecture.
+
+# Introduction
+
+CERK lets you route your [CloudEvents](https://github.com/cloudevents/spec) between different different ports.
+Ports are transport layer bindings over which CloudEvents can be exchanged.
+It is built with modularity and portability in mind.
+
+# Components
+
+CERK comes with a couple of prefabricated components, but implementing custom components is easy.
+
+A good overview is provided on [GitHub](https://github.com/ce-rust/cerk/).
+
+# This Component: gRPC Port
+
+This port serves a gRPC server, the events are encoded in the [Protobuf event format](https://github.com/cloudevents/spec/blob/master/protobuf-format.md).
+The service and the messages are defined in [`proto/cerk.proto`](https://github.com/ce-rust/cerk/tree/master/cerk_port_grpc/proto/cerk.proto) and [`proto/cloudevents.proto`](https://github.com/ce-rust/cerk/tree/master/cerk_port_grpc/proto/cloudevents.proto).
+
+## Service `cerk.v1.CloudEventService`
+
+| Method          | Description                                                                 |
+|-----------------|-----------------------------------------------------------------------------|
+| `Publish`       | routes the event and responds with the `ProcessingResult`                   |
+| `PublishStream` | routes a stream of events, a response is sent for every event               |
+| `Subscribe`     | streams the events which are routed to the port, optionally filtered by type |
+
+If the delivery guarantee of the port requires an acknowledgment, the responses are sent as soon as the event is processed.
+Otherwise, the responses are sent immediately with the result `SUCCESSFUL`.
+Invalid events result in the status `INVALID_ARGUMENT` for `Publish` and in the result `PERMANENT_ERROR` for `PublishStream`.
+
+Events which carry `proto_data` are not supported, use `binary_data` or `text_data` instead.
+
+`PublishStream` processes at most `max_in_flight` events of a stream concurrently,
+no further event is read from the stream until one of them is processed and its response is read by the client.
+
+`Subscribe` only supports the delivery guarantee best effort, the subscribers do not acknowledge the events.
+An outgoing event is successful as soon as it is queued for at least one subscriber, otherwise it failed transiently.
+So an event which is routed with at least once could still get lost, if the stream of the subscriber breaks.
+A subscriber whose queue of `subscriber_queue_size` events is full is disconnected, it has to subscribe again.
+
+## Configurations
+
+| Name                    | Type   | Default   | Description                                            |
+|-------------------------|--------|-----------|--------------------------------------------------------|
+| `ip_addr`               | String | `0.0.0.0` | the address the server listens on                      |
+| `grpc_port`             | u32    | `50051`   | the port the server listens on                         |
+| `delivery_guarantee`    | u8     | `0`       | the delivery guarantee of the published events         |
+| `max_in_flight`         | u32    | `100`     | the concurrently processed events of a `PublishStream` |
+| `subscriber_queue_size` | u32    | `100`     | the queued events of a subscriber                      |
+
+## Local Test
+
+```bash
+grpcurl -plaintext -import-path ./proto -proto cerk.proto \
+  -d '{"id":"1","source":"http://example.com","spec_version":"1.0","type":"test"}' \
+  localhost:50051 cerk.v1.CloudEventService/Publish
+```
+
+*/
+
+#![deny(missing_docs)]
+
+#[macro_use]
+extern crate log;
+
+#[macro_use]
+extern crate anyhow;
+
+mod port_grpc;
+mod proto;
+mod protobuf_format;
+
+pub use self::port_grpc::{port_grpc_start, PORT_GRPC};
@@ -0,0 +1,813 @@
+use crate::proto::{
+    CloudEvent as ProtoCloudEvent, ProcessingResult as ProtoProcessingResult, PublishResponse,
+    SubscribeRequest,
+};
+use crate::protobuf_format::{from_proto, to_proto};
+use anyhow::{Context, Result};
+use cerk::kernel::{
+    BrokerEvent, CloudEventMessageRoutingId, CloudEventRoutingArgs, Config, ConfigHelpers,
+    DeliveryGuarantee, HealthCheckRequest, HealthCheckResponse, HealthCheckStatus,
+    IncomingCloudEvent, OutgoingCloudEvent, OutgoingCloudEventProcessed, ProcessingResult,
+};
+use cerk::runtime::channel::{BoxedReceiver, BoxedSender};
+use cerk::runtime::{InternalServerFn, InternalServerFnRefStatic, InternalServerId};
+use cloudevents::AttributesReader;
+use futures::channel::mpsc::{channel, Receiver, Sender};
+use futures::executor::block_on;
+use futures::{SinkExt, StreamExt};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::task::Poll;
+use tokio::net::TcpListener;
+use tokio::runtime::Handle;
+use tokio::sync::oneshot;
+use tonic::body::BoxBody;
+use tonic::codec::{ProstCodec, Streaming};
+use tonic::codegen::{http, BoxFuture, Never, Service};
+use tonic::server::{Grpc, ServerStreamingService, StreamingService, UnaryService};
+use tonic::transport::{Body, NamedService, Server};
+use tonic::{Code, Request, Response, Status};
+use uuid::Uuid;
+
+const SERVICE_NAME: &str = "cerk.v1.CloudEventService";
+/// The gRPC status code for unknown methods.
+const UNIMPLEMENTED: &str = "12";
+const DEFAULT_MAX_IN_FLIGHT: u32 = 100;
+const DEFAULT_SUBSCRIBER_QUEUE_SIZE: u32 = 100;
+
+type SubscriberId = String;
+type ArcGrpcData = Arc<Mutex<GrpcData>>;
+
+struct Subscriber {
+    sender: Sender<Result<ProtoCloudEvent, Status>>,
+    types: Vec<String>,
+}
+
+struct GrpcConfig {
+    address: SocketAddr,
+    delivery_guarantee: DeliveryGuarantee,
+    /// how many events of a `PublishStream` are processed concurrently
+    max_in_flight: usize,
+    /// how many events are queued for a subscriber, before it is disconnected
+    subscriber_queue_size: usize,
+}
+
+struct RunningServer {
+    address: SocketAddr,
+    shutdown: oneshot::Sender<()>,
+    /// resolves as soon as the listener is dropped
+    closed: oneshot::Receiver<()>,
+}
+
+struct GrpcData {
+    id: InternalServerId,
+    config: Option<GrpcConfig>,
+    server: Option<RunningServer>,
+    sender_to_kernel: BoxedSender,
+    subscribers: HashMap<SubscriberId, Subscriber>,
+    pending_publishes: HashMap<CloudEventMessageRoutingId, oneshot::Sender<ProcessingResult>>,
+}
+
+fn build_config(config: &Config) -> Result<GrpcConfig> {
+    let ip_addr: IpAddr = config
+        .get_op_val_string("ip_addr")?
+        .unwrap_or_else(|| "0.0.0.0".to_string())
+        .parse()?;
+    let port = config.get_op_val_u32("grpc_port")?.unwrap_or(50051) as u16;
+    let delivery_guarantee = match config.get_op_val_config("delivery_guarantee")? {
+        Some(c) => DeliveryGuarantee::try_from(c)?,
+        None => DeliveryGuarantee::BestEffort,
+    };
+    let max_in_flight = config
+        .get_op_val_u32("max_in_flight")?
+        .unwrap_or(DEFAULT_MAX_IN_FLIGHT);
+    let subscriber_queue_size = config
+        .get_op_val_u32("subscriber_queue_size")?
+        .unwrap_or(DEFAULT_SUBSCRIBER_QUEUE_SIZE);
+    if max_in_flight == 0 || subscriber_queue_size == 0 {
+        bail!("max_in_flight and subscriber_queue_size must be greater than 0");
+    }
+    Ok(GrpcConfig {
+        address: SocketAddr::new(ip_addr, port),
+        delivery_guarantee,
+        max_in_flight: max_in_flight as usize,
+        subscriber_queue_size: subscriber_queue_size as usize,
+    })
+}
+
+fn to_proto_result(result: ProcessingResult) -> ProtoProcessingResult {
+    match result {
+        ProcessingResult::Successful => ProtoProcessingResult::Successful,
+        ProcessingResult::TransientError => ProtoProcessingResult::TransientError,
+        ProcessingResult::PermanentError => ProtoProcessingResult::PermanentError,
+        ProcessingResult::Timeout => ProtoProcessingResult::Timeout,
+    }
+}
+
+/// Routes the event to the kernel, the receiver resolves as soon as the event is processed.
+async fn publish(
+    data: &ArcGrpcData,
+    event: ProtoCloudEvent,
+) -> Result<oneshot::Receiver<ProcessingResult>, Status> {
+    let cloud_event =
+        from_proto(event).map_err(|e| Status::invalid_argument(format!("{:#}", e)))?;
+    let routing_id = Uuid::new_v4().to_string();
+    let (sender, receiver) = oneshot::channel();
+    let (id, delivery_guarantee, sender_to_kernel) = {
+        let mut data = data.lock().unwrap();
+        let delivery_guarantee = data
+            .config
+            .as_ref()
+            .ok_or_else(|| Status::unavailable("port is not configured"))?
+            .delivery_guarantee;
+        if delivery_guarantee.requires_acknowledgment() {
+            data.pending_publishes.insert(routing_id.clone(), sender);
+        } else {
+            let _ = sender.send(ProcessingResult::Successful);
+        }
+        (
+            data.id.clone(),
+            delivery_guarantee,
+            data.sender_to_kernel.clone_boxed(),
+        )
+    };
+    // the send blocks if the kernel inbox is full, so it must neither hold the lock nor block the tokio worker
+    tokio::task::spawn_blocking(move || {
+        sender_to_kernel.send(BrokerEvent::IncomingCloudEvent(IncomingCloudEvent {
+            incoming_id: id,
+            routing_id,
+            cloud_event,
+            args: CloudEventRoutingArgs { delivery_guarantee },
+        }))
+    })
+    .await
+    .map_err(|e| Status::internal(format!("failed to route the event: {}", e)))?;
+    Ok(receiver)
+}
+
+async fn publish_and_wait(
+    data: ArcGrpcData,
+    event: ProtoCloudEvent,
+) -> Result<PublishResponse, Status> {
+    let id = event.id.clone();
+    let receiver = publish(&data, event).await?;
+    // the sender is dropped if the port is stopped before the event is processed
+    let result = receiver.await.unwrap_or(ProcessingResult::TransientError);
+    Ok(PublishResponse {
+        id,
+        result: to_proto_result(result) as i32,
+    })
+}
+
+struct PublishService(ArcGrpcData);
+
+impl UnaryService<ProtoCloudEvent> for PublishService {
+    type Response = PublishResponse;
+    type Future = BoxFuture<Response<PublishResponse>, Status>;
+
+    fn call(&mut self, request: Request<ProtoCloudEvent>) -> Self::Future {
+        let data = self.0.clone();
+        Box::pin(async move {
+            publish_and_wait(data, request.into_inner())
+                .await
+                .map(Response::new)
+        })
+    }
+}
+
+/// Responds with the result, invalid events are rejected permanently.
+async fn publish_stream_response(data: ArcGrpcData, event: ProtoCloudEvent) -> PublishResponse {
+    let id = event.id.clone();
+    publish_and_wait(data, event)
+        .await
+        .unwrap_or_else(|status| {
+            let result = match status.code() {
+                Code::InvalidArgument => ProtoProcessingResult::PermanentError,
+                _ => ProtoProcessingResult::TransientError,
+            };
+            PublishResponse {
+                id,
+                result: result as i32,
+            }
+        })
+}
+
+struct PublishStreamService(ArcGrpcData);
+
+impl StreamingService<ProtoCloudEvent> for PublishStreamService {
+    type Response = PublishResponse;
+    type ResponseStream = Receiver<Result<PublishResponse, Status>>;
+    type Future = BoxFuture<Response<Self::ResponseStream>, Status>;
+
+    fn call(&mut self, request: Request<Streaming<ProtoCloudEvent>>) -> Self::Future {
+        let data = self.0.clone();
+        let max_in_flight = match data.lock().unwrap().config.as_ref() {
+            Some(config) => config.max_in_flight,
+            None => return Box::pin(async { Err(Status::unavailable("port is not configured")) }),
+        };
+        let events = request.into_inner();
+        let (mut sender, receiver) = channel(max_in_flight);
+        tokio::spawn(async move {
+            // the events are processed concurrently, the responses are correlated by the event id;
+            // no further event is read while `max_in_flight` events are pending or the client does not read the responses
+            let mut responses = events
+                .map(move |event| {
+                    let data = data.clone();
+                    async move {
+                        match event {
+                            Ok(event) => Ok(publish_stream_response(data, event).await),
+                            Err(status) => Err(status),
+                        }
+                    }
+                })
+                .buffer_unordered(max_in_flight);
+            while let Some(response) = responses.next().await {
+                let failed = response.is_err();
+                if sender.send(response).await.is_err() || failed {
+                    break;
+                }
+            }
+        });
+        Box::pin(async move { Ok(Response::new(receiver)) })
+    }
+}
+
+struct SubscribeService(ArcGrpcData);
+
+impl ServerStreamingService<SubscribeRequest> for SubscribeService {
+    type Response = ProtoCloudEvent;
+    type ResponseStream = Receiver<Result<ProtoCloudEvent, Status>>;
+    type Future = BoxFuture<Response<Self::ResponseStream>, Status>;
+
+    fn call(&mut self, request: Request<SubscribeRequest>) -> Self::Future {
+        let mut data = self.0.lock().unwrap();
+        let queue_size = match data.config.as_ref() {
+            Some(config) => config.subscriber_queue_size,
+            None => return Box::pin(async { Err(Status::unavailable("port is not configured")) }),
+        };
+        let (sender, receiver) = channel(queue_size);
+        let subscriber_id = Uuid::new_v4().to_string();
+        debug!("subscriber {} connected", subscriber_id);
+        data.subscribers.insert(
+            subscriber_id,
+            Subscriber {
+                sender,
+                types: request.into_inner().types,
+            },
+        );
+        Box::pin(async move { Ok(Response::new(receiver)) })
+    }
+}
+
+/// The gRPC service `cerk.v1.CloudEventService`, see `proto/cerk.proto`.
+#[derive(Clone)]
+struct CloudEventService(ArcGrpcData);
+
+impl NamedService for CloudEventService {
+    const NAME: &'static str = SERVICE_NAME;
+}
+
+impl Service<http::Request<Body>> for CloudEventService {
+    type Response = http::Response<BoxBody>;
+    type Error = Never;
+    type Future = BoxFuture<Self::Response, Never>;
+
+    fn poll_ready(&mut self, _cx: &mut std::task::Context<'_>) -> Poll<Result<(), Never>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: http::Request<Body>) -> Self::Future {
+        let data = self.0.clone();
+        let method = request
+            .uri()
+            .path()
+            .trim_start_matches(&format!("/{}/", SERVICE_NAME))
+            .to_string();
+        Box::pin(async move {
+            Ok(match method.as_str() {
+                "Publish" => {
+                    Grpc::new(ProstCodec::default())
+                        .unary(PublishService(data), request)
+                        .await
+                }
+                "PublishStream" => {
+                    Grpc::new(ProstCodec::default())
+                        .streaming(PublishStreamService(data), request)
+                        .await
+                }
+                "Subscribe" => {
+                    Grpc::new(ProstCodec::default())
+                        .server_streaming(SubscribeService(data), request)
+                        .await
+                }
+                _ => http::Response::builder()
+                    .status(200)
+                    .header("grpc-status", UNIMPLEMENTED)
+                    .header("content-type", "application/grpc")
+                    .body(BoxBody::empty())
+                    .unwrap(),
+            })
+        })
+    }
+}
+
+/// Pushes the event to all subscribers, which subscribed to its type.
+///
+/// The subscribers do not acknowledge the events, so the delivery is best effort:
+/// the event is processed successfully if it was queued for at least one subscriber, otherwise it failed transiently.
+/// A subscriber whose queue is full is disconnected, because it does not keep up with the events.
+fn broadcast(data: &ArcGrpcData, event: OutgoingCloudEvent) {
+    let (id, result, sender_to_kernel) = {
+        let mut data = data.lock().unwrap();
+        let result = match to_proto(&event.cloud_event) {
+            Ok(proto) => {
+                let ty = event.cloud_event.ty();
+                let mut deliveries = 0;
+                data.subscribers.retain(|subscriber_id, subscriber| {
+                    if !subscriber.types.is_empty() && !subscriber.types.iter().any(|t| t == ty) {
+                        return true;
+                    }
+                    match subscriber.sender.try_send(Ok(proto.clone())) {
+                        Ok(()) => {
+                            deliveries += 1;
+                            true
+                        }
+                        Err(e) if e.is_full() => {
+                            warn!("subscriber {} is too slow, disconnect it", subscriber_id);
+                            false
+                        }
+                        Err(_) => {
+                            debug!("subscriber {} disconnected", subscriber_id);
+                            false
+                        }
+                    }
+                });
+                if deliveries == 0 {
+                    warn!("{} has no subscriber for {}", data.id, event.routing_id);
+                    ProcessingResult::TransientError
+                } else {
+                    ProcessingResult::Successful
+                }
+            }
+            Err(e) => {
+                error!("{} failed to convert CloudEvent {:?}", data.id, e);
+                ProcessingResult::PermanentError
+            }
+        };
+        (data.id.clone(), result, data.sender_to_kernel.clone_boxed())
+    };
+    if event.args.delivery_guarantee.requires_acknowledgment() {
+        sender_to_kernel.send(BrokerEvent::OutgoingCloudEventProcessed(
+            OutgoingCloudEventProcessed {
+                sender_id: id,
+                routing_id: event.routing_id,
+                result,
+            },
+        ));
+    }
+}
+
+fn resolve_pending_publish(
+    data: &ArcGrpcData,
+    routing_id: &CloudEventMessageRoutingId,
+    result: ProcessingResult,
+) -> Result<()> {
+    let pending = data
+        .lock()
+        .unwrap()
+        .pending_publishes
+        .remove(routing_id)
+        .with_context(|| format!("pending publish with id={} not found", routing_id))?;
+    if pending.send(result).is_err() {
+        debug!("client disconnected before the response was sent");
+    }
+    Ok(())
+}
+
+/// Ends the streams of the subscribers, shuts the running server down and waits until its listener is closed.
+///
+/// The open connections are drained in the background, they do not block the address.
+fn stop_server(data: &ArcGrpcData) {
+    let (id, server) = {
+        let mut data = data.lock().unwrap();
+        // the streams of the previous server end, so it can shutdown gracefully
+        data.subscribers.clear();
+        (data.id.clone(), data.server.take())
+    };
+    if let Some(server) = server {
+        if server.shutdown.send(()).is_err() {
+            warn!("{} the previous server was already stopped", id);
+        }
+        // the sender is never used, the receiver resolves as soon as it is dropped with the listener
+        let _ = block_on(server.closed);
+        debug!("{} stopped server on {}", id, server.address);
+    }
+}
+
+fn start_server(data: ArcGrpcData, tokio: &Handle) -> Result<()> {
+    let (tx, rx) = oneshot::channel::<()>();
+    let (closed_tx, closed_rx) = oneshot::channel::<()>();
+    let address = data
+        .lock()
+        .unwrap()
+        .config
+        .as_ref()
+        .context("config is not set")?
+        .address;
+
+    let listener = std::net::TcpListener::bind(address)
+        .with_context(|| format!("failed to bind to {}", address))?;
+    listener.set_nonblocking(true)?;
+    let listener = tokio.enter(|| TcpListener::from_std(listener))?;
+    // the server drops the incoming stream, as soon as it receives the shutdown signal
+    let incoming = listener.map(move |stream| {
+        let _ = &closed_tx;
+        stream
+    });
+    data.lock().unwrap().server = Some(RunningServer {
+        address,
+        shutdown: tx,
+        closed: closed_rx,
+    });
+
+    let service = CloudEventService(data);
+    tokio.spawn(async move {
+        let result = Server::builder()
+            .add_service(service)
+            .serve_with_incoming_shutdown(incoming, async {
+                let _ = rx.await;
+            })
+            .await;
+        if let Err(e) = result {
+            error!("grpc server failed: {}", e);
+        }
+    });
+    info!("listening for grpc clients on {}", address);
+    Ok(())
+}
+
+/// Applies the config, the server is only restarted if the address changed.
+fn update(config: Config, data: ArcGrpcData, tokio: &Handle) -> Result<()> {
+    let config = build_config(&config)?;
+    let address = config.address;
+    let restart = {
+        let mut data = data.lock().unwrap();
+        data.config = Some(config);
+        data.server.as_ref().map(|server| server.address) != Some(address)
+    };
+    if restart {
+        // the previous listener has to be closed, before the address could be bound again
+        stop_server(&data);
+        start_server(data, tokio)?;
+    }
+    Ok(())
+}
+
+fn check_health(event: HealthCheckRequest, data: &ArcGrpcData) {
+    let data = data.lock().unwrap();
+    let status = if data.server.is_some() {
+        HealthCheckStatus::Healthy
+    } else {
+        HealthCheckStatus::Unhealthy("server is not started".to_string())
+    };
+    data.sender_to_kernel
+        .send(BrokerEvent::HealthCheckResponse(HealthCheckResponse {
+            status,
+            destination_id: event.sender_id,
+            id: event.id,
+            sender_id: event.destination_id,
+        }))
+}
+
+/// This is the main function to start the port.
+pub fn port_grpc_start(id: InternalServerId, inbox: BoxedReceiver, sender_to_kernel: BoxedSender) {
+    info!("start grpc port with id {}", id);
+    let tokio = tokio::runtime::Runtime::new().unwrap();
+    let data: ArcGrpcData = Arc::new(Mutex::new(GrpcData {
+        id: id.clone(),
+        config: None,
+        server: None,
+        sender_to_kernel,
+        subscribers: HashMap::new(),
+        pending_publishes: HashMap::new(),
+    }));
+
+    loop {
+        match inbox.receive() {
+            BrokerEvent::Init => info!("{} initiated", id),
+            BrokerEvent::ConfigUpdated(config, _) => {
+                info!("{} received ConfigUpdated", id);
+                if let Err(e) = update(config, data.clone(), tokio.handle()) {
+                    error!("{} failed to apply config {:?}", id, e)
+                }
+            }
+            BrokerEvent::OutgoingCloudEvent(event) => broadcast(&data, event),
+            BrokerEvent::IncomingCloudEventProcessed(routing_id, result) => {
+                if let Err(e) = resolve_pending_publish(&data, &routing_id, result) {
+                    warn!("{} IncomingCloudEventProcessed was not handled {:?}", id, e)
+                }
+            }
+            BrokerEvent::HealthCheckRequest(event) => check_health(event, &data),
+            broker_event => warn!("event {} not implemented", broker_event),
+        }
+    }
+}
+
+/// This is the pointer for the main function to start the port.
+pub static PORT_GRPC: InternalServerFnRefStatic = &(port_grpc_start as InternalServerFn);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cerk_runtime_threading::channel::new_channel_with_size;
+    use cloudevents::{Event, EventBuilder, EventBuilderV10};
+    use std::time::Duration;
+    use tokio::runtime::Runtime;
+    use tonic::codegen::http::uri::PathAndQuery;
+    use tonic::transport::Channel;
+
+    fn event(id: &str, ty: &str) -> Event {
+        EventBuilderV10::new()
+            .id(id)
+            .ty(ty)
+            .source("http://example.com")
+            .build()
+            .unwrap()
+    }
+
+    fn free_address() -> SocketAddr {
+        std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+    }
+
+    fn data(delivery_guarantee: DeliveryGuarantee) -> (ArcGrpcData, BoxedReceiver) {
+        let (sender_to_kernel, receiver) = new_channel_with_size(10);
+        let data = Arc::new(Mutex::new(GrpcData {
+            id: "grpc".to_string(),
+            config: Some(GrpcConfig {
+                address: free_address(),
+                delivery_guarantee,
+                max_in_flight: 2,
+                subscriber_queue_size: 2,
+            }),
+            server: None,
+            sender_to_kernel,
+            subscribers: HashMap::new(),
+            pending_publishes: HashMap::new(),
+        }));
+        (data, receiver)
+    }
+
+    fn client(runtime: &mut Runtime, data: &ArcGrpcData) -> tonic::client::Grpc<Channel> {
+        start_server(data.clone(), runtime.handle()).unwrap();
+        let address = data.lock().unwrap().config.as_ref().unwrap().address;
+        let channel = runtime
+            .block_on(
+                Channel::from_shared(format!("http://{}", address))
+                    .unwrap()
+                    .connect(),
+            )
+            .unwrap();
+        tonic::client::Grpc::new(channel)
+    }
+
+    fn path(method: &str) -> PathAndQuery {
+        PathAndQuery::from_maybe_shared(format!("/{}/{}", SERVICE_NAME, method)).unwrap()
+    }
+
+    #[test]
+    fn build_default_config() -> Result<()> {
+        let config = build_config(&Config::HashMap(HashMap::new()))?;
+        assert_eq!(config.address, "0.0.0.0:50051".parse()?);
+        assert_eq!(config.delivery_guarantee, DeliveryGuarantee::BestEffort);
+        Ok(())
+    }
+
+    #[test]
+    fn publish_and_acknowledge() {
+        let mut runtime = Runtime::new().unwrap();
+        let (data, kernel) = data(DeliveryGuarantee::AtLeastOnce);
+        let mut client = client(&mut runtime, &data);
+
+        let response = runtime.spawn(async move {
+            client.ready().await.unwrap();
+            client
+                .unary::<_, PublishResponse, _>(
+                    Request::new(to_proto(&event("1", "test")).unwrap()),
+                    path("Publish"),
+                    ProstCodec::default(),
+                )
+                .await
+        });
+        let routing_id = match kernel.receive_timeout(Duration::from_secs(1)) {
+            Some(BrokerEvent::IncomingCloudEvent(incoming)) => {
+                assert_eq!(incoming.cloud_event, event("1", "test"));
+                incoming.routing_id
+            }
+            _ => panic!("expected IncomingCloudEvent"),
+        };
+        resolve_pending_publish(&data, &routing_id, ProcessingResult::PermanentError).unwrap();
+        let response = runtime.block_on(response).unwrap().unwrap().into_inner();
+        assert_eq!(
+            response,
+            PublishResponse {
+                id: "1".to_string(),
+                result: ProtoProcessingResult::PermanentError as i32,
+            }
+        );
+    }
+
+    #[test]
+    fn publish_stream_with_invalid_event() {
+        let mut runtime = Runtime::new().unwrap();
+        let (data, kernel) = data(DeliveryGuarantee::BestEffort);
+        let mut client = client(&mut runtime, &data);
+
+        let mut invalid = to_proto(&event("2", "test")).unwrap();
+        invalid.spec_version = "0.3".to_string();
+        let events = futures::stream::iter(vec![to_proto(&event("1", "test")).unwrap(), invalid]);
+        let mut responses = runtime.block_on(async move {
+            client.ready().await.unwrap();
+            let responses = client
+                .streaming::<_, _, PublishResponse, _>(
+                    Request::new(events),
+                    path("PublishStream"),
+                    ProstCodec::default(),
+                )
+                .await
+                .unwrap()
+                .into_inner();
+            let mut collected = vec![];
+            futures::pin_mut!(responses);
+            while let Some(response) = responses.message().await.unwrap() {
+                collected.push((response.id, response.result));
+            }
+            collected
+        });
+        responses.sort();
+        assert_eq!(
+            responses,
+            vec![
+                ("1".to_string(), ProtoProcessingResult::Successful as i32),
+                (
+                    "2".to_string(),
+                    ProtoProcessingResult::PermanentError as i32
+                ),
+            ]
+        );
+        assert!(matches!(
+            kernel.receive_timeout(Duration::from_secs(1)),
+            Some(BrokerEvent::IncomingCloudEvent(_))
+        ));
+    }
+
+    #[test]
+    fn subscribe_to_types() {
+        let mut runtime = Runtime::new().unwrap();
+        let (data, kernel) = data(DeliveryGuarantee::BestEffort);
+        let mut client = client(&mut runtime, &data);
+        let outgoing = |id: &str, ty: &str| OutgoingCloudEvent {
+            routing_id: id.to_string(),
+            cloud_event: event(id, ty),
+            destination_id: "grpc".to_string(),
+            args: CloudEventRoutingArgs {
+                delivery_guarantee: DeliveryGuarantee::AtLeastOnce,
+            },
+        };
+
+        broadcast(&data, outgoing("0", "test"));
+        match kernel.receive_timeout(Duration::from_secs(1)) {
+            Some(BrokerEvent::OutgoingCloudEventProcessed(processed)) => {
+                assert_eq!(processed.result, ProcessingResult::TransientError)
+            }
+            _ => panic!("expected OutgoingCloudEventProcessed"),
+        }
+
+        let mut events = runtime.block_on(async {
+            client.ready().await.unwrap();
+            client
+                .server_streaming::<_, ProtoCloudEvent, _>(
+                    Request::new(SubscribeRequest {
+                        types: vec!["test".to_string()],
+                    }),
+                    path("Subscribe"),
+                    ProstCodec::default(),
+                )
+                .await
+                .unwrap()
+                .into_inner()
+        });
+        broadcast(&data, outgoing("1", "other"));
+        broadcast(&data, outgoing("2", "test"));
+        let received = runtime.block_on(events.message()).unwrap().unwrap();
+        assert_eq!(from_proto(received).unwrap(), event("2", "test"));
+        // the event of the other type was not delivered to any subscriber
+        for expected in [
+            ProcessingResult::TransientError,
+            ProcessingResult::Successful,
+        ] {
+            match kernel.receive_timeout(Duration::from_secs(1)) {
+                Some(BrokerEvent::OutgoingCloudEventProcessed(processed)) => {
+                    assert_eq!(processed.result, expected)
+                }
+                _ => panic!("expected OutgoingCloudEventProcessed"),
+            }
+        }
+    }
+
+    #[test]
+    fn slow_subscriber_is_disconnected() {
+        let mut runtime = Runtime::new().unwrap();
+        let (data, kernel) = data(DeliveryGuarantee::BestEffort);
+        let mut client = client(&mut runtime, &data);
+        let _events = runtime.block_on(async {
+            client.ready().await.unwrap();
+            client
+                .server_streaming::<_, ProtoCloudEvent, _>(
+                    Request::new(SubscribeRequest { types: vec![] }),
+                    path("Subscribe"),
+                    ProstCodec::default(),
+                )
+                .await
+                .unwrap()
+                .into_inner()
+        });
+        let outgoing = |id: &str| OutgoingCloudEvent {
+            routing_id: id.to_string(),
+            cloud_event: event(id, "test"),
+            destination_id: "grpc".to_string(),
+            args: CloudEventRoutingArgs {
+                delivery_guarantee: DeliveryGuarantee::AtLeastOnce,
+            },
+        };
+
+        // the stream is not read, so the queue of the subscriber fills up
+        let mut results = vec![];
+        for id in 0..5 {
+            broadcast(&data, outgoing(id.to_string().as_str()));
+            match kernel.receive_timeout(Duration::from_secs(1)) {
+                Some(BrokerEvent::OutgoingCloudEventProcessed(processed)) => {
+                    results.push(processed.result)
+                }
+                _ => panic!("expected OutgoingCloudEventProcessed"),
+            }
+        }
+        assert_eq!(
+            results.last(),
+            Some(&ProcessingResult::TransientError),
+            "{:?}",
+            results
+        );
+        assert!(data.lock().unwrap().subscribers.is_empty());
+    }
+
+    #[test]
+    fn config_update_rebinds_the_address() -> Result<()> {
+        let mut runtime = Runtime::new().unwrap();
+        let (data, _kernel) = data(DeliveryGuarantee::BestEffort);
+        let mut client = client(&mut runtime, &data);
+        let config = |address: SocketAddr| {
+            Config::HashMap(
+                [
+                    (
+                        "ip_addr".to_string(),
+                        Config::String(address.ip().to_string()),
+                    ),
+                    ("grpc_port".to_string(), Config::U32(address.port() as u32)),
+                ]
+                .iter()
+                .cloned()
+                .collect(),
+            )
+        };
+        let first = data.lock().unwrap().config.as_ref().unwrap().address;
+        let second = free_address();
+
+        // an open stream does not keep the previous listener alive
+        let mut events = runtime.block_on(async {
+            client.ready().await.unwrap();
+            client
+                .server_streaming::<_, ProtoCloudEvent, _>(
+                    Request::new(SubscribeRequest { types: vec![] }),
+                    path("Subscribe"),
+                    ProstCodec::default(),
+                )
+                .await
+                .unwrap()
+                .into_inner()
+        });
+        update(config(first), data.clone(), runtime.handle())?;
+        assert_eq!(data.lock().unwrap().subscribers.len(), 1);
+        update(config(second), data.clone(), runtime.handle())?;
+        assert!(data.lock().unwrap().subscribers.is_empty());
+        assert!(runtime.block_on(events.message()).unwrap().is_none());
+        // the first address was released by the previous server
+        update(config(first), data.clone(), runtime.handle())?;
+        stop_server(&data);
+        Ok(())
+    }
+}
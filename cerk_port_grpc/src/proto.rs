@@ -0,0 +1,90 @@
+//! The messages of `proto/cloudevents.proto` and `proto/cerk.proto`.
+//!
+//! They are written by hand instead of generated by `tonic-build`, so the build does not depend on `protoc`.
+
+// the variant names follow the field names of the proto files
+#![allow(clippy::enum_variant_names)]
+
+use std::collections::HashMap;
+
+/// `io.cloudevents.v1.CloudEvent`
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CloudEvent {
+    #[prost(string, tag = "1")]
+    pub id: String,
+    #[prost(string, tag = "2")]
+    pub source: String,
+    #[prost(string, tag = "3")]
+    pub spec_version: String,
+    #[prost(string, tag = "4")]
+    pub r#type: String,
+    #[prost(map = "string, message", tag = "5")]
+    pub attributes: HashMap<String, CloudEventAttributeValue>,
+    #[prost(oneof = "cloud_event::Data", tags = "6, 7, 8")]
+    pub data: Option<cloud_event::Data>,
+}
+
+pub mod cloud_event {
+    #[derive(Clone, PartialEq, ::prost::Oneof)]
+    pub enum Data {
+        #[prost(bytes, tag = "6")]
+        BinaryData(Vec<u8>),
+        #[prost(string, tag = "7")]
+        TextData(String),
+        #[prost(message, tag = "8")]
+        ProtoData(::prost_types::Any),
+    }
+}
+
+/// `io.cloudevents.v1.CloudEvent.CloudEventAttributeValue`
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CloudEventAttributeValue {
+    #[prost(oneof = "attribute_value::Attr", tags = "1, 2, 3, 4, 5, 6, 7")]
+    pub attr: Option<attribute_value::Attr>,
+}
+
+pub mod attribute_value {
+    #[derive(Clone, PartialEq, ::prost::Oneof)]
+    pub enum Attr {
+        #[prost(bool, tag = "1")]
+        CeBoolean(bool),
+        #[prost(int32, tag = "2")]
+        CeInteger(i32),
+        #[prost(string, tag = "3")]
+        CeString(String),
+        #[prost(bytes, tag = "4")]
+        CeBytes(Vec<u8>),
+        #[prost(string, tag = "5")]
+        CeUri(String),
+        #[prost(string, tag = "6")]
+        CeUriRef(String),
+        #[prost(message, tag = "7")]
+        CeTimestamp(::prost_types::Timestamp),
+    }
+}
+
+/// `cerk.v1.ProcessingResult`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum ProcessingResult {
+    Successful = 0,
+    TransientError = 1,
+    PermanentError = 2,
+    Timeout = 3,
+}
+
+/// `cerk.v1.PublishResponse`
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct PublishResponse {
+    #[prost(string, tag = "1")]
+    pub id: String,
+    #[prost(enumeration = "ProcessingResult", tag = "2")]
+    pub result: i32,
+}
+
+/// `cerk.v1.SubscribeRequest`
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct SubscribeRequest {
+    #[prost(string, repeated, tag = "1")]
+    pub types: Vec<String>,
+}
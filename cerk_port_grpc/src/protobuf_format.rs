@@ -0,0 +1,226 @@
+use crate::proto::attribute_value::Attr;
+use crate::proto::cloud_event::Data as ProtoData;
+use crate::proto::{CloudEvent, CloudEventAttributeValue};
+use anyhow::{Context, Result};
+use chrono::{DateTime, TimeZone, Utc};
+use cloudevents::event::{AttributeValue, ExtensionValue};
+use cloudevents::{AttributesReader, AttributesWriter, Data, Event, EventBuilder, EventBuilderV10};
+use prost_types::Timestamp;
+use std::convert::TryFrom;
+use url::Url;
+
+const SPEC_VERSION: &str = "1.0";
+
+fn attribute(attr: Attr) -> CloudEventAttributeValue {
+    CloudEventAttributeValue { attr: Some(attr) }
+}
+
+fn to_timestamp(time: &DateTime<Utc>) -> Timestamp {
+    Timestamp {
+        seconds: time.timestamp(),
+        nanos: time.timestamp_subsec_nanos() as i32,
+    }
+}
+
+fn is_json(content_type: Option<&str>) -> bool {
+    content_type.is_some_and(|c| c.contains("json"))
+}
+
+/// Converts the event to the [Protobuf event format](https://github.com/cloudevents/spec/blob/master/protobuf-format.md).
+pub fn to_proto(event: &Event) -> Result<CloudEvent> {
+    if event.specversion().as_str() != SPEC_VERSION {
+        bail!("spec version {} is not supported", event.specversion());
+    }
+    let mut proto = CloudEvent {
+        id: event.id().to_string(),
+        source: event.source().to_string(),
+        spec_version: SPEC_VERSION.to_string(),
+        r#type: event.ty().to_string(),
+        ..Default::default()
+    };
+    for (name, value) in event.iter() {
+        let attr = match value {
+            _ if matches!(name, "id" | "source" | "specversion" | "type") => continue,
+            AttributeValue::SpecVersion(v) => Attr::CeString(v.to_string()),
+            AttributeValue::String(s) => Attr::CeString(s.to_string()),
+            AttributeValue::URI(uri) => Attr::CeUri(uri.to_string()),
+            AttributeValue::URIRef(uri) => Attr::CeUriRef(uri.to_string()),
+            AttributeValue::Boolean(b) => Attr::CeBoolean(*b),
+            // the protobuf format only supports 32 bit integers
+            AttributeValue::Integer(i) => match i32::try_from(*i) {
+                Ok(i) => Attr::CeInteger(i),
+                Err(_) => Attr::CeString(i.to_string()),
+            },
+            AttributeValue::Time(time) => Attr::CeTimestamp(to_timestamp(time)),
+        };
+        proto.attributes.insert(name.to_string(), attribute(attr));
+    }
+    proto.data = match event.data() {
+        Some(Data::Binary(data)) => Some(ProtoData::BinaryData(data.clone())),
+        Some(Data::String(data)) => Some(ProtoData::TextData(data.clone())),
+        Some(Data::Json(data)) => Some(ProtoData::TextData(serde_json::to_string(data)?)),
+        None => None,
+    };
+    Ok(proto)
+}
+
+fn attribute_string(name: &str, value: &CloudEventAttributeValue) -> Result<String> {
+    Ok(match value.attr {
+        Some(Attr::CeString(ref s)) | Some(Attr::CeUri(ref s)) | Some(Attr::CeUriRef(ref s)) => {
+            s.clone()
+        }
+        _ => bail!("attribute {} has to be a string", name),
+    })
+}
+
+/// Converts an event in the Protobuf event format, `proto_data` is not supported.
+pub fn from_proto(proto: CloudEvent) -> Result<Event> {
+    if proto.spec_version != SPEC_VERSION {
+        bail!("spec version {} is not supported", proto.spec_version);
+    }
+    if proto.id.is_empty() || proto.source.is_empty() || proto.r#type.is_empty() {
+        bail!("id, source and type are required");
+    }
+    let mut event = EventBuilderV10::new()
+        .id(proto.id)
+        .source(proto.source)
+        .ty(proto.r#type)
+        .build()
+        .map_err(|e| anyhow!("invalid event: {}", e))?;
+    let mut content_type = None;
+    for (name, value) in proto.attributes.iter() {
+        match name.as_str() {
+            "datacontenttype" => content_type = Some(attribute_string(name, value)?),
+            "dataschema" => {
+                event.set_dataschema(Some(Url::parse(&attribute_string(name, value)?)?));
+            }
+            "subject" => {
+                event.set_subject(Some(attribute_string(name, value)?));
+            }
+            "time" => {
+                let time = match value.attr {
+                    Some(Attr::CeTimestamp(ref ts)) => Utc
+                        .timestamp_opt(ts.seconds, ts.nanos as u32)
+                        .single()
+                        .context("invalid time")?,
+                    _ => DateTime::parse_from_rfc3339(&attribute_string(name, value)?)?
+                        .with_timezone(&Utc),
+                };
+                event.set_time(Some(time));
+            }
+            _ => {
+                let extension = match value.attr {
+                    Some(Attr::CeBoolean(b)) => ExtensionValue::Boolean(b),
+                    Some(Attr::CeInteger(i)) => ExtensionValue::Integer(i as i64),
+                    Some(Attr::CeBytes(ref bytes)) => ExtensionValue::String(base64::encode(bytes)),
+                    Some(Attr::CeTimestamp(ref ts)) => ExtensionValue::String(
+                        Utc.timestamp_opt(ts.seconds, ts.nanos as u32)
+                            .single()
+                            .context("invalid timestamp")?
+                            .to_rfc3339(),
+                    ),
+                    _ => ExtensionValue::String(attribute_string(name, value)?),
+                };
+                event.set_extension(name, extension);
+            }
+        }
+    }
+    let data = match proto.data {
+        Some(ProtoData::BinaryData(data)) => Some(Data::Binary(data)),
+        Some(ProtoData::TextData(data)) if is_json(content_type.as_deref()) => {
+            Some(Data::Json(serde_json::from_str(&data)?))
+        }
+        Some(ProtoData::TextData(data)) => Some(Data::String(data)),
+        Some(ProtoData::ProtoData(_)) => bail!("proto_data is not supported"),
+        None => None,
+    };
+    event.set_datacontenttype(content_type);
+    if let Some(data) = data {
+        event.set_data_unchecked(data);
+    }
+    Ok(event)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn event() -> Event {
+        EventBuilderV10::new()
+            .id("1")
+            .ty("test")
+            .source("http://example.com")
+            .subject("orders")
+            .time(Utc.timestamp_opt(1_600_000_000, 5).unwrap())
+            .extension("tenant", "a")
+            .extension("attempt", 2)
+            .data("application/json", json!({"hello": "world"}))
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn round_trip() -> Result<()> {
+        let proto = to_proto(&event())?;
+        assert_eq!(proto.spec_version, "1.0");
+        assert_eq!(
+            proto.attributes.get("attempt"),
+            Some(&attribute(Attr::CeInteger(2)))
+        );
+        assert_eq!(
+            proto.attributes.get("time"),
+            Some(&attribute(Attr::CeTimestamp(Timestamp {
+                seconds: 1_600_000_000,
+                nanos: 5,
+            })))
+        );
+        assert_eq!(from_proto(proto)?, event());
+        Ok(())
+    }
+
+    #[test]
+    fn binary_and_text_data() -> Result<()> {
+        let mut proto = CloudEvent {
+            id: "1".to_string(),
+            source: "http://example.com".to_string(),
+            spec_version: "1.0".to_string(),
+            r#type: "test".to_string(),
+            data: Some(ProtoData::BinaryData(vec![1, 2])),
+            ..Default::default()
+        };
+        proto.attributes.insert(
+            "signature".to_string(),
+            attribute(Attr::CeBytes(b"abc".to_vec())),
+        );
+        let event = from_proto(proto.clone())?;
+        assert_eq!(event.data(), Some(&Data::Binary(vec![1, 2])));
+        assert_eq!(
+            event.extension("signature"),
+            Some(&ExtensionValue::String("YWJj".to_string()))
+        );
+
+        proto.data = Some(ProtoData::TextData("hello".to_string()));
+        assert_eq!(
+            from_proto(proto)?.data(),
+            Some(&Data::String("hello".to_string()))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_events() {
+        let valid = to_proto(&event()).unwrap();
+        let mut proto = valid.clone();
+        proto.spec_version = "0.3".to_string();
+        assert!(from_proto(proto).is_err());
+        let mut proto = valid.clone();
+        proto.id = String::new();
+        assert!(from_proto(proto).is_err());
+        let mut proto = valid;
+        proto
+            .attributes
+            .insert("subject".to_string(), attribute(Attr::CeBoolean(true)));
+        assert!(from_proto(proto).is_err());
+    }
+}
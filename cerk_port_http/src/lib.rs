@@ -0,0 +1,124 @@
+/*!
+
+This is a package for [CERK](https://github.com/ce-rust/cerk).
+CERK is an open source [CloudEvents](https://github.com/cloudevents/spec) Router written in Rust with a MicroKernel architecture.
+
+# Introduction
+
+CERK lets you route your [CloudEvents](https://github.com/cloudevents/spec) between different different ports.
+Ports are transport layer bindings over which CloudEvents can be exchanged.
+It is built with modularity and portability in mind.
+
+# Components
+
+CERK comes with a couple of prefabricated components, but implementing custom components is easy.
+
+A good overview is provided on [GitHub](https://github.com/ce-rust/cerk/).
+
+# This Crate: HTTP Ports
+
+## HTTP Input Port
+
+The port `port_input_http` receives CloudEvents with `POST` requests on an HTTP server.
+
+### Content Modes
+
+The port supports all content modes of the [HTTP protocol binding](https://github.com/cloudevents/spec/blob/master/http-protocol-binding.md):
+
+* binary content mode: the attributes are sent as `ce-` headers
+* structured content mode: the event is sent with the content type `application/cloudevents+json`
+* batched content mode: a JSON array of events is sent with the content type `application/cloudevents-batch+json`
+
+### Configurations
+
+| Name                 | Type | Default   | Description                                                        |
+|----------------------|------|-----------|--------------------------------------------------------------------|
+| `ip_addr`            | String | `0.0.0.0` | the address the server listens on                                |
+| `http_port`          | u32  | `8080`    | the port the server listens on                                     |
+| `delivery_guarantee` | u8   | `0`       | `0` best effort, `2` at least once                                 |
+| `timeout_ms`         | u32  | `10000`   | how long a request waits for the processing results (at least once) |
+| `max_body_size`      | u32  | `1048576` | the maximal size of a request body in bytes                        |
+
+### Responses
+
+With the delivery guarantee best effort, the request is answered with `202 Accepted` as soon as the events are forwarded to the kernel.
+
+With the delivery guarantee at least once, the response is held until all events of the request are processed by the output ports.
+For a batch, the result which requires a retry wins.
+
+| Result              | Status code                  |
+|---------------------|------------------------------|
+| successful          | `202 Accepted`               |
+| permanent error     | `422 Unprocessable Entity`   |
+| transient error     | `503 Service Unavailable`    |
+| timeout             | `504 Gateway Timeout`        |
+| invalid CloudEvent  | `400 Bad Request`            |
+| body too large      | `413 Payload Too Large`      |
+
+### Example
+
+```bash
+curl -X POST http://127.0.0.1:8080 \
+  -H 'ce-specversion: 1.0' -H 'ce-id: 1' -H 'ce-type: example' -H 'ce-source: curl' \
+  -H 'content-type: text/plain' -d 'hello'
+```
+
+## HTTP Output Port
+
+The port `port_output_http` sends CloudEvents with `POST` requests to one or more webhooks.
+
+### Configurations
+
+| Name                 | Type   | Default            | Description                                                 |
+|----------------------|--------|--------------------|-------------------------------------------------------------|
+| `url`                | String |                    | the URL of the webhook, `http` and `https` are supported    |
+| `destinations`       | Array  |                    | several webhooks instead of `url`, see below                |
+| `content_mode`       | String | `binary`           | `binary` or `structured`                                     |
+| `headers`            | Map    |                    | additional headers, e.g., `authorization`                   |
+| `timeout_ms`         | u32    | `10000`            | the timeout of a single request                              |
+| `hmac_secret`        | String |                    | if set, the body is signed with HMAC-SHA256                 |
+| `signature_header`   | String | `x-cerk-signature` | the header of the signature, the value is `sha256=<hex>`    |
+| `max_retries`        | u8     | `0`                | how many times transient errors and timeouts are retried    |
+| `retry_delay_ms`     | u32    | `1000`             | the delay before the first retry, it is doubled every retry |
+
+Each entry of `destinations` is a map with its own `url`, `headers`, `hmac_secret` and `signature_header`,
+the other options apply to all destinations.
+
+```json
+{
+  "destinations": [
+    { "url": "https://example.com/hook", "headers": { "authorization": "Bearer token" } },
+    { "url": "https://example.org/hook", "hmac_secret": "secret" }
+  ],
+  "max_retries": 3
+}
+```
+
+### Processing Results
+
+| Response                           | Result            |
+|------------------------------------|-------------------|
+| `2xx`                              | successful        |
+| `408`, `429`, `5xx`, network error | transient error   |
+| other `4xx`                        | permanent error   |
+| no response within `timeout_ms`    | timeout           |
+
+The event is sent to all destinations at once and the result which requires a retry is sent back to the kernel,
+so a retry of the kernel sends the event to all destinations again.
+The result is only sent back to the kernel if the delivery guarantee requires an acknowledgment.
+
+*/
+
+#![deny(missing_docs)]
+
+#[macro_use]
+extern crate log;
+
+#[macro_use]
+extern crate anyhow;
+
+mod port_input_http;
+mod port_output_http;
+
+pub use self::port_input_http::{port_input_http_start, PORT_INPUT_HTTP};
+pub use self::port_output_http::{port_output_http_start, PORT_OUTPUT_HTTP};
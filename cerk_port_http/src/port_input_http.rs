@@ -0,0 +1,649 @@
+use anyhow::{Context, Result};
+use cerk::kernel::{
+    BrokerEvent, CloudEventMessageRoutingId, CloudEventRoutingArgs, Config, ConfigHelpers,
+    DeliveryGuarantee, HealthCheckRequest, HealthCheckResponse, HealthCheckStatus,
+    IncomingCloudEvent, ProcessingResult,
+};
+use cerk::runtime::channel::{BoxedReceiver, BoxedSender};
+use cerk::runtime::{InternalServerFn, InternalServerFnRefStatic, InternalServerId};
+use cloudevents::binding::http::to_event;
+use cloudevents::Event;
+use futures::executor::block_on;
+use futures::future::join_all;
+use hyper::body::HttpBody;
+use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Error, HeaderMap, Method, Request, Response, Server, StatusCode};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::runtime::Handle;
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+use tokio::time::timeout;
+use uuid::Uuid;
+
+const BATCH_CONTENT_TYPE: &str = "application/cloudevents-batch+json";
+const DEFAULT_TIMEOUT_MS: u32 = 10_000;
+const DEFAULT_MAX_BODY_SIZE: u32 = 1024 * 1024;
+
+type ArcHttpInputData = Arc<Mutex<HttpInputData>>;
+
+struct HttpInputConfig {
+    address: SocketAddr,
+    delivery_guarantee: DeliveryGuarantee,
+    timeout: Duration,
+    /// the maximal size of a request body in bytes
+    max_body_size: usize,
+}
+
+struct RunningServer {
+    address: SocketAddr,
+    shutdown: oneshot::Sender<()>,
+    /// finishes after the listener was closed
+    task: JoinHandle<()>,
+}
+
+struct HttpInputData {
+    id: InternalServerId,
+    config: Option<HttpInputConfig>,
+    server: Option<RunningServer>,
+    sender_to_kernel: BoxedSender,
+    pending_deliveries: HashMap<CloudEventMessageRoutingId, oneshot::Sender<ProcessingResult>>,
+}
+
+fn build_config(config: &Config) -> Result<HttpInputConfig> {
+    let ip_addr: IpAddr = config
+        .get_op_val_string("ip_addr")?
+        .unwrap_or_else(|| "0.0.0.0".to_string())
+        .parse()?;
+    let port = config.get_op_val_u32("http_port")?.unwrap_or(8080) as u16;
+    let delivery_guarantee = match config.get_op_val_config("delivery_guarantee")? {
+        Some(c) => DeliveryGuarantee::try_from(c)?,
+        None => DeliveryGuarantee::BestEffort,
+    };
+    let timeout = config
+        .get_op_val_u32("timeout_ms")?
+        .unwrap_or(DEFAULT_TIMEOUT_MS);
+    let max_body_size = config
+        .get_op_val_u32("max_body_size")?
+        .unwrap_or(DEFAULT_MAX_BODY_SIZE);
+    Ok(HttpInputConfig {
+        address: SocketAddr::new(ip_addr, port),
+        delivery_guarantee,
+        timeout: Duration::from_millis(timeout as u64),
+        max_body_size: max_body_size as usize,
+    })
+}
+
+/// Parses the CloudEvents of a request in binary, structured or batched content mode.
+fn parse_events(headers: &HeaderMap, body: Vec<u8>) -> Result<Vec<Event>> {
+    let content_type = headers
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+    if content_type.starts_with(BATCH_CONTENT_TYPE) {
+        serde_json::from_slice(&body).context("failed to parse batch of CloudEvents")
+    } else {
+        Ok(vec![
+            to_event(headers, body).context("failed to parse CloudEvent")?
+        ])
+    }
+}
+
+/// Combines the results of a batch, a retry is preferred, because the events could be delivered multiple times anyway.
+pub(crate) fn combine_results(results: Vec<ProcessingResult>) -> ProcessingResult {
+    let priority = |r: &ProcessingResult| match r {
+        ProcessingResult::Successful => 0,
+        ProcessingResult::PermanentError => 1,
+        ProcessingResult::Timeout => 2,
+        ProcessingResult::TransientError => 3,
+    };
+    results
+        .into_iter()
+        .max_by_key(priority)
+        .unwrap_or(ProcessingResult::Successful)
+}
+
+fn status_code(result: &ProcessingResult) -> StatusCode {
+    match result {
+        ProcessingResult::Successful => StatusCode::ACCEPTED,
+        ProcessingResult::TransientError => StatusCode::SERVICE_UNAVAILABLE,
+        ProcessingResult::Timeout => StatusCode::GATEWAY_TIMEOUT,
+        ProcessingResult::PermanentError => StatusCode::UNPROCESSABLE_ENTITY,
+    }
+}
+
+fn response(status: StatusCode, message: String) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .body(Body::from(message))
+        .unwrap()
+}
+
+/// Reads the body, `None` is returned if it is larger than `max_body_size`.
+async fn read_body(
+    headers: &HeaderMap,
+    mut body: Body,
+    max_body_size: usize,
+) -> Result<Option<Vec<u8>>, Error> {
+    let content_length = headers
+        .get(CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<usize>().ok());
+    if matches!(content_length, Some(length) if length > max_body_size) {
+        return Ok(None);
+    }
+    // the length is checked while reading, because a chunked body has no content length
+    let mut bytes = Vec::with_capacity(content_length.unwrap_or_default());
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk?;
+        if bytes.len() + chunk.len() > max_body_size {
+            return Ok(None);
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+    Ok(Some(bytes))
+}
+
+async fn handle_request(
+    request: Request<Body>,
+    data: ArcHttpInputData,
+) -> Result<Response<Body>, Error> {
+    if request.method() != Method::POST {
+        return Ok(response(
+            StatusCode::METHOD_NOT_ALLOWED,
+            "only POST is supported".to_string(),
+        ));
+    }
+
+    let (id, delivery_guarantee, timeout_duration, max_body_size) = {
+        let data = data.lock().unwrap();
+        match data.config.as_ref() {
+            Some(config) => (
+                data.id.clone(),
+                config.delivery_guarantee,
+                config.timeout,
+                config.max_body_size,
+            ),
+            None => {
+                return Ok(response(
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    "port is not configured".to_string(),
+                ))
+            }
+        }
+    };
+
+    let (parts, body) = request.into_parts();
+    let body = match read_body(&parts.headers, body, max_body_size).await? {
+        Some(body) => body,
+        None => {
+            return Ok(response(
+                StatusCode::PAYLOAD_TOO_LARGE,
+                format!("the body is larger than {} bytes", max_body_size),
+            ))
+        }
+    };
+    let events = match parse_events(&parts.headers, body) {
+        Ok(events) => events,
+        Err(e) => return Ok(response(StatusCode::BAD_REQUEST, format!("{:#}", e))),
+    };
+    debug!("{} received {} CloudEvent(s)", id, events.len());
+
+    let mut receivers = Vec::new();
+    let mut routing_ids = Vec::new();
+    let mut broker_events = Vec::new();
+    for cloud_event in events {
+        let routing_id = Uuid::new_v4().to_string();
+        if delivery_guarantee.requires_acknowledgment() {
+            routing_ids.push(routing_id.clone());
+        }
+        broker_events.push(BrokerEvent::IncomingCloudEvent(IncomingCloudEvent {
+            incoming_id: id.clone(),
+            routing_id,
+            cloud_event,
+            args: CloudEventRoutingArgs { delivery_guarantee },
+        }));
+    }
+    // the pending deliveries are registered before the events are sent, so no result could get lost
+    let sender_to_kernel = {
+        let mut data = data.lock().unwrap();
+        for routing_id in routing_ids.iter() {
+            let (tx, rx) = oneshot::channel();
+            data.pending_deliveries.insert(routing_id.clone(), tx);
+            receivers.push(rx);
+        }
+        data.sender_to_kernel.clone_boxed()
+    };
+    // the send blocks if the kernel inbox is full, so it must neither hold the lock nor block the tokio worker
+    let sent = tokio::task::spawn_blocking(move || {
+        for broker_event in broker_events {
+            sender_to_kernel.send(broker_event);
+        }
+    })
+    .await;
+    if let Err(e) = sent {
+        error!(
+            "{} failed to send the CloudEvents to the kernel {:?}",
+            id, e
+        );
+        remove_pending_deliveries(&data, &routing_ids);
+        return Ok(response(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "failed to forward the CloudEvents".to_string(),
+        ));
+    }
+
+    if !delivery_guarantee.requires_acknowledgment() {
+        return Ok(response(StatusCode::ACCEPTED, "accepted".to_string()));
+    }
+
+    let result = match timeout(timeout_duration, join_all(receivers)).await {
+        Ok(results) => combine_results(
+            results
+                .into_iter()
+                .map(|r| r.unwrap_or(ProcessingResult::TransientError))
+                .collect(),
+        ),
+        Err(_) => {
+            warn!(
+                "{} did not receive all results in {:?}",
+                id, timeout_duration
+            );
+            remove_pending_deliveries(&data, &routing_ids);
+            ProcessingResult::Timeout
+        }
+    };
+    Ok(response(status_code(&result), result.to_string()))
+}
+
+fn remove_pending_deliveries(data: &ArcHttpInputData, routing_ids: &[CloudEventMessageRoutingId]) {
+    let mut data = data.lock().unwrap();
+    for routing_id in routing_ids {
+        data.pending_deliveries.remove(routing_id);
+    }
+}
+
+/// Shuts the running server down and waits until its listener is closed.
+fn stop_server(data: &ArcHttpInputData) {
+    let (id, server) = {
+        let mut data = data.lock().unwrap();
+        // the open requests are answered with 503, so the graceful shutdown does not wait for their timeout
+        data.pending_deliveries.clear();
+        (data.id.clone(), data.server.take())
+    };
+    if let Some(server) = server {
+        if server.shutdown.send(()).is_err() {
+            warn!("{} the previous server was already stopped", id);
+        }
+        if let Err(e) = block_on(server.task) {
+            warn!("{} the previous server failed {:?}", id, e);
+        }
+        debug!("{} stopped server on {}", id, server.address);
+    }
+}
+
+fn start_server(data: ArcHttpInputData, tokio: &Handle) -> Result<()> {
+    let (tx, rx) = oneshot::channel::<()>();
+    let address = data
+        .lock()
+        .unwrap()
+        .config
+        .as_ref()
+        .context("config is not set")?
+        .address;
+
+    let builder = tokio
+        .enter(|| Server::try_bind(&address))
+        .with_context(|| format!("failed to bind to {}", address))?;
+    let make_svc = {
+        let data = data.clone();
+        make_service_fn(move |_| {
+            let data = data.clone();
+            async move {
+                Ok::<_, Error>(service_fn(move |request| {
+                    handle_request(request, data.clone())
+                }))
+            }
+        })
+    };
+    let task = tokio.spawn(async move {
+        let server = builder.serve(make_svc).with_graceful_shutdown(async {
+            rx.await.ok();
+        });
+        if let Err(e) = server.await {
+            error!("server error: {}", e);
+        }
+    });
+    data.lock().unwrap().server = Some(RunningServer {
+        address,
+        shutdown: tx,
+        task,
+    });
+    info!("listening for CloudEvents on http://{}", address);
+    Ok(())
+}
+
+/// Applies the config, the server is only restarted if the address changed.
+fn update(config: Config, data: ArcHttpInputData, tokio: &Handle) -> Result<()> {
+    let config = build_config(&config)?;
+    let address = config.address;
+    let restart = {
+        let mut data = data.lock().unwrap();
+        data.config = Some(config);
+        data.server.as_ref().map(|server| server.address) != Some(address)
+    };
+    if restart {
+        // the previous listener has to be closed, before the address could be bound again
+        stop_server(&data);
+        start_server(data, tokio)?;
+    }
+    Ok(())
+}
+
+fn resolve_pending_delivery(
+    data: &ArcHttpInputData,
+    routing_id: &CloudEventMessageRoutingId,
+    result: ProcessingResult,
+) -> Result<()> {
+    let pending = data
+        .lock()
+        .unwrap()
+        .pending_deliveries
+        .remove(routing_id)
+        .with_context(|| format!("pending delivery with id={} not found", routing_id))?;
+    if pending.send(result).is_err() {
+        bail!(
+            "the request of routing_id={} was already closed",
+            routing_id
+        )
+    }
+    Ok(())
+}
+
+fn check_health(event: HealthCheckRequest, data: &ArcHttpInputData) {
+    let data = data.lock().unwrap();
+    let status = if data.server.is_some() {
+        HealthCheckStatus::Healthy
+    } else {
+        HealthCheckStatus::Unhealthy("server is not started".to_string())
+    };
+    data.sender_to_kernel
+        .send(BrokerEvent::HealthCheckResponse(HealthCheckResponse {
+            status,
+            destination_id: event.sender_id,
+            id: event.id,
+            sender_id: event.destination_id,
+        }))
+}
+
+/// This is the main function to start the port.
+pub fn port_input_http_start(
+    id: InternalServerId,
+    inbox: BoxedReceiver,
+    sender_to_kernel: BoxedSender,
+) {
+    info!("start http input port with id {}", id);
+    let tokio = tokio::runtime::Runtime::new().unwrap();
+    let data: ArcHttpInputData = Arc::new(Mutex::new(HttpInputData {
+        id: id.clone(),
+        config: None,
+        server: None,
+        sender_to_kernel,
+        pending_deliveries: HashMap::new(),
+    }));
+
+    loop {
+        match inbox.receive() {
+            BrokerEvent::Init => info!("{} initiated", id),
+            BrokerEvent::ConfigUpdated(config, _) => {
+                info!("{} received ConfigUpdated", id);
+                if let Err(e) = update(config, data.clone(), tokio.handle()) {
+                    error!("{} failed to apply config {:?}", id, e)
+                }
+            }
+            BrokerEvent::IncomingCloudEventProcessed(routing_id, result) => {
+                if let Err(e) = resolve_pending_delivery(&data, &routing_id, result) {
+                    warn!(
+                        "{} IncomingCloudEventProcessed was not delivered {:?}",
+                        id, e
+                    )
+                }
+            }
+            BrokerEvent::HealthCheckRequest(event) => check_health(event, &data),
+            broker_event => warn!("event {} not implemented", broker_event),
+        }
+    }
+}
+
+/// This is the pointer for the main function to start the port.
+pub static PORT_INPUT_HTTP: InternalServerFnRefStatic =
+    &(port_input_http_start as InternalServerFn);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cerk_runtime_threading::channel::new_channel_with_size;
+    use cloudevents::AttributesReader;
+    use std::thread;
+
+    const STRUCTURED: &str =
+        r#"{"specversion":"1.0","id":"1","type":"test","source":"http://example.com"}"#;
+
+    fn data(
+        delivery_guarantee: DeliveryGuarantee,
+        sender_to_kernel: BoxedSender,
+    ) -> ArcHttpInputData {
+        Arc::new(Mutex::new(HttpInputData {
+            id: "http-input".to_string(),
+            config: Some(HttpInputConfig {
+                address: SocketAddr::new("127.0.0.1".parse().unwrap(), 8080),
+                delivery_guarantee,
+                timeout: Duration::from_millis(500),
+                max_body_size: 1024,
+            }),
+            server: None,
+            sender_to_kernel,
+            pending_deliveries: HashMap::new(),
+        }))
+    }
+
+    fn post(data: ArcHttpInputData, content_type: &str, body: &str) -> StatusCode {
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri("/")
+            .header(CONTENT_TYPE, content_type)
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        let mut runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime
+            .block_on(handle_request(request, data))
+            .unwrap()
+            .status()
+    }
+
+    #[test]
+    fn parse_binary_event() -> Result<()> {
+        let mut headers = HeaderMap::new();
+        headers.insert("ce-specversion", "1.0".parse()?);
+        headers.insert("ce-id", "1".parse()?);
+        headers.insert("ce-type", "test".parse()?);
+        headers.insert("ce-source", "http://example.com".parse()?);
+        headers.insert(CONTENT_TYPE, "text/plain".parse()?);
+        let events = parse_events(&headers, b"hello".to_vec())?;
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].id(), "1");
+        assert_eq!(events[0].datacontenttype(), Some("text/plain"));
+        Ok(())
+    }
+
+    #[test]
+    fn parse_structured_and_batched_events() -> Result<()> {
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, "application/cloudevents+json".parse()?);
+        assert_eq!(
+            parse_events(&headers, STRUCTURED.as_bytes().to_vec())?[0].id(),
+            "1"
+        );
+
+        headers.insert(CONTENT_TYPE, BATCH_CONTENT_TYPE.parse()?);
+        let batch = format!("[{},{}]", STRUCTURED, STRUCTURED);
+        assert_eq!(parse_events(&headers, batch.into_bytes())?.len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn parse_invalid_event() {
+        let headers = HeaderMap::new();
+        assert!(parse_events(&headers, b"hello".to_vec()).is_err());
+    }
+
+    #[test]
+    fn combine_batch_results() {
+        assert_eq!(combine_results(vec![]), ProcessingResult::Successful);
+        assert_eq!(
+            combine_results(vec![
+                ProcessingResult::Successful,
+                ProcessingResult::PermanentError,
+                ProcessingResult::TransientError,
+            ]),
+            ProcessingResult::TransientError
+        );
+    }
+
+    #[test]
+    fn best_effort_request_is_accepted_immediately() {
+        let (send, receive) = new_channel_with_size(10);
+        let status = post(
+            data(DeliveryGuarantee::BestEffort, send),
+            "application/cloudevents+json",
+            STRUCTURED,
+        );
+        assert_eq!(status, StatusCode::ACCEPTED);
+        match receive.receive_timeout(Duration::from_millis(100)) {
+            Some(BrokerEvent::IncomingCloudEvent(event)) => {
+                assert_eq!(event.incoming_id, "http-input");
+                assert_eq!(event.cloud_event.id(), "1");
+            }
+            _ => panic!("expected IncomingCloudEvent"),
+        }
+    }
+
+    #[test]
+    fn at_least_once_request_waits_for_result() {
+        let (send, receive) = new_channel_with_size(10);
+        let data = data(DeliveryGuarantee::AtLeastOnce, send);
+        let cloned_data = data.clone();
+        thread::spawn(move || {
+            if let Some(BrokerEvent::IncomingCloudEvent(event)) =
+                receive.receive_timeout(Duration::from_millis(200))
+            {
+                resolve_pending_delivery(
+                    &cloned_data,
+                    &event.routing_id,
+                    ProcessingResult::PermanentError,
+                )
+                .unwrap();
+            }
+        });
+        let status = post(data.clone(), "application/cloudevents+json", STRUCTURED);
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert!(data.lock().unwrap().pending_deliveries.is_empty());
+    }
+
+    #[test]
+    fn full_kernel_inbox_does_not_block_the_results() {
+        let (send, receive) = new_channel_with_size(1);
+        let data = data(DeliveryGuarantee::AtLeastOnce, send);
+        let cloned_data = data.clone();
+        thread::spawn(move || {
+            while let Some(BrokerEvent::IncomingCloudEvent(event)) =
+                receive.receive_timeout(Duration::from_millis(200))
+            {
+                // the second event is still blocked in the send, while the first is resolved
+                thread::sleep(Duration::from_millis(20));
+                resolve_pending_delivery(
+                    &cloned_data,
+                    &event.routing_id,
+                    ProcessingResult::Successful,
+                )
+                .unwrap();
+            }
+        });
+        let batch = format!("[{},{},{}]", STRUCTURED, STRUCTURED, STRUCTURED);
+        let status = post(data, BATCH_CONTENT_TYPE, batch.as_str());
+        assert_eq!(status, StatusCode::ACCEPTED);
+    }
+
+    #[test]
+    fn too_large_body_is_rejected() {
+        let (send, receive) = new_channel_with_size(10);
+        let data = data(DeliveryGuarantee::BestEffort, send);
+        let body = "a".repeat(1025);
+        let status = post(data.clone(), "application/cloudevents+json", body.as_str());
+        assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri("/")
+            .header(CONTENT_TYPE, "application/cloudevents+json")
+            .header(CONTENT_LENGTH, "1025")
+            .body(Body::empty())
+            .unwrap();
+        let mut runtime = tokio::runtime::Runtime::new().unwrap();
+        let response = runtime.block_on(handle_request(request, data)).unwrap();
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        assert!(receive.receive_timeout(Duration::from_millis(10)).is_none());
+    }
+
+    fn free_port() -> u32 {
+        std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port() as u32
+    }
+
+    fn config(port: u32) -> Config {
+        Config::HashMap(
+            [
+                (
+                    "ip_addr".to_string(),
+                    Config::String("127.0.0.1".to_string()),
+                ),
+                ("http_port".to_string(), Config::U32(port)),
+            ]
+            .iter()
+            .cloned()
+            .collect(),
+        )
+    }
+
+    #[test]
+    fn config_update_rebinds_the_address() -> Result<()> {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let (send, _receive) = new_channel_with_size(10);
+        let data = data(DeliveryGuarantee::BestEffort, send);
+        let (first, second) = (free_port(), free_port());
+
+        update(config(first), data.clone(), runtime.handle())?;
+        // the same address keeps the running server
+        update(config(first), data.clone(), runtime.handle())?;
+        update(config(second), data.clone(), runtime.handle())?;
+        // the first address was released by the previous server
+        update(config(first), data.clone(), runtime.handle())?;
+        assert_eq!(
+            data.lock()
+                .unwrap()
+                .server
+                .as_ref()
+                .map(|s| s.address.port()),
+            Some(first as u16)
+        );
+        stop_server(&data);
+        Ok(())
+    }
+}
@@ -0,0 +1,598 @@
+use crate::port_input_http::combine_results;
+use anyhow::{Context, Result};
+use cerk::kernel::{
+    BrokerEvent, Config, ConfigHelpers, HealthCheckRequest, HealthCheckResponse, HealthCheckStatus,
+    OutgoingCloudEvent, OutgoingCloudEventProcessed, ProcessingResult,
+};
+use cerk::runtime::channel::{BoxedReceiver, BoxedSender};
+use cerk::runtime::{InternalServerFn, InternalServerFnRefStatic, InternalServerId};
+use cloudevents::Event;
+use futures::future::join_all;
+use hmac::{Hmac, Mac, NewMac};
+use hyper::client::HttpConnector;
+use hyper::header::{HeaderName, HeaderValue, CONTENT_TYPE};
+use hyper::{Body, Client, HeaderMap, Method, Request, StatusCode, Uri};
+use hyper_tls::HttpsConnector;
+use sha2::Sha256;
+use std::convert::TryFrom;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::{delay_for, timeout};
+
+const STRUCTURED_CONTENT_TYPE: &str = "application/cloudevents+json";
+const DEFAULT_SIGNATURE_HEADER: &str = "x-cerk-signature";
+const DEFAULT_TIMEOUT_MS: u32 = 10_000;
+const DEFAULT_RETRY_DELAY_MS: u32 = 1_000;
+
+type HttpClient = Client<HttpsConnector<HttpConnector>>;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ContentMode {
+    Binary,
+    Structured,
+}
+
+struct HttpDestination {
+    url: Uri,
+    headers: HeaderMap,
+    signing_secret: Option<Vec<u8>>,
+    signature_header: HeaderName,
+}
+
+struct HttpOutputConfig {
+    destinations: Vec<HttpDestination>,
+    content_mode: ContentMode,
+    timeout: Duration,
+    max_retries: u8,
+    retry_delay: Duration,
+}
+
+fn build_headers(config: &Config) -> Result<HeaderMap> {
+    let mut headers = HeaderMap::new();
+    match config.get_op_val_config("headers")? {
+        Some(Config::HashMap(values)) => {
+            for (name, value) in values.iter() {
+                let value = match value {
+                    Config::String(value) => value,
+                    _ => bail!("header {} has to be a string", name),
+                };
+                headers.insert(
+                    HeaderName::from_bytes(name.as_bytes())?,
+                    HeaderValue::from_str(value)?,
+                );
+            }
+        }
+        Some(Config::Null) | None => {}
+        Some(_) => bail!("headers have to be a map of strings"),
+    }
+    Ok(headers)
+}
+
+fn build_destination(config: &Config) -> Result<HttpDestination> {
+    let url: Uri = config
+        .get_op_val_string("url")?
+        .context("url has to be set")?
+        .parse()?;
+    let signature_header = config
+        .get_op_val_string("signature_header")?
+        .unwrap_or_else(|| DEFAULT_SIGNATURE_HEADER.to_string());
+    Ok(HttpDestination {
+        url,
+        headers: build_headers(config)?,
+        signing_secret: config
+            .get_op_val_string("hmac_secret")?
+            .map(|s| s.into_bytes()),
+        signature_header: HeaderName::from_bytes(signature_header.as_bytes())?,
+    })
+}
+
+/// The destinations are either configured as list or, for a single one, directly in the config.
+fn build_destinations(config: &Config) -> Result<Vec<HttpDestination>> {
+    match config.get_op_val_vec("destinations")? {
+        Some(destinations) => {
+            if config.get_op_val_string("url")?.is_some() {
+                bail!("url and destinations can not be set together");
+            }
+            if destinations.is_empty() {
+                bail!("destinations must not be empty");
+            }
+            destinations.iter().map(build_destination).collect()
+        }
+        None => Ok(vec![build_destination(config)?]),
+    }
+}
+
+fn build_config(config: &Config) -> Result<HttpOutputConfig> {
+    let content_mode = match config.get_op_val_string("content_mode")?.as_deref() {
+        None | Some("binary") => ContentMode::Binary,
+        Some("structured") => ContentMode::Structured,
+        Some(mode) => bail!("content_mode {} is not supported", mode),
+    };
+    Ok(HttpOutputConfig {
+        destinations: build_destinations(config)?,
+        content_mode,
+        timeout: Duration::from_millis(
+            config
+                .get_op_val_u32("timeout_ms")?
+                .unwrap_or(DEFAULT_TIMEOUT_MS) as u64,
+        ),
+        max_retries: config.get_op_val_u8("max_retries")?.unwrap_or(0),
+        retry_delay: Duration::from_millis(
+            config
+                .get_op_val_u32("retry_delay_ms")?
+                .unwrap_or(DEFAULT_RETRY_DELAY_MS) as u64,
+        ),
+    })
+}
+
+/// Signs the body with HMAC-SHA256, the signature has the format `sha256=<hex>`.
+fn sign(secret: &[u8], body: &[u8]) -> Result<String> {
+    let mut mac =
+        Hmac::<Sha256>::new_varkey(secret).map_err(|e| anyhow!("invalid hmac secret: {}", e))?;
+    mac.update(body);
+    Ok(format!(
+        "sha256={}",
+        hex::encode(mac.finalize().into_bytes())
+    ))
+}
+
+fn build_request(
+    event: Event,
+    config: &HttpOutputConfig,
+    destination: &HttpDestination,
+) -> Result<Request<Body>> {
+    let (mut parts, body) = match config.content_mode {
+        ContentMode::Binary => {
+            let request = Request::<Option<Vec<u8>>>::try_from(event)
+                .map_err(|e| anyhow!("failed to serialize CloudEvent: {}", e))?;
+            let (parts, body) = request.into_parts();
+            (parts, body.unwrap_or_default())
+        }
+        ContentMode::Structured => {
+            let (mut parts, _) = Request::new(()).into_parts();
+            parts.headers.insert(
+                CONTENT_TYPE,
+                HeaderValue::from_static(STRUCTURED_CONTENT_TYPE),
+            );
+            (parts, serde_json::to_vec(&event)?)
+        }
+    };
+    parts.method = Method::POST;
+    parts.uri = destination.url.clone();
+    for (name, value) in destination.headers.iter() {
+        parts.headers.insert(name.clone(), value.clone());
+    }
+    if let Some(secret) = destination.signing_secret.as_ref() {
+        parts.headers.insert(
+            destination.signature_header.clone(),
+            HeaderValue::from_str(sign(secret, &body)?.as_str())?,
+        );
+    }
+    Ok(Request::from_parts(parts, Body::from(body)))
+}
+
+/// 408 and 429 are client errors, but the request could succeed later.
+fn result_from_status(status: StatusCode) -> ProcessingResult {
+    if status.is_success() {
+        ProcessingResult::Successful
+    } else if status.is_server_error()
+        || status == StatusCode::REQUEST_TIMEOUT
+        || status == StatusCode::TOO_MANY_REQUESTS
+    {
+        ProcessingResult::TransientError
+    } else {
+        ProcessingResult::PermanentError
+    }
+}
+
+async fn send_once(
+    client: &HttpClient,
+    config: &HttpOutputConfig,
+    destination: &HttpDestination,
+    event: Event,
+) -> ProcessingResult {
+    let request = match build_request(event, config, destination) {
+        Ok(request) => request,
+        Err(e) => {
+            error!("failed to build request {:?}", e);
+            return ProcessingResult::PermanentError;
+        }
+    };
+    match timeout(config.timeout, client.request(request)).await {
+        Ok(Ok(response)) => {
+            debug!("{} responded with {}", destination.url, response.status());
+            result_from_status(response.status())
+        }
+        Ok(Err(e)) => {
+            warn!("failed to send CloudEvent to {}: {}", destination.url, e);
+            ProcessingResult::TransientError
+        }
+        Err(_) => ProcessingResult::Timeout,
+    }
+}
+
+/// Sends the event and retries transient errors and timeouts with an exponential backoff.
+async fn deliver(
+    client: &HttpClient,
+    config: &HttpOutputConfig,
+    destination: &HttpDestination,
+    event: &Event,
+) -> ProcessingResult {
+    let mut attempt: u8 = 0;
+    loop {
+        match send_once(client, config, destination, event.clone()).await {
+            ProcessingResult::TransientError | ProcessingResult::Timeout
+                if attempt < config.max_retries =>
+            {
+                let delay = config.retry_delay * 2u32.pow(attempt.min(10) as u32);
+                warn!(
+                    "delivery to {} failed, retry {}/{} in {:?}",
+                    destination.url,
+                    attempt + 1,
+                    config.max_retries,
+                    delay
+                );
+                delay_for(delay).await;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Sends the event to all destinations at once, the result which requires a retry wins.
+///
+/// A retry sends the event to all destinations again, not only to the failed ones.
+async fn deliver_to_all(
+    client: HttpClient,
+    config: Arc<HttpOutputConfig>,
+    event: Event,
+) -> ProcessingResult {
+    let results = join_all(
+        config
+            .destinations
+            .iter()
+            .map(|destination| deliver(&client, &config, destination, &event)),
+    )
+    .await;
+    combine_results(results)
+}
+
+fn send_cloud_event(
+    id: &InternalServerId,
+    event: OutgoingCloudEvent,
+    config: Option<Arc<HttpOutputConfig>>,
+    client: &HttpClient,
+    sender_to_kernel: &BoxedSender,
+    tokio: &tokio::runtime::Runtime,
+) {
+    let requires_acknowledgment = event.args.delivery_guarantee.requires_acknowledgment();
+    let sender_to_kernel = sender_to_kernel.clone_boxed();
+    let sender_id = id.clone();
+    let processed = move |result: ProcessingResult| {
+        if requires_acknowledgment {
+            sender_to_kernel.send(BrokerEvent::OutgoingCloudEventProcessed(
+                OutgoingCloudEventProcessed {
+                    sender_id,
+                    routing_id: event.routing_id,
+                    result,
+                },
+            ));
+        }
+    };
+    match config {
+        Some(config) => {
+            let client = client.clone();
+            let cloud_event = event.cloud_event;
+            tokio.spawn(async move {
+                processed(deliver_to_all(client, config, cloud_event).await);
+            });
+        }
+        None => {
+            error!("{} received an event, but is not configured", id);
+            processed(ProcessingResult::TransientError);
+        }
+    }
+}
+
+fn check_health(
+    event: HealthCheckRequest,
+    config: &Option<Arc<HttpOutputConfig>>,
+    sender_to_kernel: &BoxedSender,
+) {
+    let status = match config {
+        Some(_) => HealthCheckStatus::Healthy,
+        None => HealthCheckStatus::Unhealthy("port is not configured".to_string()),
+    };
+    sender_to_kernel.send(BrokerEvent::HealthCheckResponse(HealthCheckResponse {
+        status,
+        destination_id: event.sender_id,
+        id: event.id,
+        sender_id: event.destination_id,
+    }))
+}
+
+/// This is the main function to start the port.
+pub fn port_output_http_start(
+    id: InternalServerId,
+    inbox: BoxedReceiver,
+    sender_to_kernel: BoxedSender,
+) {
+    info!("start http output port with id {}", id);
+    let tokio = tokio::runtime::Runtime::new().unwrap();
+    let client: HttpClient = Client::builder().build(HttpsConnector::new());
+    let mut config: Option<Arc<HttpOutputConfig>> = None;
+
+    loop {
+        match inbox.receive() {
+            BrokerEvent::Init => info!("{} initiated", id),
+            BrokerEvent::ConfigUpdated(new_config, _) => {
+                info!("{} received ConfigUpdated", id);
+                match build_config(&new_config) {
+                    Ok(new_config) => config = Some(Arc::new(new_config)),
+                    Err(e) => error!("{} failed to apply config {:?}", id, e),
+                }
+            }
+            BrokerEvent::OutgoingCloudEvent(event) => send_cloud_event(
+                &id,
+                event,
+                config.clone(),
+                &client,
+                &sender_to_kernel,
+                &tokio,
+            ),
+            BrokerEvent::HealthCheckRequest(event) => {
+                check_health(event, &config, &sender_to_kernel)
+            }
+            broker_event => warn!("event {} not implemented", broker_event),
+        }
+    }
+}
+
+/// This is the pointer for the main function to start the port.
+pub static PORT_OUTPUT_HTTP: InternalServerFnRefStatic =
+    &(port_output_http_start as InternalServerFn);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cloudevents::{EventBuilder, EventBuilderV10};
+    use hyper::service::{make_service_fn, service_fn};
+    use hyper::{Error, Response, Server};
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn event() -> Event {
+        EventBuilderV10::new()
+            .id("1")
+            .ty("test")
+            .source("http://example.com")
+            .data("text/plain", "hello")
+            .build()
+            .unwrap()
+    }
+
+    fn config(url: &str, content_mode: &str) -> Config {
+        let mut config: HashMap<String, Config> = HashMap::new();
+        config.insert("url".to_string(), Config::String(url.to_string()));
+        config.insert(
+            "content_mode".to_string(),
+            Config::String(content_mode.to_string()),
+        );
+        config.insert(
+            "headers".to_string(),
+            Config::HashMap(
+                [(
+                    "authorization".to_string(),
+                    Config::String("Bearer token".to_string()),
+                )]
+                .iter()
+                .cloned()
+                .collect(),
+            ),
+        );
+        config.insert(
+            "hmac_secret".to_string(),
+            Config::String("Jefe".to_string()),
+        );
+        config.insert("max_retries".to_string(), Config::U8(2));
+        config.insert("retry_delay_ms".to_string(), Config::U8(1));
+        Config::HashMap(config)
+    }
+
+    #[test]
+    fn sign_body() -> Result<()> {
+        assert_eq!(
+            sign(b"Jefe", b"what do ya want for nothing?")?,
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn map_status_to_result() {
+        assert_eq!(
+            result_from_status(StatusCode::NO_CONTENT),
+            ProcessingResult::Successful
+        );
+        assert_eq!(
+            result_from_status(StatusCode::BAD_REQUEST),
+            ProcessingResult::PermanentError
+        );
+        assert_eq!(
+            result_from_status(StatusCode::TOO_MANY_REQUESTS),
+            ProcessingResult::TransientError
+        );
+        assert_eq!(
+            result_from_status(StatusCode::BAD_GATEWAY),
+            ProcessingResult::TransientError
+        );
+    }
+
+    fn destination(url: &str, secret: &str) -> Config {
+        Config::HashMap(
+            [
+                ("url".to_string(), Config::String(url.to_string())),
+                (
+                    "hmac_secret".to_string(),
+                    Config::String(secret.to_string()),
+                ),
+            ]
+            .iter()
+            .cloned()
+            .collect(),
+        )
+    }
+
+    fn destinations_config(destinations: Vec<Config>) -> Config {
+        Config::HashMap(
+            [
+                ("destinations".to_string(), Config::Vec(destinations)),
+                ("max_retries".to_string(), Config::U8(1)),
+                ("retry_delay_ms".to_string(), Config::U32(1)),
+            ]
+            .iter()
+            .cloned()
+            .collect(),
+        )
+    }
+
+    #[test]
+    fn build_invalid_config() {
+        assert!(build_config(&Config::HashMap(HashMap::new())).is_err());
+        assert!(build_config(&config("http://localhost", "batch")).is_err());
+        assert!(build_config(&destinations_config(vec![])).is_err());
+        let mut both = config("http://localhost", "binary");
+        if let Config::HashMap(ref mut values) = both {
+            values.insert(
+                "destinations".to_string(),
+                Config::Vec(vec![destination("http://localhost:8081", "secret")]),
+            );
+        }
+        assert!(build_config(&both).is_err());
+    }
+
+    #[test]
+    fn build_requests_for_all_destinations() -> Result<()> {
+        let config = build_config(&destinations_config(vec![
+            destination("http://localhost:8081/a", "first"),
+            destination("http://localhost:8082/b", "second"),
+        ]))?;
+        assert_eq!(config.destinations.len(), 2);
+        let request = build_request(event(), &config, &config.destinations[1])?;
+        assert_eq!(request.uri(), "http://localhost:8082/b");
+        assert_eq!(
+            request.headers()[DEFAULT_SIGNATURE_HEADER],
+            sign(b"second", b"hello")?.as_str()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn build_binary_request() -> Result<()> {
+        let config = build_config(&config("http://localhost:8080/hook", "binary"))?;
+        let request = build_request(event(), &config, &config.destinations[0])?;
+        assert_eq!(request.method(), Method::POST);
+        assert_eq!(request.uri(), "http://localhost:8080/hook");
+        assert_eq!(request.headers()["ce-id"], "1");
+        assert_eq!(request.headers()[CONTENT_TYPE], "text/plain");
+        assert_eq!(request.headers()["authorization"], "Bearer token");
+        assert_eq!(
+            request.headers()[DEFAULT_SIGNATURE_HEADER],
+            sign(b"Jefe", b"hello")?.as_str()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn build_structured_request() -> Result<()> {
+        let config = build_config(&config("http://localhost:8080/hook", "structured"))?;
+        let request = build_request(event(), &config, &config.destinations[0])?;
+        assert_eq!(request.headers()[CONTENT_TYPE], STRUCTURED_CONTENT_TYPE);
+        assert!(request.headers().get("ce-id").is_none());
+        let mut runtime = tokio::runtime::Runtime::new()?;
+        let body = runtime.block_on(hyper::body::to_bytes(request.into_body()))?;
+        assert_eq!(serde_json::from_slice::<Event>(&body)?, event());
+        Ok(())
+    }
+
+    #[test]
+    fn deliver_retries_transient_errors() -> Result<()> {
+        let mut runtime = tokio::runtime::Runtime::new()?;
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = requests.clone();
+        let result = runtime.block_on(async move {
+            let make_svc = make_service_fn(move |_| {
+                let counter = counter.clone();
+                async move {
+                    Ok::<_, Error>(service_fn(move |_| {
+                        let status = match counter.fetch_add(1, Ordering::SeqCst) {
+                            0 => StatusCode::SERVICE_UNAVAILABLE,
+                            _ => StatusCode::OK,
+                        };
+                        async move {
+                            Ok::<_, Error>(
+                                Response::builder()
+                                    .status(status)
+                                    .body(Body::empty())
+                                    .unwrap(),
+                            )
+                        }
+                    }))
+                }
+            });
+            let server = Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(make_svc);
+            let url = format!("http://{}/hook", server.local_addr());
+            tokio::spawn(server);
+            let config = Arc::new(build_config(&config(url.as_str(), "binary")).unwrap());
+            let client: HttpClient = Client::builder().build(HttpsConnector::new());
+            deliver_to_all(client, config, event()).await
+        });
+        assert_eq!(result, ProcessingResult::Successful);
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn report_the_worst_result_of_all_destinations() -> Result<()> {
+        let mut runtime = tokio::runtime::Runtime::new()?;
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = requests.clone();
+        let result = runtime.block_on(async move {
+            let make_svc = make_service_fn(move |_| {
+                let counter = counter.clone();
+                async move {
+                    Ok::<_, Error>(service_fn(move |request: Request<Body>| {
+                        counter.fetch_add(1, Ordering::SeqCst);
+                        let status = match request.uri().path() {
+                            "/ok" => StatusCode::OK,
+                            _ => StatusCode::SERVICE_UNAVAILABLE,
+                        };
+                        async move {
+                            Ok::<_, Error>(
+                                Response::builder()
+                                    .status(status)
+                                    .body(Body::empty())
+                                    .unwrap(),
+                            )
+                        }
+                    }))
+                }
+            });
+            let server = Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(make_svc);
+            let address = server.local_addr();
+            tokio::spawn(server);
+            let config = build_config(&destinations_config(vec![
+                destination(&format!("http://{}/ok", address), "first"),
+                destination(&format!("http://{}/unavailable", address), "second"),
+            ]))
+            .unwrap();
+            let client: HttpClient = Client::builder().build(HttpsConnector::new());
+            deliver_to_all(client, Arc::new(config), event()).await
+        });
+        assert_eq!(result, ProcessingResult::TransientError);
+        // the failed destination is retried once
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+        Ok(())
+    }
+}
@@ -0,0 +1,143 @@
+use anyhow::{Context, Result};
+use cloudevents::binding::http::to_event;
+use cloudevents::Event;
+use http::header::{HeaderName, HeaderValue, CONTENT_TYPE};
+use http::{HeaderMap, Request};
+use std::convert::TryFrom;
+
+const KAFKA_HEADER_PREFIX: &str = "ce_";
+const HTTP_HEADER_PREFIX: &str = "ce-";
+const STRUCTURED_CONTENT_TYPE: &str = "application/cloudevents+json; charset=UTF-8";
+const PARTITION_KEY_EXTENSION: &str = "partitionkey";
+
+/// The content mode of the published messages.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ContentMode {
+    /// the attributes are sent as `ce_` headers and the data as value
+    Binary,
+    /// the event is sent as JSON value
+    Structured,
+}
+
+/// A Kafka message, independent of the Kafka client.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KafkaRecord {
+    /// the value of the `partitionkey` extension
+    pub key: Option<Vec<u8>>,
+    /// the headers in the order they should be sent
+    pub headers: Vec<(String, Vec<u8>)>,
+    /// the value of the message
+    pub payload: Vec<u8>,
+}
+
+/// Converts the event to a Kafka message according to the [Kafka protocol binding](https://github.com/cloudevents/spec/blob/master/kafka-protocol-binding.md).
+///
+/// The binding is similar to the HTTP binding, only the header prefix is `ce_` instead of `ce-`.
+pub fn to_record(event: Event, content_mode: ContentMode) -> Result<KafkaRecord> {
+    let key = event
+        .extension(PARTITION_KEY_EXTENSION)
+        .map(|value| value.to_string().into_bytes());
+    match content_mode {
+        ContentMode::Binary => {
+            let request = Request::<Option<Vec<u8>>>::try_from(event)
+                .map_err(|e| anyhow!("failed to serialize CloudEvent: {}", e))?;
+            let (parts, body) = request.into_parts();
+            let headers = parts
+                .headers
+                .iter()
+                .map(|(name, value)| {
+                    let name = match name.as_str().strip_prefix(HTTP_HEADER_PREFIX) {
+                        Some(attribute) => format!("{}{}", KAFKA_HEADER_PREFIX, attribute),
+                        None => name.as_str().to_string(),
+                    };
+                    (name, value.as_bytes().to_vec())
+                })
+                .collect();
+            Ok(KafkaRecord {
+                key,
+                headers,
+                payload: body.unwrap_or_default(),
+            })
+        }
+        ContentMode::Structured => Ok(KafkaRecord {
+            key,
+            headers: vec![(
+                CONTENT_TYPE.as_str().to_string(),
+                STRUCTURED_CONTENT_TYPE.as_bytes().to_vec(),
+            )],
+            payload: serde_json::to_vec(&event)?,
+        }),
+    }
+}
+
+/// Converts a Kafka message in binary or structured content mode to an event.
+pub fn from_record<'a, I>(headers: I, payload: &[u8]) -> Result<Event>
+where
+    I: Iterator<Item = (&'a str, &'a [u8])>,
+{
+    let mut header_map = HeaderMap::new();
+    for (name, value) in headers {
+        let name = match name.strip_prefix(KAFKA_HEADER_PREFIX) {
+            Some(attribute) => format!("{}{}", HTTP_HEADER_PREFIX, attribute),
+            None => name.to_lowercase(),
+        };
+        header_map.insert(
+            HeaderName::from_bytes(name.as_bytes())?,
+            HeaderValue::from_bytes(value)?,
+        );
+    }
+    to_event(&header_map, payload.to_vec()).context("failed to parse CloudEvent")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cloudevents::{EventBuilder, EventBuilderV10};
+
+    fn event() -> Event {
+        EventBuilderV10::new()
+            .id("1")
+            .ty("test")
+            .source("http://example.com")
+            .extension("partitionkey", "customer-1")
+            .data("text/plain", b"hello".to_vec())
+            .build()
+            .unwrap()
+    }
+
+    fn headers(record: &KafkaRecord) -> impl Iterator<Item = (&str, &[u8])> {
+        record
+            .headers
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_slice()))
+    }
+
+    #[test]
+    fn binary_round_trip() -> Result<()> {
+        let record = to_record(event(), ContentMode::Binary)?;
+        assert_eq!(record.key, Some(b"customer-1".to_vec()));
+        assert_eq!(record.payload, b"hello".to_vec());
+        assert!(record
+            .headers
+            .contains(&("ce_id".to_string(), b"1".to_vec())));
+        assert!(record
+            .headers
+            .contains(&("content-type".to_string(), b"text/plain".to_vec())));
+        assert_eq!(from_record(headers(&record), &record.payload)?, event());
+        Ok(())
+    }
+
+    #[test]
+    fn structured_round_trip() -> Result<()> {
+        let record = to_record(event(), ContentMode::Structured)?;
+        assert_eq!(record.key, Some(b"customer-1".to_vec()));
+        assert_eq!(record.headers.len(), 1);
+        assert_eq!(from_record(headers(&record), &record.payload)?, event());
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_record() {
+        assert!(from_record(std::iter::empty(), b"hello").is_err());
+    }
+}
@@ -0,0 +1,93 @@
+/*!
+
+This is a package for [CERK](https://github.com/ce-rust/cerk).
+CERK is an open source [CloudEvents](https://github.com/cloudevents/spec) Router written in Rust with a MicroKernel architecture.
+
+# Introduction
+
+CERK lets you route your [CloudEvents](https://github.com/cloudevents/spec) between different different ports.
+Ports are transport layer bindings over which CloudEvents can be exchanged.
+It is built with modularity and portability in mind.
+
+# Components
+
+CERK comes with a couple of prefabricated components, but implementing custom components is easy.
+
+A good overview is provided on [GitHub](https://github.com/ce-rust/cerk/).
+
+# This Component: Kafka Port
+
+This port consumes CloudEvents from and/or publishes CloudEvents to Kafka topics.
+
+The port is implemented with [rust-rdkafka](https://github.com/fede1024/rust-rdkafka).
+
+## Content Modes
+
+The port supports the binary and the structured content mode of the [Kafka protocol binding](https://github.com/cloudevents/spec/blob/master/kafka-protocol-binding.md).
+Consumed messages are parsed in either mode, the mode of the published messages is configured per topic.
+
+The `partitionkey` extension of the event is used as key of the published messages.
+
+## Delivery Guarantees
+
+With the delivery guarantee `AtLeastOnce`, the offset of a consumed message is only committed after the message was processed successfully.
+As Kafka stores only one offset per partition, the committed offset never passes a message which is still in process.
+If the processing failed with a transient error or timed out, the message is routed again after one second,
+while the following messages are still processed, so they are not consumed twice.
+Messages which failed permanently or can not be parsed are skipped.
+If a partition is revoked during a rebalance, its messages in process are dropped and consumed again by its next owner from the committed offset.
+
+On a configuration update, the producer waits up to ten seconds for the acknowledgments of the messages in flight,
+the messages which are still not acknowledged afterwards are reported with a transient error.
+
+Published messages require the acknowledgment of all in-sync replicas (`acks=all`).
+
+## Configurations
+
+```json
+{
+  "brokers": "localhost:9092",
+  "group_id": "cerk",
+  "consume_topics": [{ "name": "input", "delivery_guarantee": 2 }],
+  "publish_topics": [{ "name": "output", "content_mode": "structured" }]
+}
+```
+
+| Name             | Type   | Default       | Description                                                        |
+|------------------|--------|---------------|--------------------------------------------------------------------|
+| `brokers`        | String |               | comma-separated list of the bootstrap brokers                      |
+| `group_id`       | String | `cerk-<id>`   | the consumer group                                                 |
+| `consume_topics` | Array  |               | the topics to consume, each with a `delivery_guarantee` (default `0`) |
+| `publish_topics` | Array  |               | the topics every event is published to, each with a `content_mode` (`binary` or `structured`, default `binary`) |
+
+## Local Broker
+
+```bash
+docker run -d -p 9092:9092 -e KAFKA_CFG_NODE_ID=0 -e KAFKA_CFG_PROCESS_ROLES=controller,broker \
+  -e KAFKA_CFG_LISTENERS=PLAINTEXT://:9092,CONTROLLER://:9093 \
+  -e KAFKA_CFG_ADVERTISED_LISTENERS=PLAINTEXT://localhost:9092 \
+  -e KAFKA_CFG_CONTROLLER_QUORUM_VOTERS=0@localhost:9093 \
+  -e KAFKA_CFG_CONTROLLER_LISTENER_NAMES=CONTROLLER bitnami/kafka
+```
+
+The tests against a broker are ignored by default, the `kafka` service of the `docker-compose.yml` runs them:
+
+```bash
+KAFKA_BROKERS=localhost:9092 cargo test -p cerk_port_kafka -- --ignored
+```
+
+*/
+
+#![deny(missing_docs)]
+
+#[macro_use]
+extern crate log;
+
+#[macro_use]
+extern crate anyhow;
+
+mod kafka_binding;
+mod offset_tracker;
+mod port_kafka;
+
+pub use self::port_kafka::{port_kafka_start, PORT_KAFKA};
@@ -0,0 +1,93 @@
+use std::collections::{BTreeSet, HashMap};
+
+#[derive(Default)]
+struct PartitionOffsets {
+    in_process: BTreeSet<i64>,
+    next: i64,
+    committed: i64,
+}
+
+/// Tracks the offsets of the consumed messages which are still in process.
+///
+/// Kafka stores one offset per partition, so the committed offset can only move forward up to the oldest message which is still in process.
+#[derive(Default)]
+pub struct OffsetTracker {
+    partitions: HashMap<(String, i32), PartitionOffsets>,
+}
+
+impl OffsetTracker {
+    /// Marks the message as in process, a message which is received again stays in process.
+    pub fn received(&mut self, topic: &str, partition: i32, offset: i64) {
+        self.partitions
+            .entry((topic.to_string(), partition))
+            .or_default()
+            .in_process
+            .insert(offset);
+    }
+
+    /// Marks the message as processed and returns the offset to commit, if it moved forward.
+    pub fn processed(&mut self, topic: &str, partition: i32, offset: i64) -> Option<i64> {
+        let offsets = self.partitions.get_mut(&(topic.to_string(), partition))?;
+        if !offsets.in_process.remove(&offset) {
+            return None;
+        }
+        offsets.next = offsets.next.max(offset + 1);
+        let commit = match offsets.in_process.iter().next() {
+            Some(oldest) => *oldest,
+            None => offsets.next,
+        };
+        if commit > offsets.committed {
+            offsets.committed = commit;
+            Some(commit)
+        } else {
+            None
+        }
+    }
+
+    /// Forgets the partition, e.g., after it was revoked, the messages in process are consumed again by its next owner.
+    pub fn revoke(&mut self, topic: &str, partition: i32) {
+        self.partitions.remove(&(topic.to_string(), partition));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn commit_up_to_oldest_message_in_process() {
+        let mut tracker = OffsetTracker::default();
+        tracker.received("a", 0, 0);
+        tracker.received("a", 0, 1);
+        tracker.received("a", 0, 2);
+        tracker.received("a", 1, 0);
+
+        assert_eq!(tracker.processed("a", 0, 1), None);
+        assert_eq!(tracker.processed("a", 1, 0), Some(1));
+        assert_eq!(tracker.processed("a", 0, 0), Some(2));
+        assert_eq!(tracker.processed("a", 0, 2), Some(3));
+    }
+
+    #[test]
+    fn ignore_unknown_and_duplicated_messages() {
+        let mut tracker = OffsetTracker::default();
+        tracker.received("a", 0, 5);
+        tracker.received("a", 0, 5);
+
+        assert_eq!(tracker.processed("b", 0, 5), None);
+        assert_eq!(tracker.processed("a", 0, 5), Some(6));
+        assert_eq!(tracker.processed("a", 0, 5), None);
+    }
+
+    #[test]
+    fn forget_revoked_partitions() {
+        let mut tracker = OffsetTracker::default();
+        tracker.received("a", 0, 0);
+        tracker.received("a", 0, 1);
+        tracker.revoke("a", 0);
+
+        assert_eq!(tracker.processed("a", 0, 1), None);
+        tracker.received("a", 0, 1);
+        assert_eq!(tracker.processed("a", 0, 1), Some(2));
+    }
+}
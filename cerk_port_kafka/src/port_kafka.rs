@@ -0,0 +1,946 @@
+use crate::kafka_binding::{from_record, to_record, ContentMode};
+use crate::offset_tracker::OffsetTracker;
+use anyhow::{Context, Result};
+use cerk::kernel::{
+    BrokerEvent, CloudEventMessageRoutingId, CloudEventRoutingArgs, Config, DeliveryGuarantee,
+    HealthCheckRequest, HealthCheckResponse, HealthCheckStatus, IncomingCloudEvent,
+    OutgoingCloudEvent, OutgoingCloudEventProcessed, ProcessingResult,
+};
+use cerk::runtime::channel::{BoxedReceiver, BoxedSender};
+use cerk::runtime::{InternalServerFn, InternalServerFnRefStatic, InternalServerId};
+use cloudevents::AttributesReader;
+use rdkafka::config::ClientConfig;
+use rdkafka::consumer::{BaseConsumer, CommitMode, Consumer, ConsumerContext, Rebalance};
+use rdkafka::error::KafkaError;
+use rdkafka::message::{BorrowedMessage, DeliveryResult, Headers, Message, OwnedHeaders};
+use rdkafka::producer::{BaseRecord, Producer, ProducerContext, ThreadedProducer};
+use rdkafka::types::RDKafkaErrorCode;
+use rdkafka::{ClientContext, Offset, TopicPartitionList};
+use std::collections::{HashMap, VecDeque};
+use std::convert::TryFrom;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+const POLL_TIMEOUT: Duration = Duration::from_millis(100);
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(10);
+
+struct KafkaOptions {
+    brokers: String,
+    group_id: String,
+    consume_topics: HashMap<String, DeliveryGuarantee>,
+    publish_topics: HashMap<String, ContentMode>,
+}
+
+struct PendingDelivery {
+    topic: String,
+    partition: i32,
+    offset: i64,
+    /// the event is routed again, if it failed transiently
+    event: IncomingCloudEvent,
+}
+
+#[derive(Default)]
+struct ConsumerState {
+    tracker: OffsetTracker,
+    pending_deliveries: HashMap<CloudEventMessageRoutingId, PendingDelivery>,
+    /// the transient failed messages, which are routed again after the retry delay
+    retries: VecDeque<(Instant, CloudEventMessageRoutingId)>,
+}
+
+impl ConsumerState {
+    /// Returns the message to commit, if the committed offset of its partition moved forward.
+    ///
+    /// A transient failed message stays in process and is routed again, so the messages after it are not consumed twice.
+    fn resolve(
+        &mut self,
+        id: &InternalServerId,
+        routing_id: &CloudEventMessageRoutingId,
+        result: ProcessingResult,
+    ) -> Result<Option<(String, i32, i64)>> {
+        if !self.pending_deliveries.contains_key(routing_id) {
+            bail!("pending delivery with id={} not found", routing_id);
+        }
+        match result {
+            ProcessingResult::Successful => {}
+            ProcessingResult::PermanentError => {
+                error!(
+                    "{} message {} failed permanently and is skipped",
+                    id, routing_id
+                )
+            }
+            ProcessingResult::TransientError | ProcessingResult::Timeout => {
+                warn!(
+                    "{} message {} failed and is routed again in {:?}",
+                    id, routing_id, RETRY_DELAY
+                );
+                self.retries
+                    .push_back((Instant::now() + RETRY_DELAY, routing_id.clone()));
+                return Ok(None);
+            }
+        }
+        let pending = self.pending_deliveries.remove(routing_id).unwrap();
+        let offset =
+            self.tracker
+                .processed(pending.topic.as_str(), pending.partition, pending.offset);
+        Ok(offset.map(|offset| (pending.topic, pending.partition, offset)))
+    }
+
+    /// Returns the events whose retry delay is over.
+    fn due_retries(&mut self, now: Instant) -> Vec<IncomingCloudEvent> {
+        let mut events = Vec::new();
+        while matches!(self.retries.front(), Some((due, _)) if *due <= now) {
+            let (_, routing_id) = self.retries.pop_front().unwrap();
+            // the partition could have been revoked in the meantime
+            if let Some(pending) = self.pending_deliveries.get(&routing_id) {
+                events.push(pending.event.clone());
+            }
+        }
+        events
+    }
+
+    /// Drops the messages of the revoked partition, its next owner consumes them again from the committed offset.
+    fn revoke(&mut self, topic: &str, partition: i32) {
+        self.tracker.revoke(topic, partition);
+        self.pending_deliveries
+            .retain(|_, pending| pending.topic != topic || pending.partition != partition);
+    }
+}
+
+struct KafkaConsumerContext {
+    id: InternalServerId,
+    state: Arc<Mutex<ConsumerState>>,
+}
+
+impl ClientContext for KafkaConsumerContext {}
+
+impl ConsumerContext for KafkaConsumerContext {
+    fn pre_rebalance<'a>(&self, rebalance: &Rebalance<'a>) {
+        if let Rebalance::Revoke(partitions) = rebalance {
+            let mut state = self.state.lock().unwrap();
+            for partition in partitions.elements() {
+                info!(
+                    "{} partition {}/{} was revoked",
+                    self.id,
+                    partition.topic(),
+                    partition.partition()
+                );
+                state.revoke(partition.topic(), partition.partition());
+            }
+        }
+    }
+}
+
+type KafkaBaseConsumer = BaseConsumer<KafkaConsumerContext>;
+
+struct KafkaConsumer {
+    consumer: Arc<KafkaBaseConsumer>,
+    state: Arc<Mutex<ConsumerState>>,
+    running: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Drop for KafkaConsumer {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            if thread.join().is_err() {
+                error!("consumer thread panicked");
+            }
+        }
+    }
+}
+
+struct PendingPublish {
+    remaining: usize,
+    result: ProcessingResult,
+}
+
+struct KafkaProducerContext {
+    id: InternalServerId,
+    sender_to_kernel: Mutex<BoxedSender>,
+    pending_publishes: Mutex<HashMap<CloudEventMessageRoutingId, PendingPublish>>,
+}
+
+struct KafkaProducer {
+    producer: ThreadedProducer<KafkaProducerContext>,
+    topics: HashMap<String, ContentMode>,
+}
+
+impl Drop for KafkaProducer {
+    fn drop(&mut self) {
+        // the queued messages are sent before the producer is replaced, otherwise their results get lost
+        self.producer.flush(FLUSH_TIMEOUT);
+        self.producer.context().fail_pending();
+    }
+}
+
+/// The result which requires a retry wins, because the event is published to the other topics again anyway.
+fn worse_result(a: ProcessingResult, b: ProcessingResult) -> ProcessingResult {
+    let priority = |r: &ProcessingResult| match r {
+        ProcessingResult::Successful => 0,
+        ProcessingResult::PermanentError => 1,
+        ProcessingResult::Timeout => 2,
+        ProcessingResult::TransientError => 3,
+    };
+    if priority(&b) > priority(&a) {
+        b
+    } else {
+        a
+    }
+}
+
+fn result_from_error(error: &KafkaError) -> ProcessingResult {
+    match error.rdkafka_error_code() {
+        Some(RDKafkaErrorCode::MessageSizeTooLarge)
+        | Some(RDKafkaErrorCode::InvalidMessage)
+        | Some(RDKafkaErrorCode::InvalidMessageSize)
+        | Some(RDKafkaErrorCode::InvalidRecord) => ProcessingResult::PermanentError,
+        Some(RDKafkaErrorCode::MessageTimedOut) => ProcessingResult::Timeout,
+        _ => ProcessingResult::TransientError,
+    }
+}
+
+impl KafkaProducerContext {
+    /// Completes one publish attempt of the event, the kernel is notified after all topics are completed.
+    fn complete(&self, routing_id: &str, result: ProcessingResult) {
+        let mut pending_publishes = self.pending_publishes.lock().unwrap();
+        let done = match pending_publishes.get_mut(routing_id) {
+            Some(pending) => {
+                pending.remaining -= 1;
+                pending.result = worse_result(pending.result.clone(), result);
+                pending.remaining == 0
+            }
+            None => false,
+        };
+        if done {
+            let pending = pending_publishes.remove(routing_id).unwrap();
+            self.sender_to_kernel
+                .lock()
+                .unwrap()
+                .send(BrokerEvent::OutgoingCloudEventProcessed(
+                    OutgoingCloudEventProcessed {
+                        sender_id: self.id.clone(),
+                        routing_id: routing_id.to_string(),
+                        result: pending.result,
+                    },
+                ));
+        }
+    }
+}
+
+impl KafkaProducerContext {
+    /// Fails the events which are still in flight, e.g., because the producer is dropped.
+    fn fail_pending(&self) {
+        let pending_publishes: Vec<CloudEventMessageRoutingId> = self
+            .pending_publishes
+            .lock()
+            .unwrap()
+            .drain()
+            .map(|(routing_id, _)| routing_id)
+            .collect();
+        let sender_to_kernel = self.sender_to_kernel.lock().unwrap();
+        for routing_id in pending_publishes {
+            warn!("{} message {} was not acknowledged", self.id, routing_id);
+            sender_to_kernel.send(BrokerEvent::OutgoingCloudEventProcessed(
+                OutgoingCloudEventProcessed {
+                    sender_id: self.id.clone(),
+                    routing_id,
+                    result: ProcessingResult::TransientError,
+                },
+            ));
+        }
+    }
+}
+
+impl ClientContext for KafkaProducerContext {}
+
+impl ProducerContext for KafkaProducerContext {
+    type DeliveryOpaque = Box<CloudEventMessageRoutingId>;
+
+    fn delivery(&self, delivery_result: &DeliveryResult<'_>, routing_id: Self::DeliveryOpaque) {
+        let result = match delivery_result {
+            Ok(_) => ProcessingResult::Successful,
+            Err((e, _)) => {
+                warn!("{} failed to publish {}: {}", self.id, routing_id, e);
+                result_from_error(e)
+            }
+        };
+        self.complete(routing_id.as_str(), result);
+    }
+}
+
+fn try_get_delivery_option(config: &HashMap<String, Config>) -> Result<DeliveryGuarantee> {
+    Ok(match config.get("delivery_guarantee") {
+        Some(config) => DeliveryGuarantee::try_from(config)?,
+        _ => DeliveryGuarantee::BestEffort,
+    })
+}
+
+fn try_get_content_mode(config: &HashMap<String, Config>) -> Result<ContentMode> {
+    Ok(match config.get("content_mode") {
+        Some(Config::String(mode)) if mode == "binary" => ContentMode::Binary,
+        Some(Config::String(mode)) if mode == "structured" => ContentMode::Structured,
+        None => ContentMode::Binary,
+        Some(mode) => bail!("content_mode {:?} is not supported", mode),
+    })
+}
+
+fn build_config(id: &InternalServerId, config: &Config) -> Result<KafkaOptions> {
+    let config_map = match config {
+        Config::HashMap(config_map) => config_map,
+        _ => bail!("{} config has to be of type HashMap", id),
+    };
+    let mut options = KafkaOptions {
+        brokers: match config_map.get("brokers") {
+            Some(Config::String(brokers)) => brokers.to_string(),
+            _ => bail!("No brokers option"),
+        },
+        group_id: match config_map.get("group_id") {
+            Some(Config::String(group_id)) => group_id.to_string(),
+            _ => format!("cerk-{}", id),
+        },
+        consume_topics: HashMap::new(),
+        publish_topics: HashMap::new(),
+    };
+
+    if let Some(Config::Vec(ref consumers)) = config_map.get("consume_topics") {
+        for consumer_config in consumers.iter() {
+            if let Config::HashMap(consumer) = consumer_config {
+                if let Some(Config::String(name)) = consumer.get("name") {
+                    options
+                        .consume_topics
+                        .insert(name.to_string(), try_get_delivery_option(consumer)?);
+                } else {
+                    bail!("consume_topics name is not set")
+                }
+            } else {
+                bail!("consume_topics entries have to be of type HashMap")
+            }
+        }
+    }
+
+    if let Some(Config::Vec(ref publishers)) = config_map.get("publish_topics") {
+        for publisher_config in publishers.iter() {
+            if let Config::HashMap(publisher) = publisher_config {
+                if let Some(Config::String(name)) = publisher.get("name") {
+                    options
+                        .publish_topics
+                        .insert(name.to_string(), try_get_content_mode(publisher)?);
+                } else {
+                    bail!("publish_topics name is not set")
+                }
+            } else {
+                bail!("publish_topics entries have to be of type HashMap")
+            }
+        }
+    }
+
+    Ok(options)
+}
+
+fn commit(consumer: &KafkaBaseConsumer, topic: &str, partition: i32, offset: Option<i64>) {
+    if let Some(offset) = offset {
+        let mut offsets = TopicPartitionList::new();
+        if let Err(e) = offsets
+            .add_partition_offset(topic, partition, Offset::Offset(offset))
+            .and_then(|_| consumer.commit(&offsets, CommitMode::Async))
+        {
+            error!(
+                "failed to commit offset {} of {}/{}: {}",
+                offset, topic, partition, e
+            );
+        }
+    }
+}
+
+fn receive_message(
+    id: &InternalServerId,
+    consumer: &KafkaBaseConsumer,
+    state: &Mutex<ConsumerState>,
+    sender_to_kernel: &BoxedSender,
+    topics: &HashMap<String, DeliveryGuarantee>,
+    message: &BorrowedMessage,
+) {
+    let (topic, partition, offset) = (message.topic(), message.partition(), message.offset());
+    debug!("{} received message {}/{}/{}", id, topic, partition, offset);
+    let delivery_guarantee = topics
+        .get(topic)
+        .cloned()
+        .unwrap_or(DeliveryGuarantee::BestEffort);
+    let headers: Vec<(&str, &[u8])> = message
+        .headers()
+        .map(|headers| {
+            (0..headers.count())
+                .filter_map(|i| headers.get(i))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    // the lock is released before the event is sent, the kernel could wait for this port
+    let mut state = state.lock().unwrap();
+    state.tracker.received(topic, partition, offset);
+    match from_record(headers.into_iter(), message.payload().unwrap_or_default()) {
+        Ok(cloud_event) => {
+            let event = IncomingCloudEvent {
+                incoming_id: id.clone(),
+                routing_id: format!("{}--{}/{}/{}", cloud_event.id(), topic, partition, offset),
+                cloud_event,
+                args: CloudEventRoutingArgs { delivery_guarantee },
+            };
+            if delivery_guarantee.requires_acknowledgment() {
+                state.pending_deliveries.insert(
+                    event.routing_id.clone(),
+                    PendingDelivery {
+                        topic: topic.to_string(),
+                        partition,
+                        offset,
+                        event: event.clone(),
+                    },
+                );
+            } else {
+                let offset = state.tracker.processed(topic, partition, offset);
+                commit(consumer, topic, partition, offset);
+            }
+            drop(state);
+            sender_to_kernel.send(BrokerEvent::IncomingCloudEvent(event));
+        }
+        Err(e) => {
+            error!(
+                "{} skips message {}/{}/{}: {:?}",
+                id, topic, partition, offset, e
+            );
+            let offset = state.tracker.processed(topic, partition, offset);
+            commit(consumer, topic, partition, offset);
+        }
+    }
+}
+
+fn setup_consumer(
+    id: &InternalServerId,
+    options: &KafkaOptions,
+    sender_to_kernel: BoxedSender,
+) -> Result<KafkaConsumer> {
+    let state = Arc::new(Mutex::new(ConsumerState::default()));
+    let consumer: KafkaBaseConsumer = ClientConfig::new()
+        .set("bootstrap.servers", options.brokers.as_str())
+        .set("group.id", options.group_id.as_str())
+        .set("enable.auto.commit", "false")
+        .set("auto.offset.reset", "earliest")
+        .create_with_context(KafkaConsumerContext {
+            id: id.clone(),
+            state: state.clone(),
+        })
+        .context("failed to create consumer")?;
+    let topics: Vec<&str> = options.consume_topics.keys().map(|t| t.as_str()).collect();
+    consumer
+        .subscribe(topics.as_slice())
+        .context("failed to subscribe to topics")?;
+
+    let consumer = Arc::new(consumer);
+    let running = Arc::new(AtomicBool::new(true));
+    let thread = {
+        let id = id.clone();
+        let consumer = consumer.clone();
+        let state = state.clone();
+        let running = running.clone();
+        let topics = options.consume_topics.clone();
+        thread::spawn(move || {
+            while running.load(Ordering::SeqCst) {
+                let retries = state.lock().unwrap().due_retries(Instant::now());
+                for event in retries {
+                    sender_to_kernel.send(BrokerEvent::IncomingCloudEvent(event));
+                }
+                match consumer.poll(POLL_TIMEOUT) {
+                    Some(Ok(message)) => receive_message(
+                        &id,
+                        &consumer,
+                        &state,
+                        &sender_to_kernel,
+                        &topics,
+                        &message,
+                    ),
+                    Some(Err(e)) => warn!("{} failed to consume: {}", id, e),
+                    None => {}
+                }
+            }
+        })
+    };
+    Ok(KafkaConsumer {
+        consumer,
+        state,
+        running,
+        thread: Some(thread),
+    })
+}
+
+fn setup_producer(
+    id: &InternalServerId,
+    options: &KafkaOptions,
+    sender_to_kernel: BoxedSender,
+) -> Result<KafkaProducer> {
+    let context = KafkaProducerContext {
+        id: id.clone(),
+        sender_to_kernel: Mutex::new(sender_to_kernel),
+        pending_publishes: Mutex::new(HashMap::new()),
+    };
+    let producer = ClientConfig::new()
+        .set("bootstrap.servers", options.brokers.as_str())
+        .set("acks", "all")
+        .create_with_context(context)
+        .context("failed to create producer")?;
+    Ok(KafkaProducer {
+        producer,
+        topics: options.publish_topics.clone(),
+    })
+}
+
+fn publish_record(
+    producer: &KafkaProducer,
+    topic: &str,
+    content_mode: ContentMode,
+    event: &OutgoingCloudEvent,
+) -> Result<()> {
+    let record = to_record(event.cloud_event.clone(), content_mode)?;
+    let mut headers = OwnedHeaders::new();
+    for (name, value) in record.headers.iter() {
+        headers = headers.add(name.as_str(), value.as_slice());
+    }
+    let mut base_record =
+        BaseRecord::<Vec<u8>, Vec<u8>, Box<CloudEventMessageRoutingId>>::with_opaque_to(
+            topic,
+            Box::new(event.routing_id.clone()),
+        )
+        .payload(&record.payload)
+        .headers(headers);
+    if let Some(key) = record.key.as_ref() {
+        base_record = base_record.key(key);
+    }
+    producer
+        .producer
+        .send(base_record)
+        .map_err(|(e, _)| anyhow!("failed to enqueue message: {}", e))
+}
+
+fn send_cloud_event(
+    id: &InternalServerId,
+    event: OutgoingCloudEvent,
+    producer: &Option<KafkaProducer>,
+    sender_to_kernel: &BoxedSender,
+) {
+    let requires_acknowledgment = event.args.delivery_guarantee.requires_acknowledgment();
+    let producer = match producer {
+        Some(producer) if !producer.topics.is_empty() => producer,
+        _ => {
+            error!(
+                "{} received an event, but no publish topic is configured",
+                id
+            );
+            if requires_acknowledgment {
+                sender_to_kernel.send(BrokerEvent::OutgoingCloudEventProcessed(
+                    OutgoingCloudEventProcessed {
+                        sender_id: id.clone(),
+                        routing_id: event.routing_id,
+                        result: ProcessingResult::PermanentError,
+                    },
+                ));
+            }
+            return;
+        }
+    };
+
+    let context = producer.producer.context();
+    if requires_acknowledgment {
+        context.pending_publishes.lock().unwrap().insert(
+            event.routing_id.clone(),
+            PendingPublish {
+                remaining: producer.topics.len(),
+                result: ProcessingResult::Successful,
+            },
+        );
+    }
+    for (topic, content_mode) in producer.topics.iter() {
+        if let Err(e) = publish_record(producer, topic, *content_mode, &event) {
+            error!("{} failed to publish to {}: {:?}", id, topic, e);
+            context.complete(&event.routing_id, ProcessingResult::TransientError);
+        }
+    }
+}
+
+/// Commits the message after a successful or permanent failed processing, otherwise it is routed again after a delay.
+fn resolve_pending_delivery(
+    id: &InternalServerId,
+    consumer: &Option<KafkaConsumer>,
+    routing_id: &CloudEventMessageRoutingId,
+    result: ProcessingResult,
+) -> Result<()> {
+    let consumer = consumer.as_ref().context("no consumer is configured")?;
+    let commit_offset = consumer
+        .state
+        .lock()
+        .unwrap()
+        .resolve(id, routing_id, result)?;
+    if let Some((topic, partition, offset)) = commit_offset {
+        commit(&consumer.consumer, topic.as_str(), partition, Some(offset));
+    }
+    Ok(())
+}
+
+fn check_health(event: HealthCheckRequest, configured: bool, sender_to_kernel: &BoxedSender) {
+    let status = if configured {
+        HealthCheckStatus::Healthy
+    } else {
+        HealthCheckStatus::Unhealthy("port is not configured".to_string())
+    };
+    sender_to_kernel.send(BrokerEvent::HealthCheckResponse(HealthCheckResponse {
+        status,
+        destination_id: event.sender_id,
+        id: event.id,
+        sender_id: event.destination_id,
+    }))
+}
+
+/// This is the main function to start the port.
+pub fn port_kafka_start(id: InternalServerId, inbox: BoxedReceiver, sender_to_kernel: BoxedSender) {
+    info!("start kafka port with id {}", id);
+    let mut consumer: Option<KafkaConsumer> = None;
+    let mut producer: Option<KafkaProducer> = None;
+    let mut configured = false;
+
+    loop {
+        match inbox.receive() {
+            BrokerEvent::Init => info!("{} initiated", id),
+            BrokerEvent::ConfigUpdated(config, _) => {
+                info!("{} received ConfigUpdated", id);
+                consumer = None;
+                producer = None;
+                configured = false;
+                match build_config(&id, &config) {
+                    Ok(options) => {
+                        configured = true;
+                        if !options.consume_topics.is_empty() {
+                            match setup_consumer(&id, &options, sender_to_kernel.clone_boxed()) {
+                                Ok(c) => consumer = Some(c),
+                                Err(e) => {
+                                    configured = false;
+                                    error!("{} failed to setup consumer {:?}", id, e)
+                                }
+                            }
+                        }
+                        if !options.publish_topics.is_empty() {
+                            match setup_producer(&id, &options, sender_to_kernel.clone_boxed()) {
+                                Ok(p) => producer = Some(p),
+                                Err(e) => {
+                                    configured = false;
+                                    error!("{} failed to setup producer {:?}", id, e)
+                                }
+                            }
+                        }
+                    }
+                    Err(e) => error!("{} failed to apply config {:?}", id, e),
+                }
+            }
+            BrokerEvent::OutgoingCloudEvent(event) => {
+                send_cloud_event(&id, event, &producer, &sender_to_kernel)
+            }
+            BrokerEvent::IncomingCloudEventProcessed(routing_id, result) => {
+                if let Err(e) = resolve_pending_delivery(&id, &consumer, &routing_id, result) {
+                    warn!("{} IncomingCloudEventProcessed was not handled {:?}", id, e)
+                }
+            }
+            BrokerEvent::HealthCheckRequest(event) => {
+                check_health(event, configured, &sender_to_kernel)
+            }
+            broker_event => warn!("event {} not implemented", broker_event),
+        }
+    }
+}
+
+/// This is the pointer for the main function to start the port.
+pub static PORT_KAFKA: InternalServerFnRefStatic = &(port_kafka_start as InternalServerFn);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cerk_runtime_threading::channel::new_channel_with_size;
+    use cloudevents::{Event, EventBuilder, EventBuilderV10};
+
+    fn topic(name: &str, key: &str, value: Config) -> Config {
+        Config::HashMap(
+            [
+                ("name".to_string(), Config::String(name.to_string())),
+                (key.to_string(), value),
+            ]
+            .iter()
+            .cloned()
+            .collect(),
+        )
+    }
+
+    #[test]
+    fn build_full_config() -> Result<()> {
+        let config = Config::HashMap(
+            [
+                (
+                    "brokers".to_string(),
+                    Config::String("localhost:9092".to_string()),
+                ),
+                (
+                    "consume_topics".to_string(),
+                    Config::Vec(vec![topic("in", "delivery_guarantee", Config::U8(2))]),
+                ),
+                (
+                    "publish_topics".to_string(),
+                    Config::Vec(vec![topic(
+                        "out",
+                        "content_mode",
+                        Config::String("structured".to_string()),
+                    )]),
+                ),
+            ]
+            .iter()
+            .cloned()
+            .collect(),
+        );
+        let options = build_config(&"kafka".to_string(), &config)?;
+        assert_eq!(options.brokers, "localhost:9092");
+        assert_eq!(options.group_id, "cerk-kafka");
+        assert_eq!(
+            options.consume_topics.get("in"),
+            Some(&DeliveryGuarantee::AtLeastOnce)
+        );
+        assert_eq!(
+            options.publish_topics.get("out"),
+            Some(&ContentMode::Structured)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn build_invalid_config() {
+        let id = "kafka".to_string();
+        assert!(build_config(&id, &Config::Null).is_err());
+        assert!(build_config(&id, &Config::HashMap(HashMap::new())).is_err());
+    }
+
+    #[test]
+    fn notify_kernel_after_all_topics_are_completed() {
+        let (send, receive) = new_channel_with_size(10);
+        let context = KafkaProducerContext {
+            id: "kafka".to_string(),
+            sender_to_kernel: Mutex::new(send),
+            pending_publishes: Mutex::new(HashMap::new()),
+        };
+        context.pending_publishes.lock().unwrap().insert(
+            "1".to_string(),
+            PendingPublish {
+                remaining: 2,
+                result: ProcessingResult::Successful,
+            },
+        );
+
+        context.complete("1", ProcessingResult::TransientError);
+        assert!(receive.receive_timeout(Duration::from_millis(10)).is_none());
+        context.complete("1", ProcessingResult::Successful);
+        match receive.receive_timeout(Duration::from_millis(100)) {
+            Some(BrokerEvent::OutgoingCloudEventProcessed(processed)) => {
+                assert_eq!(processed.routing_id, "1");
+                assert_eq!(processed.result, ProcessingResult::TransientError);
+            }
+            _ => panic!("expected OutgoingCloudEventProcessed"),
+        }
+    }
+
+    fn pending_delivery(state: &mut ConsumerState, offset: i64) -> CloudEventMessageRoutingId {
+        let routing_id = format!("{}", offset);
+        state.tracker.received("in", 0, offset);
+        state.pending_deliveries.insert(
+            routing_id.clone(),
+            PendingDelivery {
+                topic: "in".to_string(),
+                partition: 0,
+                offset,
+                event: IncomingCloudEvent {
+                    incoming_id: "kafka".to_string(),
+                    routing_id: routing_id.clone(),
+                    cloud_event: Event::default(),
+                    args: CloudEventRoutingArgs {
+                        delivery_guarantee: DeliveryGuarantee::AtLeastOnce,
+                    },
+                },
+            },
+        );
+        routing_id
+    }
+
+    #[test]
+    fn retry_transient_failed_message_without_consuming_the_others_again() -> Result<()> {
+        let id = "kafka".to_string();
+        let mut state = ConsumerState::default();
+        let first = pending_delivery(&mut state, 0);
+        let second = pending_delivery(&mut state, 1);
+
+        assert_eq!(
+            state.resolve(&id, &first, ProcessingResult::TransientError)?,
+            None
+        );
+        // the second message is not committed while the first one is in process
+        assert_eq!(
+            state.resolve(&id, &second, ProcessingResult::Successful)?,
+            None
+        );
+        assert!(state.due_retries(Instant::now()).is_empty());
+        let retries = state.due_retries(Instant::now() + RETRY_DELAY);
+        assert_eq!(retries.len(), 1);
+        assert_eq!(retries[0].routing_id, first);
+
+        assert_eq!(
+            state.resolve(&id, &first, ProcessingResult::Successful)?,
+            Some(("in".to_string(), 0, 2))
+        );
+        assert!(state
+            .resolve(&id, &first, ProcessingResult::Successful)
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn drop_pending_deliveries_of_revoked_partitions() {
+        let id = "kafka".to_string();
+        let mut state = ConsumerState::default();
+        let first = pending_delivery(&mut state, 0);
+        assert!(state
+            .resolve(&id, &first, ProcessingResult::Timeout)
+            .unwrap()
+            .is_none());
+
+        state.revoke("in", 0);
+        assert!(state.pending_deliveries.is_empty());
+        assert!(state.due_retries(Instant::now() + RETRY_DELAY).is_empty());
+        assert!(state
+            .resolve(&id, &first, ProcessingResult::Successful)
+            .is_err());
+    }
+
+    #[test]
+    fn fail_pending_publishes() {
+        let (send, receive) = new_channel_with_size(10);
+        let context = KafkaProducerContext {
+            id: "kafka".to_string(),
+            sender_to_kernel: Mutex::new(send),
+            pending_publishes: Mutex::new(HashMap::new()),
+        };
+        context.pending_publishes.lock().unwrap().insert(
+            "1".to_string(),
+            PendingPublish {
+                remaining: 1,
+                result: ProcessingResult::Successful,
+            },
+        );
+
+        context.fail_pending();
+        assert!(context.pending_publishes.lock().unwrap().is_empty());
+        match receive.receive_timeout(Duration::from_millis(100)) {
+            Some(BrokerEvent::OutgoingCloudEventProcessed(processed)) => {
+                assert_eq!(processed.routing_id, "1");
+                assert_eq!(processed.result, ProcessingResult::TransientError);
+            }
+            _ => panic!("expected OutgoingCloudEventProcessed"),
+        }
+    }
+
+    /// Runs against a real broker, e.g., the `kafka` service of the `docker-compose.yml`:
+    /// `KAFKA_BROKERS=localhost:9092 cargo test -p cerk_port_kafka -- --ignored`
+    #[test]
+    #[ignore]
+    fn interop_with_broker() {
+        let brokers = std::env::var("KAFKA_BROKERS").expect("KAFKA_BROKERS is not set");
+        let topic_name = format!(
+            "cerk-interop-{}",
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_millis()
+        );
+        let (send_to_port, inbox) = new_channel_with_size(10);
+        let (sender_to_kernel, kernel) = new_channel_with_size(10);
+        thread::spawn(move || PORT_KAFKA("kafka".to_string(), inbox, sender_to_kernel));
+        send_to_port.send(BrokerEvent::ConfigUpdated(
+            Config::HashMap(
+                [
+                    ("brokers".to_string(), Config::String(brokers)),
+                    ("group_id".to_string(), Config::String(topic_name.clone())),
+                    (
+                        "consume_topics".to_string(),
+                        Config::Vec(vec![topic(
+                            &topic_name,
+                            "delivery_guarantee",
+                            Config::U8(2),
+                        )]),
+                    ),
+                    (
+                        "publish_topics".to_string(),
+                        Config::Vec(vec![topic(
+                            &topic_name,
+                            "content_mode",
+                            Config::String("binary".to_string()),
+                        )]),
+                    ),
+                ]
+                .iter()
+                .cloned()
+                .collect(),
+            ),
+            "kafka".to_string(),
+        ));
+
+        let cloud_event = EventBuilderV10::new()
+            .id("interop")
+            .ty("test")
+            .source("http://example.com")
+            .data("text/plain", "hello")
+            .build()
+            .unwrap();
+        send_to_port.send(BrokerEvent::OutgoingCloudEvent(OutgoingCloudEvent {
+            routing_id: "interop".to_string(),
+            cloud_event: cloud_event.clone(),
+            destination_id: "kafka".to_string(),
+            args: CloudEventRoutingArgs {
+                delivery_guarantee: DeliveryGuarantee::AtLeastOnce,
+            },
+        }));
+
+        // the broker might still be starting and creates the topic on the first publish
+        let deadline = Instant::now() + Duration::from_secs(60);
+        let mut processed = false;
+        let mut deliveries = 0;
+        while !(processed && deliveries == 2) {
+            match kernel.receive_timeout(Duration::from_secs(1)) {
+                Some(BrokerEvent::OutgoingCloudEventProcessed(event)) => {
+                    assert_eq!(event.result, ProcessingResult::Successful);
+                    processed = true;
+                }
+                Some(BrokerEvent::IncomingCloudEvent(event)) => {
+                    assert_eq!(event.cloud_event, cloud_event);
+                    deliveries += 1;
+                    // the first delivery fails, so the message is routed again
+                    let result = if deliveries == 1 {
+                        ProcessingResult::TransientError
+                    } else {
+                        ProcessingResult::Successful
+                    };
+                    send_to_port.send(BrokerEvent::IncomingCloudEventProcessed(
+                        event.routing_id,
+                        result,
+                    ));
+                }
+                Some(event) => panic!("unexpected {}", event),
+                None if Instant::now() < deadline => {}
+                None => panic!("timeout, processed={} deliveries={}", processed, deliveries),
+            }
+        }
+    }
+}
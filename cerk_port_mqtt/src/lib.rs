@@ -23,7 +23,7 @@ A good overview is provided on [GitHub](https://github.com/ce-rust/cerk/).
 
 # This Component: MQTT Port
 
-This port publishes and/or subscribe CloudEvents to/from an MQTT v3.1 topic.
+This port publishes and/or subscribe CloudEvents to/from MQTT 5 or MQTT 3.1.1 topics.
 
 The port is implemented with a [Eclipse Paho MQTT Rust Client](https://github.com/eclipse/paho.mqtt.rust)
 and sends and receives messages according to the
@@ -42,20 +42,111 @@ The value has to by of type `Config::String` and contain a host name with protoc
 
 E.g. `Config::String(String::from("tcp://mqtt-broker:1883"))`
 
+The protocol `ssl://` connects with TLS, e.g., `Config::String(String::from("ssl://mqtt-broker:8883"))`.
+
 ## Optional Fields
 
 ### send_topic
 
 The value has to by of type `Config::String` and contain the MQTT topic name where the message will be sent to.
 
-E.g. `Config::String(String::from("test"))`
+The topic may contain `{attribute}` placeholders, which are replaced by the attributes or extensions of the event.
+Events without the attribute or with a `+` or `#` in it are rejected with a permanent error.
+
+E.g. `Config::String(String::from("devices/{subject}/events"))`
 
 The following configurations are optional.
 
 ### subscribe_topic
 
-The value has to by of type `Config::String` and contain the MQTT topic name  which the router should subscribe to.
+The value has to by of type `Config::String` and contain the MQTT topic filter which the router should subscribe to.
+
+### subscribe_qos
+
+The value has to by of type `Config::U8` and contain the QoS level (0, 1 or 2) of the `subscribe_topic`, the default is 0.
+
+### subscriptions
+
+The value has to by of type `Config::Vec` and contain further subscriptions of type `Config::HashMap`:
+
+| Name                 | Type   | Default | Description                                                             |
+|----------------------|--------|---------|-------------------------------------------------------------------------|
+| `topic`              | String |         | the topic filter, wildcards are allowed                                 |
+| `qos`                | u8     | `0`     | the QoS level (0, 1 or 2)                                               |
+| `delivery_guarantee` | u8     |         | `BestEffort` (`0`) or `AtLeastOnce` (`2`), the default depends on `qos` |
+
+### mqtt_version
+
+The value has to by of type `Config::String` and contain the MQTT version, either `5` (default) or `3.1.1`.
+
+### content_mode
+
+The value has to by of type `Config::String` and contain the content mode of the published messages, either `structured` (default) or `binary`.
+
+### username and password
+
+The values have to by of type `Config::String` and contain the credentials of the client.
+A `password` requires a `username`.
+
+### session_expiry
+
+The value has to by of type `Config::U32` and contain the session expiry interval of MQTT 5 in seconds, the default is 3600.
+The broker keeps the subscriptions and queues the messages for this time after the connection is lost.
+With MQTT 3.1.1, the session is kept as long as the broker is configured to.
+
+### keep_alive
+
+The value has to by of type `Config::U32` and contain the keep alive interval in seconds, the default is 60.
+
+### TLS
+
+The TLS options require an `ssl://` host and have to by of type `Config::String`:
+
+| Name               | Description                                                            |
+|--------------------|------------------------------------------------------------------------|
+| `tls_ca_path`      | the PEM file with the CA certificates, the default are the system ones |
+| `tls_cert_path`    | the PEM file with the client certificate, requires `tls_key_path`      |
+| `tls_key_path`     | the PEM file with the private key of the client certificate            |
+| `tls_key_password` | the password of the private key                                        |
+
+### status_topic, status_online and status_offline
+
+The values have to by of type `Config::String`.
+If `status_topic` is set, the port publishes the retained status `status_online` (default `online`) to it after every connect,
+and registers `status_offline` (default `offline`) as last will, which the broker publishes if the connection is lost.
+
+## Status
 
+The status messages are published with QoS 1 and the retain flag, so a monitoring subscribing to the `status_topic` always receives the current status.
+The broker discards the last will on a graceful disconnect, therefore, the port publishes the offline status itself before it reconnects with an updated configuration.
+
+## Content Modes
+
+In the structured content mode, the event is sent as JSON payload with the content type `application/cloudevents+json`.
+In the binary content mode, the data is sent as payload with the `datacontenttype` as content type
+and all other attributes and extensions as user properties, e.g., `specversion` or `id`.
+
+User properties require MQTT 5, therefore, the port falls back to the structured content mode with MQTT 3.1.1.
+Received messages with a `specversion` user property are read in the binary content mode, all others in the structured content mode.
+
+## Delivery Guarantees
+
+With QoS 0, the incoming events are routed with the delivery guarantee "Best Effort".
+With QoS 1 or 2, they are routed with the delivery guarantee "At Least Once", unless the subscription sets `delivery_guarantee` to `0`:
+the events are routed one after another, and events which failed transiently are routed again after one second,
+until all output ports processed them successfully. Events which failed permanently are dropped.
+
+The messages are acknowledged by the Paho library when they are received, not when they are routed,
+because the receive thread of the library must not wait for the routing.
+Therefore, the broker does not deliver a message again if the router stops before the event was processed,
+it only keeps the messages which were not received yet for the `session_expiry`.
+
+## Wildcards
+
+The levels of a received topic which are matched by the wildcards `+` and `#` are set as CloudEvent `subject`, unless the event already has one.
+E.g., a message on `devices/sensor-1/events` received by the subscription `devices/+/events` gets the subject `sensor-1`,
+which the `send_topic` `devices/{subject}/events` maps back to the topic.
+If several subscriptions match the topic, the first one is used.
 
 ## Configuration Examples
 
@@ -68,7 +159,38 @@ use cerk::kernel::Config;
 let map: HashMap<String, Config> = [
     ("host".to_string(), Config::String("tcp://mqtt-broker:1883".to_string())),
     ("subscribe_topic".to_string(), Config::String("inbox".to_string())),
+    ("subscriptions".to_string(), Config::Vec(vec![Config::HashMap(
+        [
+            ("topic".to_string(), Config::String("devices/+/events".to_string())),
+            ("qos".to_string(), Config::U8(1)),
+        ]
+        .iter()
+        .cloned()
+        .collect(),
+    )])),
+    ("send_topic".to_string(), Config::String("outbox/{subject}".to_string())),
+]
+.iter()
+.cloned()
+.collect();
+
+let config = Config::HashMap(map);
+```
+
+### Configuration for a TLS connection with status
+
+```
+use std::collections::HashMap;
+use cerk::kernel::Config;
+
+let map: HashMap<String, Config> = [
+    ("host".to_string(), Config::String("ssl://mqtt-broker:8883".to_string())),
     ("send_topic".to_string(), Config::String("outbox".to_string())),
+    ("username".to_string(), Config::String("gateway-1".to_string())),
+    ("password".to_string(), Config::String("secret".to_string())),
+    ("keep_alive".to_string(), Config::U32(30)),
+    ("tls_ca_path".to_string(), Config::String("/etc/cerk/ca.pem".to_string())),
+    ("status_topic".to_string(), Config::String("gateways/gateway-1/status".to_string())),
 ]
 .iter()
 .cloned()
@@ -103,6 +225,7 @@ use cerk::kernel::Config;
 let map: HashMap<String, Config> = [
     ("host".to_string(), Config::String("tcp://mqtt-broker:1883".to_string())),
     ("subscribe_topic".to_string(), Config::String("inbox".to_string())),
+    ("subscribe_qos".to_string(), Config::U8(1)),
 ]
 .iter()
 .cloned()
@@ -122,6 +245,7 @@ let config = Config::HashMap(map);
 #[macro_use]
 extern crate log;
 
+mod mqtt_binding;
 mod port_mqtt;
 
 pub use self::port_mqtt::{port_mqtt_start, PORT_MQTT};
@@ -0,0 +1,178 @@
+use anyhow::{anyhow, Context, Result};
+pub use cerk_port_common::content_mode::ContentMode;
+use cloudevents::binding::http::to_event;
+use cloudevents::Event;
+use http::header::{HeaderName, HeaderValue, CONTENT_TYPE};
+use http::{HeaderMap, Request};
+use paho_mqtt::{Message, MessageBuilder, Properties, PropertyCode};
+use std::convert::TryFrom;
+
+const HTTP_HEADER_PREFIX: &str = "ce-";
+const STRUCTURED_CONTENT_TYPE: &str = "application/cloudevents+json; charset=UTF-8";
+const STRUCTURED_CONTENT_TYPE_PREFIX: &str = "application/cloudevents";
+const SPECVERSION: &str = "specversion";
+
+/// Converts the event to an MQTT message according to the [MQTT protocol binding](https://github.com/cloudevents/spec/blob/v1.0/mqtt-protocol-binding.md).
+///
+/// The binary content mode reuses the HTTP binding, the `ce-` headers become user properties without prefix.
+/// The properties are only sent by MQTT 5 clients.
+pub fn to_message(
+    event: &Event,
+    topic: &str,
+    qos: i32,
+    content_mode: ContentMode,
+) -> Result<Message> {
+    let mut properties = Properties::new();
+    let payload = match content_mode {
+        ContentMode::Binary => {
+            let request = Request::<Option<Vec<u8>>>::try_from(event.clone())
+                .map_err(|e| anyhow!("failed to serialize CloudEvent: {}", e))?;
+            let (parts, body) = request.into_parts();
+            for (name, value) in parts.headers.iter() {
+                let value = value.to_str()?;
+                match name.as_str().strip_prefix(HTTP_HEADER_PREFIX) {
+                    Some(attribute) => {
+                        properties.push_string_pair(PropertyCode::UserProperty, attribute, value)?
+                    }
+                    None if name == CONTENT_TYPE => {
+                        properties.push_string(PropertyCode::ContentType, value)?
+                    }
+                    None => {}
+                }
+            }
+            body.unwrap_or_default()
+        }
+        ContentMode::Structured => {
+            properties.push_string(PropertyCode::ContentType, STRUCTURED_CONTENT_TYPE)?;
+            serde_json::to_vec(event)?
+        }
+    };
+    Ok(MessageBuilder::new()
+        .topic(topic)
+        .payload(payload)
+        .qos(qos)
+        .properties(properties)
+        .finalize())
+}
+
+/// Converts an MQTT message to an event.
+///
+/// Messages with a `specversion` user property are in the binary content mode,
+/// unless their content type starts with `application/cloudevents`.
+/// All others, e.g., the messages of MQTT 3.1.1 clients, have to be in the structured content mode.
+pub fn from_message(message: &Message) -> Result<Event> {
+    let properties = message.properties();
+    let content_type = properties.get_string(PropertyCode::ContentType);
+    let attributes: Vec<(String, String)> = properties.user_iter().collect();
+    let structured = content_type
+        .as_deref()
+        .is_some_and(|c| c.starts_with(STRUCTURED_CONTENT_TYPE_PREFIX))
+        || !attributes.iter().any(|(name, _)| name == SPECVERSION);
+    if structured {
+        return serde_json::from_slice::<Event>(message.payload())
+            .context("failed to parse structured CloudEvent");
+    }
+    let mut header_map = HeaderMap::new();
+    if let Some(content_type) = content_type {
+        header_map.insert(CONTENT_TYPE, HeaderValue::from_str(&content_type)?);
+    }
+    for (attribute, value) in attributes {
+        header_map.insert(
+            HeaderName::from_bytes(format!("{}{}", HTTP_HEADER_PREFIX, attribute).as_bytes())?,
+            HeaderValue::from_str(&value)?,
+        );
+    }
+    to_event(&header_map, message.payload().to_vec()).context("failed to parse binary CloudEvent")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cloudevents::{AttributesReader, Data, EventBuilder, EventBuilderV10};
+
+    fn event() -> Event {
+        EventBuilderV10::new()
+            .id("1")
+            .ty("test")
+            .source("http://example.com")
+            .extension("tenant", "a")
+            .data("application/octet-stream", vec![0xff, 0x00, 0xfe])
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn binary_round_trip() -> Result<()> {
+        let message = to_message(&event(), "devices", 1, ContentMode::Binary)?;
+        assert_eq!(message.topic(), "devices");
+        assert_eq!(message.qos(), 1);
+        assert_eq!(message.payload(), &[0xff, 0x00, 0xfe]);
+        assert_eq!(
+            message.properties().get_string(PropertyCode::ContentType),
+            Some("application/octet-stream".to_string())
+        );
+        assert!(message
+            .properties()
+            .user_iter()
+            .any(|(name, value)| name == "tenant" && value == "a"));
+        assert_eq!(from_message(&message)?, event());
+        Ok(())
+    }
+
+    #[test]
+    fn structured_round_trip() -> Result<()> {
+        let message = to_message(&event(), "devices", 0, ContentMode::Structured)?;
+        assert_eq!(message.properties().user_iter().count(), 0);
+        assert_eq!(
+            message.properties().get_string(PropertyCode::ContentType),
+            Some(STRUCTURED_CONTENT_TYPE.to_string())
+        );
+        assert_eq!(from_message(&message)?, event());
+        // messages of MQTT 3.1.1 clients have no properties
+        let message = Message::new("devices", serde_json::to_vec(&event())?, 0);
+        assert_eq!(from_message(&message)?, event());
+        Ok(())
+    }
+
+    #[test]
+    fn binary_message_with_text_data() -> Result<()> {
+        let mut properties = Properties::new();
+        for (name, value) in [
+            ("specversion", "1.0"),
+            ("id", "1"),
+            ("type", "test"),
+            ("source", "http://example.com"),
+            ("subject", "sensor-1"),
+        ] {
+            properties.push_string_pair(PropertyCode::UserProperty, name, value)?;
+        }
+        properties.push_string(PropertyCode::ContentType, "text/plain")?;
+        let message = MessageBuilder::new()
+            .topic("devices")
+            .payload("hello")
+            .properties(properties)
+            .finalize();
+
+        let event = from_message(&message)?;
+        assert_eq!(event.id(), "1");
+        assert_eq!(event.subject(), Some("sensor-1"));
+        assert_eq!(event.datacontenttype(), Some("text/plain"));
+        assert_eq!(event.data(), Some(&Data::Binary(b"hello".to_vec())));
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_messages() {
+        assert!(from_message(&Message::new("devices", vec![0xff], 0)).is_err());
+        let mut properties = Properties::new();
+        properties
+            .push_string_pair(PropertyCode::UserProperty, "specversion", "1.0")
+            .unwrap();
+        let message = MessageBuilder::new()
+            .topic("devices")
+            .properties(properties)
+            .finalize();
+        assert!(from_message(&message).is_err());
+        assert!(ContentMode::parse("batched").is_err());
+    }
+}
@@ -1,97 +1,246 @@
-use anyhow::{bail, Result};
+use crate::mqtt_binding::{from_message, to_message, ContentMode};
+use anyhow::{bail, Context, Result};
 use async_std::task::block_on;
 use cerk::kernel::{
-    BrokerEvent, CloudEventRoutingArgs, Config, DeliveryGuarantee, IncomingCloudEvent,
-    OutgoingCloudEvent, OutgoingCloudEventProcessed, ProcessingResult,
+    BrokerEvent, CloudEventMessageRoutingId, CloudEventRoutingArgs, Config, ConfigHelpers,
+    DeliveryGuarantee, IncomingCloudEvent, OutgoingCloudEvent, OutgoingCloudEventProcessed,
+    ProcessingResult,
 };
 use cerk::runtime::channel::{BoxedReceiver, BoxedSender};
 use cerk::runtime::{InternalServerFn, InternalServerFnRefStatic, InternalServerId};
-use cloudevents::event::Event;
-use cloudevents::AttributesReader;
+use cerk_port_common::mqtt_connection_options::{
+    try_get_connection_options, ConnectionOptions, STATUS_QOS,
+};
+use cerk_port_common::mqtt_topics::{
+    find_subscription, render_topic, try_get_subscriptions, MqttSubscription,
+};
+use cloudevents::{AttributesReader, AttributesWriter};
 use paho_mqtt::{
-    AsyncClient, ConnectOptionsBuilder, CreateOptionsBuilder, Message, PersistenceType,
+    AsyncClient, ConnectOptionsBuilder, CreateOptionsBuilder, Message, PersistenceType, Properties,
+    PropertyCode, SslOptionsBuilder, MQTT_VERSION_3_1_1, MQTT_VERSION_5,
 };
-use serde_json;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread;
 use std::time::Duration;
 
+/// The delay before an event is sent to the kernel again, after its processing failed transiently.
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+/// The broker keeps the session of an MQTT 5 client this long after the connection is lost.
+const DEFAULT_SESSION_EXPIRY: u32 = 3600;
+
+type ProcessedSender = Sender<(CloudEventMessageRoutingId, ProcessingResult)>;
+type ProcessedReceiver = Receiver<(CloudEventMessageRoutingId, ProcessingResult)>;
+
 struct MqttConnection {
     client: AsyncClient,
+    /// template of the topic, see [`render_topic`]
     send_topic: Option<String>,
-    subscribe_topic: Option<String>,
+    subscriptions: Vec<MqttSubscription>,
+    mqtt_version: u32,
+    /// the session expiry interval of MQTT 5 in seconds
+    session_expiry: u32,
+    content_mode: ContentMode,
+    options: ConnectionOptions,
+    /// forwards the results of the incoming events to the message worker
+    processed_sender: Option<ProcessedSender>,
 }
 
-fn build_connection(id: &InternalServerId, config: Config) -> MqttConnection {
+fn build_connection(id: &InternalServerId, config: Config) -> Result<MqttConnection> {
     match config {
         Config::HashMap(ref config_map) => {
             let host = match config_map.get("host") {
                 Some(Config::String(host)) => host,
-                _ => panic!("{} invalid value for host", id),
+                _ => bail!("{} invalid value for host", id),
             };
 
             let send_topic = match config_map.get("send_topic") {
                 Some(Config::String(topic)) => Some(topic.clone()),
-                Some(_) => panic!("{} invalid value for send_topic", id),
+                Some(_) => bail!("{} invalid value for send_topic", id),
                 _ => None,
             };
 
-            let subscribe_topic = match config_map.get("subscribe_topic") {
-                Some(Config::String(topic)) => Some(topic.clone()),
-                Some(_) => panic!("{} invalid value for subscribe_topic", id),
-                _ => None,
+            let subscriptions = try_get_subscriptions(&config, 2)
+                .with_context(|| format!("{} invalid subscriptions", id))?;
+
+            let mqtt_version = match config_map.get("mqtt_version") {
+                Some(Config::String(version)) if version == "5" => MQTT_VERSION_5,
+                Some(Config::String(version)) if version == "3.1.1" => MQTT_VERSION_3_1_1,
+                None => MQTT_VERSION_5,
+                Some(_) => bail!("{} invalid value for mqtt_version", id),
+            };
+
+            let content_mode = match config_map.get("content_mode") {
+                Some(Config::String(content_mode)) => ContentMode::parse(content_mode)?,
+                Some(_) => bail!("{} invalid value for content_mode", id),
+                None => ContentMode::Structured,
             };
+            // MQTT 3.1.1 has no user properties to carry the attributes
+            let content_mode =
+                if mqtt_version < MQTT_VERSION_5 && content_mode == ContentMode::Binary {
+                    warn!(
+                    "{} the binary content mode requires MQTT 5 -> use the structured content mode",
+                    id
+                );
+                    ContentMode::Structured
+                } else {
+                    content_mode
+                };
+
+            let session_expiry = config
+                .get_op_val_u32("session_expiry")
+                .with_context(|| format!("{} session_expiry has to be of type U32", id))?
+                .unwrap_or(DEFAULT_SESSION_EXPIRY);
+
+            let options = try_get_connection_options(host, &config)
+                .with_context(|| format!("{} invalid connection options", id))?;
 
             let mqtt_config = CreateOptionsBuilder::new()
                 .client_id(format!("cerk-{}", id))
                 .server_uri(host)
                 .persistence(PersistenceType::None)
-                .mqtt_version(5)
+                .mqtt_version(mqtt_version)
                 .finalize();
 
-            let client = AsyncClient::new(mqtt_config).unwrap_or_else(|err| {
-                panic!("Error creating the client: {}", err);
-            });
+            let client = AsyncClient::new(mqtt_config).context("Error creating the client")?;
 
-            return MqttConnection {
+            Ok(MqttConnection {
                 client,
                 send_topic,
-                subscribe_topic,
-            };
+                subscriptions,
+                mqtt_version,
+                session_expiry,
+                content_mode,
+                options,
+                processed_sender: None,
+            })
         }
-        _ => panic!("{} received invalide config", id),
+        _ => bail!("{} received invalide config", id),
     }
 }
 
-fn message_handler(
+/// Sends the event to the kernel and blocks until it is processed, events which failed transiently are sent again after the `retry_delay`.
+///
+/// Returns the final result, or `None` if the port was reconfigured meanwhile.
+fn send_and_await_processed(
+    id: &InternalServerId,
+    sender_to_kernel: &BoxedSender,
+    event: IncomingCloudEvent,
+    processed_receiver: &ProcessedReceiver,
+    retry_delay: Duration,
+) -> Option<ProcessingResult> {
+    loop {
+        sender_to_kernel.send(BrokerEvent::IncomingCloudEvent(event.clone()));
+        let result = loop {
+            match processed_receiver.recv() {
+                Ok((routing_id, result)) if routing_id == event.routing_id => break result,
+                Ok((routing_id, _)) => warn!(
+                    "{} expected result for event {}, got {}",
+                    id, event.routing_id, routing_id
+                ),
+                Err(_) => {
+                    warn!(
+                        "{} was reconfigured before event {} was processed",
+                        id, event.routing_id
+                    );
+                    return None;
+                }
+            }
+        };
+        match result {
+            ProcessingResult::Successful => {
+                debug!("{} event {} processed", id, event.routing_id);
+                return Some(result);
+            }
+            ProcessingResult::PermanentError => {
+                error!(
+                    "{} event {} could not be processed and is dropped",
+                    id, event.routing_id
+                );
+                return Some(result);
+            }
+            ProcessingResult::TransientError | ProcessingResult::Timeout => {
+                warn!(
+                    "{} event {} could not be processed ({}) -> retry in {:?}",
+                    id, event.routing_id, result, retry_delay
+                );
+                thread::sleep(retry_delay);
+            }
+        }
+    }
+}
+
+/// Routes the received messages one after another, until the port is reconfigured.
+///
+/// It runs on its own thread, because the message callback runs on the receive thread of the Paho C library,
+/// which must not be blocked: it handles the keep alive and the acknowledgments of the published messages.
+/// The PUBACK of a message is therefore already sent, when the event is routed.
+fn message_worker(
     id: InternalServerId,
     sender_to_kernel: BoxedSender,
-    routing_args: CloudEventRoutingArgs,
-) -> Box<dyn Fn(&AsyncClient, Option<paho_mqtt::Message>)> {
-    Box::new(
-        move |_client: &AsyncClient, msg: Option<paho_mqtt::Message>| {
-            debug!("{} received message callback", id);
-            if let Some(msg) = msg {
-                debug!("{} received cloudevent on topic {}", id, msg.topic());
-                let payload_str = msg.payload_str();
-                match serde_json::from_str::<Event>(&payload_str) {
-                    Ok(cloud_event) => {
-                        debug!("{} deserialized event successfully", id);
-                        let routing_id = cloud_event.id().to_string();
-                        sender_to_kernel.send(BrokerEvent::IncomingCloudEvent(
-                            IncomingCloudEvent {
-                                routing_id,
-                                incoming_id: id.clone(),
-                                cloud_event,
-                                args: routing_args.clone(),
-                            },
-                        ));
-                    }
-                    Err(err) => {
-                        error!("{} while converting string to CloudEvent: {:?}", id, err);
-                    }
+    subscriptions: Vec<MqttSubscription>,
+    messages: Receiver<Message>,
+    processed_receiver: ProcessedReceiver,
+) {
+    for msg in messages.iter() {
+        debug!("{} received cloudevent on topic {}", id, msg.topic());
+        let (subscription, subject) = match find_subscription(&subscriptions, msg.topic()) {
+            Some(found) => found,
+            None => {
+                // e.g., a subscription of the persistent session before the config was updated
+                warn!(
+                    "{} received message on {} which matches no subscription -> message will be dropped",
+                    id,
+                    msg.topic()
+                );
+                continue;
+            }
+        };
+        match from_message(&msg) {
+            Ok(mut cloud_event) => {
+                debug!("{} deserialized event successfully", id);
+                if cloud_event.subject().is_none() {
+                    cloud_event.set_subject(subject);
+                }
+                let delivery_guarantee = subscription.delivery_guarantee;
+                let event = IncomingCloudEvent {
+                    routing_id: cloud_event.id().to_string(),
+                    incoming_id: id.clone(),
+                    cloud_event,
+                    args: CloudEventRoutingArgs { delivery_guarantee },
+                };
+                if !delivery_guarantee.requires_acknowledgment() {
+                    sender_to_kernel.send(BrokerEvent::IncomingCloudEvent(event));
+                } else if send_and_await_processed(
+                    &id,
+                    &sender_to_kernel,
+                    event,
+                    &processed_receiver,
+                    RETRY_DELAY,
+                )
+                .is_none()
+                {
+                    return;
                 }
             }
-        },
-    )
+            Err(err) => {
+                error!("{} while converting message to CloudEvent: {:?}", id, err);
+            }
+        }
+    }
+}
+
+fn message_handler(
+    id: InternalServerId,
+    message_sender: Sender<Message>,
+) -> impl FnMut(&AsyncClient, Option<paho_mqtt::Message>) + 'static {
+    move |_client: &AsyncClient, msg: Option<paho_mqtt::Message>| {
+        debug!("{} received message callback", id);
+        if let Some(msg) = msg {
+            if message_sender.send(msg).is_err() {
+                warn!("{} message worker stopped -> message will be dropped", id);
+            }
+        }
+    }
 }
 
 async fn setup_connection(
@@ -101,30 +250,104 @@ async fn setup_connection(
 ) -> Result<MqttConnection> {
     debug!("{} start connection to mqtt broker", id);
 
-    let connection = build_connection(id, config);
-
-    let connection_options = ConnectOptionsBuilder::new()
-        .clean_session(false)
-        .clean_start(false)
-        .automatic_reconnect(Duration::from_secs(1), Duration::from_secs(5))
-        .finalize();
+    let mut connection = build_connection(id, config)?;
 
-    connection.client.connect(connection_options).await?;
+    let mut connection_options = ConnectOptionsBuilder::new();
+    connection_options.mqtt_version(connection.mqtt_version);
+    if connection.mqtt_version < MQTT_VERSION_5 {
+        connection_options.clean_session(false);
+    } else {
+        // without an expiry interval, the broker discards the session when the connection is closed
+        let mut properties = Properties::new();
+        properties.push_int(
+            PropertyCode::SessionExpiryInterval,
+            i32::try_from(connection.session_expiry).unwrap_or(i32::MAX),
+        )?;
+        connection_options.clean_start(false);
+        connection_options.properties(properties);
+    }
+    connection_options.automatic_reconnect(Duration::from_secs(1), Duration::from_secs(5));
+    let options = &connection.options;
+    if let Some(keep_alive) = options.keep_alive {
+        connection_options.keep_alive_interval(keep_alive);
+    }
+    if let Some(ref username) = options.username {
+        connection_options.user_name(username);
+    }
+    if let Some(ref password) = options.password {
+        connection_options.password(password);
+    }
+    if let Some(ref tls) = options.tls {
+        let mut ssl_options = SslOptionsBuilder::new();
+        if let Some(ref ca_path) = tls.ca_path {
+            ssl_options.trust_store(ca_path)?;
+        }
+        if let Some(ref cert_path) = tls.cert_path {
+            ssl_options.key_store(cert_path)?;
+        }
+        if let Some(ref key_path) = tls.key_path {
+            ssl_options.private_key(key_path)?;
+        }
+        if let Some(ref key_password) = tls.key_password {
+            ssl_options.private_key_password(key_password);
+        }
+        connection_options.ssl_options(ssl_options.finalize());
+    }
+    if let Some(ref status) = options.status {
+        connection_options.will_message(Message::new_retained(
+            &status.topic,
+            status.offline.as_str(),
+            STATUS_QOS.into(),
+        ));
+        // runs after every reconnect to replace the last will, which the broker published meanwhile
+        let online =
+            Message::new_retained(&status.topic, status.online.as_str(), STATUS_QOS.into());
+        let id = id.clone();
+        connection.client.set_connected_callback(move |client| {
+            debug!("{} connected -> publish online status", id);
+            client.publish(online.clone());
+        });
+    }
 
-    let routing_args = CloudEventRoutingArgs {
-        delivery_guarantee: DeliveryGuarantee::BestEffort,
-    };
+    connection
+        .client
+        .connect(connection_options.finalize())
+        .await?;
 
-    connection.client.set_message_callback(message_handler(
-        id.clone(),
-        sender_to_kernel,
-        routing_args,
-    ));
+    let (processed_sender, processed_receiver) = channel();
+    let (message_sender, messages) = channel();
+    connection.processed_sender = Some(processed_sender);
+    {
+        let id = id.clone();
+        let subscriptions = connection.subscriptions.clone();
+        thread::spawn(move || {
+            message_worker(
+                id,
+                sender_to_kernel,
+                subscriptions,
+                messages,
+                processed_receiver,
+            )
+        });
+    }
+    connection
+        .client
+        .set_message_callback(message_handler(id.clone(), message_sender));
 
-    if let Some(ref subscribe_topic) = connection.subscribe_topic {
-        debug!("{} subscribes to {} with qos 0", id, subscribe_topic,);
+    if !connection.subscriptions.is_empty() {
+        let topics: Vec<&str> = connection
+            .subscriptions
+            .iter()
+            .map(|subscription| subscription.topic.as_str())
+            .collect();
+        let qos: Vec<i32> = connection
+            .subscriptions
+            .iter()
+            .map(|subscription| subscription.qos as i32)
+            .collect();
+        debug!("{} subscribes to {:?} with qos {:?}", id, topics, qos);
 
-        connection.client.subscribe(subscribe_topic, 0).await?;
+        connection.client.subscribe_many(&topics, &qos).await?;
     }
 
     Ok(connection)
@@ -134,32 +357,77 @@ async fn send_cloud_event(
     id: &InternalServerId,
     event: &OutgoingCloudEvent,
     connection: &MqttConnection,
-) -> Result<ProcessingResult> {
+) -> ProcessingResult {
     if let Some(ref send_topic) = connection.send_topic {
-        let serialized = serde_json::to_string(&event.cloud_event).unwrap();
-        debug!("{} message serialized", id);
+        let send_topic = match render_topic(send_topic, &event.cloud_event) {
+            Ok(send_topic) => send_topic,
+            Err(e) => {
+                error!("{} failed to render the topic {:?}", id, e);
+                return ProcessingResult::PermanentError;
+            }
+        };
         let send_qos = match event.args.delivery_guarantee {
             DeliveryGuarantee::BestEffort => 0,
             DeliveryGuarantee::AtLeastOnce => 1,
         };
-        let msg = Message::new(send_topic, serialized, send_qos);
+        let msg = match to_message(
+            &event.cloud_event,
+            &send_topic,
+            send_qos,
+            connection.content_mode,
+        ) {
+            Ok(msg) => msg,
+            Err(e) => {
+                error!("{} failed to serialize the event {:?}", id, e);
+                return ProcessingResult::PermanentError;
+            }
+        };
+        debug!("{} message serialized", id);
         debug!("start publishing on {}", send_topic);
 
         match connection.client.publish(msg).await {
-            Ok(_) => Ok(ProcessingResult::Successful),
+            Ok(_) => ProcessingResult::Successful,
             Err(e) => {
+                // e.g., the client is disconnected and reconnects automatically
                 error!("{} error while publishing {:?}", id, e);
-                Ok(ProcessingResult::PermanentError)
+                ProcessingResult::TransientError
             }
         }
     } else {
-        bail!(
-            "{} received event before the mqtt port was configured as output port -> message will be dropped",
+        error!(
+            "{} received event but the mqtt port is not configured as output port -> message will be dropped",
             id
-        )
+        );
+        ProcessingResult::PermanentError
+    }
+}
+
+/// Publishes the offline status, because the broker discards the last will on a graceful disconnect.
+fn publish_offline_status(id: &InternalServerId, connection: &MqttConnection) {
+    if let Some(ref status) = connection.options.status {
+        let offline =
+            Message::new_retained(&status.topic, status.offline.as_str(), STATUS_QOS.into());
+        if let Err(err) = block_on(connection.client.publish(offline)) {
+            warn!("{} failed to publish the offline status {:?}", id, err);
+        }
     }
 }
 
+fn send_processed_event(
+    id: &InternalServerId,
+    sender_to_kernel: &BoxedSender,
+    routing_id: CloudEventMessageRoutingId,
+    result: ProcessingResult,
+) {
+    sender_to_kernel.send(BrokerEvent::OutgoingCloudEventProcessed(
+        OutgoingCloudEventProcessed {
+            sender_id: id.clone(),
+            routing_id,
+            result,
+        },
+    ));
+}
+
 /// This is the main function to start the port.
 pub fn port_mqtt_start(id: InternalServerId, inbox: BoxedReceiver, sender_to_kernel: BoxedSender) {
     let mut connection: Option<MqttConnection> = None;
@@ -173,10 +441,13 @@ pub fn port_mqtt_start(id: InternalServerId, inbox: BoxedReceiver, sender_to_ker
             }
             BrokerEvent::ConfigUpdated(config, _) => {
                 info!("{} received ConfigUpdated", &id);
-                if let Some(ref connection) = connection {
+                if let Some(mut connection) = connection.take() {
+                    // stops the message worker, which waits for a processed event
+                    connection.processed_sender = None;
+                    publish_offline_status(&id, &connection);
                     match block_on(connection.client.disconnect(None)) {
                         Ok(_) => debug!("disconnected succesfully"),
-                        Err(err) => panic!("{} disconnects failed {:?}", id, err),
+                        Err(err) => warn!("{} disconnects failed {:?}", id, err),
                     }
                 }
 
@@ -188,28 +459,31 @@ pub fn port_mqtt_start(id: InternalServerId, inbox: BoxedReceiver, sender_to_ker
                     Ok(new_connection) => {
                         connection = Some(new_connection);
                     }
-                    Err(err) => panic!("{} connection setup failed {:?}", id, err),
+                    Err(err) => error!("{} connection setup failed {:?}", id, err),
                 }
             }
             BrokerEvent::OutgoingCloudEvent(event) => {
                 debug!("{} cloudevent received", &id);
-                if let Some(ref connection) = connection {
-                    match block_on(send_cloud_event(&id, &event, &connection)) {
-                        Ok(result) => {
-                            debug!("{} cloudevent sent -> {:?}", &id, &result);
-
-                            sender_to_kernel.send(BrokerEvent::OutgoingCloudEventProcessed(
-                                OutgoingCloudEventProcessed {
-                                    sender_id: id.clone(),
-                                    routing_id: event.routing_id,
-                                    result: result,
-                                },
-                            ));
+                let result = if let Some(ref connection) = connection {
+                    block_on(send_cloud_event(&id, &event, connection))
+                } else {
+                    error!("{} can not send message, no connection configured", id);
+                    ProcessingResult::TransientError
+                };
+                debug!("{} cloudevent sent -> {:?}", &id, &result);
+                send_processed_event(&id, &sender_to_kernel, event.routing_id, result);
+            }
+            BrokerEvent::IncomingCloudEventProcessed(routing_id, result) => {
+                match connection
+                    .as_ref()
+                    .and_then(|connection| connection.processed_sender.as_ref())
+                {
+                    Some(processed_sender) => {
+                        if processed_sender.send((routing_id, result)).is_err() {
+                            warn!("{} message callback is not waiting for a result", id);
                         }
-                        Err(err) => panic!("{} connection setup failed {:?}", id, err),
                     }
-                } else {
-                    panic!("{} can not send message, no connection configured", id)
+                    None => warn!("{} no active connection - can't forward the result", id),
                 }
             }
             broker_event => warn!("event {} not implemented", broker_event),
@@ -219,3 +493,100 @@ pub fn port_mqtt_start(id: InternalServerId, inbox: BoxedReceiver, sender_to_ker
 
 /// This is the pointer for the main function to start the port.
 pub static PORT_MQTT: InternalServerFnRefStatic = &(port_mqtt_start as InternalServerFn);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cerk_runtime_threading::channel::new_channel_with_size;
+    use cloudevents::{EventBuilder, EventBuilderV10};
+
+    fn incoming_event(id: &str) -> IncomingCloudEvent {
+        IncomingCloudEvent {
+            incoming_id: "mqtt".to_string(),
+            routing_id: id.to_string(),
+            cloud_event: EventBuilderV10::new()
+                .id(id)
+                .ty("test")
+                .source("http://example.com")
+                .build()
+                .unwrap(),
+            args: CloudEventRoutingArgs {
+                delivery_guarantee: DeliveryGuarantee::AtLeastOnce,
+            },
+        }
+    }
+
+    fn receive_routing_id(kernel: &BoxedReceiver) -> CloudEventMessageRoutingId {
+        match kernel.receive_timeout(Duration::from_secs(1)) {
+            Some(BrokerEvent::IncomingCloudEvent(event)) => event.routing_id,
+            _ => panic!("expected IncomingCloudEvent"),
+        }
+    }
+
+    #[test]
+    fn retry_transient_errors_until_processed() {
+        let (sender_to_kernel, kernel) = new_channel_with_size(10);
+        let (processed_sender, processed_receiver) = channel();
+        let worker = thread::spawn(move || {
+            send_and_await_processed(
+                &"mqtt".to_string(),
+                &sender_to_kernel,
+                incoming_event("1"),
+                &processed_receiver,
+                Duration::from_millis(1),
+            )
+        });
+
+        assert_eq!(receive_routing_id(&kernel), "1");
+        // a result of another event is ignored
+        processed_sender
+            .send(("0".to_string(), ProcessingResult::Successful))
+            .unwrap();
+        processed_sender
+            .send(("1".to_string(), ProcessingResult::TransientError))
+            .unwrap();
+        assert_eq!(receive_routing_id(&kernel), "1");
+        processed_sender
+            .send(("1".to_string(), ProcessingResult::Timeout))
+            .unwrap();
+        assert_eq!(receive_routing_id(&kernel), "1");
+        processed_sender
+            .send(("1".to_string(), ProcessingResult::Successful))
+            .unwrap();
+        assert_eq!(worker.join().unwrap(), Some(ProcessingResult::Successful));
+        assert!(kernel.receive_timeout(Duration::from_millis(10)).is_none());
+    }
+
+    #[test]
+    fn drop_permanently_failed_events() {
+        let (sender_to_kernel, kernel) = new_channel_with_size(10);
+        let (processed_sender, processed_receiver) = channel();
+        processed_sender
+            .send(("1".to_string(), ProcessingResult::PermanentError))
+            .unwrap();
+        let result = send_and_await_processed(
+            &"mqtt".to_string(),
+            &sender_to_kernel,
+            incoming_event("1"),
+            &processed_receiver,
+            Duration::from_millis(1),
+        );
+        assert_eq!(result, Some(ProcessingResult::PermanentError));
+        assert_eq!(receive_routing_id(&kernel), "1");
+    }
+
+    #[test]
+    fn stop_waiting_when_reconfigured() {
+        let (sender_to_kernel, _kernel) = new_channel_with_size(10);
+        let (processed_sender, processed_receiver) = channel();
+        drop(processed_sender);
+        let result = send_and_await_processed(
+            &"mqtt".to_string(),
+            &sender_to_kernel,
+            incoming_event("1"),
+            &processed_receiver,
+            Duration::from_millis(1),
+        );
+        assert_eq!(result, None);
+    }
+}
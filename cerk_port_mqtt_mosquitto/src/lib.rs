@@ -53,6 +53,8 @@ The value has to by of type `Config::String` and contain a host name with protoc
 
 E.g. `Config::String(String::from("tcp://mqtt-broker:1883"))`
 
+The protocol `ssl://` connects with TLS and uses the port 8883 by default, e.g., `Config::String(String::from("ssl://mqtt-broker"))`.
+
 ## Optional Fields
 
 The following configurations are optional.
@@ -61,17 +63,20 @@ The following configurations are optional.
 
 The value has to by of type `Config::String` and contain the MQTT topic name where the message will be sent to.
 
-E.g. `Config::String(String::from("inbox"))`
+The topic may contain `{attribute}` placeholders, which are replaced by the attributes or extensions of the event.
+Events without the attribute or with a `+` or `#` in it are rejected with a permanent error.
+
+E.g. `Config::String(String::from("devices/{subject}/events"))`
 
 ### subscribe_topic
 
-The value has to by of type `Config::String` and contain the MQTT topic which the router should subscribe to.
+The value has to by of type `Config::String` and contain the MQTT topic filter which the router should subscribe to.
 
 E.g. `Config::String(String::from("outbox"))`
 
 ### subscribe_qos
 
-The value has to by of type `Config::U8` and contain the [quality of service](http://docs.oasis-open.org/mqtt/mqtt/v3.1.1/os/mqtt-v3.1.1-os.html#_Toc398718099) for message delivery.
+The value has to by of type `Config::U8` and contain the [quality of service](http://docs.oasis-open.org/mqtt/mqtt/v3.1.1/os/mqtt-v3.1.1-os.html#_Toc398718099) of the `subscribe_topic`.
 
 Currently, the following values are supported:
 
@@ -80,6 +85,63 @@ Currently, the following values are supported:
 
 E.g. `Config::U8(1)`
 
+### subscriptions
+
+The value has to by of type `Config::Vec` and contain further subscriptions of type `Config::HashMap`:
+
+| Name                 | Type   | Default | Description                                                             |
+|----------------------|--------|---------|-------------------------------------------------------------------------|
+| `topic`              | String |         | the topic filter, wildcards are allowed                                 |
+| `qos`                | u8     | `0`     | the quality of service (0 or 1)                                         |
+| `delivery_guarantee` | u8     |         | `BestEffort` (`0`) or `AtLeastOnce` (`2`), the default depends on `qos` |
+
+Events received with QoS 1 are routed with the delivery guarantee "At Least Once", unless `delivery_guarantee` is set to `0`.
+
+### content_mode
+
+The value has to by of type `Config::String` and contain the content mode of the published messages, either `structured` (default) or `binary`.
+
+The binary content mode carries the attributes as MQTT 5 user properties.
+libmosquitto connects with MQTT 3.1.1, therefore, the port falls back to the structured content mode and all received messages have to be in the structured content mode.
+Use cerk_port_mqtt for the binary content mode.
+
+### keep_alive
+
+The value has to by of type `Config::U32` and contain the keep alive interval in seconds, the default is 5.
+
+### TLS
+
+The TLS options require an `ssl://` host and have to by of type `Config::String`:
+
+| Name               | Description                                                 |
+|--------------------|-------------------------------------------------------------|
+| `tls_ca_path`      | the PEM file with the CA certificates                       |
+| `tls_cert_path`    | the PEM file with the client certificate                    |
+| `tls_key_path`     | the PEM file with the private key of the client certificate |
+| `tls_key_password` | the password of the private key                             |
+
+libmosquitto does not fall back to the system certificates, therefore, `tls_ca_path` is required.
+The client certificate is optional, but `tls_cert_path` and `tls_key_path` have to be set together.
+
+### username and password
+
+The values have to by of type `Config::String` and contain the credentials of the client.
+A `password` requires a `username`.
+
+### status_topic, status_online and status_offline
+
+The values have to by of type `Config::String`.
+If `status_topic` is set, the port publishes the retained status `status_online` (default `online`) to it with QoS 1 after every connect,
+and registers `status_offline` (default `offline`) as last will, which the broker publishes if the connection is lost.
+The broker discards the last will on a graceful disconnect, therefore, the port publishes the offline status itself before it reconnects with an updated configuration.
+
+## Wildcards
+
+The levels of a received topic which are matched by the wildcards `+` and `#` are set as CloudEvent `subject`, unless the event already has one.
+E.g., a message on `devices/sensor-1/events` received by the subscription `devices/+/events` gets the subject `sensor-1`,
+which the `send_topic` `devices/{subject}/events` maps back to the topic.
+If several subscriptions match the topic, the first one is used.
+
 ## Configuration Examples
 
 ### Minimal Configuration to send events
@@ -150,6 +212,64 @@ let config = Config::HashMap(map);
 # }
 ```
 
+### Configuration for a TLS connection with status
+
+```
+use std::collections::HashMap;
+use cerk::kernel::Config;
+# use cerk_port_mqtt_mosquitto::check_configurations;
+# use anyhow::Result;
+
+# fn main() -> Result<()> {
+let map: HashMap<String, Config> = [
+    ("host".to_string(), Config::String("ssl://mqtt-broker:8883".to_string())),
+    ("send_topic".to_string(), Config::String("inbox".to_string())),
+    ("keep_alive".to_string(), Config::U32(30)),
+    ("tls_ca_path".to_string(), Config::String("/etc/cerk/ca.pem".to_string())),
+    ("tls_cert_path".to_string(), Config::String("/etc/cerk/gateway-1.pem".to_string())),
+    ("tls_key_path".to_string(), Config::String("/etc/cerk/gateway-1.key".to_string())),
+    ("status_topic".to_string(), Config::String("gateways/gateway-1/status".to_string())),
+]
+.iter()
+.cloned()
+.collect();
+
+let config = Config::HashMap(map);
+# check_configurations(config) // validate example config
+# }
+```
+
+### Full Configuration for sending and receiving events
+
+```
+use std::collections::HashMap;
+use cerk::kernel::Config;
+# use cerk_port_mqtt_mosquitto::check_configurations;
+# use anyhow::Result;
+
+# fn main() -> Result<()> {
+let subscription: HashMap<String, Config> = [
+    ("topic".to_string(), Config::String("devices/+/events".to_string())),
+    ("qos".to_string(), Config::U8(1)),
+]
+.iter()
+.cloned()
+.collect();
+
+let map: HashMap<String, Config> = [
+    ("host".to_string(), Config::String("tcp://mqtt-broker:1883".to_string())),
+    ("subscriptions".to_string(), Config::Vec(vec![Config::HashMap(subscription)])),
+    ("send_topic".to_string(), Config::String("routed/{subject}".to_string())),
+]
+.iter()
+.cloned()
+.collect();
+
+let config = Config::HashMap(map);
+# check_configurations(config) // validate example config
+# }
+```
+
 */
 
 #![deny(missing_docs)]
@@ -160,6 +280,7 @@ extern crate anyhow;
 #[macro_use]
 extern crate log;
 
+mod mosquitto_ffi;
 mod port_mqtt;
 
 pub use self::port_mqtt::{check_configurations, port_mqtt_mosquitto_start, PORT_MQTT_MOSQUITTO};
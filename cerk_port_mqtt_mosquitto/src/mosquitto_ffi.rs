@@ -0,0 +1,90 @@
+use anyhow::Result;
+use mosquitto_client_wrapper::sys;
+use mosquitto_client_wrapper::Mosquitto;
+use std::ffi::CString;
+use std::mem;
+use std::os::raw::{c_char, c_int};
+use std::ptr;
+
+/// Mirrors the private fields of `Mosquitto` in mosquitto-client-wrapper 0.3.1, which does not expose the libmosquitto handle.
+///
+/// The fields have the same types in the same order, so both structs get the same layout.
+/// The dependency is pinned to `=0.3.1`, because a new version could change the fields.
+struct RawMosquitto {
+    mosq: *mut sys::Mosq,
+    _owned: bool,
+}
+
+const _: () = assert!(mem::size_of::<RawMosquitto>() == mem::size_of::<Mosquitto>());
+
+fn raw_handle(client: &Mosquitto) -> *mut sys::Mosq {
+    // the copy has no `Drop`, so the handle is still only destroyed by the client
+    unsafe { mem::transmute_copy::<Mosquitto, RawMosquitto>(client).mosq }
+}
+
+fn check(function: &str, rc: c_int) -> Result<()> {
+    if rc != sys::MOSQ_ERR_SUCCESS {
+        bail!("{} failed: {}", function, sys::mosq_strerror(rc));
+    }
+    Ok(())
+}
+
+fn c_string(value: Option<&str>) -> Result<Option<CString>> {
+    Ok(value.map(CString::new).transpose()?)
+}
+
+fn c_ptr(value: &Option<CString>) -> *const c_char {
+    value.as_ref().map_or(ptr::null(), |v| v.as_ptr())
+}
+
+/// Sets the credentials of the connection with `mosquitto_username_pw_set`, a password requires a username.
+pub(crate) fn username_pw_set(
+    client: &Mosquitto,
+    username: Option<&str>,
+    password: Option<&str>,
+) -> Result<()> {
+    let username = c_string(username)?;
+    let password = c_string(password)?;
+    check("mosquitto_username_pw_set", unsafe {
+        sys::mosquitto_username_pw_set(raw_handle(client), c_ptr(&username), c_ptr(&password))
+    })
+}
+
+/// Enables TLS with `mosquitto_tls_set`, the client certificate and key are optional.
+///
+/// libmosquitto copies the paths, so they only have to live during the call.
+pub(crate) fn tls_set(
+    client: &Mosquitto,
+    ca_path: &str,
+    cert_path: Option<&str>,
+    key_path: Option<&str>,
+) -> Result<()> {
+    let ca_path = c_string(Some(ca_path))?;
+    let cert_path = c_string(cert_path)?;
+    let key_path = c_string(key_path)?;
+    check("mosquitto_tls_set", unsafe {
+        sys::mosquitto_tls_set(
+            raw_handle(client),
+            c_ptr(&ca_path),
+            ptr::null(),
+            c_ptr(&cert_path),
+            c_ptr(&key_path),
+            None,
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn call_libmosquitto_with_the_raw_handle() {
+        let client = Mosquitto::new("cerk-ffi-test").unwrap();
+        assert!(username_pw_set(&client, Some("cerk"), Some("secret")).is_ok());
+        assert!(username_pw_set(&client, Some("cerk"), None).is_ok());
+        // libmosquitto checks that the CA file could be read
+        assert!(tls_set(&client, "Cargo.toml", None, None).is_ok());
+        assert!(tls_set(&client, "nonexisting.pem", None, None).is_err());
+    }
+}
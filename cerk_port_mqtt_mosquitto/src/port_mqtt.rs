@@ -1,34 +1,44 @@
+use crate::mosquitto_ffi;
 use anyhow::{Context, Result};
 use cerk::kernel::{
     BrokerEvent, CloudEventMessageRoutingId, CloudEventRoutingArgs, Config, ConfigHelpers,
-    DeliveryGuarantee, IncomingCloudEvent, OutgoingCloudEvent, OutgoingCloudEventProcessed,
-    ProcessingResult,
+    IncomingCloudEvent, OutgoingCloudEvent, OutgoingCloudEventProcessed, ProcessingResult,
 };
 use cerk::runtime::channel::{BoxedReceiver, BoxedSender};
 use cerk::runtime::{InternalServerFn, InternalServerFnRefStatic, InternalServerId};
+use cerk_port_common::content_mode::ContentMode;
+use cerk_port_common::mqtt_connection_options::{
+    try_get_connection_options, ConnectionOptions, STATUS_QOS,
+};
+use cerk_port_common::mqtt_topics::{
+    find_subscription, render_topic, try_get_subscriptions, MqttSubscription,
+};
 use cloudevents::event::Event;
-use cloudevents::AttributesReader;
+use cloudevents::{AttributesReader, AttributesWriter};
 use mosquitto_client_wrapper::Mosquitto;
-use serde_json;
 use std::collections::HashMap;
 use std::sync::mpsc::{channel, Sender};
 use std::sync::{Arc, Mutex};
-use std::thread;
+use std::thread::{self, JoinHandle};
 use url::Url;
 
 const MOSQ_OPT_DELAYED_ACK: u32 = 14;
+const DEFAULT_KEEP_ALIVE_SECONDS: u32 = 5;
 
 struct Data {
     unacked: HashMap<i32, String>,
+    /// message id of the last published online status
+    status_message_id: Option<i32>,
 }
 
 #[derive(Clone)]
 struct Configurations {
+    /// template of the topic, see [`render_topic`]
     send_topic: Option<String>,
-    subscribe_topic: Option<String>,
-    subscribe_qos: u8,
+    subscriptions: Vec<MqttSubscription>,
     host_name: String,
     host_port: u16,
+    options: ConnectionOptions,
 }
 
 #[derive(Clone)]
@@ -40,13 +50,24 @@ struct Connection {
 type ArcData = Arc<Mutex<Data>>;
 
 fn build_configurations(config: Config) -> Result<Configurations> {
-    let host = config.get_op_val_string("host")?.unwrap();
+    let host = config
+        .get_op_val_string("host")?
+        .context("No host option")?;
     let send_topic = config.get_op_val_string("send_topic")?;
-    let subscribe_topic = config.get_op_val_string("subscribe_topic")?;
-    let subscribe_qos = config.get_op_val_u8("subscribe_qos")?.unwrap_or(0);
+    // QoS 2 is not supported by the delayed acknowledgment of libmosquitto
+    let subscriptions = try_get_subscriptions(&config, 1)?;
 
-    if send_topic.is_some() && subscribe_topic.is_some() {
-        bail!("received send_topic and subscribe_topic - only one is allowed!")
+    // libmosquitto connects with MQTT 3.1.1, which has no user properties to carry the attributes
+    if let Some(content_mode) = config.get_op_val_string("content_mode")? {
+        if ContentMode::parse(&content_mode)? == ContentMode::Binary {
+            warn!("the binary content mode requires MQTT 5 -> use the structured content mode");
+        }
+    }
+
+    let options = try_get_connection_options(&host, &config)?;
+    // libmosquitto does not fall back to the system certificates
+    if matches!(options.tls, Some(ref tls) if tls.ca_path.is_none()) {
+        bail!("TLS requires tls_ca_path");
     }
 
     let host = Url::parse(&host)?;
@@ -54,13 +75,15 @@ fn build_configurations(config: Config) -> Result<Configurations> {
         .host_str()
         .ok_or(anyhow!("no host was provided"))?
         .to_string();
-    let host_port = host.port().unwrap_or(1883);
+    let host_port = host
+        .port()
+        .unwrap_or(if options.tls.is_some() { 8883 } else { 1883 });
     Ok(Configurations {
         send_topic,
-        subscribe_topic,
-        subscribe_qos,
+        subscriptions,
         host_name,
         host_port,
+        options,
     })
 }
 
@@ -89,30 +112,93 @@ fn build_connection(id: &InternalServerId, config: Config) -> Result<Connection>
         RECONNECT_DELAY_MAX_SECONDS,
         true,
     )?;
-    client.connect(configs.host_name.as_str(), configs.host_port.into(), 5)?;
-
-    let connection = Connection { client, configs };
+    if configs.options.username.is_some() {
+        mosquitto_ffi::username_pw_set(
+            &client,
+            configs.options.username.as_deref(),
+            configs.options.password.as_deref(),
+        )?;
+    }
+    if let Some(ref tls) = configs.options.tls {
+        let ca_path = tls.ca_path.as_deref().unwrap_or_default();
+        match (&tls.cert_path, &tls.key_path, &tls.key_password) {
+            // only the wrapper passes a callback for the password of the key
+            (Some(cert_path), Some(key_path), Some(_)) => {
+                client.tls_set(ca_path, cert_path, key_path, tls.key_password.as_deref())?
+            }
+            _ => mosquitto_ffi::tls_set(
+                &client,
+                ca_path,
+                tls.cert_path.as_deref(),
+                tls.key_path.as_deref(),
+            )?,
+        }
+    }
+    if let Some(ref status) = configs.options.status {
+        client.will_set(
+            &status.topic,
+            status.offline.as_bytes(),
+            STATUS_QOS.into(),
+            true,
+        )?;
+    }
+    let keep_alive = configs
+        .options
+        .keep_alive
+        .map_or(DEFAULT_KEEP_ALIVE_SECONDS, |keep_alive| {
+            keep_alive.as_secs() as u32
+        });
+    client.connect(
+        configs.host_name.as_str(),
+        configs.host_port.into(),
+        keep_alive,
+    )?;
 
-    return Ok(connection);
+    Ok(Connection { client, configs })
 }
 
+/// The sender of the processing results to `on_message` and the thread of the network loop.
+type ConnectionThread = (
+    Sender<(CloudEventMessageRoutingId, ProcessingResult)>,
+    JoinHandle<()>,
+);
+
 fn connect(
     id: InternalServerId,
     connection: Connection,
     sender_to_kernel: BoxedSender,
     data: ArcData,
-) -> Result<Sender<(CloudEventMessageRoutingId, ProcessingResult)>> {
+) -> Result<ConnectionThread> {
     let (sender, receiver) = channel();
-    let sub_delivery_guarantee = match connection.configs.subscribe_qos {
-        1 => DeliveryGuarantee::AtLeastOnce,
-        _ => DeliveryGuarantee::BestEffort,
-    };
-    thread::spawn(move || {
+    let handle = thread::spawn(move || {
         let mut callbacks = connection.client.callbacks(Vec::<()>::new());
         callbacks.on_message(|_, msg| {
-            let text = msg.text();
-            debug!("received cloud event (on_message), text={}", text);
-            let cloudevent: Event = serde_json::from_str(text).with_context(|| format!("{} failed to deserialize cloudevent {}", id, text)).unwrap();
+            debug!("{} received message on {}", id, msg.topic());
+            let (subscription, subject) = match find_subscription(&connection.configs.subscriptions, msg.topic()) {
+                Some(found) => found,
+                None => {
+                    // e.g., a subscription of the persistent session before the config was updated
+                    warn!("{} received message on {} which matches no subscription -> message will be dropped", id, msg.topic());
+                    return;
+                }
+            };
+            let sub_delivery_guarantee = subscription.delivery_guarantee;
+            let mut cloudevent: Event = match serde_json::from_slice(msg.payload()) {
+                Ok(cloudevent) => cloudevent,
+                Err(e) => {
+                    // the message is acknowledged, because it would fail again
+                    error!(
+                        "{} failed to deserialize cloudevent {:?} -> message will be dropped: {:?}",
+                        id,
+                        String::from_utf8_lossy(msg.payload()),
+                        e
+                    );
+                    return;
+                }
+            };
+            if cloudevent.subject().is_none() {
+                cloudevent.set_subject(subject);
+            }
             let routing_id = cloudevent.id().to_string();
             sender_to_kernel.send(BrokerEvent::IncomingCloudEvent(IncomingCloudEvent {
                 incoming_id: id.clone(),
@@ -125,7 +211,14 @@ fn connect(
             if sub_delivery_guarantee.requires_acknowledgment() {
                 debug!("ack required - block on_message");
                 loop {
-                    let (received_routing_id, result) = receiver.recv().unwrap();
+                    let (received_routing_id, result) = match receiver.recv() {
+                        Ok(received) => received,
+                        Err(_) => {
+                            // the connection is already closed, the broker redelivers the message to the session
+                            debug!("{} disconnected before {} was processed", id, routing_id);
+                            return;
+                        }
+                    };
                     if received_routing_id == routing_id {
                         debug!("received result for incoming cloud event: {}", result);
                         match result {
@@ -155,20 +248,34 @@ fn connect(
                     routing_id,
                     ProcessingResult::Successful,
                 );
+            } else if data_lock.status_message_id == Some(message_id) {
+                debug!("{} published online status", id);
             } else {
                 warn!("on_publish {} was not expected", message_id)
             }
         });
         callbacks.on_connect(|_, connection_id| {
             debug!("{} connected: {}", id, connection_id);
-            if let Some(ref subscribe_topic) = connection.configs.subscribe_topic {
+            // the broker published the last will, if the connection was lost
+            if let Some(ref status) = connection.configs.options.status {
+                match connection.client.publish(
+                    &status.topic,
+                    status.online.as_bytes(),
+                    STATUS_QOS.into(),
+                    true,
+                ) {
+                    Ok(message_id) => data.lock().unwrap().status_message_id = Some(message_id),
+                    Err(e) => warn!("{} failed to publish the online status {:?}", id, e),
+                }
+            }
+            for subscription in connection.configs.subscriptions.iter() {
                 debug!(
                     "subscribe to: {} with qos {}",
-                    subscribe_topic, connection.configs.subscribe_qos
+                    subscription.topic, subscription.qos
                 );
                 connection
                     .client
-                    .subscribe(&subscribe_topic, connection.configs.subscribe_qos.into())
+                    .subscribe(&subscription.topic, subscription.qos.into())
                     .unwrap();
             }
         });
@@ -178,37 +285,87 @@ fn connect(
         connection.client.loop_until_disconnect(200).unwrap();
     });
 
-    return Ok(sender);
+    Ok((sender, handle))
 }
 
+/// Publishes the offline status and closes the connection, because the broker discards the last will on a graceful disconnect.
+///
+/// The events which were not acknowledged by the broker are reported as transient errors.
+fn disconnect(
+    id: &InternalServerId,
+    connection: &Connection,
+    sender_to_kernel: &BoxedSender,
+    data: &ArcData,
+) {
+    if let Some(ref status) = connection.configs.options.status {
+        if let Err(e) = connection.client.publish(
+            &status.topic,
+            status.offline.as_bytes(),
+            STATUS_QOS.into(),
+            true,
+        ) {
+            warn!("{} failed to publish the offline status {:?}", id, e);
+        }
+    }
+    if let Err(e) = connection.client.disconnect() {
+        warn!("{} failed to disconnect {:?}", id, e);
+    }
+    let mut data_lock = data.lock().unwrap();
+    data_lock.status_message_id = None;
+    // the message ids are reused by the next client
+    for (_, routing_id) in data_lock.unacked.drain() {
+        send_processed_event(
+            id.clone(),
+            sender_to_kernel,
+            routing_id,
+            ProcessingResult::TransientError,
+        );
+    }
+}
+
+/// Publishes the event, the result is sent to the kernel by `on_publish` or returned as error.
 fn send_cloud_event(
     id: &InternalServerId,
     event: &OutgoingCloudEvent,
     connection: &Connection,
     data: ArcData,
-) -> Result<()> {
-    let serialized = serde_json::to_string(&event.cloud_event)?;
+) -> Result<(), ProcessingResult> {
+    let serialized = serde_json::to_string(&event.cloud_event).map_err(|e| {
+        error!("{} failed to serialize the event {:?}", id, e);
+        ProcessingResult::PermanentError
+    })?;
 
     if let Some(ref send_topic) = connection.configs.send_topic {
+        let send_topic = render_topic(send_topic, &event.cloud_event).map_err(|e| {
+            error!("{} failed to render the topic {:?}", id, e);
+            ProcessingResult::PermanentError
+        })?;
         let mut data_lock = data.lock().unwrap();
-        let message_id = connection.client.publish(
-            send_topic,
-            serialized.as_bytes(),
-            if event.args.delivery_guarantee.requires_acknowledgment() {
-                1
-            } else {
-                0
-            },
-            false,
-        )?;
+        let message_id = connection
+            .client
+            .publish(
+                &send_topic,
+                serialized.as_bytes(),
+                if event.args.delivery_guarantee.requires_acknowledgment() {
+                    1
+                } else {
+                    0
+                },
+                false,
+            )
+            .map_err(|e| {
+                error!("{} failed to send event {:?}", id, e);
+                ProcessingResult::TransientError
+            })?;
         data_lock
             .unacked
             .insert(message_id, event.routing_id.clone());
         debug!("{} sent publish with id {}", id, message_id);
     } else {
         error!("{} not send_topic configured", id);
+        return Err(ProcessingResult::PermanentError);
     }
-    return Ok(());
+    Ok(())
 }
 
 fn send_processed_event(
@@ -234,9 +391,10 @@ pub fn port_mqtt_mosquitto_start(
 ) {
     info!("start mqtt port with id {}", id);
     let mut connection: Option<Connection> = None;
-    let mut sender: Option<Sender<(CloudEventMessageRoutingId, ProcessingResult)>> = None;
+    let mut connection_thread: Option<ConnectionThread> = None;
     let data: ArcData = Arc::new(Mutex::new(Data {
         unacked: HashMap::new(),
+        status_message_id: None,
     }));
 
     loop {
@@ -246,6 +404,17 @@ pub fn port_mqtt_mosquitto_start(
             }
             BrokerEvent::ConfigUpdated(config, _) => {
                 info!("{} received ConfigUpdated", &id);
+                if let Some(old_connection) = connection.take() {
+                    disconnect(&id, &old_connection, &sender_to_kernel, &data);
+                    // the network loop uses the client until it returns
+                    if let Some((sender, handle)) = connection_thread.take() {
+                        // unblocks an on_message waiting for its result
+                        drop(sender);
+                        if handle.join().is_err() {
+                            warn!("{} the network loop of the old connection failed", id);
+                        }
+                    }
+                }
                 connection = match build_connection(&id, config) {
                     Ok(new_connection) => Some(new_connection),
                     Err(e) => {
@@ -254,13 +423,13 @@ pub fn port_mqtt_mosquitto_start(
                     }
                 };
                 if let Some(ref connection) = connection {
-                    sender = match connect(
+                    connection_thread = match connect(
                         id.clone(),
                         connection.clone(),
                         sender_to_kernel.clone_boxed(),
                         data.clone(),
                     ) {
-                        Ok(connection) => Some(connection),
+                        Ok(connection_thread) => Some(connection_thread),
                         Err(e) => {
                             error!("failed to connect {:?}", e);
                             None
@@ -272,13 +441,12 @@ pub fn port_mqtt_mosquitto_start(
                 debug!("{} cloudevent received", &id);
                 if let Some(ref connection) = connection {
                     debug!("{} will send event out", &id);
-                    if let Err(e) = send_cloud_event(&id, &event, &connection, data.clone()) {
-                        error!("failed to send event {:?}", e);
+                    if let Err(result) = send_cloud_event(&id, &event, connection, data.clone()) {
                         send_processed_event(
                             id.clone(),
                             &sender_to_kernel,
                             event.routing_id.clone(),
-                            ProcessingResult::TransientError,
+                            result,
                         );
                     }
                 } else {
@@ -292,7 +460,7 @@ pub fn port_mqtt_mosquitto_start(
                 }
             }
             BrokerEvent::IncomingCloudEventProcessed(routing_id, result) => {
-                if let Some(ref sender) = sender {
+                if let Some((ref sender, _)) = connection_thread {
                     debug!(
                         "received IncomingCloudEventProcessed -> send result to on_message handler"
                     );
@@ -315,7 +483,7 @@ mod tests {
     use super::*;
 
     #[test]
-    fn build_subscribe_config() {
+    fn build_send_and_subscribe_config() {
         let map: HashMap<String, Config> = [
             (
                 "host".to_string(),
@@ -334,6 +502,85 @@ mod tests {
         .iter()
         .cloned()
         .collect();
-        assert!(check_configurations(Config::HashMap(map)).is_err());
+        assert!(check_configurations(Config::HashMap(map)).is_ok());
+    }
+
+    #[test]
+    fn build_content_mode_config() {
+        let config = |content_mode: &str| {
+            let map: HashMap<String, Config> = [
+                (
+                    "host".to_string(),
+                    Config::String("tcp://mqtt-broker:1883".to_string()),
+                ),
+                (
+                    "content_mode".to_string(),
+                    Config::String(content_mode.to_string()),
+                ),
+            ]
+            .iter()
+            .cloned()
+            .collect();
+            Config::HashMap(map)
+        };
+        assert!(check_configurations(config("structured")).is_ok());
+        assert!(check_configurations(config("binary")).is_ok());
+        assert!(check_configurations(config("batched")).is_err());
+    }
+
+    #[test]
+    fn build_connection_options_config() {
+        let config = |options: &[(&str, &str)]| {
+            let mut map: HashMap<String, Config> = options
+                .iter()
+                .map(|(key, value)| (key.to_string(), Config::String(value.to_string())))
+                .collect();
+            map.insert("keep_alive".to_string(), Config::U32(30));
+            Config::HashMap(map)
+        };
+        assert!(check_configurations(config(&[
+            ("host", "ssl://mqtt-broker"),
+            ("tls_ca_path", "ca.pem"),
+            ("tls_cert_path", "client.pem"),
+            ("tls_key_path", "client.key"),
+            ("status_topic", "gateways/cerk/status"),
+        ]))
+        .is_ok());
+        assert_eq!(
+            build_configurations(config(&[
+                ("host", "ssl://mqtt-broker"),
+                ("tls_ca_path", "ca.pem"),
+                ("tls_cert_path", "client.pem"),
+                ("tls_key_path", "client.key"),
+            ]))
+            .unwrap()
+            .host_port,
+            8883
+        );
+        assert!(check_configurations(config(&[
+            ("host", "ssl://mqtt-broker"),
+            ("tls_ca_path", "ca.pem"),
+            ("username", "cerk"),
+            ("password", "secret"),
+        ]))
+        .is_ok());
+        // libmosquitto requires a CA file
+        assert!(check_configurations(config(&[
+            ("host", "ssl://mqtt-broker"),
+            ("tls_cert_path", "client.pem"),
+            ("tls_key_path", "client.key"),
+        ]))
+        .is_err());
+        assert!(check_configurations(config(&[
+            ("host", "ssl://mqtt-broker"),
+            ("tls_ca_path", "ca.pem"),
+            ("tls_cert_path", "client.pem"),
+        ]))
+        .is_err());
+        assert!(check_configurations(config(&[
+            ("host", "tcp://mqtt-broker"),
+            ("password", "secret"),
+        ]))
+        .is_err());
     }
 }
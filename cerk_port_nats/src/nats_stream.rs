@@ -0,0 +1,107 @@
+use anyhow::Result;
+use native_tls::{TlsConnector, TlsStream};
+use std::io::{ErrorKind, Read, Write};
+use std::net::{Shutdown, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// The reader of a TLS connection holds the stream at most this long, before the writer gets a chance to send.
+const TLS_POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// A plain or TLS encrypted connection, which can be cloned to read and write from different threads.
+///
+/// A TLS stream can not be split, so the clones share it and the reader releases it between short reads.
+pub struct NatsStream {
+    socket: TcpStream,
+    tls: Option<Arc<Mutex<TlsStream<TcpStream>>>>,
+    /// the timeout of a read, a TLS read is retried until it expires
+    read_timeout: Arc<Mutex<Option<Duration>>>,
+}
+
+impl NatsStream {
+    pub fn plain(socket: TcpStream) -> Self {
+        NatsStream {
+            socket,
+            tls: None,
+            read_timeout: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Establishes the TLS session, this blocks until the handshake is done.
+    pub fn tls(socket: TcpStream, connector: &TlsConnector, domain: &str) -> Result<Self> {
+        let stream = connector
+            .connect(domain, socket.try_clone()?)
+            .map_err(|e| anyhow!("TLS handshake with {} failed: {}", domain, e))?;
+        socket.set_read_timeout(Some(TLS_POLL_INTERVAL))?;
+        Ok(NatsStream {
+            socket,
+            tls: Some(Arc::new(Mutex::new(stream))),
+            read_timeout: Arc::new(Mutex::new(None)),
+        })
+    }
+
+    pub fn try_clone(&self) -> Result<Self> {
+        Ok(NatsStream {
+            socket: self.socket.try_clone()?,
+            tls: self.tls.clone(),
+            read_timeout: self.read_timeout.clone(),
+        })
+    }
+
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> Result<()> {
+        match self.tls {
+            Some(_) => *self.read_timeout.lock().unwrap() = timeout,
+            None => self.socket.set_read_timeout(timeout)?,
+        }
+        Ok(())
+    }
+
+    /// Closes the connection of all clones, a blocked read returns afterwards.
+    pub fn shutdown(&self) -> Result<()> {
+        Ok(self.socket.shutdown(Shutdown::Both)?)
+    }
+}
+
+impl Read for NatsStream {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let tls = match self.tls {
+            Some(ref tls) => tls,
+            None => return self.socket.read(buf),
+        };
+        let deadline = self
+            .read_timeout
+            .lock()
+            .unwrap()
+            .map(|timeout| Instant::now() + timeout);
+        loop {
+            let result = tls.lock().unwrap().read(buf);
+            match result {
+                Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                    if matches!(deadline, Some(deadline) if Instant::now() >= deadline) {
+                        return Err(e);
+                    }
+                    // the mutex is not fair, the waiting writer has to get the stream before the next read
+                    thread::sleep(Duration::from_millis(1));
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+impl Write for NatsStream {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self.tls {
+            Some(ref tls) => tls.lock().unwrap().write(buf),
+            None => self.socket.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self.tls {
+            Some(ref tls) => tls.lock().unwrap().flush(),
+            None => self.socket.flush(),
+        }
+    }
+}
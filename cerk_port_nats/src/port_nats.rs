@@ -0,0 +1,1231 @@
+use crate::nats_client::{
+    connect, request, NatsMessage, NatsReader, NatsServer, NatsWriter, ServerOp,
+};
+use crate::nats_stream::NatsStream;
+use crate::subject_mapping::{render_subject, wildcard_tokens};
+use anyhow::{Context, Result};
+use cerk::kernel::{
+    BrokerEvent, CloudEventMessageRoutingId, CloudEventRoutingArgs, Config, ConfigHelpers,
+    DeliveryGuarantee, HealthCheckRequest, HealthCheckResponse, HealthCheckStatus,
+    IncomingCloudEvent, OutgoingCloudEvent, OutgoingCloudEventProcessed, ProcessingResult,
+};
+use cerk::runtime::channel::{BoxedReceiver, BoxedSender};
+use cerk::runtime::{InternalServerFn, InternalServerFnRefStatic, InternalServerId};
+use cloudevents::{AttributesReader, AttributesWriter, Event};
+use native_tls::{Certificate, TlsConnector};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::io::BufRead;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const RECONNECT_MIN_BACKOFF: Duration = Duration::from_millis(500);
+const RECONNECT_MAX_BACKOFF: Duration = Duration::from_secs(30);
+/// The status of a reply if no stream is bound to the subject.
+const NO_RESPONDERS: u16 = 503;
+const PUBLISH_ACK_SID: u64 = 0;
+const ACK: &[u8] = b"+ACK";
+const NAK: &[u8] = b"-NAK";
+const TERM: &[u8] = b"+TERM";
+
+#[derive(Clone)]
+struct JetStreamConsumer {
+    stream: String,
+    durable: String,
+}
+
+#[derive(Clone)]
+struct NatsSubscription {
+    subject: String,
+    queue_group: Option<String>,
+    delivery_guarantee: DeliveryGuarantee,
+    jetstream: Option<JetStreamConsumer>,
+}
+
+#[derive(Clone)]
+struct NatsPublication {
+    subject: String,
+    jetstream: bool,
+}
+
+struct NatsOptions {
+    server: NatsServer,
+    tls: TlsConnector,
+    subscriptions: Vec<NatsSubscription>,
+    publications: Vec<NatsPublication>,
+}
+
+/// The reply subjects of the JetStream messages, which are acknowledged after the routing.
+///
+/// They are kept over reconnects, because a message can be acknowledged on any connection.
+type PendingDeliveries = Arc<Mutex<HashMap<CloudEventMessageRoutingId, String>>>;
+
+struct PendingPublish {
+    remaining: usize,
+    result: ProcessingResult,
+}
+
+struct ConnectionState {
+    id: InternalServerId,
+    sender_to_kernel: Mutex<BoxedSender>,
+    writer: NatsWriter<NatsStream>,
+    connected: AtomicBool,
+    max_payload: usize,
+    subscriptions: HashMap<u64, NatsSubscription>,
+    inbox_prefix: String,
+    next_inbox: AtomicU64,
+    pending_deliveries: PendingDeliveries,
+    publish_acks: Mutex<HashMap<String, CloudEventMessageRoutingId>>,
+    pending_publishes: Mutex<HashMap<CloudEventMessageRoutingId, PendingPublish>>,
+}
+
+struct NatsConnection {
+    state: Arc<ConnectionState>,
+    publications: Vec<NatsPublication>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Drop for NatsConnection {
+    fn drop(&mut self) {
+        self.state.connected.store(false, Ordering::SeqCst);
+        self.state.writer.close();
+        if let Some(thread) = self.thread.take() {
+            if thread.join().is_err() {
+                error!("reader thread panicked");
+            }
+        }
+        // the acknowledgments of JetStream are not received anymore
+        self.state.publish_acks.lock().unwrap().clear();
+        let pending_publishes: Vec<_> = self
+            .state
+            .pending_publishes
+            .lock()
+            .unwrap()
+            .drain()
+            .map(|(routing_id, _)| routing_id)
+            .collect();
+        for routing_id in pending_publishes {
+            self.state
+                .send_to_kernel(BrokerEvent::OutgoingCloudEventProcessed(
+                    OutgoingCloudEventProcessed {
+                        sender_id: self.state.id.clone(),
+                        routing_id,
+                        result: ProcessingResult::TransientError,
+                    },
+                ));
+        }
+    }
+}
+
+/// The result which requires a retry wins, because the event is published to the other subjects again anyway.
+fn worse_result(a: ProcessingResult, b: ProcessingResult) -> ProcessingResult {
+    let priority = |r: &ProcessingResult| match r {
+        ProcessingResult::Successful => 0,
+        ProcessingResult::PermanentError => 1,
+        ProcessingResult::Timeout => 2,
+        ProcessingResult::TransientError => 3,
+    };
+    if priority(&b) > priority(&a) {
+        b
+    } else {
+        a
+    }
+}
+
+/// Evaluates the response of JetStream to a published message, an empty response means that no stream is bound to the subject.
+fn result_from_publish_ack(payload: &[u8]) -> ProcessingResult {
+    match serde_json::from_slice::<Value>(payload) {
+        Ok(response) => match response.get("error") {
+            None => ProcessingResult::Successful,
+            Some(error) => match error.get("code").and_then(|code| code.as_u64()) {
+                Some(code) if code < 500 => ProcessingResult::PermanentError,
+                _ => ProcessingResult::TransientError,
+            },
+        },
+        Err(_) => ProcessingResult::TransientError,
+    }
+}
+
+impl ConnectionState {
+    fn new_inbox(&self) -> String {
+        format!(
+            "{}.{}",
+            self.inbox_prefix,
+            self.next_inbox.fetch_add(1, Ordering::SeqCst)
+        )
+    }
+
+    fn send_to_kernel(&self, event: BrokerEvent) {
+        self.sender_to_kernel.lock().unwrap().send(event);
+    }
+
+    /// Completes one publish attempt of the event, the kernel is notified after all subjects are completed.
+    fn complete(&self, routing_id: &str, result: ProcessingResult) {
+        let mut pending_publishes = self.pending_publishes.lock().unwrap();
+        let done = match pending_publishes.get_mut(routing_id) {
+            Some(pending) => {
+                pending.remaining -= 1;
+                pending.result = worse_result(pending.result.clone(), result);
+                pending.remaining == 0
+            }
+            None => false,
+        };
+        if done {
+            let pending = pending_publishes.remove(routing_id).unwrap();
+            drop(pending_publishes);
+            self.send_to_kernel(BrokerEvent::OutgoingCloudEventProcessed(
+                OutgoingCloudEventProcessed {
+                    sender_id: self.id.clone(),
+                    routing_id: routing_id.to_string(),
+                    result: pending.result,
+                },
+            ));
+        }
+    }
+
+    fn acknowledge(&self, reply_to: &str, ack: &[u8]) {
+        if let Err(e) = self.writer.publish(reply_to, None, ack) {
+            error!("{} failed to acknowledge {}: {:?}", self.id, reply_to, e);
+        }
+    }
+
+    fn receive_publish_ack(&self, message: NatsMessage) {
+        let routing_id = self.publish_acks.lock().unwrap().remove(&message.subject);
+        match routing_id {
+            Some(routing_id) => {
+                let result = match message.headers.as_ref().and_then(|h| h.status) {
+                    Some(NO_RESPONDERS) => ProcessingResult::TransientError,
+                    _ => result_from_publish_ack(&message.payload),
+                };
+                if result != ProcessingResult::Successful {
+                    warn!(
+                        "{} failed to publish {}: {}",
+                        self.id,
+                        routing_id,
+                        String::from_utf8_lossy(&message.payload)
+                    );
+                }
+                self.complete(&routing_id, result);
+            }
+            None => warn!("{} received unknown ack {}", self.id, message.subject),
+        }
+    }
+
+    fn receive_message(&self, message: NatsMessage) {
+        let subscription = match self.subscriptions.get(&message.sid) {
+            Some(subscription) => subscription,
+            None => {
+                warn!(
+                    "{} received message for unknown sid {}",
+                    self.id, message.sid
+                );
+                return;
+            }
+        };
+        if let Some(status) = message.headers.as_ref().and_then(|h| h.status) {
+            debug!(
+                "{} skips status message {} on {}",
+                self.id, status, message.subject
+            );
+            return;
+        }
+        debug!("{} received message on {}", self.id, message.subject);
+        // only messages of JetStream consumers have to be acknowledged
+        let reply_to = match subscription.jetstream {
+            Some(_) => message.reply_to.as_deref(),
+            None => None,
+        };
+        match serde_json::from_slice::<Event>(&message.payload) {
+            Ok(mut cloud_event) => {
+                if cloud_event.subject().is_none() {
+                    if let Some(tokens) = wildcard_tokens(&subscription.subject, &message.subject) {
+                        cloud_event.set_subject(Some(tokens));
+                    }
+                }
+                let routing_id = format!("{}--{}", cloud_event.id(), Uuid::new_v4());
+                let delivery_guarantee = subscription.delivery_guarantee;
+                if let Some(reply_to) = reply_to {
+                    if delivery_guarantee.requires_acknowledgment() {
+                        self.pending_deliveries
+                            .lock()
+                            .unwrap()
+                            .insert(routing_id.clone(), reply_to.to_string());
+                    } else {
+                        self.acknowledge(reply_to, ACK);
+                    }
+                }
+                self.send_to_kernel(BrokerEvent::IncomingCloudEvent(IncomingCloudEvent {
+                    incoming_id: self.id.clone(),
+                    routing_id,
+                    cloud_event,
+                    args: CloudEventRoutingArgs { delivery_guarantee },
+                }));
+            }
+            Err(e) => {
+                error!("{} skips message on {}: {:?}", self.id, message.subject, e);
+                if let Some(reply_to) = reply_to {
+                    self.acknowledge(reply_to, TERM);
+                }
+            }
+        }
+    }
+}
+
+fn try_get_delivery_option(config: &Config) -> Result<DeliveryGuarantee> {
+    Ok(match config.get_op_val_config("delivery_guarantee")? {
+        Some(config) => DeliveryGuarantee::try_from(config)?,
+        _ => DeliveryGuarantee::BestEffort,
+    })
+}
+
+fn build_subscription(config: &Config) -> Result<NatsSubscription> {
+    let subject = config
+        .get_op_val_string("name")?
+        .context("subscribe_subjects name is not set")?;
+    let delivery_guarantee = try_get_delivery_option(config)?;
+    let jetstream = match config.get_op_val_string("stream")? {
+        Some(stream) => Some(JetStreamConsumer {
+            stream,
+            durable: config
+                .get_op_val_string("durable")?
+                .context("subscribe_subjects with a stream require a durable name")?,
+        }),
+        None => None,
+    };
+    if jetstream.is_none() && delivery_guarantee.requires_acknowledgment() {
+        bail!(
+            "subscription {} requires a stream for the delivery guarantee {:?}",
+            subject,
+            delivery_guarantee
+        );
+    }
+    Ok(NatsSubscription {
+        subject,
+        queue_group: config.get_op_val_string("queue_group")?,
+        delivery_guarantee,
+        jetstream,
+    })
+}
+
+fn build_publication(config: &Config) -> Result<NatsPublication> {
+    Ok(NatsPublication {
+        subject: config
+            .get_op_val_string("name")?
+            .context("publish_subjects name is not set")?,
+        jetstream: match config.get_op_val_config("jetstream")? {
+            Some(Config::Bool(jetstream)) => *jetstream,
+            None => false,
+            Some(value) => bail!("jetstream {:?} has to be of type Bool", value),
+        },
+    })
+}
+
+fn build_config(id: &InternalServerId, config: &Config) -> Result<NatsOptions> {
+    if !matches!(config, Config::HashMap(_)) {
+        bail!("{} config has to be of type HashMap", id);
+    }
+    let mut server =
+        NatsServer::parse(&config.get_op_val_string("url")?.context("No url option")?)?;
+    server.token = config.get_op_val_string("token")?;
+    let mut tls = TlsConnector::builder();
+    if let Some(path) = config.get_op_val_string("tls_ca_path")? {
+        let pem = std::fs::read(&path).with_context(|| format!("failed to read {}", path))?;
+        tls.add_root_certificate(Certificate::from_pem(&pem)?);
+    }
+    let subscriptions = config
+        .get_op_val_vec("subscribe_subjects")?
+        .unwrap_or_default()
+        .iter()
+        .map(build_subscription)
+        .collect::<Result<Vec<_>>>()?;
+    let publications = config
+        .get_op_val_vec("publish_subjects")?
+        .unwrap_or_default()
+        .iter()
+        .map(build_publication)
+        .collect::<Result<Vec<_>>>()?;
+    Ok(NatsOptions {
+        server,
+        tls: tls.build()?,
+        subscriptions,
+        publications,
+    })
+}
+
+/// Creates or updates a durable push consumer, which delivers the messages of the subscription to the deliver subject.
+fn create_jetstream_consumer<R: BufRead>(
+    reader: &mut NatsReader<R>,
+    writer: &NatsWriter<NatsStream>,
+    subscription: &NatsSubscription,
+    jetstream: &JetStreamConsumer,
+    deliver_subject: &str,
+    inbox: &str,
+    sid: u64,
+) -> Result<()> {
+    let consumer = json!({
+        "stream_name": jetstream.stream,
+        "config": {
+            "durable_name": jetstream.durable,
+            "deliver_subject": deliver_subject,
+            "deliver_group": subscription.queue_group,
+            "filter_subject": subscription.subject,
+            "deliver_policy": "all",
+            "ack_policy": "explicit",
+        },
+    });
+    let subject = format!(
+        "$JS.API.CONSUMER.DURABLE.CREATE.{}.{}",
+        jetstream.stream, jetstream.durable
+    );
+    let response = request(
+        reader,
+        writer,
+        &subject,
+        consumer.to_string().as_bytes(),
+        inbox,
+        sid,
+    )?;
+    let response: Value =
+        serde_json::from_slice(&response).context("invalid JetStream API response")?;
+    if let Some(error) = response.get("error") {
+        bail!(
+            "failed to create consumer {} on stream {}: {}",
+            jetstream.durable,
+            jetstream.stream,
+            error
+        );
+    }
+    Ok(())
+}
+
+fn setup_connection(
+    id: &InternalServerId,
+    options: &NatsOptions,
+    pending_deliveries: PendingDeliveries,
+    sender_to_kernel: BoxedSender,
+) -> Result<NatsConnection> {
+    let (writer, mut reader) = connect(&options.server, &options.tls, id, CONNECT_TIMEOUT)?;
+    let inbox_prefix = format!("_INBOX.{}", Uuid::new_v4().to_simple());
+    let mut subscriptions = HashMap::new();
+    for (index, subscription) in options.subscriptions.iter().enumerate() {
+        let sid = index as u64 + 1;
+        let subject = match subscription.jetstream {
+            Some(ref jetstream) => {
+                let deliver_subject =
+                    format!("cerk.deliver.{}.{}", jetstream.stream, jetstream.durable);
+                create_jetstream_consumer(
+                    &mut reader,
+                    &writer,
+                    subscription,
+                    jetstream,
+                    &deliver_subject,
+                    &format!("{}.setup", inbox_prefix),
+                    // the sid is unsubscribed after the request and can be used for the subscription
+                    sid,
+                )?;
+                deliver_subject
+            }
+            None => subscription.subject.clone(),
+        };
+        writer.subscribe(&subject, subscription.queue_group.as_deref(), sid)?;
+        subscriptions.insert(sid, subscription.clone());
+    }
+    if options.publications.iter().any(|p| p.jetstream) {
+        writer.subscribe(&format!("{}.*", inbox_prefix), None, PUBLISH_ACK_SID)?;
+    }
+    writer.set_read_timeout(None)?;
+
+    let state = Arc::new(ConnectionState {
+        id: id.clone(),
+        sender_to_kernel: Mutex::new(sender_to_kernel),
+        writer,
+        connected: AtomicBool::new(true),
+        max_payload: reader.max_payload(),
+        subscriptions,
+        inbox_prefix,
+        next_inbox: AtomicU64::new(0),
+        pending_deliveries,
+        publish_acks: Mutex::new(HashMap::new()),
+        pending_publishes: Mutex::new(HashMap::new()),
+    });
+    let thread = {
+        let state = state.clone();
+        thread::spawn(move || loop {
+            match reader.next_op() {
+                Ok(ServerOp::Msg(message)) if message.sid == PUBLISH_ACK_SID => {
+                    state.receive_publish_ack(message)
+                }
+                Ok(ServerOp::Msg(message)) => state.receive_message(message),
+                Ok(ServerOp::Ping) => {
+                    if let Err(e) = state.writer.pong() {
+                        warn!("{} failed to send PONG: {:?}", state.id, e)
+                    }
+                }
+                Ok(ServerOp::Err(e)) => error!("{} received error: {}", state.id, e),
+                Ok(_) => {}
+                Err(e) => {
+                    if state.connected.swap(false, Ordering::SeqCst) {
+                        error!("{} lost the connection: {:?}", state.id, e);
+                    }
+                    break;
+                }
+            }
+        })
+    };
+    Ok(NatsConnection {
+        state,
+        publications: options.publications.clone(),
+        thread: Some(thread),
+    })
+}
+
+/// Publishes the event to all publish subjects, it failed transiently while the port reconnects.
+fn send_cloud_event(
+    id: &InternalServerId,
+    event: OutgoingCloudEvent,
+    connection: &Option<NatsConnection>,
+    recovery: &Option<Recovery>,
+    sender_to_kernel: &BoxedSender,
+) {
+    let requires_acknowledgment = event.args.delivery_guarantee.requires_acknowledgment();
+    let connection = match connection {
+        Some(connection) if !connection.publications.is_empty() => connection,
+        _ => {
+            let result = if recovery.is_some() {
+                warn!("{} received an event while reconnecting", id);
+                ProcessingResult::TransientError
+            } else {
+                error!(
+                    "{} received an event, but no publish subject is configured",
+                    id
+                );
+                ProcessingResult::PermanentError
+            };
+            if requires_acknowledgment {
+                sender_to_kernel.send(BrokerEvent::OutgoingCloudEventProcessed(
+                    OutgoingCloudEventProcessed {
+                        sender_id: id.clone(),
+                        routing_id: event.routing_id,
+                        result,
+                    },
+                ));
+            }
+            return;
+        }
+    };
+
+    let state = &connection.state;
+    if requires_acknowledgment {
+        state.pending_publishes.lock().unwrap().insert(
+            event.routing_id.clone(),
+            PendingPublish {
+                remaining: connection.publications.len(),
+                result: ProcessingResult::Successful,
+            },
+        );
+    }
+    let payload = match serde_json::to_vec(&event.cloud_event) {
+        // the server closes the connection if a message exceeds its max payload
+        Ok(payload) if payload.len() > state.max_payload => {
+            error!(
+                "{} failed to publish {}, the size {} exceeds the max payload {}",
+                id,
+                event.routing_id,
+                payload.len(),
+                state.max_payload
+            );
+            for _ in connection.publications.iter() {
+                state.complete(&event.routing_id, ProcessingResult::PermanentError);
+            }
+            return;
+        }
+        Ok(payload) => payload,
+        Err(e) => {
+            error!("{} failed to serialize {}: {:?}", id, event.routing_id, e);
+            for _ in connection.publications.iter() {
+                state.complete(&event.routing_id, ProcessingResult::PermanentError);
+            }
+            return;
+        }
+    };
+    for publication in connection.publications.iter() {
+        let subject = match render_subject(&publication.subject, event.cloud_event.subject()) {
+            Ok(subject) => subject,
+            Err(e) => {
+                error!("{} failed to publish {}: {:?}", id, event.routing_id, e);
+                state.complete(&event.routing_id, ProcessingResult::PermanentError);
+                continue;
+            }
+        };
+        let reply_to = if publication.jetstream && requires_acknowledgment {
+            let inbox = state.new_inbox();
+            state
+                .publish_acks
+                .lock()
+                .unwrap()
+                .insert(inbox.clone(), event.routing_id.clone());
+            Some(inbox)
+        } else {
+            None
+        };
+        match state
+            .writer
+            .publish(&subject, reply_to.as_deref(), payload.as_slice())
+        {
+            // the acknowledgment of JetStream completes the publish attempt
+            Ok(()) if reply_to.is_some() => {}
+            Ok(()) => state.complete(&event.routing_id, ProcessingResult::Successful),
+            Err(e) => {
+                error!("{} failed to publish to {}: {:?}", id, subject, e);
+                if let Some(inbox) = reply_to {
+                    state.publish_acks.lock().unwrap().remove(&inbox);
+                }
+                state.complete(&event.routing_id, ProcessingResult::TransientError);
+            }
+        }
+    }
+}
+
+/// Acknowledges the message after a successful processing, a transient failed message is redelivered by JetStream.
+fn resolve_pending_delivery(
+    connection: &Option<NatsConnection>,
+    routing_id: &CloudEventMessageRoutingId,
+    result: ProcessingResult,
+) -> Result<()> {
+    let state = &connection
+        .as_ref()
+        .context("no connection is established")?
+        .state;
+    let reply_to = state
+        .pending_deliveries
+        .lock()
+        .unwrap()
+        .remove(routing_id)
+        .with_context(|| format!("pending delivery with id={} not found", routing_id))?;
+    let ack = match result {
+        ProcessingResult::Successful => ACK,
+        ProcessingResult::PermanentError => {
+            error!(
+                "{} message {} failed permanently and is terminated",
+                state.id, routing_id
+            );
+            TERM
+        }
+        ProcessingResult::TransientError | ProcessingResult::Timeout => {
+            warn!(
+                "{} message {} failed and is redelivered",
+                state.id, routing_id
+            );
+            NAK
+        }
+    };
+    state.writer.publish(&reply_to, None, ack)
+}
+
+fn check_health(
+    event: HealthCheckRequest,
+    connection: &Option<NatsConnection>,
+    recovery: &Option<Recovery>,
+    sender_to_kernel: &BoxedSender,
+) {
+    let status = match connection {
+        _ if recovery.is_some() => HealthCheckStatus::Unhealthy(format!(
+            "connection lost, {} reconnect attempts failed",
+            recovery.as_ref().unwrap().attempt
+        )),
+        Some(connection) if connection.state.connected.load(Ordering::SeqCst) => {
+            HealthCheckStatus::Healthy
+        }
+        Some(_) => HealthCheckStatus::Unhealthy("connection is lost".to_string()),
+        None => HealthCheckStatus::Unhealthy("port is not configured".to_string()),
+    };
+    sender_to_kernel.send(BrokerEvent::HealthCheckResponse(HealthCheckResponse {
+        status,
+        destination_id: event.sender_id,
+        id: event.id,
+        sender_id: event.destination_id,
+    }))
+}
+
+/// Tracks the recovery of a lost connection.
+struct Recovery {
+    /// the number of failed reconnect attempts
+    attempt: u32,
+    next_attempt: Instant,
+}
+
+impl Recovery {
+    fn new() -> Self {
+        Recovery {
+            attempt: 0,
+            next_attempt: Instant::now(),
+        }
+    }
+
+    /// The delay after the given number of failed attempts.
+    fn backoff(attempt: u32) -> Duration {
+        RECONNECT_MIN_BACKOFF
+            .saturating_mul(1 << attempt.saturating_sub(1).min(16))
+            .min(RECONNECT_MAX_BACKOFF)
+    }
+
+    fn failed(&mut self) {
+        self.attempt += 1;
+        self.next_attempt = Instant::now() + Recovery::backoff(self.attempt);
+    }
+
+    fn is_due(&self) -> bool {
+        Instant::now() >= self.next_attempt
+    }
+}
+
+/// Replaces the connection with a new one, the previous connection is closed first.
+fn reconnect(
+    id: &InternalServerId,
+    options: &NatsOptions,
+    pending_deliveries: &PendingDeliveries,
+    connection: &mut Option<NatsConnection>,
+    sender_to_kernel: &BoxedSender,
+) -> Result<()> {
+    *connection = None;
+    *connection = Some(setup_connection(
+        id,
+        options,
+        pending_deliveries.clone(),
+        sender_to_kernel.clone_boxed(),
+    )?);
+    Ok(())
+}
+
+/// This is the main function to start the port.
+pub fn port_nats_start(id: InternalServerId, inbox: BoxedReceiver, sender_to_kernel: BoxedSender) {
+    info!("start nats port with id {}", id);
+    let mut connection: Option<NatsConnection> = None;
+    // the options of the last config, they are used to reconnect
+    let mut options: Option<NatsOptions> = None;
+    let mut recovery: Option<Recovery> = None;
+    let pending_deliveries = PendingDeliveries::default();
+
+    loop {
+        if let Some(broker_event) = inbox.receive_timeout(Duration::from_millis(100)) {
+            match broker_event {
+                BrokerEvent::Init => info!("{} initiated", id),
+                BrokerEvent::ConfigUpdated(config, _) => {
+                    info!("{} received ConfigUpdated", id);
+                    connection = None;
+                    recovery = None;
+                    options = match build_config(&id, &config) {
+                        Ok(o) => Some(o),
+                        Err(e) => {
+                            error!("{} failed to apply config {:?}", id, e);
+                            None
+                        }
+                    };
+                    if let Some(o) = options.as_ref() {
+                        if let Err(e) = reconnect(
+                            &id,
+                            o,
+                            &pending_deliveries,
+                            &mut connection,
+                            &sender_to_kernel,
+                        ) {
+                            warn!("{} was not able to establish a connection: {:?}", id, e);
+                            let mut r = Recovery::new();
+                            r.failed();
+                            recovery = Some(r);
+                        }
+                    }
+                }
+                BrokerEvent::OutgoingCloudEvent(event) => {
+                    send_cloud_event(&id, event, &connection, &recovery, &sender_to_kernel)
+                }
+                BrokerEvent::IncomingCloudEventProcessed(routing_id, result) => {
+                    if let Err(e) = resolve_pending_delivery(&connection, &routing_id, result) {
+                        warn!("{} IncomingCloudEventProcessed was not handled {:?}", id, e)
+                    }
+                }
+                BrokerEvent::HealthCheckRequest(event) => {
+                    check_health(event, &connection, &recovery, &sender_to_kernel)
+                }
+                broker_event => warn!("event {} not implemented", broker_event),
+            }
+        }
+
+        let lost = matches!(connection, Some(ref c) if !c.state.connected.load(Ordering::SeqCst));
+        if recovery.is_none() && lost {
+            warn!("{} lost the connection, start to reconnect", id);
+            recovery = Some(Recovery::new());
+        }
+        if let (Some(r), Some(o)) = (recovery.as_mut(), options.as_ref()) {
+            if r.is_due() {
+                match reconnect(
+                    &id,
+                    o,
+                    &pending_deliveries,
+                    &mut connection,
+                    &sender_to_kernel,
+                ) {
+                    Ok(()) => {
+                        info!("{} reconnected after {} failed attempts", id, r.attempt);
+                        recovery = None;
+                    }
+                    Err(e) => {
+                        r.failed();
+                        warn!(
+                            "{} reconnect attempt {} failed, retry in {:?}: {:?}",
+                            id,
+                            r.attempt,
+                            Recovery::backoff(r.attempt),
+                            e
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// This is the pointer for the main function to start the port.
+pub static PORT_NATS: InternalServerFnRefStatic = &(port_nats_start as InternalServerFn);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cerk_runtime_threading::channel::new_channel_with_size;
+    use cloudevents::EventBuilder;
+    use cloudevents::EventBuilderV10;
+    use native_tls::{Identity, TlsAcceptor};
+    use std::io::{BufReader, Read, Write};
+    use std::net::{Shutdown, TcpListener};
+
+    fn entry(values: &[(&str, Config)]) -> Config {
+        Config::HashMap(
+            values
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.clone()))
+                .collect(),
+        )
+    }
+
+    fn jetstream_config(url: &str) -> Config {
+        entry(&[
+            ("url", Config::String(url.to_string())),
+            (
+                "subscribe_subjects",
+                Config::Vec(vec![entry(&[
+                    ("name", Config::String("orders.*".to_string())),
+                    ("stream", Config::String("ORDERS".to_string())),
+                    ("durable", Config::String("cerk".to_string())),
+                    ("delivery_guarantee", Config::U8(2)),
+                ])]),
+            ),
+            (
+                "publish_subjects",
+                Config::Vec(vec![entry(&[
+                    ("name", Config::String("output.>".to_string())),
+                    ("jetstream", Config::Bool(true)),
+                ])]),
+            ),
+        ])
+    }
+
+    fn read_line(reader: &mut impl BufRead) -> String {
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        line.trim_end().to_string()
+    }
+
+    fn core_config(url: &str) -> Config {
+        entry(&[
+            ("url", Config::String(url.to_string())),
+            (
+                "subscribe_subjects",
+                Config::Vec(vec![entry(&[(
+                    "name",
+                    Config::String("orders".to_string()),
+                )])]),
+            ),
+            (
+                "publish_subjects",
+                Config::Vec(vec![entry(&[(
+                    "name",
+                    Config::String("output".to_string()),
+                )])]),
+            ),
+        ])
+    }
+
+    /// Accepts the connection of the client like a server, which does not send any further INFO.
+    fn accept_client<S: Read + Write>(stream: S) -> BufReader<S> {
+        let mut reader = BufReader::new(stream);
+        assert!(read_line(&mut reader).starts_with("CONNECT "));
+        assert_eq!(read_line(&mut reader), "PING");
+        reader.get_mut().write_all(b"PONG\r\n").unwrap();
+        reader
+    }
+
+    fn send_event<S: Write>(stream: &mut S, id: &str) {
+        let event = format!(
+            r#"{{"specversion":"1.0","id":"{}","type":"test","source":"http://example.com"}}"#,
+            id
+        );
+        write!(stream, "MSG orders 1 {}\r\n{}\r\n", event.len(), event).unwrap();
+        stream.flush().unwrap();
+    }
+
+    fn receive_event_id(kernel: &BoxedReceiver) -> String {
+        match kernel.receive_timeout(Duration::from_secs(5)) {
+            Some(BrokerEvent::IncomingCloudEvent(incoming)) => {
+                incoming.cloud_event.id().to_string()
+            }
+            _ => panic!("expected IncomingCloudEvent"),
+        }
+    }
+
+    #[test]
+    fn build_full_config() -> Result<()> {
+        let options = build_config(
+            &"nats".to_string(),
+            &jetstream_config("nats://token@localhost"),
+        )?;
+        assert_eq!(options.server.address, "localhost:4222");
+        assert_eq!(options.server.user, Some("token".to_string()));
+        assert_eq!(options.subscriptions.len(), 1);
+        assert_eq!(
+            options.subscriptions[0].delivery_guarantee,
+            DeliveryGuarantee::AtLeastOnce
+        );
+        assert_eq!(
+            options.subscriptions[0].jetstream.as_ref().unwrap().durable,
+            "cerk"
+        );
+        assert!(options.publications[0].jetstream);
+        Ok(())
+    }
+
+    #[test]
+    fn build_invalid_config() {
+        let id = "nats".to_string();
+        assert!(build_config(&id, &Config::Null).is_err());
+        assert!(build_config(&id, &Config::HashMap(HashMap::new())).is_err());
+        // at least once requires JetStream
+        assert!(build_config(
+            &id,
+            &entry(&[
+                ("url", Config::String("localhost".to_string())),
+                (
+                    "subscribe_subjects",
+                    Config::Vec(vec![entry(&[
+                        ("name", Config::String("orders".to_string())),
+                        ("delivery_guarantee", Config::U8(2)),
+                    ])]),
+                ),
+            ])
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn evaluate_publish_acks() {
+        assert_eq!(
+            result_from_publish_ack(br#"{"stream":"ORDERS","seq":1}"#),
+            ProcessingResult::Successful
+        );
+        assert_eq!(
+            result_from_publish_ack(br#"{"error":{"code":400,"description":"bad request"}}"#),
+            ProcessingResult::PermanentError
+        );
+        assert_eq!(
+            result_from_publish_ack(br#"{"error":{"code":503}}"#),
+            ProcessingResult::TransientError
+        );
+        // no responders
+        assert_eq!(
+            result_from_publish_ack(b""),
+            ProcessingResult::TransientError
+        );
+    }
+
+    #[test]
+    fn acknowledge_jetstream_message_after_routing() -> Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let address = listener.local_addr()?.to_string();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            stream.write_all(b"INFO {\"headers\":true}\r\n").unwrap();
+            assert!(read_line(&mut reader).starts_with("CONNECT "));
+            assert_eq!(read_line(&mut reader), "PING");
+            stream.write_all(b"PONG\r\n").unwrap();
+
+            let inbox_subscription = read_line(&mut reader);
+            let inbox = inbox_subscription.split(' ').nth(1).unwrap().to_string();
+            assert!(read_line(&mut reader)
+                .starts_with("PUB $JS.API.CONSUMER.DURABLE.CREATE.ORDERS.cerk "));
+            let consumer: Value = serde_json::from_str(&read_line(&mut reader)).unwrap();
+            assert_eq!(consumer["config"]["filter_subject"], "orders.*");
+            assert_eq!(consumer["config"]["ack_policy"], "explicit");
+            let response = br#"{"name":"cerk"}"#;
+            write!(stream, "MSG {} 1 {}\r\n", inbox, response.len()).unwrap();
+            stream.write_all(response).unwrap();
+            stream.write_all(b"\r\n").unwrap();
+            assert_eq!(read_line(&mut reader), "UNSUB 1");
+            assert_eq!(read_line(&mut reader), "SUB cerk.deliver.ORDERS.cerk 1");
+            assert!(read_line(&mut reader).ends_with(".* 0"));
+            (stream, reader)
+        });
+
+        let (send, receive) = new_channel_with_size(10);
+        let id = "nats".to_string();
+        let options = build_config(&id, &jetstream_config(&address))?;
+        let connection = Some(setup_connection(
+            &id,
+            &options,
+            PendingDeliveries::default(),
+            send,
+        )?);
+        let (mut stream, mut reader) = server.join().unwrap();
+
+        let event =
+            br#"{"specversion":"1.0","id":"1","type":"test","source":"http://example.com"}"#;
+        write!(
+            stream,
+            "MSG orders.eu 1 $JS.ACK.ORDERS.cerk.1 {}\r\n",
+            event.len()
+        )?;
+        stream.write_all(event)?;
+        stream.write_all(b"\r\n")?;
+
+        let routing_id = match receive.receive_timeout(Duration::from_secs(1)) {
+            Some(BrokerEvent::IncomingCloudEvent(incoming)) => {
+                assert_eq!(incoming.cloud_event.subject(), Some("eu"));
+                assert_eq!(
+                    incoming.args.delivery_guarantee,
+                    DeliveryGuarantee::AtLeastOnce
+                );
+                incoming.routing_id
+            }
+            _ => panic!("expected IncomingCloudEvent"),
+        };
+        resolve_pending_delivery(&connection, &routing_id, ProcessingResult::Successful)?;
+        assert_eq!(read_line(&mut reader), "PUB $JS.ACK.ORDERS.cerk.1 4");
+        assert_eq!(read_line(&mut reader), "+ACK");
+        Ok(())
+    }
+
+    #[test]
+    fn reconnect_backoff() {
+        assert_eq!(Recovery::backoff(1), Duration::from_millis(500));
+        assert_eq!(Recovery::backoff(3), Duration::from_secs(2));
+        assert_eq!(Recovery::backoff(u32::MAX), RECONNECT_MAX_BACKOFF);
+    }
+
+    #[test]
+    fn reconnect_after_connection_loss() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("nats://{}", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            for (index, stream) in listener.incoming().take(2).enumerate() {
+                let mut stream = stream.unwrap();
+                stream.write_all(b"INFO {}\r\n").unwrap();
+                let mut reader = accept_client(stream.try_clone().unwrap());
+                assert_eq!(read_line(&mut reader), "SUB orders 1");
+                send_event(&mut stream, &index.to_string());
+                // the first connection is dropped by the server
+                if index == 0 {
+                    stream.shutdown(Shutdown::Both).unwrap();
+                } else {
+                    return (stream, reader);
+                }
+            }
+            unreachable!()
+        });
+        let (send_to_port, inbox) = new_channel_with_size(10);
+        let (sender_to_kernel, kernel) = new_channel_with_size(10);
+        thread::spawn(move || PORT_NATS("nats".to_string(), inbox, sender_to_kernel));
+        send_to_port.send(BrokerEvent::ConfigUpdated(
+            core_config(&url),
+            "nats".to_string(),
+        ));
+
+        assert_eq!(receive_event_id(&kernel), "0");
+        assert_eq!(receive_event_id(&kernel), "1");
+        let (_stream, mut reader) = server.join().unwrap();
+
+        let cloud_event = EventBuilderV10::new()
+            .id("2")
+            .ty("test")
+            .source("http://example.com")
+            .build()
+            .unwrap();
+        send_to_port.send(BrokerEvent::OutgoingCloudEvent(OutgoingCloudEvent {
+            routing_id: "2".to_string(),
+            cloud_event,
+            destination_id: "nats".to_string(),
+            args: CloudEventRoutingArgs {
+                delivery_guarantee: DeliveryGuarantee::AtLeastOnce,
+            },
+        }));
+        assert!(read_line(&mut reader).starts_with("PUB output "));
+        match kernel.receive_timeout(Duration::from_secs(1)) {
+            Some(BrokerEvent::OutgoingCloudEventProcessed(processed)) => {
+                assert_eq!(processed.result, ProcessingResult::Successful)
+            }
+            _ => panic!("expected OutgoingCloudEventProcessed"),
+        }
+    }
+
+    #[test]
+    fn connect_with_tls_if_the_server_requires_it() {
+        let identity = Identity::from_pkcs8(
+            &std::fs::read("./src/test_data/cert.pem").unwrap(),
+            &std::fs::read("./src/test_data/key.pem").unwrap(),
+        )
+        .unwrap();
+        let acceptor = TlsAcceptor::new(identity).unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        // the certificate is issued for localhost
+        let url = format!("localhost:{}", listener.local_addr().unwrap().port());
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            stream
+                .write_all(b"INFO {\"tls_required\":true}\r\n")
+                .unwrap();
+            let stream = acceptor.accept(stream).unwrap();
+            let mut reader = accept_client(stream);
+            assert_eq!(read_line(&mut reader), "SUB orders 1");
+            reader
+        });
+
+        let (send, kernel) = new_channel_with_size(10);
+        let id = "nats".to_string();
+        let mut config = core_config(&url);
+        if let Config::HashMap(ref mut values) = config {
+            values.insert(
+                "tls_ca_path".to_string(),
+                Config::String("./src/test_data/cert.pem".to_string()),
+            );
+        }
+        let options = build_config(&id, &config).unwrap();
+        let connection =
+            setup_connection(&id, &options, PendingDeliveries::default(), send).unwrap();
+        let mut reader = server.join().unwrap();
+
+        // the reader thread shares the TLS stream with the writer
+        send_event(reader.get_mut(), "1");
+        assert_eq!(receive_event_id(&kernel), "1");
+        connection
+            .state
+            .writer
+            .publish("output", None, b"hi")
+            .unwrap();
+        assert_eq!(read_line(&mut reader), "PUB output 2");
+        assert_eq!(read_line(&mut reader), "hi");
+    }
+
+    /// Runs against a real server with JetStream, e.g., the `nats` service of the `docker-compose.yml`:
+    /// `NATS_URL=nats://localhost:4222 cargo test -p cerk_port_nats -- --ignored`
+    #[test]
+    #[ignore]
+    fn interop_with_server() {
+        let url = std::env::var("NATS_URL").expect("NATS_URL is not set");
+        let stream = format!("INTEROP{}", Uuid::new_v4().to_simple());
+        let subject = format!("{}.events", stream);
+        let config = entry(&[
+            ("url", Config::String(url.clone())),
+            (
+                "subscribe_subjects",
+                Config::Vec(vec![entry(&[
+                    ("name", Config::String(subject.clone())),
+                    ("stream", Config::String(stream.clone())),
+                    ("durable", Config::String("cerk".to_string())),
+                    ("delivery_guarantee", Config::U8(2)),
+                ])]),
+            ),
+            (
+                "publish_subjects",
+                Config::Vec(vec![entry(&[
+                    ("name", Config::String(subject.clone())),
+                    ("jetstream", Config::Bool(true)),
+                ])]),
+            ),
+        ]);
+        let options = build_config(&"nats".to_string(), &config).unwrap();
+
+        // the server might still be starting, the stream is created as soon as it is available
+        let deadline = Instant::now() + Duration::from_secs(60);
+        let (writer, mut reader) = loop {
+            match connect(&options.server, &options.tls, "interop", CONNECT_TIMEOUT) {
+                Ok(connection) => break connection,
+                Err(_) if Instant::now() < deadline => thread::sleep(Duration::from_millis(500)),
+                Err(e) => panic!("failed to connect to {}: {:?}", url, e),
+            }
+        };
+        let response = request(
+            &mut reader,
+            &writer,
+            &format!("$JS.API.STREAM.CREATE.{}", stream),
+            json!({ "name": stream, "subjects": [subject] })
+                .to_string()
+                .as_bytes(),
+            "_INBOX.interop",
+            1,
+        )
+        .unwrap();
+        let response: Value = serde_json::from_slice(&response).unwrap();
+        assert!(response.get("error").is_none(), "{}", response);
+
+        let (send_to_port, inbox) = new_channel_with_size(10);
+        let (sender_to_kernel, kernel) = new_channel_with_size(10);
+        thread::spawn(move || PORT_NATS("nats".to_string(), inbox, sender_to_kernel));
+        send_to_port.send(BrokerEvent::ConfigUpdated(config, "nats".to_string()));
+
+        let cloud_event = EventBuilderV10::new()
+            .id("interop")
+            .ty("test")
+            .source("http://example.com")
+            .data("text/plain", "hello")
+            .build()
+            .unwrap();
+        send_to_port.send(BrokerEvent::OutgoingCloudEvent(OutgoingCloudEvent {
+            routing_id: "interop".to_string(),
+            cloud_event: cloud_event.clone(),
+            destination_id: "nats".to_string(),
+            args: CloudEventRoutingArgs {
+                delivery_guarantee: DeliveryGuarantee::AtLeastOnce,
+            },
+        }));
+        let mut processed = false;
+        let mut deliveries = 0;
+        while !(processed && deliveries == 2) {
+            match kernel.receive_timeout(Duration::from_secs(10)) {
+                Some(BrokerEvent::OutgoingCloudEventProcessed(event)) => {
+                    assert_eq!(event.result, ProcessingResult::Successful);
+                    processed = true;
+                }
+                Some(BrokerEvent::IncomingCloudEvent(event)) => {
+                    assert_eq!(event.cloud_event, cloud_event);
+                    deliveries += 1;
+                    // the first delivery is rejected with -NAK, so the server delivers it again
+                    let result = if deliveries == 1 {
+                        ProcessingResult::TransientError
+                    } else {
+                        ProcessingResult::Successful
+                    };
+                    send_to_port.send(BrokerEvent::IncomingCloudEventProcessed(
+                        event.routing_id,
+                        result,
+                    ));
+                }
+                Some(event) => panic!("unexpected {}", event),
+                None => panic!("timeout, processed={} deliveries={}", processed, deliveries),
+            }
+        }
+    }
+}
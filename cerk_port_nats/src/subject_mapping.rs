@@ -0,0 +1,126 @@
+use anyhow::{Context, Result};
+
+const SINGLE_WILDCARD: &str = "*";
+const FULL_WILDCARD: &str = ">";
+
+/// Returns true if the subject contains a `*` or `>` wildcard.
+fn has_wildcards(subject: &str) -> bool {
+    subject
+        .split('.')
+        .any(|token| token == SINGLE_WILDCARD || token == FULL_WILDCARD)
+}
+
+/// Returns the tokens of the subject which are matched by the wildcards of the pattern, joined by `.`.
+///
+/// For the pattern `orders.*.created` and the subject `orders.eu.created`, `eu` is returned.
+pub fn wildcard_tokens(pattern: &str, subject: &str) -> Option<String> {
+    let subject: Vec<&str> = subject.split('.').collect();
+    let mut matched = Vec::new();
+    for (i, token) in pattern.split('.').enumerate() {
+        match token {
+            FULL_WILDCARD => {
+                if i >= subject.len() {
+                    return None;
+                }
+                matched.extend_from_slice(&subject[i..]);
+                return Some(matched.join("."));
+            }
+            SINGLE_WILDCARD => matched.push(*subject.get(i)?),
+            token => {
+                if subject.get(i) != Some(&token) {
+                    return None;
+                }
+            }
+        }
+    }
+    if pattern.split('.').count() != subject.len() || matched.is_empty() {
+        None
+    } else {
+        Some(matched.join("."))
+    }
+}
+
+/// Replaces the wildcards of the pattern with the tokens of the CloudEvent `subject`.
+///
+/// For the pattern `orders.*.created` and the CloudEvent subject `eu`, `orders.eu.created` is returned.
+pub fn render_subject(pattern: &str, event_subject: Option<&str>) -> Result<String> {
+    if !has_wildcards(pattern) {
+        return Ok(pattern.to_string());
+    }
+    let event_subject =
+        event_subject.context("the event has no subject to replace the wildcards")?;
+    let mut tokens = event_subject.split('.');
+    if let Some(token) = event_subject.split('.').find(|token| {
+        token.is_empty()
+            || *token == SINGLE_WILDCARD
+            || *token == FULL_WILDCARD
+            || token.contains(char::is_whitespace)
+    }) {
+        bail!(
+            "the event subject {} contains the invalid token {:?}",
+            event_subject,
+            token
+        );
+    }
+    let mut rendered = Vec::new();
+    for token in pattern.split('.') {
+        match token {
+            FULL_WILDCARD => rendered.extend(tokens.by_ref()),
+            SINGLE_WILDCARD => rendered.push(tokens.next().with_context(|| {
+                format!("the event subject {} has too few tokens", event_subject)
+            })?),
+            token => rendered.push(token),
+        }
+    }
+    if tokens.next().is_some() {
+        bail!("the event subject {} has too many tokens", event_subject);
+    }
+    Ok(rendered.join("."))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn match_wildcard_tokens() {
+        assert_eq!(
+            wildcard_tokens("orders.*.created", "orders.eu.created"),
+            Some("eu".to_string())
+        );
+        assert_eq!(
+            wildcard_tokens("orders.>", "orders.eu.created"),
+            Some("eu.created".to_string())
+        );
+        assert_eq!(
+            wildcard_tokens("*.*.>", "orders.eu.created"),
+            Some("orders.eu.created".to_string())
+        );
+        assert_eq!(wildcard_tokens("orders.created", "orders.created"), None);
+        assert_eq!(wildcard_tokens("orders.>", "orders"), None);
+        assert_eq!(wildcard_tokens("orders.*", "orders.eu.created"), None);
+        assert_eq!(
+            wildcard_tokens("orders.*.created", "orders.eu.deleted"),
+            None
+        );
+    }
+
+    #[test]
+    fn render_wildcards() -> Result<()> {
+        assert_eq!(render_subject("orders.created", None)?, "orders.created");
+        assert_eq!(
+            render_subject("orders.*.created", Some("eu"))?,
+            "orders.eu.created"
+        );
+        assert_eq!(
+            render_subject("orders.>", Some("eu.created"))?,
+            "orders.eu.created"
+        );
+        assert!(render_subject("orders.>", None).is_err());
+        assert!(render_subject("orders.>", Some("")).is_err());
+        assert!(render_subject("orders.*", Some("eu.created")).is_err());
+        assert!(render_subject("orders.*.*", Some("eu")).is_err());
+        assert!(render_subject("orders.*", Some("e u")).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,82 @@
+/*!
+
+This is a package for [CERK](https://github.com/ce-rust/cerk).
+CERK is an open source [CloudEvents](https://github.com/cloudevents/spec) Router written in Rust with a MicroKernel architecture.
+
+# Introduction
+
+CERK lets you route your [CloudEvents](https://github.com/cloudevents/spec) between different different ports.
+Ports are transport layer bindings over which CloudEvents can be exchanged.
+It is built with modularity and portability in mind.
+
+# Components
+
+CERK comes with a couple of prefabricated components, but implementing custom components is easy.
+
+A good overview is provided on [GitHub](https://github.com/ce-rust/cerk/).
+
+# This Component: Redis Streams Port
+
+This port consumes CloudEvents from and/or adds CloudEvents to [Redis Streams](https://redis.io/docs/data-types/streams/).
+It is a lightweight option for the delivery guarantee `AtLeastOnce` in deployments without a message broker like RabbitMQ.
+
+The port is implemented with [redis-rs](https://github.com/redis-rs/redis-rs) and requires Redis 6.2 or newer.
+
+## Entries
+
+There is no CloudEvents binding for Redis, the port stores each event JSON encoded in the field `event` of a stream entry.
+Entries without a valid event are skipped.
+
+## Consumer Groups
+
+The streams are read through a consumer group, which is created if it does not exist yet, together with the stream.
+Multiple instances of the port share the entries, if they use the same group and different consumer names.
+
+With the delivery guarantee `AtLeastOnce`, an entry is only acknowledged (`XACK`) after it was processed successfully or failed permanently.
+After a transient error, the entry stays pending.
+On startup and then periodically, entries which are pending longer than `claim_min_idle_ms` are claimed (`XAUTOCLAIM`) and delivered again.
+This includes the entries which a crashed or restarted consumer had not acknowledged.
+
+Outgoing events are added (`XADD`) to all publish streams, the event is processed as soon as Redis confirmed the entries.
+
+## Configurations
+
+```json
+{
+  "url": "redis://localhost:6379",
+  "group": "cerk",
+  "consume_streams": [{ "name": "input", "delivery_guarantee": 2 }],
+  "publish_streams": [{ "name": "output", "max_len": 10000 }]
+}
+```
+
+| Name                | Type   | Default  | Description                                                                  |
+|---------------------|--------|----------|------------------------------------------------------------------------------|
+| `url`               | String |          | the server, e.g., `redis://:password@localhost:6379/0`                       |
+| `group`             | String | `cerk`   | the consumer group                                                           |
+| `consumer`          | String | `<id>`   | the consumer name within the group                                           |
+| `consume_streams`   | Array  |          | the streams to consume, each with a `delivery_guarantee` (default `0`)       |
+| `publish_streams`   | Array  |          | the streams every event is added to, each with an optional approximate `max_len` |
+| `claim_min_idle_ms` | u32    | `30000`  | the time after which pending entries are delivered again                     |
+| `timeout_ms`        | u32    | `5000`   | the timeout of the commands to acknowledge and add entries                   |
+
+## Local Server
+
+```bash
+docker run -d -p 6379:6379 redis
+```
+
+*/
+
+#![deny(missing_docs)]
+
+#[macro_use]
+extern crate log;
+
+#[macro_use]
+extern crate anyhow;
+
+mod port_redis;
+mod stream_reply;
+
+pub use self::port_redis::{port_redis_start, PORT_REDIS};
@@ -0,0 +1,835 @@
+use crate::stream_reply::{parse_claim_reply, parse_read_reply, StreamEntry, EVENT_FIELD};
+use anyhow::{Context, Result};
+use cerk::kernel::{
+    BrokerEvent, CloudEventMessageRoutingId, CloudEventRoutingArgs, Config, ConfigHelpers,
+    DeliveryGuarantee, HealthCheckRequest, HealthCheckResponse, HealthCheckStatus,
+    IncomingCloudEvent, OutgoingCloudEvent, OutgoingCloudEventProcessed, ProcessingResult,
+};
+use cerk::runtime::channel::{BoxedReceiver, BoxedSender};
+use cerk::runtime::{InternalServerFn, InternalServerFnRefStatic, InternalServerId};
+use cloudevents::{AttributesReader, Event};
+use redis::{Client, Cmd, Connection, ErrorKind, RedisError, RedisResult, Value};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+const BLOCK_MS: u32 = 1000;
+const READ_COUNT: u32 = 100;
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+const INITIAL_CURSOR: &str = "0-0";
+
+#[derive(Clone)]
+struct RedisOptions {
+    url: String,
+    group: String,
+    consumer: String,
+    consume_streams: HashMap<String, DeliveryGuarantee>,
+    publish_streams: HashMap<String, Option<u32>>,
+    claim_min_idle: Duration,
+    timeout: Duration,
+}
+
+struct PendingDelivery {
+    stream: String,
+    entry_id: String,
+}
+
+type PendingDeliveries = Arc<Mutex<HashMap<CloudEventMessageRoutingId, PendingDelivery>>>;
+
+/// A connection which is established on first use and after it was dropped.
+struct RedisConnection {
+    client: Client,
+    connection: Option<Connection>,
+    timeout: Option<Duration>,
+}
+
+impl RedisConnection {
+    fn query(&mut self, cmd: &Cmd) -> RedisResult<Value> {
+        let connection = match self.connection {
+            Some(ref mut connection) => connection,
+            None => {
+                let connection = self.client.get_connection()?;
+                connection.set_read_timeout(self.timeout)?;
+                connection.set_write_timeout(self.timeout)?;
+                self.connection.get_or_insert(connection)
+            }
+        };
+        let result = cmd.query(connection);
+        if let Err(ref e) = result {
+            if e.is_io_error() {
+                self.connection = None;
+            }
+        }
+        result
+    }
+}
+
+struct RedisConsumer {
+    pending_deliveries: PendingDeliveries,
+    running: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Drop for RedisConsumer {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            if thread.join().is_err() {
+                error!("consumer thread panicked");
+            }
+        }
+    }
+}
+
+struct RedisPort {
+    options: RedisOptions,
+    connection: RedisConnection,
+    consumer: Option<RedisConsumer>,
+}
+
+/// The result which requires a retry wins, because the event is added to the other streams again anyway.
+fn worse_result(a: ProcessingResult, b: ProcessingResult) -> ProcessingResult {
+    let priority = |r: &ProcessingResult| match r {
+        ProcessingResult::Successful => 0,
+        ProcessingResult::PermanentError => 1,
+        ProcessingResult::Timeout => 2,
+        ProcessingResult::TransientError => 3,
+    };
+    if priority(&b) > priority(&a) {
+        b
+    } else {
+        a
+    }
+}
+
+fn result_from_error(error: &RedisError) -> ProcessingResult {
+    if error.is_timeout() {
+        return ProcessingResult::Timeout;
+    }
+    match error.kind() {
+        ErrorKind::IoError | ErrorKind::BusyLoadingError => ProcessingResult::TransientError,
+        ErrorKind::ExtensionError => match error.extension_error_code() {
+            Some("OOM") | Some("READONLY") | Some("MASTERDOWN") | Some("NOREPLICAS") => {
+                ProcessingResult::TransientError
+            }
+            _ => ProcessingResult::PermanentError,
+        },
+        _ => ProcessingResult::PermanentError,
+    }
+}
+
+fn try_get_delivery_option(config: &Config) -> Result<DeliveryGuarantee> {
+    Ok(match config.get_op_val_config("delivery_guarantee")? {
+        Some(config) => DeliveryGuarantee::try_from(config)?,
+        _ => DeliveryGuarantee::BestEffort,
+    })
+}
+
+fn build_config(id: &InternalServerId, config: &Config) -> Result<RedisOptions> {
+    if !matches!(config, Config::HashMap(_)) {
+        bail!("{} config has to be of type HashMap", id);
+    }
+    let mut options = RedisOptions {
+        url: config.get_op_val_string("url")?.context("No url option")?,
+        group: config
+            .get_op_val_string("group")?
+            .unwrap_or_else(|| "cerk".to_string()),
+        consumer: config
+            .get_op_val_string("consumer")?
+            .unwrap_or_else(|| id.clone()),
+        consume_streams: HashMap::new(),
+        publish_streams: HashMap::new(),
+        claim_min_idle: Duration::from_millis(
+            config.get_op_val_u32("claim_min_idle_ms")?.unwrap_or(30000) as u64,
+        ),
+        timeout: Duration::from_millis(config.get_op_val_u32("timeout_ms")?.unwrap_or(5000) as u64),
+    };
+
+    for stream in config
+        .get_op_val_vec("consume_streams")?
+        .unwrap_or_default()
+        .iter()
+    {
+        let name = stream
+            .get_op_val_string("name")?
+            .context("consume_streams name is not set")?;
+        options
+            .consume_streams
+            .insert(name, try_get_delivery_option(stream)?);
+    }
+
+    for stream in config
+        .get_op_val_vec("publish_streams")?
+        .unwrap_or_default()
+        .iter()
+    {
+        let name = stream
+            .get_op_val_string("name")?
+            .context("publish_streams name is not set")?;
+        options
+            .publish_streams
+            .insert(name, stream.get_op_val_u32("max_len")?);
+    }
+
+    Ok(options)
+}
+
+fn acknowledge(connection: &mut RedisConnection, group: &str, stream: &str, entry_id: &str) {
+    if let Err(e) = connection.query(redis::cmd("XACK").arg(stream).arg(group).arg(entry_id)) {
+        error!("failed to acknowledge {}/{}: {}", stream, entry_id, e);
+    }
+}
+
+fn receive_entry(
+    id: &InternalServerId,
+    options: &RedisOptions,
+    connection: &mut RedisConnection,
+    pending_deliveries: &PendingDeliveries,
+    sender_to_kernel: &BoxedSender,
+    stream: &str,
+    entry: StreamEntry,
+) {
+    let delivery_guarantee = options
+        .consume_streams
+        .get(stream)
+        .cloned()
+        .unwrap_or(DeliveryGuarantee::BestEffort);
+    // the lock is released before the event is sent, the kernel could wait for this port
+    let mut pending = pending_deliveries.lock().unwrap();
+    if pending
+        .values()
+        .any(|p| p.stream == stream && p.entry_id == entry.id)
+    {
+        debug!("{} entry {}/{} is still in process", id, stream, entry.id);
+        return;
+    }
+    let cloud_event = entry
+        .event
+        .as_ref()
+        .context("entry has no event field")
+        .and_then(|event| {
+            serde_json::from_slice::<Event>(event).context("failed to parse CloudEvent")
+        });
+    match cloud_event {
+        Ok(cloud_event) => {
+            let routing_id = format!("{}--{}/{}", cloud_event.id(), stream, entry.id);
+            if delivery_guarantee.requires_acknowledgment() {
+                pending.insert(
+                    routing_id.clone(),
+                    PendingDelivery {
+                        stream: stream.to_string(),
+                        entry_id: entry.id,
+                    },
+                );
+                drop(pending);
+            } else {
+                drop(pending);
+                acknowledge(connection, &options.group, stream, &entry.id);
+            }
+            sender_to_kernel.send(BrokerEvent::IncomingCloudEvent(IncomingCloudEvent {
+                incoming_id: id.clone(),
+                routing_id,
+                cloud_event,
+                args: CloudEventRoutingArgs { delivery_guarantee },
+            }));
+        }
+        Err(e) => {
+            drop(pending);
+            error!("{} skips entry {}/{}: {:?}", id, stream, entry.id, e);
+            acknowledge(connection, &options.group, stream, &entry.id);
+        }
+    }
+}
+
+fn create_groups(options: &RedisOptions, connection: &mut RedisConnection) -> Result<()> {
+    for stream in options.consume_streams.keys() {
+        let result = connection.query(
+            redis::cmd("XGROUP")
+                .arg("CREATE")
+                .arg(stream)
+                .arg(&options.group)
+                .arg("0")
+                .arg("MKSTREAM"),
+        );
+        match result {
+            Err(e) if e.extension_error_code() != Some("BUSYGROUP") => {
+                return Err(e).with_context(|| format!("failed to create group on {}", stream))
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Claims the entries which are pending longer than `claim_min_idle` and delivers them again, this includes the entries of crashed consumers.
+fn claim_pending_entries(
+    id: &InternalServerId,
+    options: &RedisOptions,
+    connection: &mut RedisConnection,
+    pending_deliveries: &PendingDeliveries,
+    sender_to_kernel: &BoxedSender,
+) -> Result<()> {
+    for stream in options.consume_streams.keys() {
+        let mut cursor = INITIAL_CURSOR.to_string();
+        loop {
+            let reply = connection.query(
+                redis::cmd("XAUTOCLAIM")
+                    .arg(stream)
+                    .arg(&options.group)
+                    .arg(&options.consumer)
+                    .arg(options.claim_min_idle.as_millis() as u64)
+                    .arg(&cursor)
+                    .arg("COUNT")
+                    .arg(READ_COUNT),
+            )?;
+            let (next_cursor, entries) = parse_claim_reply(&reply)?;
+            for entry in entries {
+                receive_entry(
+                    id,
+                    options,
+                    connection,
+                    pending_deliveries,
+                    sender_to_kernel,
+                    stream,
+                    entry,
+                );
+            }
+            if next_cursor == INITIAL_CURSOR {
+                break;
+            }
+            cursor = next_cursor;
+        }
+    }
+    Ok(())
+}
+
+fn read_new_entries(
+    id: &InternalServerId,
+    options: &RedisOptions,
+    connection: &mut RedisConnection,
+    pending_deliveries: &PendingDeliveries,
+    sender_to_kernel: &BoxedSender,
+) -> Result<()> {
+    let mut cmd = redis::cmd("XREADGROUP");
+    cmd.arg("GROUP")
+        .arg(&options.group)
+        .arg(&options.consumer)
+        .arg("COUNT")
+        .arg(READ_COUNT)
+        .arg("BLOCK")
+        .arg(BLOCK_MS)
+        .arg("STREAMS");
+    for stream in options.consume_streams.keys() {
+        cmd.arg(stream);
+    }
+    for _ in options.consume_streams.keys() {
+        cmd.arg(">");
+    }
+    let reply = connection.query(&cmd)?;
+    for (stream, entries) in parse_read_reply(&reply)? {
+        for entry in entries {
+            receive_entry(
+                id,
+                options,
+                connection,
+                pending_deliveries,
+                sender_to_kernel,
+                &stream,
+                entry,
+            );
+        }
+    }
+    Ok(())
+}
+
+fn setup_consumer(
+    id: &InternalServerId,
+    options: &RedisOptions,
+    client: Client,
+    sender_to_kernel: BoxedSender,
+) -> RedisConsumer {
+    let pending_deliveries: PendingDeliveries = Arc::new(Mutex::new(HashMap::new()));
+    let running = Arc::new(AtomicBool::new(true));
+    let thread = {
+        let id = id.clone();
+        let options = options.clone();
+        let pending_deliveries = pending_deliveries.clone();
+        let running = running.clone();
+        thread::spawn(move || {
+            // the read timeout is disabled, because XREADGROUP blocks on the server
+            let mut connection = RedisConnection {
+                client,
+                connection: None,
+                timeout: None,
+            };
+            let mut groups_created = false;
+            let mut last_claim: Option<Instant> = None;
+            while running.load(Ordering::SeqCst) {
+                let result = (|| {
+                    if !groups_created {
+                        create_groups(&options, &mut connection)?;
+                        groups_created = true;
+                    }
+                    if !matches!(last_claim, Some(t) if t.elapsed() < options.claim_min_idle) {
+                        claim_pending_entries(
+                            &id,
+                            &options,
+                            &mut connection,
+                            &pending_deliveries,
+                            &sender_to_kernel,
+                        )?;
+                        last_claim = Some(Instant::now());
+                    }
+                    read_new_entries(
+                        &id,
+                        &options,
+                        &mut connection,
+                        &pending_deliveries,
+                        &sender_to_kernel,
+                    )
+                })();
+                if let Err(e) = result {
+                    warn!("{} failed to consume: {:?}", id, e);
+                    thread::sleep(RECONNECT_DELAY);
+                }
+            }
+        })
+    };
+    RedisConsumer {
+        pending_deliveries,
+        running,
+        thread: Some(thread),
+    }
+}
+
+fn setup_port(
+    id: &InternalServerId,
+    options: RedisOptions,
+    sender_to_kernel: BoxedSender,
+) -> Result<RedisPort> {
+    let client = Client::open(options.url.as_str()).context("invalid url")?;
+    let consumer = if options.consume_streams.is_empty() {
+        None
+    } else {
+        Some(setup_consumer(
+            id,
+            &options,
+            client.clone(),
+            sender_to_kernel,
+        ))
+    };
+    Ok(RedisPort {
+        connection: RedisConnection {
+            client,
+            connection: None,
+            timeout: Some(options.timeout),
+        },
+        options,
+        consumer,
+    })
+}
+
+fn add_entry(
+    connection: &mut RedisConnection,
+    stream: &str,
+    max_len: Option<u32>,
+    event: &[u8],
+) -> RedisResult<Value> {
+    let mut cmd = redis::cmd("XADD");
+    cmd.arg(stream);
+    if let Some(max_len) = max_len {
+        cmd.arg("MAXLEN").arg("~").arg(max_len);
+    }
+    cmd.arg("*").arg(EVENT_FIELD).arg(event);
+    connection.query(&cmd)
+}
+
+fn send_cloud_event(
+    id: &InternalServerId,
+    event: OutgoingCloudEvent,
+    port: &mut Option<RedisPort>,
+    sender_to_kernel: &BoxedSender,
+) {
+    let result = match port {
+        Some(port) if !port.options.publish_streams.is_empty() => {
+            match serde_json::to_vec(&event.cloud_event) {
+                Ok(payload) => {
+                    let mut result = ProcessingResult::Successful;
+                    for (stream, max_len) in port.options.publish_streams.iter() {
+                        if let Err(e) =
+                            add_entry(&mut port.connection, stream, *max_len, payload.as_slice())
+                        {
+                            error!("{} failed to add event to {}: {}", id, stream, e);
+                            result = worse_result(result, result_from_error(&e));
+                        }
+                    }
+                    result
+                }
+                Err(e) => {
+                    error!("{} failed to serialize {}: {:?}", id, event.routing_id, e);
+                    ProcessingResult::PermanentError
+                }
+            }
+        }
+        _ => {
+            error!(
+                "{} received an event, but no publish stream is configured",
+                id
+            );
+            ProcessingResult::PermanentError
+        }
+    };
+    if event.args.delivery_guarantee.requires_acknowledgment() {
+        sender_to_kernel.send(BrokerEvent::OutgoingCloudEventProcessed(
+            OutgoingCloudEventProcessed {
+                sender_id: id.clone(),
+                routing_id: event.routing_id,
+                result,
+            },
+        ));
+    }
+}
+
+/// Acknowledges the entry after a successful or permanent failed processing, otherwise it stays pending and is claimed again later.
+fn resolve_pending_delivery(
+    id: &InternalServerId,
+    port: &mut Option<RedisPort>,
+    routing_id: &CloudEventMessageRoutingId,
+    result: ProcessingResult,
+) -> Result<()> {
+    let port = port.as_mut().context("port is not configured")?;
+    let pending = port
+        .consumer
+        .as_ref()
+        .context("no consumer is configured")?
+        .pending_deliveries
+        .lock()
+        .unwrap()
+        .remove(routing_id)
+        .with_context(|| format!("pending delivery with id={} not found", routing_id))?;
+    match result {
+        ProcessingResult::Successful => {}
+        ProcessingResult::PermanentError => {
+            error!(
+                "{} entry {} failed permanently and is skipped",
+                id, routing_id
+            )
+        }
+        ProcessingResult::TransientError | ProcessingResult::Timeout => {
+            warn!(
+                "{} entry {} failed and is claimed again after {:?}",
+                id, routing_id, port.options.claim_min_idle
+            );
+            return Ok(());
+        }
+    }
+    port.connection
+        .query(
+            redis::cmd("XACK")
+                .arg(&pending.stream)
+                .arg(&port.options.group)
+                .arg(&pending.entry_id),
+        )
+        .with_context(|| format!("failed to acknowledge {}", routing_id))?;
+    Ok(())
+}
+
+fn check_health(event: HealthCheckRequest, configured: bool, sender_to_kernel: &BoxedSender) {
+    let status = if configured {
+        HealthCheckStatus::Healthy
+    } else {
+        HealthCheckStatus::Unhealthy("port is not configured".to_string())
+    };
+    sender_to_kernel.send(BrokerEvent::HealthCheckResponse(HealthCheckResponse {
+        status,
+        destination_id: event.sender_id,
+        id: event.id,
+        sender_id: event.destination_id,
+    }))
+}
+
+/// This is the main function to start the port.
+pub fn port_redis_start(id: InternalServerId, inbox: BoxedReceiver, sender_to_kernel: BoxedSender) {
+    info!("start redis port with id {}", id);
+    let mut port: Option<RedisPort> = None;
+
+    loop {
+        match inbox.receive() {
+            BrokerEvent::Init => info!("{} initiated", id),
+            BrokerEvent::ConfigUpdated(config, _) => {
+                info!("{} received ConfigUpdated", id);
+                port = None;
+                match build_config(&id, &config)
+                    .and_then(|options| setup_port(&id, options, sender_to_kernel.clone_boxed()))
+                {
+                    Ok(p) => port = Some(p),
+                    Err(e) => error!("{} failed to apply config {:?}", id, e),
+                }
+            }
+            BrokerEvent::OutgoingCloudEvent(event) => {
+                send_cloud_event(&id, event, &mut port, &sender_to_kernel)
+            }
+            BrokerEvent::IncomingCloudEventProcessed(routing_id, result) => {
+                if let Err(e) = resolve_pending_delivery(&id, &mut port, &routing_id, result) {
+                    warn!("{} IncomingCloudEventProcessed was not handled {:?}", id, e)
+                }
+            }
+            BrokerEvent::HealthCheckRequest(event) => {
+                check_health(event, port.is_some(), &sender_to_kernel)
+            }
+            broker_event => warn!("event {} not implemented", broker_event),
+        }
+    }
+}
+
+/// This is the pointer for the main function to start the port.
+pub static PORT_REDIS: InternalServerFnRefStatic = &(port_redis_start as InternalServerFn);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cerk_runtime_threading::channel::new_channel_with_size;
+    use cloudevents::{EventBuilder, EventBuilderV10};
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+
+    type Commands = Arc<Mutex<Vec<Vec<String>>>>;
+
+    const EVENT: &str =
+        r#"{"specversion":"1.0","id":"1","type":"test","source":"http://example.com"}"#;
+    static DELIVERED: AtomicBool = AtomicBool::new(false);
+
+    fn read_command(reader: &mut impl BufRead) -> Option<Vec<String>> {
+        let mut line = String::new();
+        if reader.read_line(&mut line).ok()? == 0 {
+            return None;
+        }
+        let count: usize = line.trim_end()[1..].parse().ok()?;
+        (0..count)
+            .map(|_| {
+                let mut length = String::new();
+                reader.read_line(&mut length).ok()?;
+                let length: usize = length.trim_end()[1..].parse().ok()?;
+                let mut argument = vec![0; length + 2];
+                reader.read_exact(&mut argument).ok()?;
+                argument.truncate(length);
+                String::from_utf8(argument).ok()
+            })
+            .collect()
+    }
+
+    fn respond(command: &[String]) -> String {
+        match (command[0].as_str(), command[1].as_str()) {
+            ("XGROUP", _) => "-BUSYGROUP Consumer Group name already exists\r\n".to_string(),
+            ("XAUTOCLAIM", _) => "*3\r\n$3\r\n0-0\r\n*0\r\n*0\r\n".to_string(),
+            ("XREADGROUP", _) if !DELIVERED.swap(true, Ordering::SeqCst) => format!(
+                "*1\r\n*2\r\n$6\r\norders\r\n*1\r\n*2\r\n$3\r\n1-0\r\n*2\r\n$5\r\nevent\r\n${}\r\n{}\r\n",
+                EVENT.len(),
+                EVENT
+            ),
+            ("XREADGROUP", _) => {
+                thread::sleep(Duration::from_millis(10));
+                "*-1\r\n".to_string()
+            }
+            ("XACK", _) => ":1\r\n".to_string(),
+            ("XADD", "readonly") => {
+                "-READONLY You can't write against a read only replica.\r\n".to_string()
+            }
+            ("XADD", _) => "$3\r\n2-0\r\n".to_string(),
+            _ => "-ERR unknown command\r\n".to_string(),
+        }
+    }
+
+    fn fake_server() -> (String, Commands) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("redis://{}", listener.local_addr().unwrap());
+        let commands: Commands = Arc::new(Mutex::new(vec![]));
+        let recorded = commands.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let commands = recorded.clone();
+                thread::spawn(move || {
+                    let mut reader = BufReader::new(stream.try_clone().unwrap());
+                    while let Some(command) = read_command(&mut reader) {
+                        let response = respond(&command);
+                        commands.lock().unwrap().push(command);
+                        stream.write_all(response.as_bytes()).unwrap();
+                    }
+                });
+            }
+        });
+        (url, commands)
+    }
+
+    fn stream(name: &str, key: &str, value: Config) -> Config {
+        Config::HashMap(
+            [
+                ("name".to_string(), Config::String(name.to_string())),
+                (key.to_string(), value),
+            ]
+            .iter()
+            .cloned()
+            .collect(),
+        )
+    }
+
+    fn config(url: &str, consume_streams: Vec<Config>, publish_streams: Vec<Config>) -> Config {
+        Config::HashMap(
+            [
+                ("url".to_string(), Config::String(url.to_string())),
+                ("consume_streams".to_string(), Config::Vec(consume_streams)),
+                ("publish_streams".to_string(), Config::Vec(publish_streams)),
+            ]
+            .iter()
+            .cloned()
+            .collect(),
+        )
+    }
+
+    fn contains(commands: &Commands, command: &[&str]) -> bool {
+        commands
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|c| c.iter().map(|a| a.as_str()).eq(command.iter().cloned()))
+    }
+
+    #[test]
+    fn build_full_config() -> Result<()> {
+        let options = build_config(
+            &"redis".to_string(),
+            &config(
+                "redis://localhost",
+                vec![stream("in", "delivery_guarantee", Config::U8(2))],
+                vec![stream("out", "max_len", Config::U32(1000))],
+            ),
+        )?;
+        assert_eq!(options.group, "cerk");
+        assert_eq!(options.consumer, "redis");
+        assert_eq!(options.claim_min_idle, Duration::from_secs(30));
+        assert_eq!(
+            options.consume_streams.get("in"),
+            Some(&DeliveryGuarantee::AtLeastOnce)
+        );
+        assert_eq!(options.publish_streams.get("out"), Some(&Some(1000)));
+        Ok(())
+    }
+
+    #[test]
+    fn build_invalid_config() {
+        let id = "redis".to_string();
+        assert!(build_config(&id, &Config::Null).is_err());
+        assert!(build_config(&id, &Config::HashMap(HashMap::new())).is_err());
+    }
+
+    #[test]
+    fn acknowledge_entry_after_routing() -> Result<()> {
+        let (url, commands) = fake_server();
+        let (send, receive) = new_channel_with_size(10);
+        let id = "redis".to_string();
+        let options = build_config(
+            &id,
+            &config(
+                &url,
+                vec![stream("orders", "delivery_guarantee", Config::U8(2))],
+                vec![],
+            ),
+        )?;
+        let mut port = Some(setup_port(&id, options, send)?);
+
+        let routing_id = match receive.receive_timeout(Duration::from_secs(1)) {
+            Some(BrokerEvent::IncomingCloudEvent(incoming)) => {
+                assert_eq!(
+                    incoming.args.delivery_guarantee,
+                    DeliveryGuarantee::AtLeastOnce
+                );
+                incoming.routing_id
+            }
+            _ => panic!("expected IncomingCloudEvent"),
+        };
+        assert_eq!(routing_id, "1--orders/1-0");
+        assert!(!contains(&commands, &["XACK", "orders", "cerk", "1-0"]));
+        resolve_pending_delivery(&id, &mut port, &routing_id, ProcessingResult::Successful)?;
+        assert!(contains(&commands, &["XACK", "orders", "cerk", "1-0"]));
+        assert!(contains(
+            &commands,
+            &[
+                "XAUTOCLAIM",
+                "orders",
+                "cerk",
+                "redis",
+                "30000",
+                "0-0",
+                "COUNT",
+                "100"
+            ]
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn add_event_to_all_streams() -> Result<()> {
+        let (url, commands) = fake_server();
+        let (send, receive) = new_channel_with_size(10);
+        let id = "redis".to_string();
+        let options = build_config(
+            &id,
+            &config(
+                &url,
+                vec![],
+                vec![
+                    stream("out", "max_len", Config::U32(100)),
+                    Config::HashMap(
+                        [("name".to_string(), Config::String("readonly".to_string()))]
+                            .iter()
+                            .cloned()
+                            .collect(),
+                    ),
+                ],
+            ),
+        )?;
+        let mut port = Some(setup_port(&id, options, send.clone_boxed())?);
+        let cloud_event = EventBuilderV10::new()
+            .id("1")
+            .ty("test")
+            .source("http://example.com")
+            .build()
+            .unwrap();
+        let payload = serde_json::to_string(&cloud_event)?;
+        send_cloud_event(
+            &id,
+            OutgoingCloudEvent {
+                routing_id: "1".to_string(),
+                cloud_event,
+                destination_id: id.clone(),
+                args: CloudEventRoutingArgs {
+                    delivery_guarantee: DeliveryGuarantee::AtLeastOnce,
+                },
+            },
+            &mut port,
+            &send,
+        );
+
+        assert!(contains(
+            &commands,
+            &["XADD", "out", "MAXLEN", "~", "100", "*", "event", &payload]
+        ));
+        assert!(contains(
+            &commands,
+            &["XADD", "readonly", "*", "event", &payload]
+        ));
+        match receive.receive_timeout(Duration::from_millis(100)) {
+            Some(BrokerEvent::OutgoingCloudEventProcessed(processed)) => {
+                assert_eq!(processed.routing_id, "1");
+                assert_eq!(processed.result, ProcessingResult::TransientError);
+            }
+            _ => panic!("expected OutgoingCloudEventProcessed"),
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,151 @@
+use anyhow::{Context, Result};
+use redis::Value;
+
+/// The field of a stream entry, which contains the JSON encoded CloudEvent.
+pub const EVENT_FIELD: &str = "event";
+
+/// An entry of a stream, the event is `None` if the entry was deleted or has no event field.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StreamEntry {
+    pub id: String,
+    pub event: Option<Vec<u8>>,
+}
+
+fn as_string(value: &Value) -> Result<String> {
+    match value {
+        Value::Data(data) => Ok(String::from_utf8(data.clone())?),
+        Value::Status(status) => Ok(status.clone()),
+        _ => bail!("expected string, but received {:?}", value),
+    }
+}
+
+fn as_bulk(value: &Value) -> Result<&[Value]> {
+    match value {
+        Value::Bulk(values) => Ok(values.as_slice()),
+        _ => bail!("expected array, but received {:?}", value),
+    }
+}
+
+/// Parses the entries in the form `[[id, [field, value, ...]], ...]`.
+fn parse_entries(value: &Value) -> Result<Vec<StreamEntry>> {
+    as_bulk(value)?
+        .iter()
+        .map(|entry| {
+            let entry = as_bulk(entry)?;
+            let id = as_string(entry.first().context("entry without id")?)?;
+            let event = match entry.get(1) {
+                Some(Value::Bulk(fields)) => fields
+                    .chunks(2)
+                    .find(|field| {
+                        field
+                            .first()
+                            .and_then(|name| as_string(name).ok())
+                            .as_deref()
+                            == Some(EVENT_FIELD)
+                    })
+                    .and_then(|field| match field.get(1) {
+                        Some(Value::Data(data)) => Some(data.clone()),
+                        _ => None,
+                    }),
+                // the entry was deleted while it was pending
+                _ => None,
+            };
+            Ok(StreamEntry { id, event })
+        })
+        .collect()
+}
+
+/// Parses the reply of `XREADGROUP`, which is `nil` if the command timed out.
+pub fn parse_read_reply(value: &Value) -> Result<Vec<(String, Vec<StreamEntry>)>> {
+    match value {
+        Value::Nil => Ok(vec![]),
+        value => as_bulk(value)?
+            .iter()
+            .map(|stream| {
+                let stream = as_bulk(stream)?;
+                let name = as_string(stream.first().context("stream without name")?)?;
+                let entries = parse_entries(stream.get(1).context("stream without entries")?)?;
+                Ok((name, entries))
+            })
+            .collect(),
+    }
+}
+
+/// Parses the reply of `XAUTOCLAIM` and returns the cursor for the next call and the claimed entries.
+pub fn parse_claim_reply(value: &Value) -> Result<(String, Vec<StreamEntry>)> {
+    let reply = as_bulk(value)?;
+    let cursor = as_string(reply.first().context("claim reply without cursor")?)?;
+    let entries = parse_entries(reply.get(1).context("claim reply without entries")?)?;
+    Ok((cursor, entries))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data(value: &str) -> Value {
+        Value::Data(value.as_bytes().to_vec())
+    }
+
+    fn entry(id: &str, event: &str) -> Value {
+        Value::Bulk(vec![
+            data(id),
+            Value::Bulk(vec![
+                data("other"),
+                data("x"),
+                data(EVENT_FIELD),
+                data(event),
+            ]),
+        ])
+    }
+
+    #[test]
+    fn parse_read_replies() -> Result<()> {
+        assert_eq!(parse_read_reply(&Value::Nil)?, vec![]);
+        let reply = Value::Bulk(vec![Value::Bulk(vec![
+            data("events"),
+            Value::Bulk(vec![entry("1-0", "{}"), entry("2-0", "[]")]),
+        ])]);
+        assert_eq!(
+            parse_read_reply(&reply)?,
+            vec![(
+                "events".to_string(),
+                vec![
+                    StreamEntry {
+                        id: "1-0".to_string(),
+                        event: Some(b"{}".to_vec()),
+                    },
+                    StreamEntry {
+                        id: "2-0".to_string(),
+                        event: Some(b"[]".to_vec()),
+                    },
+                ]
+            )]
+        );
+        assert!(parse_read_reply(&Value::Okay).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn parse_claim_replies() -> Result<()> {
+        let reply = Value::Bulk(vec![
+            data("3-0"),
+            Value::Bulk(vec![
+                entry("1-0", "{}"),
+                Value::Bulk(vec![data("2-0"), Value::Nil]),
+            ]),
+            Value::Bulk(vec![]),
+        ]);
+        let (cursor, entries) = parse_claim_reply(&reply)?;
+        assert_eq!(cursor, "3-0");
+        assert_eq!(entries[0].event, Some(b"{}".to_vec()));
+        assert_eq!(
+            entries[1],
+            StreamEntry {
+                id: "2-0".to_string(),
+                event: None,
+            }
+        );
+        Ok(())
+    }
+}
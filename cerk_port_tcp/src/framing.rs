@@ -0,0 +1,148 @@
+use anyhow::{Context, Result};
+use std::io::{BufRead, ErrorKind, Read, Write};
+
+/// The size of the length prefix in bytes.
+const LENGTH_PREFIX_SIZE: usize = 4;
+
+/// Separates the events in a TCP stream.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Framing {
+    /// every event is terminated by a newline (`\n`)
+    NewLine,
+    /// every event is prefixed with its length as 32 bit unsigned integer in network byte order
+    LengthPrefixed,
+}
+
+impl Framing {
+    pub fn parse(name: &str) -> Result<Framing> {
+        match name {
+            "newline" => Ok(Framing::NewLine),
+            "length_prefixed" => Ok(Framing::LengthPrefixed),
+            _ => bail!("framing {} is not supported", name),
+        }
+    }
+
+    /// Reads the next frame, `None` if the stream is closed.
+    pub fn read_frame<R: BufRead>(
+        &self,
+        reader: &mut R,
+        max_frame_size: usize,
+    ) -> Result<Option<Vec<u8>>> {
+        match self {
+            Framing::NewLine => {
+                let mut frame = vec![];
+                // a frame of max_frame_size bytes plus the newline is accepted
+                let read = (&mut *reader)
+                    .take(max_frame_size as u64 + 1)
+                    .read_until(b'\n', &mut frame)?;
+                if read == 0 {
+                    return Ok(None);
+                }
+                if frame.last() == Some(&b'\n') {
+                    frame.pop();
+                    if frame.last() == Some(&b'\r') {
+                        frame.pop();
+                    }
+                } else if frame.len() > max_frame_size {
+                    bail!("frame is larger than {} bytes", max_frame_size);
+                }
+                Ok(Some(frame))
+            }
+            Framing::LengthPrefixed => {
+                let mut prefix = [0u8; LENGTH_PREFIX_SIZE];
+                if let Err(e) = reader.read_exact(&mut prefix) {
+                    return match e.kind() {
+                        ErrorKind::UnexpectedEof => Ok(None),
+                        _ => Err(e.into()),
+                    };
+                }
+                let size = u32::from_be_bytes(prefix) as usize;
+                if size > max_frame_size {
+                    bail!(
+                        "frame of {} bytes is larger than {} bytes",
+                        size,
+                        max_frame_size
+                    );
+                }
+                let mut frame = vec![0u8; size];
+                reader
+                    .read_exact(&mut frame)
+                    .context("stream closed within a frame")?;
+                Ok(Some(frame))
+            }
+        }
+    }
+
+    pub fn write_frame<W: Write>(&self, writer: &mut W, frame: &[u8]) -> Result<()> {
+        match self {
+            Framing::NewLine => {
+                if frame.contains(&b'\n') {
+                    bail!("newline framed frames must not contain a newline");
+                }
+                writer.write_all(frame)?;
+                writer.write_all(b"\n")?;
+            }
+            Framing::LengthPrefixed => {
+                let size = u32::try_from(frame.len()).context("frame is too large")?;
+                writer.write_all(&size.to_be_bytes())?;
+                writer.write_all(frame)?;
+            }
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn read_all(framing: Framing, bytes: Vec<u8>, max_frame_size: usize) -> Result<Vec<Vec<u8>>> {
+        let mut reader = Cursor::new(bytes);
+        let mut frames = vec![];
+        while let Some(frame) = framing.read_frame(&mut reader, max_frame_size)? {
+            frames.push(frame);
+        }
+        Ok(frames)
+    }
+
+    #[test]
+    fn newline_frames() -> Result<()> {
+        let mut bytes = vec![];
+        Framing::NewLine.write_frame(&mut bytes, b"{}")?;
+        bytes.extend_from_slice(b"{\"a\":1}\r\nlast");
+        assert_eq!(
+            read_all(Framing::NewLine, bytes, 10)?,
+            vec![b"{}".to_vec(), b"{\"a\":1}".to_vec(), b"last".to_vec()]
+        );
+        assert!(Framing::NewLine.write_frame(&mut vec![], b"a\nb").is_err());
+        assert!(read_all(Framing::NewLine, b"0123456789abc\n".to_vec(), 10).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn length_prefixed_frames() -> Result<()> {
+        let mut bytes = vec![];
+        Framing::LengthPrefixed.write_frame(&mut bytes, b"a\nb")?;
+        Framing::LengthPrefixed.write_frame(&mut bytes, b"")?;
+        assert_eq!(&bytes[..4], &[0, 0, 0, 3]);
+        assert_eq!(
+            read_all(Framing::LengthPrefixed, bytes.clone(), 10)?,
+            vec![b"a\nb".to_vec(), vec![]]
+        );
+        assert!(read_all(Framing::LengthPrefixed, bytes.clone(), 2).is_err());
+        assert!(read_all(Framing::LengthPrefixed, bytes[..5].to_vec(), 10).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!(Framing::parse("newline").unwrap(), Framing::NewLine);
+        assert_eq!(
+            Framing::parse("length_prefixed").unwrap(),
+            Framing::LengthPrefixed
+        );
+        assert!(Framing::parse("xml").is_err());
+    }
+}
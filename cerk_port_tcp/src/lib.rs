@@ -0,0 +1,77 @@
+/*!
+
+This is a package for [CERK](https://github.com/ce-rust/cerk).
+CERK is an open source [CloudEvents](https://github.com/cloudevents/spec) Router written in Rust with a MicroKernel architecture.
+
+# Introduction
+
+CERK lets you route your [CloudEvents](https://github.com/cloudevents/spec) between different different ports.
+Ports are transport layer bindings over which CloudEvents can be exchanged.
+It is built with modularity and portability in mind.
+
+# Components
+
+CERK comes with a couple of prefabricated components, but implementing custom components is easy.
+
+A good overview is provided on [GitHub](https://github.com/ce-rust/cerk/).
+
+# This Crate: TCP Ports
+
+These ports serve a TCP server and read or write JSON encoded CloudEvents from/to all connected clients,
+like the [UNIX Socket ports](https://github.com/ce-rust/cerk/tree/master/cerk_port_unix_socket/) do locally.
+
+The ports are:
+
+* port_input_tcp_json: reads the events of all clients and sends them to the Kernel
+* port_output_tcp_json: writes the routed events to all clients
+
+Every client is handled in its own thread.
+The output port processes an event successfully if at least one client received it, otherwise it failed transiently.
+Clients which do not read their events are disconnected after five seconds.
+
+The input port does not support any `DeliveryGuarantee` other than `BestEffort`.
+
+## Framing
+
+| Name              | Description                                                                          |
+|-------------------|--------------------------------------------------------------------------------------|
+| `newline`         | every event is terminated by a newline (`\n`), the events must not contain newlines |
+| `length_prefixed` | every event is prefixed with its length as 32 bit unsigned integer (big endian)      |
+
+## Configurations
+
+The ports expect a `Config::String` with the address to listen on, e.g., `Config::String(String::from("0.0.0.0:9000"))`,
+or a `Config::HashMap` with the following options:
+
+| Name             | Type   | Default   | Description                                                       |
+|------------------|--------|-----------|-------------------------------------------------------------------|
+| `address`        | String |           | the address to listen on, e.g., `0.0.0.0:9000`                    |
+| `framing`        | String | `newline` | `newline` or `length_prefixed`                                    |
+| `max_frame_size` | u32    | `1048576` | the maximum size of a received event in bytes                     |
+| `tls_cert_path`  | String |           | the PEM encoded certificate (chain), enables TLS                  |
+| `tls_key_path`   | String |           | the PEM encoded PKCS #8 private key, required with `tls_cert_path` |
+
+## Local Test
+
+```bash
+echo '{"specversion":"1.0","id":"1","type":"test","source":"http://example.com"}' | nc localhost 9000
+openssl s_client -connect localhost:9000 -quiet
+```
+
+*/
+
+#![deny(missing_docs)]
+
+#[macro_use]
+extern crate log;
+
+#[macro_use]
+extern crate anyhow;
+
+mod framing;
+mod port_input_tcp;
+mod port_output_tcp;
+mod server;
+
+pub use self::port_input_tcp::{port_input_tcp_json_start, PORT_INPUT_TCP};
+pub use self::port_output_tcp::{port_output_tcp_json_start, PORT_OUTPUT_TCP};
@@ -0,0 +1,231 @@
+use crate::server::{build_config, handshake, Connection, Server, TcpConfig};
+use anyhow::Result;
+use cerk::kernel::{
+    BrokerEvent, CloudEventRoutingArgs, Config, HealthCheckRequest, HealthCheckResponse,
+    HealthCheckStatus, IncomingCloudEvent,
+};
+use cerk::runtime::channel::{BoxedReceiver, BoxedSender};
+use cerk::runtime::{InternalServerFn, InternalServerFnRefStatic, InternalServerId};
+use cloudevents::event::Event;
+use std::io::BufReader;
+use std::thread;
+use std::time::Duration;
+use uuid::Uuid;
+
+fn read_events(
+    id: &InternalServerId,
+    connection: Connection,
+    config: &TcpConfig,
+    sender_to_kernel: &BoxedSender,
+) {
+    let mut reader = BufReader::new(connection.stream);
+    loop {
+        match config
+            .framing
+            .read_frame(&mut reader, config.max_frame_size)
+        {
+            Ok(Some(frame)) => match serde_json::from_slice::<Event>(&frame) {
+                Ok(cloud_event) => {
+                    debug!("{} received event from {}", id, connection.peer);
+                    sender_to_kernel.send(BrokerEvent::IncomingCloudEvent(IncomingCloudEvent {
+                        routing_id: Uuid::new_v4().to_string(),
+                        incoming_id: id.clone(),
+                        cloud_event,
+                        args: CloudEventRoutingArgs::default(),
+                    }))
+                }
+                Err(err) => {
+                    error!("{} while converting frame to CloudEvent: {:?}", id, err);
+                }
+            },
+            Ok(None) => break,
+            Err(err) => {
+                // the stream can not be resynchronized after a framing error
+                error!("{} failed to read from {} {:?}", id, connection.peer, err);
+                break;
+            }
+        }
+    }
+    debug!("{} client {} disconnected", id, connection.peer);
+}
+
+fn accept_clients(id: &InternalServerId, server: &Server, sender_to_kernel: &BoxedSender) {
+    let clients = match server.accept() {
+        Ok(clients) => clients,
+        Err(e) => {
+            error!("{} failed to accept clients {:?}", id, e);
+            return;
+        }
+    };
+    // every client is read in its own thread
+    for (socket, peer) in clients {
+        let id = id.clone();
+        let config = server.config.clone();
+        let sender_to_kernel = sender_to_kernel.clone_boxed();
+        thread::spawn(move || match handshake(socket, peer, &config) {
+            Ok(connection) => read_events(&id, connection, &config, &sender_to_kernel),
+            Err(e) => warn!("{} client {} failed to connect {:?}", id, peer, e),
+        });
+    }
+}
+
+fn update_config(config: &Config) -> Result<Server> {
+    Server::bind(build_config(config)?)
+}
+
+fn check_health(
+    event: HealthCheckRequest,
+    server: &Option<Server>,
+    sender_to_kernel: &BoxedSender,
+) {
+    let status = match server {
+        Some(_) => HealthCheckStatus::Healthy,
+        None => HealthCheckStatus::Unhealthy("server is not started".to_string()),
+    };
+    sender_to_kernel.send(BrokerEvent::HealthCheckResponse(HealthCheckResponse {
+        status,
+        destination_id: event.sender_id,
+        id: event.id,
+        sender_id: event.destination_id,
+    }))
+}
+
+/// This is the main function to start the port.
+///
+/// This port reads CloudEvents from TCP clients and sends them to the Kernel.
+pub fn port_input_tcp_json_start(
+    id: InternalServerId,
+    inbox: BoxedReceiver,
+    sender_to_kernel: BoxedSender,
+) {
+    info!("start input JSON over TCP port with id {}", id);
+    let mut server: Option<Server> = None;
+
+    loop {
+        if let Some(broker_event) = inbox.receive_timeout(Duration::from_millis(100)) {
+            match broker_event {
+                BrokerEvent::Init => {
+                    info!("{} initiated", id);
+                }
+                BrokerEvent::ConfigUpdated(config, _) => {
+                    info!("{} received ConfigUpdated", id);
+                    // the previous listener has to be closed before the address can be reused
+                    server = None;
+                    match update_config(&config) {
+                        Ok(s) => server = Some(s),
+                        Err(e) => error!("{} ConfigUpdated failed {:?}", id, e),
+                    }
+                }
+                BrokerEvent::HealthCheckRequest(event) => {
+                    check_health(event, &server, &sender_to_kernel)
+                }
+                broker_event => warn!("event {} not implemented", broker_event),
+            }
+        }
+
+        if let Some(server) = server.as_ref() {
+            accept_clients(&id, server, &sender_to_kernel);
+        }
+    }
+}
+
+/// This is the pointer for the main function to start the port.
+pub static PORT_INPUT_TCP: InternalServerFnRefStatic =
+    &(port_input_tcp_json_start as InternalServerFn);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::framing::Framing;
+    use crate::server::tests::{connect, free_address, tls_config};
+    use cerk_runtime_threading::channel::new_channel_with_size;
+    use cloudevents::{AttributesReader, EventBuilder, EventBuilderV10};
+    use std::collections::HashSet;
+
+    const ID: &str = "port-id";
+
+    /// The port panics as soon as the returned sender is dropped.
+    fn start(config: Config) -> (BoxedSender, BoxedReceiver) {
+        let (send_to_port, recv) = new_channel_with_size(1);
+        let (send, recv_from_port) = new_channel_with_size(10);
+        thread::spawn(move || {
+            PORT_INPUT_TCP(ID.to_string(), recv, send);
+        });
+        send_to_port.send(BrokerEvent::ConfigUpdated(config, ID.to_string()));
+        (send_to_port, recv_from_port)
+    }
+
+    fn event(id: &str) -> Vec<u8> {
+        let event = EventBuilderV10::new()
+            .id(id)
+            .ty("test")
+            .source("http://example.com")
+            .build()
+            .unwrap();
+        serde_json::to_vec(&event).unwrap()
+    }
+
+    fn receive_ids(
+        recv_from_port: &BoxedReceiver,
+        count: usize,
+    ) -> (HashSet<String>, HashSet<String>) {
+        let mut ids = HashSet::new();
+        let mut routing_ids = HashSet::new();
+        for _ in 0..count {
+            match recv_from_port.receive_timeout(Duration::from_secs(5)) {
+                Some(BrokerEvent::IncomingCloudEvent(e)) => {
+                    assert_eq!(e.incoming_id, ID);
+                    ids.insert(e.cloud_event.id().to_string());
+                    routing_ids.insert(e.routing_id);
+                }
+                _ => panic!("expected IncomingCloudEvent"),
+            }
+        }
+        (ids, routing_ids)
+    }
+
+    #[test]
+    fn receive_from_concurrent_clients() {
+        let address = free_address();
+        let (_send_to_port, recv_from_port) = start(Config::String(address.clone()));
+        let mut first = connect(&address, false);
+        let mut second = connect(&address, false);
+        Framing::NewLine
+            .write_frame(&mut first, &event("1"))
+            .unwrap();
+        Framing::NewLine
+            .write_frame(&mut second, b"invalid")
+            .unwrap();
+        Framing::NewLine
+            .write_frame(&mut second, &event("2"))
+            .unwrap();
+        Framing::NewLine
+            .write_frame(&mut first, &event("3"))
+            .unwrap();
+
+        let (ids, routing_ids) = receive_ids(&recv_from_port, 3);
+        assert_eq!(ids, ["1", "2", "3"].iter().map(|s| s.to_string()).collect());
+        assert_eq!(routing_ids.len(), 3);
+    }
+
+    #[test]
+    fn receive_length_prefixed_over_tls() {
+        let address = free_address();
+        let (_send_to_port, recv_from_port) = start(tls_config(&address, "length_prefixed"));
+        let mut client = connect(&address, true);
+        // length prefixed frames may contain newlines
+        let pretty = serde_json::to_vec_pretty(
+            &serde_json::from_slice::<serde_json::Value>(&event("1")).unwrap(),
+        )
+        .unwrap();
+        Framing::LengthPrefixed
+            .write_frame(&mut client, &pretty)
+            .unwrap();
+        Framing::LengthPrefixed
+            .write_frame(&mut client, &event("2"))
+            .unwrap();
+
+        let (ids, _) = receive_ids(&recv_from_port, 2);
+        assert_eq!(ids, ["1", "2"].iter().map(|s| s.to_string()).collect());
+    }
+}
@@ -0,0 +1,302 @@
+use crate::server::{build_config, handshake, Connection, Server};
+use anyhow::Result;
+use cerk::kernel::{
+    BrokerEvent, Config, HealthCheckRequest, HealthCheckResponse, HealthCheckStatus,
+    OutgoingCloudEvent, OutgoingCloudEventProcessed, ProcessingResult,
+};
+use cerk::runtime::channel::{BoxedReceiver, BoxedSender};
+use cerk::runtime::{InternalServerFn, InternalServerFnRefStatic, InternalServerId};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// A client which does not read its events within this time is disconnected.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+
+type Clients = Arc<Mutex<Vec<Connection>>>;
+
+struct Output {
+    server: Server,
+    clients: Clients,
+}
+
+fn accept_clients(id: &InternalServerId, output: &Output) {
+    let clients = match output.server.accept() {
+        Ok(clients) => clients,
+        Err(e) => {
+            error!("{} failed to accept clients {:?}", id, e);
+            return;
+        }
+    };
+    // the handshake is done in a separate thread, so a slow client does not block the port
+    for (socket, peer) in clients {
+        let id = id.clone();
+        let config = output.server.config.clone();
+        let clients = output.clients.clone();
+        thread::spawn(move || {
+            let connection = handshake(socket, peer, &config).and_then(|connection| {
+                connection.socket.set_write_timeout(Some(WRITE_TIMEOUT))?;
+                Ok(connection)
+            });
+            match connection {
+                Ok(connection) => clients.lock().unwrap().push(connection),
+                Err(e) => warn!("{} client {} failed to connect {:?}", id, peer, e),
+            }
+        });
+    }
+}
+
+/// Writes the event to all connected clients, the clients which fail are disconnected.
+///
+/// The event is processed successfully if at least one client received it.
+fn write_to_clients(
+    id: &InternalServerId,
+    output: &Output,
+    event: &OutgoingCloudEvent,
+) -> ProcessingResult {
+    let frame = match serde_json::to_vec(&event.cloud_event) {
+        Ok(frame) => frame,
+        Err(e) => {
+            error!("{} failed to serialize event {:?}", id, e);
+            return ProcessingResult::PermanentError;
+        }
+    };
+    let framing = output.server.config.framing;
+    let mut clients = output.clients.lock().unwrap();
+    clients.retain_mut(
+        |client| match framing.write_frame(&mut client.stream, &frame) {
+            Ok(()) => true,
+            Err(e) => {
+                debug!("{} failed to write to client {} {:?}", id, client.peer, e);
+                false
+            }
+        },
+    );
+    if clients.is_empty() {
+        warn!("{} has no client for {}", id, event.routing_id);
+        ProcessingResult::TransientError
+    } else {
+        ProcessingResult::Successful
+    }
+}
+
+fn send_event_out(
+    id: &InternalServerId,
+    output: &Option<Output>,
+    event: &OutgoingCloudEvent,
+    sender_to_kernel: &BoxedSender,
+) {
+    debug!("{} cloudevent received", id);
+    let result = match output {
+        Some(output) => {
+            // clients which connected since the last poll receive the event as well
+            accept_clients(id, output);
+            write_to_clients(id, output, event)
+        }
+        None => {
+            error!("{} is not configured", id);
+            ProcessingResult::PermanentError
+        }
+    };
+    if event.args.delivery_guarantee.requires_acknowledgment() {
+        sender_to_kernel.send(BrokerEvent::OutgoingCloudEventProcessed(
+            OutgoingCloudEventProcessed {
+                result,
+                routing_id: event.routing_id.to_string(),
+                sender_id: id.clone(),
+            },
+        ));
+    }
+}
+
+fn update_config(config: &Config) -> Result<Output> {
+    Ok(Output {
+        server: Server::bind(build_config(config)?)?,
+        clients: Arc::new(Mutex::new(vec![])),
+    })
+}
+
+fn check_health(
+    event: HealthCheckRequest,
+    output: &Option<Output>,
+    sender_to_kernel: &BoxedSender,
+) {
+    let status = match output {
+        Some(_) => HealthCheckStatus::Healthy,
+        None => HealthCheckStatus::Unhealthy("server is not started".to_string()),
+    };
+    sender_to_kernel.send(BrokerEvent::HealthCheckResponse(HealthCheckResponse {
+        status,
+        destination_id: event.sender_id,
+        id: event.id,
+        sender_id: event.destination_id,
+    }))
+}
+
+/// This is the main function to start the port.
+///
+/// This port writes CloudEvents to all connected TCP clients.
+pub fn port_output_tcp_json_start(
+    id: InternalServerId,
+    inbox: BoxedReceiver,
+    sender_to_kernel: BoxedSender,
+) {
+    info!("start output JSON over TCP port with id {}", id);
+    let mut output: Option<Output> = None;
+
+    loop {
+        if let Some(broker_event) = inbox.receive_timeout(Duration::from_millis(100)) {
+            match broker_event {
+                BrokerEvent::Init => {
+                    info!("{} initiated", id);
+                }
+                BrokerEvent::ConfigUpdated(config, _) => {
+                    info!("{} received ConfigUpdated", id);
+                    // the previous listener and its clients are closed
+                    output = None;
+                    match update_config(&config) {
+                        Ok(o) => output = Some(o),
+                        Err(e) => error!("{} ConfigUpdated failed {:?}", id, e),
+                    }
+                }
+                BrokerEvent::OutgoingCloudEvent(event) => {
+                    send_event_out(&id, &output, &event, &sender_to_kernel)
+                }
+                BrokerEvent::HealthCheckRequest(event) => {
+                    check_health(event, &output, &sender_to_kernel)
+                }
+                broker_event => warn!("event {} not implemented", broker_event),
+            }
+        }
+
+        if let Some(output) = output.as_ref() {
+            accept_clients(&id, output);
+        }
+    }
+}
+
+/// This is the pointer for the main function to start the port.
+pub static PORT_OUTPUT_TCP: InternalServerFnRefStatic =
+    &(port_output_tcp_json_start as InternalServerFn);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::framing::Framing;
+    use crate::server::tests::{connect, free_address, tls_config};
+    use crate::server::Stream;
+    use cerk::kernel::{CloudEventRoutingArgs, DeliveryGuarantee};
+    use cerk_runtime_threading::channel::new_channel_with_size;
+    use cloudevents::{AttributesReader, Event, EventBuilder, EventBuilderV10};
+    use std::io::BufReader;
+
+    const ID: &str = "port-id";
+
+    fn build_event(id: &str) -> OutgoingCloudEvent {
+        OutgoingCloudEvent {
+            routing_id: id.to_string(),
+            cloud_event: EventBuilderV10::new()
+                .id(id)
+                .ty("test")
+                .source("http://example.com")
+                .build()
+                .unwrap(),
+            destination_id: ID.to_string(),
+            args: CloudEventRoutingArgs {
+                delivery_guarantee: DeliveryGuarantee::AtLeastOnce,
+            },
+        }
+    }
+
+    fn start() -> (BoxedSender, BoxedReceiver) {
+        let (send_to_port, recv) = new_channel_with_size(1);
+        let (send, recv_from_port) = new_channel_with_size(1);
+        thread::spawn(move || {
+            PORT_OUTPUT_TCP(ID.to_string(), recv, send);
+        });
+        (send_to_port, recv_from_port)
+    }
+
+    fn send(
+        send_to_port: &BoxedSender,
+        recv_from_port: &BoxedReceiver,
+        id: &str,
+    ) -> ProcessingResult {
+        send_to_port.send(BrokerEvent::OutgoingCloudEvent(build_event(id)));
+        match recv_from_port.receive_timeout(Duration::from_secs(5)) {
+            Some(BrokerEvent::OutgoingCloudEventProcessed(e)) => {
+                assert_eq!(e.routing_id, id);
+                e.result
+            }
+            _ => panic!("expected OutgoingCloudEventProcessed"),
+        }
+    }
+
+    /// Sends events until the clients are connected, the handshake is done asynchronously.
+    fn send_until_connected(send_to_port: &BoxedSender, recv_from_port: &BoxedReceiver) {
+        for _ in 0..50 {
+            if send(send_to_port, recv_from_port, "connect") == ProcessingResult::Successful {
+                return;
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+        panic!("client did not connect")
+    }
+
+    fn read_event(framing: Framing, reader: &mut BufReader<Box<dyn Stream>>) -> Event {
+        let frame = framing.read_frame(reader, 1024 * 1024).unwrap().unwrap();
+        serde_json::from_slice(&frame).unwrap()
+    }
+
+    #[test]
+    fn send_without_config_receive_nack() {
+        let (send_to_port, recv_from_port) = start();
+        assert_eq!(
+            send(&send_to_port, &recv_from_port, "1"),
+            ProcessingResult::PermanentError
+        );
+    }
+
+    #[test]
+    fn send_to_all_clients() {
+        let address = free_address();
+        let (send_to_port, recv_from_port) = start();
+        send_to_port.send(BrokerEvent::ConfigUpdated(
+            Config::String(address.clone()),
+            ID.to_string(),
+        ));
+        assert_eq!(
+            send(&send_to_port, &recv_from_port, "0"),
+            ProcessingResult::TransientError
+        );
+
+        let mut first = BufReader::new(connect(&address, false));
+        send_until_connected(&send_to_port, &recv_from_port);
+        let mut second = BufReader::new(connect(&address, false));
+        thread::sleep(Duration::from_millis(200));
+        assert_eq!(
+            send(&send_to_port, &recv_from_port, "1"),
+            ProcessingResult::Successful
+        );
+
+        while read_event(Framing::NewLine, &mut first).id() == "connect" {}
+        assert_eq!(read_event(Framing::NewLine, &mut second).id(), "1");
+    }
+
+    #[test]
+    fn send_length_prefixed_over_tls() {
+        let address = free_address();
+        let (send_to_port, recv_from_port) = start();
+        send_to_port.send(BrokerEvent::ConfigUpdated(
+            tls_config(&address, "length_prefixed"),
+            ID.to_string(),
+        ));
+        let mut client = BufReader::new(connect(&address, true));
+        send_until_connected(&send_to_port, &recv_from_port);
+        assert_eq!(
+            send(&send_to_port, &recv_from_port, "1"),
+            ProcessingResult::Successful
+        );
+        while read_event(Framing::LengthPrefixed, &mut client).id() == "connect" {}
+    }
+}
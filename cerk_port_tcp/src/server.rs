@@ -0,0 +1,220 @@
+use crate::framing::Framing;
+use anyhow::{Context, Result};
+use cerk::kernel::{Config, ConfigHelpers};
+use native_tls::{Identity, TlsAcceptor};
+use std::io::{ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// A client has to finish the TLS handshake within this time.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+const DEFAULT_MAX_FRAME_SIZE: u32 = 1024 * 1024;
+
+/// A plain or TLS encrypted stream.
+pub trait Stream: Read + Write + Send {}
+
+impl<T: Read + Write + Send> Stream for T {}
+
+pub struct TcpConfig {
+    pub address: SocketAddr,
+    pub framing: Framing,
+    pub max_frame_size: usize,
+    pub tls: Option<TlsAcceptor>,
+}
+
+/// A connected client, the TLS handshake is already done.
+pub struct Connection {
+    pub peer: SocketAddr,
+    pub stream: Box<dyn Stream>,
+    /// the underlying socket, e.g., to set timeouts
+    pub socket: TcpStream,
+}
+
+fn resolve(address: &str) -> Result<SocketAddr> {
+    address
+        .to_socket_addrs()
+        .with_context(|| format!("failed to resolve {}", address))?
+        .next()
+        .with_context(|| format!("{} resolved to no address", address))
+}
+
+fn build_tls(config: &Config) -> Result<Option<TlsAcceptor>> {
+    let cert_path = config.get_op_val_string("tls_cert_path")?;
+    let key_path = config.get_op_val_string("tls_key_path")?;
+    match (cert_path, key_path) {
+        (Some(cert_path), Some(key_path)) => {
+            let cert = std::fs::read(&cert_path)
+                .with_context(|| format!("failed to read {}", cert_path))?;
+            let key =
+                std::fs::read(&key_path).with_context(|| format!("failed to read {}", key_path))?;
+            let identity = Identity::from_pkcs8(&cert, &key)
+                .context("failed to load the TLS certificate and key")?;
+            Ok(Some(TlsAcceptor::new(identity)?))
+        }
+        (None, None) => Ok(None),
+        _ => bail!("tls_cert_path and tls_key_path have to be set together"),
+    }
+}
+
+pub fn build_config(config: &Config) -> Result<TcpConfig> {
+    match config {
+        Config::String(address) => Ok(TcpConfig {
+            address: resolve(address)?,
+            framing: Framing::NewLine,
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE as usize,
+            tls: None,
+        }),
+        Config::HashMap(_) => Ok(TcpConfig {
+            address: resolve(
+                &config
+                    .get_op_val_string("address")?
+                    .context("address is required")?,
+            )?,
+            framing: match config.get_op_val_string("framing")? {
+                Some(framing) => Framing::parse(&framing)?,
+                None => Framing::NewLine,
+            },
+            max_frame_size: config
+                .get_op_val_u32("max_frame_size")?
+                .unwrap_or(DEFAULT_MAX_FRAME_SIZE) as usize,
+            tls: build_tls(config)?,
+        }),
+        _ => bail!("config has to be a string or a hash map"),
+    }
+}
+
+/// Listens for clients, the port polls for new clients between the broker events.
+pub struct Server {
+    listener: TcpListener,
+    pub config: Arc<TcpConfig>,
+}
+
+impl Server {
+    pub fn bind(config: TcpConfig) -> Result<Server> {
+        let listener = TcpListener::bind(config.address)
+            .with_context(|| format!("failed to bind to {}", config.address))?;
+        listener.set_nonblocking(true)?;
+        Ok(Server {
+            listener,
+            config: Arc::new(config),
+        })
+    }
+
+    /// Accepts all pending clients, the TLS handshake has to be done with [`handshake`].
+    pub fn accept(&self) -> Result<Vec<(TcpStream, SocketAddr)>> {
+        let mut clients = vec![];
+        loop {
+            match self.listener.accept() {
+                Ok((socket, peer)) => {
+                    debug!("client {} connected", peer);
+                    clients.push((socket, peer));
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(clients),
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+}
+
+/// Establishes the TLS session if TLS is configured, this blocks until the client finished the handshake.
+pub fn handshake(socket: TcpStream, peer: SocketAddr, config: &TcpConfig) -> Result<Connection> {
+    socket.set_nonblocking(false)?;
+    let stream: Box<dyn Stream> = match config.tls.as_ref() {
+        Some(tls) => {
+            socket.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+            socket.set_write_timeout(Some(HANDSHAKE_TIMEOUT))?;
+            let stream = tls
+                .accept(socket.try_clone()?)
+                .map_err(|e| anyhow!("TLS handshake failed: {}", e))?;
+            socket.set_read_timeout(None)?;
+            socket.set_write_timeout(None)?;
+            Box::new(stream)
+        }
+        None => Box::new(socket.try_clone()?),
+    };
+    Ok(Connection {
+        peer,
+        stream,
+        socket,
+    })
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use native_tls::{Certificate, TlsConnector};
+    use std::collections::HashMap;
+    use std::thread::sleep;
+
+    pub const CERT_PATH: &str = "./src/test_data/cert.pem";
+    pub const KEY_PATH: &str = "./src/test_data/key.pem";
+
+    pub fn free_address() -> String {
+        TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .to_string()
+    }
+
+    /// Connects to the port, retries until the port is listening.
+    pub fn connect(address: &str, tls: bool) -> Box<dyn Stream> {
+        for _ in 0..50 {
+            if let Ok(socket) = TcpStream::connect(address) {
+                if !tls {
+                    return Box::new(socket);
+                }
+                let cert = Certificate::from_pem(&std::fs::read(CERT_PATH).unwrap()).unwrap();
+                let connector = TlsConnector::builder()
+                    .add_root_certificate(cert)
+                    .build()
+                    .unwrap();
+                return Box::new(connector.connect("localhost", socket).unwrap());
+            }
+            sleep(Duration::from_millis(20));
+        }
+        panic!("failed to connect to {}", address)
+    }
+
+    pub fn tls_config(address: &str, framing: &str) -> Config {
+        let mut config = HashMap::new();
+        config.insert("address".to_string(), Config::String(address.to_string()));
+        config.insert("framing".to_string(), Config::String(framing.to_string()));
+        config.insert(
+            "tls_cert_path".to_string(),
+            Config::String(CERT_PATH.to_string()),
+        );
+        config.insert(
+            "tls_key_path".to_string(),
+            Config::String(KEY_PATH.to_string()),
+        );
+        Config::HashMap(config)
+    }
+
+    #[test]
+    fn build_configs() -> Result<()> {
+        let config = build_config(&Config::String("127.0.0.1:9000".to_string()))?;
+        assert_eq!(config.address, "127.0.0.1:9000".parse()?);
+        assert_eq!(config.framing, Framing::NewLine);
+        assert!(config.tls.is_none());
+
+        let config = build_config(&tls_config("127.0.0.1:9000", "length_prefixed"))?;
+        assert_eq!(config.framing, Framing::LengthPrefixed);
+        assert_eq!(config.max_frame_size, 1024 * 1024);
+        assert!(config.tls.is_some());
+
+        let mut incomplete = HashMap::new();
+        incomplete.insert(
+            "address".to_string(),
+            Config::String("127.0.0.1:9000".into()),
+        );
+        incomplete.insert(
+            "tls_cert_path".to_string(),
+            Config::String(CERT_PATH.into()),
+        );
+        assert!(build_config(&Config::HashMap(incomplete)).is_err());
+        assert!(build_config(&Config::HashMap(HashMap::new())).is_err());
+        Ok(())
+    }
+}
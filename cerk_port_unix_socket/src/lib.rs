@@ -24,6 +24,10 @@ The ports are:
 * port_input_unix_socket_json
 * port_output_unix_socket_json
 
+The input port accepts many concurrent clients.
+If its delivery guarantee requires acknowledgments, it writes an ack or nack line per event back to the client,
+see [port_input_unix_socket_json_start](fn.port_input_unix_socket_json_start.html).
+
 */
 
 #![deny(missing_docs)]
@@ -1,71 +1,268 @@
-use cerk::kernel::{BrokerEvent, CloudEventRoutingArgs, Config, IncomingCloudEvent};
+use anyhow::{Context, Result};
+use cerk::kernel::{
+    BrokerEvent, CloudEventMessageRoutingId, CloudEventRoutingArgs, Config, ConfigHelpers,
+    DeliveryGuarantee, IncomingCloudEvent, ProcessingResult,
+};
 use cerk::runtime::channel::{BoxedReceiver, BoxedSender};
 use cerk::runtime::{InternalServerFn, InternalServerFnRefStatic, InternalServerId};
 use cloudevents::event::Event;
-use serde_json;
-use std::io::{BufRead, BufReader};
+use cloudevents::AttributesReader;
+use serde_derive::Serialize;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::net::Shutdown;
 use std::os::unix::net::{UnixListener, UnixStream};
+use std::sync::mpsc::{sync_channel, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread;
 use std::time::Duration;
+use uuid::Uuid;
 
-fn liten_to_stream(
-    id: &InternalServerId,
-    listener: &UnixListener,
-    mut stream: Option<BufReader<UnixStream>>,
-    sender_to_kernel: &BoxedSender,
-    max_tries: usize,
-) -> Option<BufReader<UnixStream>> {
-    if max_tries == 0 {
-        panic!("too many failures while trying to connect to stream");
-    }
-    debug!("listen to stream...");
-    match stream.as_mut() {
-        None => match listener.accept() {
-            Ok((socket, _)) => {
-                let stream = BufReader::new(socket);
-                liten_to_stream(id, listener, Some(stream), sender_to_kernel, max_tries - 1)
+/// The responses which are queued per client, a client which does not read them is disconnected.
+const CLIENT_QUEUE_SIZE: usize = 100;
+const WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The lines the port writes back to the clients if the delivery guarantee requires acknowledgments.
+#[derive(Debug, Serialize, PartialEq)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum ResponseLine {
+    /// the event with the id was processed successfully
+    Ack { id: String },
+    /// the event with the id could not be processed
+    Nack { id: String, result: String },
+    /// the line could not be parsed as CloudEvent
+    Error { error: String },
+}
+
+/// Queues the responses of a client, which are written by its own thread, so a slow client does not block the port.
+struct Writer {
+    sender: SyncSender<String>,
+    stream: UnixStream,
+}
+
+impl Writer {
+    fn new(id: &InternalServerId, stream: UnixStream) -> Result<Arc<Writer>> {
+        let (sender, receiver) = sync_channel::<String>(CLIENT_QUEUE_SIZE);
+        let mut thread_stream = stream.try_clone()?;
+        thread_stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+        let id = id.clone();
+        thread::spawn(move || {
+            for line in receiver {
+                if let Err(e) = thread_stream.write_all(line.as_bytes()) {
+                    warn!("{} failed to write to client, disconnect it {:?}", id, e);
+                    let _ = thread_stream.shutdown(Shutdown::Both);
+                    return;
+                }
             }
-            Err(err) => std::panic::panic_any(err),
-        },
-        Some(stream) => {
-            let mut line = String::new();
-
-            loop {
-                match stream.read_line(&mut line) {
-                    Ok(0) => break,
-                    Err(err) => {
-                        error!("{} read_line error {:?}", id, err);
-                        break;
-                    }
-                    Ok(_) => {
-                        debug!("{} received new line", id);
-                        match serde_json::from_str::<Event>(&line) {
-                            Ok(cloud_event) => {
-                                debug!("{} deserialized event successfully", id);
-                                sender_to_kernel.send(BrokerEvent::IncomingCloudEvent(
-                                    IncomingCloudEvent {
-                                        routing_id: id.clone(),
-                                        incoming_id: id.clone(),
-                                        cloud_event,
-                                        args: CloudEventRoutingArgs::default(),
-                                    },
-                                ))
-                            }
-                            Err(err) => {
-                                error!("{} while converting string to CloudEvent: {:?}", id, err);
-                            }
-                        }
-                    }
+        });
+        Ok(Arc::new(Writer { sender, stream }))
+    }
+
+    fn disconnect(&self) {
+        let _ = self.stream.shutdown(Shutdown::Both);
+    }
+}
+
+/// The connected clients, they are disconnected if the listener is replaced.
+type Clients = Arc<Mutex<HashMap<Uuid, UnixStream>>>;
+
+struct PendingAck {
+    event_id: String,
+    writer: Arc<Writer>,
+}
+
+type PendingAcks = Arc<Mutex<HashMap<CloudEventMessageRoutingId, PendingAck>>>;
+
+struct InputConfig {
+    socket_path: String,
+    delivery_guarantee: DeliveryGuarantee,
+}
+
+fn build_config(config: &Config) -> Result<InputConfig> {
+    match config {
+        Config::String(socket_path) => Ok(InputConfig {
+            socket_path: socket_path.clone(),
+            delivery_guarantee: DeliveryGuarantee::BestEffort,
+        }),
+        Config::HashMap(_) => Ok(InputConfig {
+            socket_path: config
+                .get_op_val_string("socket_path")?
+                .context("socket_path is required")?,
+            delivery_guarantee: match config.get_op_val_config("delivery_guarantee")? {
+                Some(c) => DeliveryGuarantee::try_from(c)?,
+                None => DeliveryGuarantee::BestEffort,
+            },
+        }),
+        _ => bail!("config has to be a string or a hash map"),
+    }
+}
+
+fn write_response(writer: &Writer, response: &ResponseLine) -> Result<()> {
+    let mut line = serde_json::to_string(response)?;
+    line.push('\n');
+    match writer.sender.try_send(line) {
+        Ok(()) => Ok(()),
+        Err(TrySendError::Full(_)) => {
+            writer.disconnect();
+            bail!("client does not read its responses and was disconnected")
+        }
+        Err(TrySendError::Disconnected(_)) => bail!("client disconnected"),
+    }
+}
+
+struct Client {
+    id: InternalServerId,
+    delivery_guarantee: DeliveryGuarantee,
+    sender_to_kernel: BoxedSender,
+    pending_acks: PendingAcks,
+}
+
+impl Client {
+    fn requires_acknowledgment(&self) -> bool {
+        self.delivery_guarantee.requires_acknowledgment()
+    }
+
+    fn receive_line(&self, line: &str, writer: &Arc<Writer>) -> Result<()> {
+        let cloud_event = match serde_json::from_str::<Event>(line) {
+            Ok(cloud_event) => cloud_event,
+            Err(err) => {
+                error!(
+                    "{} while converting string to CloudEvent: {:?}",
+                    self.id, err
+                );
+                if self.requires_acknowledgment() {
+                    write_response(
+                        writer,
+                        &ResponseLine::Error {
+                            error: err.to_string(),
+                        },
+                    )?;
                 }
-                line.clear();
+                return Ok(());
             }
-            None
+        };
+        debug!("{} deserialized event successfully", self.id);
+        let routing_id = Uuid::new_v4().to_string();
+        if self.requires_acknowledgment() {
+            self.pending_acks.lock().unwrap().insert(
+                routing_id.clone(),
+                PendingAck {
+                    event_id: cloud_event.id().to_string(),
+                    writer: writer.clone(),
+                },
+            );
         }
+        self.sender_to_kernel
+            .send(BrokerEvent::IncomingCloudEvent(IncomingCloudEvent {
+                routing_id,
+                incoming_id: self.id.clone(),
+                cloud_event,
+                args: CloudEventRoutingArgs {
+                    delivery_guarantee: self.delivery_guarantee,
+                },
+            }));
+        Ok(())
+    }
+
+    fn listen_to_stream(&self, stream: UnixStream) -> Result<()> {
+        let writer = Writer::new(&self.id, stream.try_clone()?)?;
+        let mut reader = BufReader::new(stream);
+        let mut line = String::new();
+        loop {
+            if reader.read_line(&mut line)? == 0 {
+                return Ok(());
+            }
+            debug!("{} received new line", self.id);
+            self.receive_line(&line, &writer)?;
+            line.clear();
+        }
+    }
+}
+
+fn accept_clients(
+    id: &InternalServerId,
+    listener: &UnixListener,
+    config: &InputConfig,
+    sender_to_kernel: &BoxedSender,
+    pending_acks: &PendingAcks,
+    clients: &Clients,
+) -> Result<()> {
+    loop {
+        let stream = match listener.accept() {
+            Ok((stream, _)) => stream,
+            Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+        stream.set_nonblocking(false)?;
+        let client = Client {
+            id: id.clone(),
+            delivery_guarantee: config.delivery_guarantee,
+            sender_to_kernel: sender_to_kernel.clone_boxed(),
+            pending_acks: pending_acks.clone(),
+        };
+        let client_id = Uuid::new_v4();
+        clients
+            .lock()
+            .unwrap()
+            .insert(client_id, stream.try_clone()?);
+        debug!("{} client connected", id);
+        // every client is read in its own thread
+        let clients = clients.clone();
+        thread::spawn(move || {
+            if let Err(err) = client.listen_to_stream(stream) {
+                error!("{} failed to read from stream {:?}", client.id, err);
+            }
+            clients.lock().unwrap().remove(&client_id);
+            debug!("{} client disconnected", client.id);
+        });
+    }
+}
+
+fn bind(config: &InputConfig) -> Result<UnixListener> {
+    let listener = UnixListener::bind(&config.socket_path)
+        .with_context(|| format!("failed to bind to {}", config.socket_path))?;
+    // the port polls for new clients between the broker events
+    listener.set_nonblocking(true)?;
+    Ok(listener)
+}
+
+/// Stops the reader threads of the clients of the replaced listener.
+fn disconnect_clients(clients: &Clients) {
+    for (_, stream) in clients.lock().unwrap().drain() {
+        let _ = stream.shutdown(Shutdown::Both);
     }
 }
 
+fn acknowledge(
+    pending_acks: &PendingAcks,
+    routing_id: &CloudEventMessageRoutingId,
+    result: ProcessingResult,
+) -> Result<()> {
+    let pending = pending_acks
+        .lock()
+        .unwrap()
+        .remove(routing_id)
+        .with_context(|| format!("pending ack with id={} not found", routing_id))?;
+    let response = match result {
+        ProcessingResult::Successful => ResponseLine::Ack {
+            id: pending.event_id,
+        },
+        result => ResponseLine::Nack {
+            id: pending.event_id,
+            result: result.to_string(),
+        },
+    };
+    write_response(&pending.writer, &response)
+        .context("client disconnected before the ack was sent")
+}
+
 /// This is the main function to start the port.
 ///
-/// This port reads CloudEvents form a UNIX Socket and sens them to the Kernel.
+/// This port reads CloudEvents from a UNIX Socket and sends them to the Kernel.
+/// Every line has to contain one JSON encoded CloudEvent.
+/// Many clients can be connected at the same time, each of them is read in its own thread.
+/// The clients are disconnected if the configuration is updated.
 ///
 /// # Configurations
 ///
@@ -74,13 +271,31 @@ fn liten_to_stream(
 ///
 /// e.g. `Config::String(String::from("path/to/the/socket"))`
 ///
-/// # Examples
+/// Alternatively, a `Config::HashMap` with the following options can be used:
 ///
-/// * [UNIX Socket Example](https://github.com/ce-rust/cerk/tree/master/examples/examples/src/unix_socket)
+/// | Name                 | Type   | Default | Description                                      |
+/// |----------------------|--------|---------|--------------------------------------------------|
+/// | `socket_path`        | String |         | the file path where the UNIX Socket is created   |
+/// | `delivery_guarantee` | u8     | `0`     | the delivery guarantee of the received events    |
 ///
-/// # Limitations
+/// # Acknowledgments
 ///
-/// * **reliability** this port does not support any `DeliveryGuarantee` other then `BestEffort` and so does never send a `IncomingCloudEventProcessed` message
+/// If the delivery guarantee requires acknowledgments, the port writes a JSON encoded response line
+/// back to the client as soon as an event is processed:
+///
+/// | Response                                                | Description                                      |
+/// |---------------------------------------------------------|--------------------------------------------------|
+/// | `{"type":"ack","id":"1"}`                               | the event with the id `1` was processed          |
+/// | `{"type":"nack","id":"1","result":"TransientError"}`    | the event with the id `1` could not be processed |
+/// | `{"type":"error","error":"..."}`                        | the line is not a valid CloudEvent               |
+///
+/// The responses are written in the order the events are processed, which can differ from the order they were sent.
+/// Every client has a queue of 100 responses, which is written by its own thread.
+/// A client is disconnected if the queue is full or a response could not be written within 5 seconds.
+///
+/// # Examples
+///
+/// * [UNIX Socket Example](https://github.com/ce-rust/cerk/tree/master/examples/examples/src/unix_socket)
 ///
 /// # open issues
 ///
@@ -92,8 +307,9 @@ pub fn port_input_unix_socket_json_start(
     sender_to_kernel: BoxedSender,
 ) {
     info!("start input JSON over unix socket port with id {}", id);
-    let mut listener: Option<UnixListener> = None;
-    let mut stream: Option<BufReader<UnixStream>> = None;
+    let mut listener: Option<(UnixListener, InputConfig)> = None;
+    let pending_acks: PendingAcks = Arc::new(Mutex::new(HashMap::new()));
+    let clients: Clients = Arc::new(Mutex::new(HashMap::new()));
 
     loop {
         if let Some(broker_event) = inbox.receive_timeout(Duration::from_millis(100)) {
@@ -103,19 +319,34 @@ pub fn port_input_unix_socket_json_start(
                 }
                 BrokerEvent::ConfigUpdated(config, _) => {
                     info!("{} received ConfigUpdated", id);
-                    match config {
-                        Config::String(socket_path) => {
-                            listener = Some(UnixListener::bind(socket_path).unwrap());
+                    match build_config(&config).and_then(|c| Ok((bind(&c)?, c))) {
+                        Ok(l) => {
+                            disconnect_clients(&clients);
+                            listener = Some(l)
                         }
-                        _ => error!("{} received invalid config", id),
-                    };
+                        Err(e) => error!("{} ConfigUpdated failed {:?}", id, e),
+                    }
+                }
+                BrokerEvent::IncomingCloudEventProcessed(routing_id, result) => {
+                    if let Err(e) = acknowledge(&pending_acks, &routing_id, result) {
+                        warn!("{} IncomingCloudEventProcessed was not handled {:?}", id, e)
+                    }
                 }
                 broker_event => warn!("event {} not implemented", broker_event),
             }
         }
 
-        if let Some(listener) = listener.as_ref() {
-            stream = liten_to_stream(&id, listener, stream, &sender_to_kernel, 10);
+        if let Some((listener, config)) = listener.as_ref() {
+            if let Err(e) = accept_clients(
+                &id,
+                listener,
+                config,
+                &sender_to_kernel,
+                &pending_acks,
+                &clients,
+            ) {
+                error!("{} failed to accept clients {:?}", id, e);
+            }
         }
     }
 }
@@ -123,3 +354,171 @@ pub fn port_input_unix_socket_json_start(
 /// This is the pointer for the main function to start the port.
 pub static PORT_INPUT_UNIX_SOCKET: InternalServerFnRefStatic =
     &(port_input_unix_socket_json_start as InternalServerFn);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cerk_runtime_threading::channel::new_channel_with_size;
+    use cloudevents::{EventBuilder, EventBuilderV10};
+    use std::collections::HashSet;
+
+    const ID: &str = "port-id";
+
+    fn socket_path() -> String {
+        std::env::temp_dir()
+            .join(format!("cerk-{}.sock", Uuid::new_v4()))
+            .to_string_lossy()
+            .to_string()
+    }
+
+    fn event_line(id: &str) -> String {
+        let event = EventBuilderV10::new()
+            .id(id)
+            .ty("test")
+            .source("http://example.com")
+            .build()
+            .unwrap();
+        format!("{}\n", serde_json::to_string(&event).unwrap())
+    }
+
+    /// The port panics as soon as the returned sender is dropped.
+    fn start(config: Config) -> (BoxedSender, BoxedReceiver) {
+        let (send_to_port, recv) = new_channel_with_size(1);
+        let (send, recv_from_port) = new_channel_with_size(10);
+        thread::spawn(move || {
+            PORT_INPUT_UNIX_SOCKET(ID.to_string(), recv, send);
+        });
+        send_to_port.send(BrokerEvent::ConfigUpdated(config, ID.to_string()));
+        (send_to_port, recv_from_port)
+    }
+
+    /// Connects to the port, retries until the socket is created.
+    fn connect(socket_path: &str) -> UnixStream {
+        for _ in 0..50 {
+            if let Ok(stream) = UnixStream::connect(socket_path) {
+                return stream;
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+        panic!("failed to connect to {}", socket_path)
+    }
+
+    fn receive_event(recv_from_port: &BoxedReceiver) -> IncomingCloudEvent {
+        match recv_from_port.receive_timeout(Duration::from_secs(5)) {
+            Some(BrokerEvent::IncomingCloudEvent(event)) => event,
+            _ => panic!("expected IncomingCloudEvent"),
+        }
+    }
+
+    #[test]
+    fn receive_from_concurrent_clients() {
+        let socket_path = socket_path();
+        let (_send_to_port, recv_from_port) = start(Config::String(socket_path.clone()));
+        let mut first = connect(&socket_path);
+        let mut second = connect(&socket_path);
+        first.write_all(event_line("1").as_bytes()).unwrap();
+        second.write_all(event_line("2").as_bytes()).unwrap();
+        first.write_all(event_line("3").as_bytes()).unwrap();
+
+        let mut ids = HashSet::new();
+        let mut routing_ids = HashSet::new();
+        for _ in 0..3 {
+            let event = receive_event(&recv_from_port);
+            assert_eq!(event.incoming_id, ID);
+            assert_eq!(event.args.delivery_guarantee, DeliveryGuarantee::BestEffort);
+            ids.insert(event.cloud_event.id().to_string());
+            routing_ids.insert(event.routing_id);
+        }
+        assert_eq!(ids, ["1", "2", "3"].iter().map(|s| s.to_string()).collect());
+        assert_eq!(routing_ids.len(), 3);
+        std::fs::remove_file(socket_path).unwrap();
+    }
+
+    #[test]
+    fn acknowledge_events() {
+        let socket_path = socket_path();
+        let mut config = HashMap::new();
+        config.insert(
+            "socket_path".to_string(),
+            Config::String(socket_path.clone()),
+        );
+        config.insert("delivery_guarantee".to_string(), Config::U8(2));
+        let (send_to_port, recv_from_port) = start(Config::HashMap(config));
+        let mut client = connect(&socket_path);
+        let mut responses = BufReader::new(client.try_clone().unwrap()).lines();
+
+        client.write_all(event_line("1").as_bytes()).unwrap();
+        client.write_all(event_line("2").as_bytes()).unwrap();
+        client.write_all(b"invalid\n").unwrap();
+        let first = receive_event(&recv_from_port);
+        let second = receive_event(&recv_from_port);
+        assert_eq!(
+            first.args.delivery_guarantee,
+            DeliveryGuarantee::AtLeastOnce
+        );
+        assert!(responses
+            .next()
+            .unwrap()
+            .unwrap()
+            .starts_with(r#"{"type":"error","error":"#));
+
+        send_to_port.send(BrokerEvent::IncomingCloudEventProcessed(
+            second.routing_id,
+            ProcessingResult::TransientError,
+        ));
+        send_to_port.send(BrokerEvent::IncomingCloudEventProcessed(
+            first.routing_id,
+            ProcessingResult::Successful,
+        ));
+        assert_eq!(
+            responses.next().unwrap().unwrap(),
+            r#"{"type":"nack","id":"2","result":"TransientError"}"#
+        );
+        assert_eq!(
+            responses.next().unwrap().unwrap(),
+            r#"{"type":"ack","id":"1"}"#
+        );
+        std::fs::remove_file(socket_path).unwrap();
+    }
+
+    #[test]
+    fn disconnect_slow_client() -> Result<()> {
+        let (stream, _peer) = UnixStream::pair()?;
+        let writer = Writer::new(&ID.to_string(), stream)?;
+        let response = ResponseLine::Error {
+            error: "x".repeat(1000),
+        };
+        // the peer never reads, so the socket buffer and the queue run full without blocking the port
+        let mut written = 0;
+        while write_response(&writer, &response).is_ok() {
+            written += 1;
+            assert!(written < 100_000, "the client was not disconnected");
+        }
+        assert!(write_response(&writer, &response).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn disconnect_clients_on_config_update() {
+        let old_socket_path = socket_path();
+        let (send_to_port, _recv_from_port) = start(Config::String(old_socket_path.clone()));
+        let mut client = connect(&old_socket_path);
+        client
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        // the client is accepted by the port before the config is updated
+        client.write_all(event_line("1").as_bytes()).unwrap();
+        thread::sleep(Duration::from_millis(300));
+
+        let new_socket_path = socket_path();
+        send_to_port.send(BrokerEvent::ConfigUpdated(
+            Config::String(new_socket_path.clone()),
+            ID.to_string(),
+        ));
+        let mut buffer = [0; 1];
+        assert_eq!(std::io::Read::read(&mut client, &mut buffer).unwrap(), 0);
+        connect(&new_socket_path);
+        std::fs::remove_file(old_socket_path).unwrap();
+        std::fs::remove_file(new_socket_path).unwrap();
+    }
+}
@@ -0,0 +1,69 @@
+/*!
+
+This is a package for [CERK](https://github.com/ce-rust/cerk).
+CERK is an open source [CloudEvents](https://github.com/cloudevents/spec) Router written in Rust with a MicroKernel architecture.
+
+# Introduction
+
+CERK lets you route your [CloudEvents](https://github.com/cloudevents/spec) between different different ports.
+Ports are transport layer bindings over which CloudEvents can be exchanged.
+It is built with modularity and portability in mind.
+
+# Components
+
+CERK comes with a couple of prefabricated components, but implementing custom components is easy.
+
+A good overview is provided on [GitHub](https://github.com/ce-rust/cerk/).
+
+# This Component: WebSocket Port
+
+This port serves a WebSocket server, e.g., to stream CloudEvents to dashboards in the browser.
+Clients subscribe to the events which are routed to the port and can publish events to the router.
+
+All messages are JSON encoded text messages.
+
+## Client Messages
+
+| Message                                                   | Description                                          |
+|-----------------------------------------------------------|------------------------------------------------------|
+| `{"type":"subscribe"}`                                    | receive all events routed to the port                |
+| `{"type":"subscribe","filter":{"Exact":["Type","a"]}}`    | receive only the events which match the filter       |
+| `{"type":"unsubscribe"}`                                  | stop receiving events                                |
+| `{"type":"publish","event":{...}}`                        | send a CloudEvent to the router                      |
+
+The filter uses the same format as the routing rules of the [rule-based router](https://github.com/ce-rust/cerk/tree/master/cerk_router_rule_based/).
+
+## Server Messages
+
+The routed events are sent in the structured content mode, i.e., as JSON encoded CloudEvents.
+Besides the events, the port sends the following messages:
+
+| Message                                                   | Description                                          |
+|-----------------------------------------------------------|------------------------------------------------------|
+| `{"type":"ack","id":"1","result":"Successful"}`           | the published event with the id `1` was processed    |
+| `{"type":"error","error":"..."}`                          | a message of the client was invalid                  |
+
+Acknowledgments are only sent if the delivery guarantee of the port requires them.
+
+## Configurations
+
+| Name                 | Type   | Default   | Description                                          |
+|----------------------|--------|-----------|------------------------------------------------------|
+| `ip_addr`            | String | `0.0.0.0` | the address the server listens on                    |
+| `websocket_port`     | u32    | `8090`    | the port the server listens on                       |
+| `delivery_guarantee` | u8     | `0`       | the delivery guarantee of the published events       |
+| `client_queue_size`  | u32    | `100`     | the queued messages of a client                      |
+
+Events are pushed to the clients with best effort, an outgoing event is successful as soon as it is handed to the connections.
+A client whose queue of `client_queue_size` messages is full is disconnected, it has to connect and subscribe again.
+
+*/
+
+#![deny(missing_docs)]
+
+#[macro_use]
+extern crate log;
+
+mod port_websocket;
+
+pub use self::port_websocket::{port_websocket_start, PORT_WEBSOCKET};
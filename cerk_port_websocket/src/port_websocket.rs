@@ -0,0 +1,677 @@
+use anyhow::{bail, Context, Result};
+use cerk::kernel::{
+    BrokerEvent, CloudEventMessageRoutingId, CloudEventRoutingArgs, Config, ConfigHelpers,
+    DeliveryGuarantee, HealthCheckRequest, HealthCheckResponse, HealthCheckStatus,
+    IncomingCloudEvent, OutgoingCloudEvent, OutgoingCloudEventProcessed, ProcessingResult,
+};
+use cerk::runtime::channel::{BoxedReceiver, BoxedSender};
+use cerk::runtime::{InternalServerFn, InternalServerFnRefStatic, InternalServerId};
+use cerk_router_rule_based::RoutingRules;
+use cloudevents::{AttributesReader, Event};
+use futures::channel::mpsc::{channel, Sender};
+use futures::executor::block_on;
+use futures::{StreamExt, TryStreamExt};
+use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::runtime::Handle;
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+use tokio_tungstenite::tungstenite::Message;
+use uuid::Uuid;
+
+const DEFAULT_CLIENT_QUEUE_SIZE: u32 = 100;
+
+type ClientId = String;
+type ArcWebSocketData = Arc<Mutex<WebSocketData>>;
+
+/// The messages the clients send to the port.
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum ClientMessage {
+    /// receive all events which match the filter, or all events if no filter is set
+    Subscribe { filter: Option<RoutingRules> },
+    /// stop receiving events
+    Unsubscribe,
+    /// route the event to the kernel
+    Publish { event: Box<Event> },
+}
+
+/// The messages the port sends to the clients, besides the CloudEvents.
+#[derive(Debug, Serialize, PartialEq)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum ServerMessage {
+    Ack { id: String, result: String },
+    Error { error: String },
+}
+
+struct WebSocketClient {
+    /// the queue of the messages to the client, the client is disconnected if it is full
+    sender: Sender<Message>,
+    subscribed: bool,
+    filter: Option<RoutingRules>,
+}
+
+struct PendingPublish {
+    client_id: ClientId,
+    event_id: String,
+}
+
+struct WebSocketConfig {
+    address: SocketAddr,
+    delivery_guarantee: DeliveryGuarantee,
+    /// how many messages are queued for a client, before it is disconnected
+    client_queue_size: usize,
+}
+
+struct RunningServer {
+    address: SocketAddr,
+    shutdown: oneshot::Sender<()>,
+    /// finishes after the listener was closed
+    task: JoinHandle<()>,
+}
+
+struct WebSocketData {
+    id: InternalServerId,
+    config: Option<WebSocketConfig>,
+    server: Option<RunningServer>,
+    sender_to_kernel: BoxedSender,
+    clients: HashMap<ClientId, WebSocketClient>,
+    pending_publishes: HashMap<CloudEventMessageRoutingId, PendingPublish>,
+}
+
+fn build_config(config: &Config) -> Result<WebSocketConfig> {
+    let ip_addr: IpAddr = config
+        .get_op_val_string("ip_addr")?
+        .unwrap_or_else(|| "0.0.0.0".to_string())
+        .parse()?;
+    let port = config.get_op_val_u32("websocket_port")?.unwrap_or(8090) as u16;
+    let delivery_guarantee = match config.get_op_val_config("delivery_guarantee")? {
+        Some(c) => DeliveryGuarantee::try_from(c)?,
+        None => DeliveryGuarantee::BestEffort,
+    };
+    let client_queue_size = config
+        .get_op_val_u32("client_queue_size")?
+        .unwrap_or(DEFAULT_CLIENT_QUEUE_SIZE);
+    if client_queue_size == 0 {
+        bail!("client_queue_size must be greater than 0");
+    }
+    Ok(WebSocketConfig {
+        address: SocketAddr::new(ip_addr, port),
+        delivery_guarantee,
+        client_queue_size: client_queue_size as usize,
+    })
+}
+
+/// Queues the message for the client, false is returned if the client has to be removed.
+///
+/// A client whose queue is full does not keep up with the messages, so it is disconnected by dropping its queue.
+fn try_send(client_id: &str, client: &mut WebSocketClient, message: Message) -> bool {
+    match client.sender.try_send(message) {
+        Ok(()) => true,
+        Err(e) if e.is_full() => {
+            warn!("client {} is too slow, disconnect it", client_id);
+            false
+        }
+        // the client is disconnected and is removed by its connection task
+        Err(_) => true,
+    }
+}
+
+fn send_to_client(data: &mut WebSocketData, client_id: &str, message: &ServerMessage) {
+    let message = Message::Text(serde_json::to_string(message).unwrap());
+    if let Some(client) = data.clients.get_mut(client_id) {
+        if !try_send(client_id, client, message) {
+            data.clients.remove(client_id);
+        }
+    }
+}
+
+fn publish(data: &mut WebSocketData, client_id: &str, cloud_event: Event) -> Result<()> {
+    // the client could have been disconnected, because its queue was full
+    if !data.clients.contains_key(client_id) {
+        bail!("client {} is not connected", client_id);
+    }
+    let delivery_guarantee = data
+        .config
+        .as_ref()
+        .context("port is not configured")?
+        .delivery_guarantee;
+    let routing_id = Uuid::new_v4().to_string();
+    if delivery_guarantee.requires_acknowledgment() {
+        data.pending_publishes.insert(
+            routing_id.clone(),
+            PendingPublish {
+                client_id: client_id.to_string(),
+                event_id: cloud_event.id().to_string(),
+            },
+        );
+    }
+    data.sender_to_kernel
+        .send(BrokerEvent::IncomingCloudEvent(IncomingCloudEvent {
+            incoming_id: data.id.clone(),
+            routing_id,
+            cloud_event,
+            args: CloudEventRoutingArgs { delivery_guarantee },
+        }));
+    Ok(())
+}
+
+fn handle_client_message(data: &ArcWebSocketData, client_id: &str, text: &str) {
+    let mut data = data.lock().unwrap();
+    let result = serde_json::from_str::<ClientMessage>(text)
+        .context("invalid message")
+        .and_then(|message| match message {
+            ClientMessage::Subscribe { filter } => {
+                let client = data.clients.get_mut(client_id).context("unknown client")?;
+                client.subscribed = true;
+                client.filter = filter;
+                Ok(())
+            }
+            ClientMessage::Unsubscribe => {
+                let client = data.clients.get_mut(client_id).context("unknown client")?;
+                client.subscribed = false;
+                client.filter = None;
+                Ok(())
+            }
+            ClientMessage::Publish { event } => publish(&mut data, client_id, *event),
+        });
+    if let Err(e) = result {
+        send_to_client(
+            &mut data,
+            client_id,
+            &ServerMessage::Error {
+                error: format!("{:#}", e),
+            },
+        );
+    }
+}
+
+/// Pushes the event to all subscribed clients with a matching filter.
+fn broadcast(data: &ArcWebSocketData, event: OutgoingCloudEvent) {
+    let mut data = data.lock().unwrap();
+    match serde_json::to_string(&event.cloud_event) {
+        Ok(json) => {
+            data.clients.retain(|client_id, client| {
+                let matches = client.subscribed
+                    && match client.filter.as_ref() {
+                        Some(filter) => filter.matches(&event.cloud_event),
+                        None => true,
+                    };
+                !matches || try_send(client_id, client, Message::Text(json.clone()))
+            });
+        }
+        Err(e) => error!("{} failed to serialize CloudEvent {:?}", data.id, e),
+    }
+    if event.args.delivery_guarantee.requires_acknowledgment() {
+        data.sender_to_kernel
+            .send(BrokerEvent::OutgoingCloudEventProcessed(
+                OutgoingCloudEventProcessed {
+                    sender_id: data.id.clone(),
+                    routing_id: event.routing_id,
+                    result: ProcessingResult::Successful,
+                },
+            ));
+    }
+}
+
+fn resolve_pending_publish(
+    data: &ArcWebSocketData,
+    routing_id: &CloudEventMessageRoutingId,
+    result: ProcessingResult,
+) -> Result<()> {
+    let mut data = data.lock().unwrap();
+    let pending = data
+        .pending_publishes
+        .remove(routing_id)
+        .with_context(|| format!("pending publish with id={} not found", routing_id))?;
+    if data.clients.contains_key(&pending.client_id) {
+        send_to_client(
+            &mut data,
+            &pending.client_id,
+            &ServerMessage::Ack {
+                id: pending.event_id,
+                result: result.to_string(),
+            },
+        );
+    } else {
+        debug!(
+            "client {} disconnected before the ack was sent",
+            pending.client_id
+        );
+    }
+    Ok(())
+}
+
+async fn handle_connection(stream: TcpStream, data: ArcWebSocketData) {
+    let websocket = match tokio_tungstenite::accept_async(stream).await {
+        Ok(websocket) => websocket,
+        Err(e) => {
+            warn!("websocket handshake failed: {}", e);
+            return;
+        }
+    };
+    let client_id = Uuid::new_v4().to_string();
+    let (sink, mut stream) = websocket.split();
+    {
+        let mut data = data.lock().unwrap();
+        let queue_size = match data.config.as_ref() {
+            Some(config) => config.client_queue_size,
+            None => return,
+        };
+        let (sender, receiver) = channel(queue_size);
+        data.clients.insert(
+            client_id.clone(),
+            WebSocketClient {
+                sender,
+                subscribed: false,
+                filter: None,
+            },
+        );
+        // the connection is closed, as soon as the client is removed and its queue is dropped
+        tokio::spawn(receiver.map(Ok).forward(sink));
+    }
+    debug!("client {} connected", client_id);
+
+    loop {
+        match stream.try_next().await {
+            Ok(Some(Message::Text(text))) => handle_client_message(&data, &client_id, &text),
+            Ok(Some(Message::Close(_))) | Ok(None) => break,
+            Ok(Some(_)) => {}
+            Err(e) => {
+                warn!("client {} failed: {}", client_id, e);
+                break;
+            }
+        }
+    }
+
+    data.lock().unwrap().clients.remove(&client_id);
+    debug!("client {} disconnected", client_id);
+}
+
+/// Closes the connections of all clients, shuts the running server down and waits until its listener is closed.
+fn stop_server(data: &ArcWebSocketData) {
+    let (id, server) = {
+        let mut data = data.lock().unwrap();
+        for (client_id, mut client) in data.clients.drain() {
+            debug!("close connection of client {}", client_id);
+            // the client answers the close frame, which ends its connection task,
+            // a full queue is dropped, which closes the connection as well
+            let _ = client.sender.try_send(Message::Close(None));
+        }
+        (data.id.clone(), data.server.take())
+    };
+    if let Some(server) = server {
+        if server.shutdown.send(()).is_err() {
+            warn!("{} the previous server was already stopped", id);
+        }
+        if let Err(e) = block_on(server.task) {
+            warn!("{} the previous server failed {:?}", id, e);
+        }
+        debug!("{} stopped server on {}", id, server.address);
+    }
+}
+
+fn start_server(data: ArcWebSocketData, tokio: &Handle) -> Result<()> {
+    let (tx, mut rx) = oneshot::channel::<()>();
+    let address = data
+        .lock()
+        .unwrap()
+        .config
+        .as_ref()
+        .context("config is not set")?
+        .address;
+
+    let listener = std::net::TcpListener::bind(address)
+        .with_context(|| format!("failed to bind to {}", address))?;
+    listener.set_nonblocking(true)?;
+    let mut listener = tokio.enter(|| TcpListener::from_std(listener))?;
+
+    let task = {
+        let data = data.clone();
+        tokio.spawn(async move {
+            loop {
+                tokio::select! {
+                    _ = &mut rx => break,
+                    accepted = listener.accept() => match accepted {
+                        Ok((stream, _)) => {
+                            tokio::spawn(handle_connection(stream, data.clone()));
+                        }
+                        Err(e) => error!("failed to accept connection: {}", e),
+                    }
+                }
+            }
+        })
+    };
+    data.lock().unwrap().server = Some(RunningServer {
+        address,
+        shutdown: tx,
+        task,
+    });
+    info!("listening for websocket clients on ws://{}", address);
+    Ok(())
+}
+
+/// Applies the config, the server is only restarted if the address changed.
+fn update(config: Config, data: ArcWebSocketData, tokio: &Handle) -> Result<()> {
+    let config = build_config(&config)?;
+    let address = config.address;
+    let restart = {
+        let mut data = data.lock().unwrap();
+        data.config = Some(config);
+        data.server.as_ref().map(|server| server.address) != Some(address)
+    };
+    if restart {
+        // the previous listener has to be closed, before the address could be bound again
+        stop_server(&data);
+        start_server(data, tokio)?;
+    }
+    Ok(())
+}
+
+fn check_health(event: HealthCheckRequest, data: &ArcWebSocketData) {
+    let data = data.lock().unwrap();
+    let status = if data.server.is_some() {
+        HealthCheckStatus::Healthy
+    } else {
+        HealthCheckStatus::Unhealthy("server is not started".to_string())
+    };
+    data.sender_to_kernel
+        .send(BrokerEvent::HealthCheckResponse(HealthCheckResponse {
+            status,
+            destination_id: event.sender_id,
+            id: event.id,
+            sender_id: event.destination_id,
+        }))
+}
+
+/// This is the main function to start the port.
+pub fn port_websocket_start(
+    id: InternalServerId,
+    inbox: BoxedReceiver,
+    sender_to_kernel: BoxedSender,
+) {
+    info!("start websocket port with id {}", id);
+    let tokio = tokio::runtime::Runtime::new().unwrap();
+    let data: ArcWebSocketData = Arc::new(Mutex::new(WebSocketData {
+        id: id.clone(),
+        config: None,
+        server: None,
+        sender_to_kernel,
+        clients: HashMap::new(),
+        pending_publishes: HashMap::new(),
+    }));
+
+    loop {
+        match inbox.receive() {
+            BrokerEvent::Init => info!("{} initiated", id),
+            BrokerEvent::ConfigUpdated(config, _) => {
+                info!("{} received ConfigUpdated", id);
+                if let Err(e) = update(config, data.clone(), tokio.handle()) {
+                    error!("{} failed to apply config {:?}", id, e)
+                }
+            }
+            BrokerEvent::OutgoingCloudEvent(event) => broadcast(&data, event),
+            BrokerEvent::IncomingCloudEventProcessed(routing_id, result) => {
+                if let Err(e) = resolve_pending_publish(&data, &routing_id, result) {
+                    warn!(
+                        "{} IncomingCloudEventProcessed was not delivered {:?}",
+                        id, e
+                    )
+                }
+            }
+            BrokerEvent::HealthCheckRequest(event) => check_health(event, &data),
+            broker_event => warn!("event {} not implemented", broker_event),
+        }
+    }
+}
+
+/// This is the pointer for the main function to start the port.
+pub static PORT_WEBSOCKET: InternalServerFnRefStatic = &(port_websocket_start as InternalServerFn);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cerk_router_rule_based::CloudEventFields;
+    use cerk_runtime_threading::channel::new_channel_with_size;
+    use cloudevents::{EventBuilder, EventBuilderV10};
+    use futures::channel::mpsc::Receiver;
+    use std::time::Duration;
+
+    fn event(ty: &str) -> Event {
+        EventBuilderV10::new()
+            .id("1")
+            .ty(ty)
+            .source("http://example.com")
+            .build()
+            .unwrap()
+    }
+
+    fn data(
+        delivery_guarantee: DeliveryGuarantee,
+        sender_to_kernel: BoxedSender,
+    ) -> ArcWebSocketData {
+        Arc::new(Mutex::new(WebSocketData {
+            id: "websocket".to_string(),
+            config: Some(WebSocketConfig {
+                address: SocketAddr::new("127.0.0.1".parse().unwrap(), 8090),
+                delivery_guarantee,
+                client_queue_size: 2,
+            }),
+            server: None,
+            sender_to_kernel,
+            clients: HashMap::new(),
+            pending_publishes: HashMap::new(),
+        }))
+    }
+
+    fn connect(data: &ArcWebSocketData, client_id: &str) -> Receiver<Message> {
+        // the capacity of the channel is the buffer plus one slot for the sender
+        let (sender, receiver) = channel(1);
+        data.lock().unwrap().clients.insert(
+            client_id.to_string(),
+            WebSocketClient {
+                sender,
+                subscribed: false,
+                filter: None,
+            },
+        );
+        receiver
+    }
+
+    fn next_text(receiver: &mut Receiver<Message>) -> Option<String> {
+        match receiver.try_recv() {
+            Ok(Message::Text(text)) => Some(text),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn parse_client_messages() {
+        assert_eq!(
+            serde_json::from_str::<ClientMessage>(
+                r#"{"type":"subscribe","filter":{"Exact":["Type","a"]}}"#
+            )
+            .unwrap(),
+            ClientMessage::Subscribe {
+                filter: Some(RoutingRules::Exact(
+                    CloudEventFields::Type,
+                    Some("a".to_string())
+                ))
+            }
+        );
+        assert_eq!(
+            serde_json::from_str::<ClientMessage>(r#"{"type":"subscribe"}"#).unwrap(),
+            ClientMessage::Subscribe { filter: None }
+        );
+        assert_eq!(
+            serde_json::from_str::<ClientMessage>(
+                r#"{"type":"publish","event":{"specversion":"1.0","id":"1","type":"a","source":"http://example.com"}}"#
+            )
+            .unwrap(),
+            ClientMessage::Publish {
+                event: Box::new(event("a"))
+            }
+        );
+    }
+
+    #[test]
+    fn broadcast_to_matching_subscriptions() {
+        let (send, receive) = new_channel_with_size(10);
+        let data = data(DeliveryGuarantee::BestEffort, send);
+        let mut all = connect(&data, "all");
+        let mut filtered = connect(&data, "filtered");
+        let mut unsubscribed = connect(&data, "unsubscribed");
+        handle_client_message(&data, "all", r#"{"type":"subscribe"}"#);
+        handle_client_message(
+            &data,
+            "filtered",
+            r#"{"type":"subscribe","filter":{"Exact":["Type","a"]}}"#,
+        );
+
+        broadcast(
+            &data,
+            OutgoingCloudEvent {
+                routing_id: "1".to_string(),
+                cloud_event: event("b"),
+                destination_id: "websocket".to_string(),
+                args: CloudEventRoutingArgs {
+                    delivery_guarantee: DeliveryGuarantee::AtLeastOnce,
+                },
+            },
+        );
+
+        let json = next_text(&mut all).unwrap();
+        assert_eq!(serde_json::from_str::<Event>(&json).unwrap(), event("b"));
+        assert!(next_text(&mut filtered).is_none());
+        assert!(next_text(&mut unsubscribed).is_none());
+        match receive.receive_timeout(Duration::from_millis(100)) {
+            Some(BrokerEvent::OutgoingCloudEventProcessed(processed)) => {
+                assert_eq!(processed.routing_id, "1");
+                assert_eq!(processed.result, ProcessingResult::Successful);
+            }
+            _ => panic!("expected OutgoingCloudEventProcessed"),
+        }
+    }
+
+    #[test]
+    fn publish_and_acknowledge() {
+        let (send, receive) = new_channel_with_size(10);
+        let data = data(DeliveryGuarantee::AtLeastOnce, send);
+        let mut client = connect(&data, "client");
+        handle_client_message(
+            &data,
+            "client",
+            r#"{"type":"publish","event":{"specversion":"1.0","id":"1","type":"a","source":"http://example.com"}}"#,
+        );
+
+        let routing_id = match receive.receive_timeout(Duration::from_millis(100)) {
+            Some(BrokerEvent::IncomingCloudEvent(event)) => {
+                assert_eq!(event.incoming_id, "websocket");
+                assert_eq!(event.cloud_event.ty(), "a");
+                event.routing_id
+            }
+            _ => panic!("expected IncomingCloudEvent"),
+        };
+        resolve_pending_publish(&data, &routing_id, ProcessingResult::Successful).unwrap();
+
+        assert_eq!(
+            next_text(&mut client).unwrap(),
+            serde_json::to_string(&ServerMessage::Ack {
+                id: "1".to_string(),
+                result: ProcessingResult::Successful.to_string(),
+            })
+            .unwrap()
+        );
+    }
+
+    #[test]
+    fn invalid_message_returns_error() {
+        let (send, _receive) = new_channel_with_size(10);
+        let data = data(DeliveryGuarantee::BestEffort, send);
+        let mut client = connect(&data, "client");
+        handle_client_message(&data, "client", r#"{"type":"unknown"}"#);
+        assert!(next_text(&mut client)
+            .unwrap()
+            .contains(r#""type":"error""#));
+    }
+
+    #[test]
+    fn slow_client_is_disconnected() {
+        let (send, receive) = new_channel_with_size(10);
+        let data = data(DeliveryGuarantee::BestEffort, send);
+        let mut client = connect(&data, "slow");
+        handle_client_message(&data, "slow", r#"{"type":"subscribe"}"#);
+        let outgoing = OutgoingCloudEvent {
+            routing_id: "1".to_string(),
+            cloud_event: event("a"),
+            destination_id: "websocket".to_string(),
+            args: CloudEventRoutingArgs {
+                delivery_guarantee: DeliveryGuarantee::BestEffort,
+            },
+        };
+
+        for _ in 0..3 {
+            broadcast(&data, outgoing.clone());
+        }
+        assert!(!data.lock().unwrap().clients.contains_key("slow"));
+        // the queued messages are still sent, then the connection ends
+        assert!(next_text(&mut client).is_some());
+        assert!(next_text(&mut client).is_some());
+        assert!(block_on(client.next()).is_none());
+
+        // the publishes of the disconnected client are rejected
+        handle_client_message(
+            &data,
+            "slow",
+            r#"{"type":"publish","event":{"specversion":"1.0","id":"1","type":"a","source":"http://example.com"}}"#,
+        );
+        assert!(receive.receive_timeout(Duration::from_millis(10)).is_none());
+    }
+
+    fn config(port: u32) -> Config {
+        Config::HashMap(
+            [
+                (
+                    "ip_addr".to_string(),
+                    Config::String("127.0.0.1".to_string()),
+                ),
+                ("websocket_port".to_string(), Config::U32(port)),
+            ]
+            .iter()
+            .cloned()
+            .collect(),
+        )
+    }
+
+    #[test]
+    fn config_update_rebinds_and_closes_clients() -> Result<()> {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let (send, _receive) = new_channel_with_size(10);
+        let data = data(DeliveryGuarantee::BestEffort, send);
+        let free_port = || {
+            std::net::TcpListener::bind("127.0.0.1:0")
+                .unwrap()
+                .local_addr()
+                .unwrap()
+                .port() as u32
+        };
+        let (first, second) = (free_port(), free_port());
+
+        update(config(first), data.clone(), runtime.handle())?;
+        let mut receiver = connect(&data, "a");
+        // the same address keeps the running server and its clients
+        update(config(first), data.clone(), runtime.handle())?;
+        assert!(data.lock().unwrap().clients.contains_key("a"));
+        update(config(second), data.clone(), runtime.handle())?;
+        assert!(matches!(receiver.try_recv(), Ok(Message::Close(None))));
+        assert!(data.lock().unwrap().clients.is_empty());
+        // the first address was released by the previous server
+        update(config(first), data.clone(), runtime.handle())?;
+        stop_server(&data);
+        Ok(())
+    }
+}
@@ -25,7 +25,7 @@ where
     }
 }
 
-fn route_to_port(rules: &RoutingRules, cloud_event: &Event) -> bool {
+pub(crate) fn route_to_port(rules: &RoutingRules, cloud_event: &Event) -> bool {
     match rules {
         RoutingRules::And(rules) => rules.iter().all(|rule| route_to_port(rule, cloud_event)),
         RoutingRules::Or(rules) => rules.iter().any(|rule| route_to_port(rule, cloud_event)),
@@ -104,7 +104,10 @@ pub fn router_start(id: InternalServerId, inbox: BoxedReceiver, sender_to_kernel
                 if let Config::String(string_config) = updated_config {
                     match parse_config(string_config) {
                         Ok(parsed_config) => config = Some(parsed_config),
-                        Err(err) => panic!("was not able to parse configs {:?}", err),
+                        Err(err) => error!(
+                            "{} was not able to parse configs, the routing rules stay unchanged {:?}",
+                            id, err
+                        ),
                     }
                 }
             }
@@ -1,3 +1,5 @@
+use crate::router::route_to_port;
+use cloudevents::Event;
 use serde_derive::{Deserialize, Serialize};
 use std::collections::HashMap;
 
@@ -59,6 +61,13 @@ pub enum RoutingRules {
     EndsWith(CloudEventFields, String),
 }
 
+impl RoutingRules {
+    /// Checks if the event matches the rules, e.g., to filter events outside of the router.
+    pub fn matches(&self, cloud_event: &Event) -> bool {
+        route_to_port(self, cloud_event)
+    }
+}
+
 /// routing rules table
 ///
 /// Routing rules indexed by the adapter that should receive the event
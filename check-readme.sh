@@ -13,13 +13,25 @@ function check {
 }
 
 check cerk
+check cerk_config_loader_composite
 check cerk_config_loader_file
+check cerk_config_loader_http
+check cerk_file_format
 check cerk_loader_file
 check cerk_port_amqp
+check cerk_port_amqp10
+check cerk_port_common
 check cerk_port_dummies
+check cerk_port_grpc
 check cerk_port_health_check_http
+check cerk_port_http
+check cerk_port_kafka
 check cerk_port_mqtt
+check cerk_port_nats
+check cerk_port_redis
+check cerk_port_tcp
 check cerk_port_unix_socket
+check cerk_port_websocket
 check cerk_router_broadcast
 check cerk_router_rule_based
 check cerk_runtime_threading